@@ -117,6 +117,7 @@ async fn main() -> Result<()> {
         liquidation_threshold: 1.1, // 110% liquidation (whitepaper default)
         min_mint_amount: 10.0,
         enabled: true,
+        ..Default::default()
     });
     
     vault_manager.add_currency(Currency::GBP, CurrencyConfig {
@@ -126,6 +127,7 @@ async fn main() -> Result<()> {
         liquidation_threshold: 1.1, // 110% liquidation (whitepaper default)
         min_mint_amount: 10.0,
         enabled: true,
+        ..Default::default()
     });
 
     vault_manager.update_exchange_rates(exchange_rates.clone());
@@ -0,0 +1,454 @@
+//! Persistent peer address book and ban list
+//! Fed by config seeds, handshakes, announcements and address exchange
+
+use std::collections::{HashMap, HashSet};
+use bitcoin::PublicKey;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use crate::{BitStableError, Result};
+use crate::database::DatabaseManager;
+use crate::network::ServiceType;
+
+/// Most addresses accepted from, or sent in, one exchange message
+pub const MAX_ADDRESSES_PER_MESSAGE: usize = 100;
+const MAX_ENTRIES: usize = 10_000;
+/// Non-seed entries are forgotten after this many failed dials in a row
+const MAX_FAILURES: u32 = 10;
+const MAX_BACKOFF_MINUTES: i64 = 24 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AddressSource {
+    Seed,
+    Manual,
+    Handshake,
+    Announcement,
+    Exchange,
+}
+
+/// Address record as carried in address exchange messages
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerAddress {
+    pub pubkey: PublicKey,
+    pub address: String,
+    pub services: Vec<ServiceType>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressEntry {
+    pub pubkey: PublicKey,
+    pub address: String,
+    pub services: Vec<ServiceType>,
+    pub source: AddressSource,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub last_attempt: Option<DateTime<Utc>>,
+    pub last_success: Option<DateTime<Utc>>,
+    pub failures: u32,
+}
+
+impl AddressEntry {
+    /// Failed entries wait 2^failures minutes, capped at a day, before the next dial
+    pub fn next_attempt(&self) -> Option<DateTime<Utc>> {
+        let last_attempt = self.last_attempt?;
+        if self.failures == 0 {
+            return None;
+        }
+        let minutes = 1i64.checked_shl(self.failures).unwrap_or(i64::MAX).min(MAX_BACKOFF_MINUTES);
+        Some(last_attempt + Duration::minutes(minutes))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BanEntry {
+    pub pubkey: PublicKey,
+    pub reason: String,
+    pub banned_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,   // None bans permanently
+}
+
+impl BanEntry {
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_none_or(|expires_at| now < expires_at)
+    }
+}
+
+/// Parse a `pubkey@host:port` peer URI
+pub fn parse_peer_uri(uri: &str) -> Result<(PublicKey, String)> {
+    let (pubkey, address) = uri.split_once('@')
+        .ok_or_else(|| BitStableError::InvalidConfig(format!("Peer '{}' must be pubkey@host:port", uri)))?;
+    let pubkey: PublicKey = pubkey.parse()
+        .map_err(|e| BitStableError::InvalidConfig(format!("Invalid peer public key in '{}': {}", uri, e)))?;
+    validate_address(address)?;
+    Ok((pubkey, address.to_string()))
+}
+
+fn validate_address(address: &str) -> Result<()> {
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(()),
+        _ => Err(BitStableError::InvalidConfig(format!("Peer address '{}' must be host:port", address))),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AddressBook {
+    entries: HashMap<PublicKey, AddressEntry>,
+    bans: HashMap<PublicKey, BanEntry>,
+    database: Option<DatabaseManager>,
+}
+
+impl AddressBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load persisted addresses and bans, and keep them persisted from now on
+    pub fn with_database(database: DatabaseManager) -> Result<Self> {
+        let entries = database.load_peer_addresses()?
+            .into_iter()
+            .map(|entry| (entry.pubkey, entry))
+            .collect();
+        let bans = database.load_peer_bans()?
+            .into_iter()
+            .map(|ban| (ban.pubkey, ban))
+            .collect();
+
+        Ok(Self {
+            entries,
+            bans,
+            database: Some(database),
+        })
+    }
+
+    pub fn add_seed(&mut self, uri: &str, now: DateTime<Utc>) -> Result<PublicKey> {
+        let (pubkey, address) = parse_peer_uri(uri)?;
+        self.record(pubkey, address, Vec::new(), AddressSource::Seed, now);
+        Ok(pubkey)
+    }
+
+    /// Insert or refresh an address; returns false if it was rejected
+    pub fn record(
+        &mut self,
+        pubkey: PublicKey,
+        address: String,
+        services: Vec<ServiceType>,
+        source: AddressSource,
+        now: DateTime<Utc>,
+    ) -> bool {
+        if validate_address(&address).is_err() {
+            return false;
+        }
+        if !self.entries.contains_key(&pubkey) && self.entries.len() >= MAX_ENTRIES && !self.evict_for(source) {
+            return false;
+        }
+
+        let entry = self.entries.entry(pubkey).or_insert_with(|| AddressEntry {
+            pubkey,
+            address: address.clone(),
+            services: services.clone(),
+            source,
+            first_seen: now,
+            last_seen: now,
+            last_attempt: None,
+            last_success: None,
+            failures: 0,
+        });
+
+        // Seeds and manual entries keep their origin; second-hand gossip never moves a known address
+        if source != AddressSource::Exchange {
+            entry.address = address;
+        }
+        if !services.is_empty() {
+            entry.services = services;
+        }
+        if matches!(source, AddressSource::Seed | AddressSource::Manual)
+            || (entry.source == AddressSource::Exchange && source != AddressSource::Exchange)
+        {
+            entry.source = source;
+        }
+        entry.last_seen = entry.last_seen.max(now);
+
+        let entry = entry.clone();
+        self.persist(&entry);
+        true
+    }
+
+    pub fn record_attempt(&mut self, pubkey: &PublicKey, now: DateTime<Utc>) {
+        if let Some(entry) = self.entries.get_mut(pubkey) {
+            entry.last_attempt = Some(now);
+            let entry = entry.clone();
+            self.persist(&entry);
+        }
+    }
+
+    pub fn record_success(&mut self, pubkey: &PublicKey, now: DateTime<Utc>) {
+        if let Some(entry) = self.entries.get_mut(pubkey) {
+            entry.last_success = Some(now);
+            entry.last_seen = now;
+            entry.failures = 0;
+            let entry = entry.clone();
+            self.persist(&entry);
+        }
+    }
+
+    pub fn record_failure(&mut self, pubkey: &PublicKey) {
+        let Some(entry) = self.entries.get_mut(pubkey) else {
+            return;
+        };
+        entry.failures += 1;
+
+        if entry.failures >= MAX_FAILURES && !matches!(entry.source, AddressSource::Seed | AddressSource::Manual) {
+            self.forget(pubkey);
+        } else {
+            let entry = entry.clone();
+            self.persist(&entry);
+        }
+    }
+
+    pub fn get(&self, pubkey: &PublicKey) -> Option<&AddressEntry> {
+        self.entries.get(pubkey)
+    }
+
+    pub fn entries(&self) -> Vec<&AddressEntry> {
+        let mut entries: Vec<_> = self.entries.values().collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.last_seen));
+        entries
+    }
+
+    pub fn seeds(&self) -> Vec<&AddressEntry> {
+        self.entries.values().filter(|entry| entry.source == AddressSource::Seed).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Dialable entries offering `service`, most reliable first
+    pub fn candidates(
+        &self,
+        service: Option<&ServiceType>,
+        exclude: &HashSet<PublicKey>,
+        now: DateTime<Utc>,
+    ) -> Vec<&AddressEntry> {
+        let mut candidates: Vec<_> = self.entries.values()
+            .filter(|entry| !exclude.contains(&entry.pubkey) && !self.is_banned(&entry.pubkey, now))
+            .filter(|entry| service.is_none_or(|service| entry.services.contains(service)))
+            .filter(|entry| entry.next_attempt().is_none_or(|next| now >= next))
+            .collect();
+
+        candidates.sort_by(|a, b| {
+            a.failures.cmp(&b.failures)
+                .then(b.last_success.cmp(&a.last_success))
+                .then(b.last_seen.cmp(&a.last_seen))
+        });
+        candidates
+    }
+
+    /// Recently seen, unbanned addresses to share with a peer
+    pub fn sample(&self, limit: usize, now: DateTime<Utc>) -> Vec<PeerAddress> {
+        self.entries()
+            .into_iter()
+            .filter(|entry| !self.is_banned(&entry.pubkey, now))
+            .take(limit.min(MAX_ADDRESSES_PER_MESSAGE))
+            .map(|entry| PeerAddress {
+                pubkey: entry.pubkey,
+                address: entry.address.clone(),
+                services: entry.services.clone(),
+            })
+            .collect()
+    }
+
+    pub fn ban(&mut self, pubkey: PublicKey, reason: &str, duration: Option<Duration>, now: DateTime<Utc>) -> Result<()> {
+        let ban = BanEntry {
+            pubkey,
+            reason: reason.to_string(),
+            banned_at: now,
+            expires_at: duration.map(|duration| now + duration),
+        };
+        if let Some(database) = &self.database {
+            database.save_peer_ban(&ban)?;
+        }
+        self.bans.insert(pubkey, ban);
+        Ok(())
+    }
+
+    /// Returns whether a ban was lifted
+    pub fn unban(&mut self, pubkey: &PublicKey) -> Result<bool> {
+        if let Some(database) = &self.database {
+            database.remove_peer_ban(pubkey)?;
+        }
+        Ok(self.bans.remove(pubkey).is_some())
+    }
+
+    pub fn is_banned(&self, pubkey: &PublicKey, now: DateTime<Utc>) -> bool {
+        self.bans.get(pubkey).is_some_and(|ban| ban.is_active(now))
+    }
+
+    pub fn bans(&self, now: DateTime<Utc>) -> Vec<&BanEntry> {
+        let mut bans: Vec<_> = self.bans.values().filter(|ban| ban.is_active(now)).collect();
+        bans.sort_by_key(|ban| std::cmp::Reverse(ban.banned_at));
+        bans
+    }
+
+    /// Drop bans that have run out; returns how many
+    pub fn purge_expired_bans(&mut self, now: DateTime<Utc>) -> Result<usize> {
+        let expired: Vec<_> = self.bans.values()
+            .filter(|ban| !ban.is_active(now))
+            .map(|ban| ban.pubkey)
+            .collect();
+        for pubkey in &expired {
+            self.unban(pubkey)?;
+        }
+        Ok(expired.len())
+    }
+
+    /// Make room in a full book for an entry from `source`. Gossiped entries go first,
+    /// worst first; an exchanged address may only displace one that never connected,
+    /// and seeds and manual entries are never evicted.
+    fn evict_for(&mut self, source: AddressSource) -> bool {
+        let victim = self.entries.values()
+            .filter(|entry| match source {
+                AddressSource::Exchange => entry.source == AddressSource::Exchange && entry.last_success.is_none(),
+                _ => !matches!(entry.source, AddressSource::Seed | AddressSource::Manual),
+            })
+            .min_by(|a, b| {
+                (a.source != AddressSource::Exchange).cmp(&(b.source != AddressSource::Exchange))
+                    .then(a.last_success.is_some().cmp(&b.last_success.is_some()))
+                    .then(b.failures.cmp(&a.failures))
+                    .then(a.last_seen.cmp(&b.last_seen))
+            })
+            .map(|entry| entry.pubkey);
+
+        match victim {
+            Some(pubkey) => {
+                self.forget(&pubkey);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn forget(&mut self, pubkey: &PublicKey) {
+        self.entries.remove(pubkey);
+        if let Some(database) = &self.database {
+            if let Err(e) = database.remove_peer_address(pubkey) {
+                log::warn!("Failed to remove peer address {}: {}", pubkey, e);
+            }
+        }
+    }
+
+    /// Address bookkeeping is best effort; a failed write only costs us the entry on restart
+    fn persist(&self, entry: &AddressEntry) {
+        if let Some(database) = &self.database {
+            if let Err(e) = database.save_peer_address(entry) {
+                log::warn!("Failed to persist peer address {}: {}", entry.pubkey, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn pubkey(byte: u8) -> PublicKey {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let secret = bitcoin::secp256k1::SecretKey::from_slice(&[byte; 32]).unwrap();
+        PublicKey::new(bitcoin::secp256k1::PublicKey::from_secret_key(&secp, &secret))
+    }
+
+    #[test]
+    fn test_address_book_persists_entries_and_bans() {
+        let temp_dir = TempDir::new().unwrap();
+        let now = Utc::now();
+
+        let database = DatabaseManager::new(temp_dir.path()).unwrap();
+        {
+            let mut book = AddressBook::with_database(database.clone()).unwrap();
+            let seed = book.add_seed(&format!("{}@10.0.0.1:8335", pubkey(1)), now).unwrap();
+            assert!(book.add_seed("not-a-uri", now).is_err());
+
+            book.record(pubkey(2), "10.0.0.2:8335".to_string(), vec![ServiceType::Oracle], AddressSource::Exchange, now);
+            book.record(pubkey(3), "10.0.0.3:8335".to_string(), vec![ServiceType::Oracle], AddressSource::Announcement, now);
+            assert!(!book.record(pubkey(4), "no-port".to_string(), Vec::new(), AddressSource::Exchange, now));
+
+            // Gossip about a seed doesn't demote it
+            book.record(seed, "10.0.0.9:8335".to_string(), vec![ServiceType::Liquidator], AddressSource::Exchange, now);
+            assert_eq!(book.get(&seed).unwrap().source, AddressSource::Seed);
+
+            book.ban(pubkey(3), "spam", Some(Duration::hours(1)), now).unwrap();
+            book.ban(pubkey(4), "forged prices", None, now).unwrap();
+
+            // A failed dial backs off; bans hide entries from dialing
+            book.record_attempt(&pubkey(2), now);
+            book.record_failure(&pubkey(2));
+            let oracles = book.candidates(Some(&ServiceType::Oracle), &HashSet::new(), now);
+            assert!(oracles.is_empty());
+            let later = book.candidates(Some(&ServiceType::Oracle), &HashSet::new(), now + Duration::minutes(3));
+            assert_eq!(later.iter().map(|entry| entry.pubkey).collect::<Vec<_>>(), vec![pubkey(2)]);
+        }
+
+        // Reopen from disk once the first handle is gone, so sled releases its lock
+        drop(database);
+        let mut book = AddressBook::with_database(DatabaseManager::new(temp_dir.path()).unwrap()).unwrap();
+
+        assert_eq!(book.len(), 3);
+        assert_eq!(book.seeds().len(), 1);
+        assert_eq!(book.get(&pubkey(2)).unwrap().failures, 1);
+        assert!(book.is_banned(&pubkey(3), now));
+        assert!(!book.is_banned(&pubkey(3), now + Duration::hours(2)));
+
+        // Expired bans are purged, permanent ones stay until lifted
+        assert_eq!(book.purge_expired_bans(now + Duration::hours(2)).unwrap(), 1);
+        assert_eq!(book.bans(now + Duration::hours(2)).len(), 1);
+        assert!(book.unban(&pubkey(4)).unwrap());
+        assert!(book.bans(now).is_empty());
+    }
+
+    #[test]
+    fn test_full_book_evicts_gossip_first() {
+        let now = Utc::now();
+        let mut book = AddressBook::new();
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let key = |n: u32| {
+            let mut bytes = [1u8; 32];
+            bytes[..4].copy_from_slice(&n.to_be_bytes());
+            let secret = bitcoin::secp256k1::SecretKey::from_slice(&bytes).unwrap();
+            PublicKey::new(bitcoin::secp256k1::PublicKey::from_secret_key(&secp, &secret))
+        };
+
+        book.record(key(0), "10.0.0.1:8335".to_string(), Vec::new(), AddressSource::Seed, now);
+        book.record(key(1), "10.0.0.2:8335".to_string(), Vec::new(), AddressSource::Exchange, now);
+        book.record(key(2), "10.0.0.3:8335".to_string(), Vec::new(), AddressSource::Exchange, now);
+        book.record_success(&key(2), now);
+        for n in 3..MAX_ENTRIES as u32 {
+            book.record(key(n), "10.0.1.1:8335".to_string(), Vec::new(), AddressSource::Handshake, now + Duration::seconds(1));
+        }
+        assert_eq!(book.len(), MAX_ENTRIES);
+
+        // Gossip can't move a known peer's address
+        book.record(key(1), "10.6.6.6:8335".to_string(), Vec::new(), AddressSource::Exchange, now);
+        assert_eq!(book.get(&key(1)).unwrap().address, "10.0.0.2:8335");
+
+        // A new exchanged address displaces the exchanged entry that never connected
+        assert!(book.record(key(100_000), "10.0.2.1:8335".to_string(), Vec::new(), AddressSource::Exchange, now));
+        assert!(book.get(&key(1)).is_none());
+        assert_eq!(book.len(), MAX_ENTRIES);
+
+        // Once only proven or first-hand entries are left, gossip is refused
+        book.record_success(&key(100_000), now);
+        assert!(!book.record(key(100_001), "10.0.2.2:8335".to_string(), Vec::new(), AddressSource::Exchange, now));
+
+        // A handshake still gets in, pushing out the remaining gossip before first-hand entries
+        assert!(book.record(key(100_002), "10.0.2.3:8335".to_string(), Vec::new(), AddressSource::Handshake, now));
+        assert!(book.record(key(100_003), "10.0.2.4:8335".to_string(), Vec::new(), AddressSource::Handshake, now));
+        assert!(book.get(&key(2)).is_none() && book.get(&key(100_000)).is_none());
+        assert!(book.record(key(100_004), "10.0.2.5:8335".to_string(), Vec::new(), AddressSource::Handshake, now));
+        assert!(book.get(&key(0)).is_some());
+        assert_eq!(book.len(), MAX_ENTRIES);
+    }
+}
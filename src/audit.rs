@@ -0,0 +1,344 @@
+//! Audit log
+//! Append-only, hash-chained record of privileged actions. Each entry commits to the
+//! previous entry's hash and is signed by the actor; anchors pin the chain head on-chain.
+
+use bitcoin::secp256k1::{ecdsa::Signature, Message, Secp256k1, SecretKey};
+use bitcoin::{PublicKey, Txid};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::database::{DatabaseManager, UnitOfWork};
+use crate::governance::ExecutionResult;
+use crate::insurance::InsurancePayout;
+use crate::multi_currency::Currency;
+use crate::proof_of_reserves::ReservesCommitment;
+use crate::{BitStableError, Result};
+
+/// `prev_hash` of the first entry
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AuditAction {
+    GovernanceExecuted {
+        proposal_id: u64,
+        result: ExecutionResult,
+    },
+    KeyUpdate {
+        proposal_id: Option<u64>,
+        removed: Vec<PublicKey>,
+        added: Vec<PublicKey>,
+    },
+    EmergencyShutdown {
+        reason: String,
+    },
+    EmergencyOverride {
+        enabled: bool,
+    },
+    CircuitBreakerOverride {
+        currency: Option<Currency>,   // `None` overrides every currency
+        until: DateTime<Utc>,
+    },
+    InsurancePayout {
+        payouts: Vec<InsurancePayout>,
+    },
+}
+
+impl AuditAction {
+    /// Key rotations are recorded as key updates, other proposals as executions
+    pub fn governance(proposal_id: u64, result: &ExecutionResult) -> Self {
+        match result {
+            ExecutionResult::KeyRotationCompleted { removed_keys, added_keys } => AuditAction::KeyUpdate {
+                proposal_id: Some(proposal_id),
+                removed: removed_keys.clone(),
+                added: added_keys.clone(),
+            },
+            _ => AuditAction::GovernanceExecuted { proposal_id, result: result.clone() },
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub sequence: u64,
+    pub timestamp: DateTime<Utc>,
+    pub actor: PublicKey,
+    pub action: AuditAction,
+    pub prev_hash: String,
+    pub hash: String,        // SHA-256 over every field above, hex
+    pub signature: String,   // Actor's compact ECDSA signature of `hash`, hex
+}
+
+impl AuditEntry {
+    fn content_hash(&self) -> Result<[u8; 32]> {
+        // Hash the sorted-key form, so map fields don't depend on HashMap order
+        let content = serde_json::to_value((&self.sequence, &self.timestamp, &self.actor, &self.action, &self.prev_hash))?;
+        Ok(Sha256::digest(serde_json::to_vec(&content)?).into())
+    }
+
+    fn check_signature(&self) -> bool {
+        let (Ok(signature), Ok(hash)) = (hex::decode(&self.signature), hex::decode(&self.hash)) else {
+            return false;
+        };
+        let (Ok(signature), Ok(hash)) = (Signature::from_compact(&signature), <[u8; 32]>::try_from(hash)) else {
+            return false;
+        };
+        Secp256k1::verification_only()
+            .verify_ecdsa(&Message::from_digest(hash), &signature, &self.actor.inner)
+            .is_ok()
+    }
+}
+
+/// The latest entry, as committed to by an anchor
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditHead {
+    pub sequence: u64,
+    pub hash: String,
+}
+
+/// A chain head published in a proof-of-reserves OP_RETURN
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditAnchor {
+    pub head: AuditHead,
+    pub txid: Txid,
+    pub anchored_at: DateTime<Utc>,
+    #[serde(default)]
+    pub commitment: Option<ReservesCommitment>,   // What the OP_RETURN digest was computed over
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditVerifyReport {
+    pub entries: usize,
+    pub anchors: usize,
+    pub head: Option<AuditHead>,
+    pub problems: Vec<String>,
+}
+
+impl AuditVerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    database: DatabaseManager,
+}
+
+impl AuditLog {
+    pub fn new(database: DatabaseManager) -> Self {
+        Self { database }
+    }
+
+    pub fn head(&self) -> Result<Option<AuditHead>> {
+        Ok(self.database.last_audit_entry()?.map(|entry| AuditHead { sequence: entry.sequence, hash: entry.hash }))
+    }
+
+    /// Sign the next entry and stage it in `work`, so it commits with the action it
+    /// records. Stage at most one entry per unit of work.
+    pub fn stage(&self, work: &mut UnitOfWork, actor: &SecretKey, action: AuditAction) -> Result<AuditEntry> {
+        let secp = Secp256k1::new();
+        let (sequence, prev_hash) = match self.head()? {
+            Some(head) => (head.sequence + 1, head.hash),
+            None => (0, GENESIS_HASH.to_string()),
+        };
+        let mut entry = AuditEntry {
+            sequence,
+            timestamp: Utc::now(),
+            actor: PublicKey::new(actor.public_key(&secp)),
+            action,
+            prev_hash,
+            hash: String::new(),
+            signature: String::new(),
+        };
+        let hash = entry.content_hash()?;
+        entry.hash = hex::encode(hash);
+        entry.signature = hex::encode(secp.sign_ecdsa(&Message::from_digest(hash), actor).serialize_compact());
+
+        work.put_audit_entry(&entry)?;
+        log::info!("Audit entry {} by {}: {:?}", entry.sequence, entry.actor, entry.action);
+        Ok(entry)
+    }
+
+    /// Record that `commitment`, carrying a chain head, was published on-chain in `txid`
+    pub fn record_anchor(&self, commitment: ReservesCommitment, txid: Txid) -> Result<AuditAnchor> {
+        let head = commitment.audit_head.clone()
+            .ok_or_else(|| BitStableError::InvalidConfig("Commitment carries no audit log head".to_string()))?;
+        let anchor = AuditAnchor { head, txid, anchored_at: Utc::now(), commitment: Some(commitment) };
+        self.database.save_audit_anchor(&anchor)?;
+        Ok(anchor)
+    }
+
+    pub fn entries(&self) -> Result<Vec<AuditEntry>> {
+        self.database.get_audit_entries()
+    }
+
+    pub fn anchors(&self) -> Result<Vec<AuditAnchor>> {
+        self.database.get_audit_anchors()
+    }
+
+    /// Walk the chain checking sequence numbers, links, hashes and signatures, then
+    /// check every anchored head is still in it. Dropping entries from the end is only
+    /// caught once an anchor covers them.
+    pub fn verify(&self) -> Result<AuditVerifyReport> {
+        let entries = self.entries()?;
+        let anchors = self.anchors()?;
+        let mut problems = Vec::new();
+
+        let mut expected_sequence = 0;
+        let mut prev_hash = GENESIS_HASH.to_string();
+        for entry in &entries {
+            if entry.sequence != expected_sequence {
+                problems.push(format!("Entries {} to {} are missing", expected_sequence, entry.sequence.saturating_sub(1)));
+            }
+            if entry.prev_hash != prev_hash {
+                problems.push(format!("Entry {} does not link to the entry before it", entry.sequence));
+            }
+            if hex::encode(entry.content_hash()?) != entry.hash {
+                problems.push(format!("Entry {} was edited: its hash does not match its contents", entry.sequence));
+            } else if !entry.check_signature() {
+                problems.push(format!("Entry {} is not signed by its actor {}", entry.sequence, entry.actor));
+            }
+            expected_sequence = entry.sequence + 1;
+            prev_hash = entry.hash.clone();
+        }
+
+        for anchor in &anchors {
+            match entries.iter().find(|entry| entry.sequence == anchor.head.sequence) {
+                Some(entry) if entry.hash == anchor.head.hash => {}
+                Some(_) => problems.push(format!(
+                    "Entry {} differs from the head anchored in {}", anchor.head.sequence, anchor.txid
+                )),
+                None => problems.push(format!(
+                    "Entry {} anchored in {} is missing from the log", anchor.head.sequence, anchor.txid
+                )),
+            }
+        }
+
+        Ok(AuditVerifyReport {
+            entries: entries.len(),
+            anchors: anchors.len(),
+            head: entries.last().map(|entry| AuditHead { sequence: entry.sequence, hash: entry.hash.clone() }),
+            problems,
+        })
+    }
+
+    /// `verify`, then recompute each anchor's commitment digest and compare it with
+    /// the OP_RETURN data `op_return` finds in the anchoring transaction
+    pub fn verify_on_chain(&self, op_return: impl Fn(Txid) -> Result<Option<Vec<u8>>>) -> Result<AuditVerifyReport> {
+        let mut report = self.verify()?;
+        for anchor in self.anchors()? {
+            let Some(commitment) = &anchor.commitment else {
+                report.problems.push(format!("Anchor in {} has no stored commitment to check", anchor.txid));
+                continue;
+            };
+            if commitment.audit_head.as_ref() != Some(&anchor.head) {
+                report.problems.push(format!("Commitment anchored in {} is for a different head", anchor.txid));
+                continue;
+            }
+            match op_return(anchor.txid) {
+                Ok(Some(data)) if data == commitment.op_return_digest() => {}
+                Ok(Some(_)) => report.problems.push(format!(
+                    "OP_RETURN in {} does not match the anchored commitment", anchor.txid
+                )),
+                Ok(None) => report.problems.push(format!("Transaction {} has no OP_RETURN output", anchor.txid)),
+                Err(e) => report.problems.push(format!("Could not fetch anchor transaction {}: {}", anchor.txid, e)),
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof_of_reserves::{ProofOfReservesSystem, SystemStateSnapshot};
+    use crate::schema;
+    use bitcoin::hashes::Hash;
+
+    fn append(log: &AuditLog, database: &DatabaseManager, actor: &SecretKey, action: AuditAction) -> AuditEntry {
+        let mut work = UnitOfWork::new();
+        let entry = log.stage(&mut work, actor, action).unwrap();
+        database.commit(work).unwrap();
+        entry
+    }
+
+    fn commitment(head: AuditHead) -> ReservesCommitment {
+        let mut reserves = ProofOfReservesSystem::new();
+        let system_state = SystemStateSnapshot {
+            system_collateral_ratio: 2.0,
+            total_debt_all_currencies: 0.0,
+            total_collateral_btc: bitcoin::Amount::ZERO,
+            oracle_health: 1.0,
+            insurance_balance: bitcoin::Amount::ZERO,
+            active_oracles: 0,
+            emergency_state: false,
+        };
+        reserves.generate_commitment(&[], system_state, 800_000).unwrap();
+        reserves.attach_audit_head(head).unwrap()
+    }
+
+    #[test]
+    fn test_verify_detects_edits_gaps_and_truncation() {
+        let database = DatabaseManager::open("memory://").unwrap();
+        let log = AuditLog::new(database.clone());
+        let actor = SecretKey::from_slice(&[7u8; 32]).unwrap();
+
+        for enabled in [true, false, true] {
+            append(&log, &database, &actor, AuditAction::EmergencyOverride { enabled });
+        }
+        let head = log.head().unwrap().unwrap();
+        assert_eq!(head.sequence, 2);
+        log.record_anchor(commitment(head), Txid::from_raw_hash(bitcoin::hashes::sha256d::Hash::all_zeros())).unwrap();
+        assert!(log.verify().unwrap().is_ok());
+
+        // Rewriting an entry's action breaks its hash
+        let storage = database.storage();
+        let mut edited = log.entries().unwrap()[1].clone();
+        edited.action = AuditAction::EmergencyOverride { enabled: true };
+        storage.insert("audit_log", &1u64.to_be_bytes(), &schema::encode_record(&edited).unwrap()).unwrap();
+        let report = log.verify().unwrap();
+        assert!(report.problems.iter().any(|problem| problem.contains("Entry 1 was edited")));
+
+        // Dropping it leaves a gap, and truncating drops the anchored head
+        storage.remove("audit_log", &1u64.to_be_bytes()).unwrap();
+        storage.remove("audit_log", &2u64.to_be_bytes()).unwrap();
+        let report = log.verify().unwrap();
+        assert_eq!(report.entries, 1);
+        assert!(report.problems.iter().any(|problem| problem.contains("anchored in") && problem.contains("missing")));
+
+        // Rebuilding the chain past the anchor still can't reproduce the anchored head
+        append(&log, &database, &actor, AuditAction::EmergencyShutdown { reason: "test".to_string() });
+        append(&log, &database, &actor, AuditAction::EmergencyOverride { enabled: false });
+        let report = log.verify().unwrap();
+        assert_eq!(report.head.unwrap().sequence, 2);
+        assert_eq!(report.problems, vec![format!("Entry 2 differs from the head anchored in {}", Txid::all_zeros())]);
+    }
+
+    #[tokio::test]
+    async fn test_protocol_anchor_commits_to_vaults_and_head() {
+        let database = DatabaseManager::open("memory://").unwrap();
+        let mut protocol = crate::BitStableProtocol::with_database(crate::ProtocolConfig::testnet(), database).unwrap();
+        let actor = SecretKey::from_slice(&[7u8; 32]).unwrap();
+        let owner: PublicKey = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".parse().unwrap();
+        assert!(protocol.prepare_audit_anchor(800_000).unwrap().is_none());
+
+        protocol.oracle_network.apply_btc_prices(&std::collections::HashMap::from([(Currency::USD, 100000.0)]), Utc::now()).unwrap();
+        protocol.vault_manager.update_exchange_rates(protocol.oracle_network.get_exchange_rates().clone());
+        protocol.open_vault(owner, bitcoin::Amount::from_btc(1.0).unwrap(), Currency::USD, 40000.0).await.unwrap();
+        protocol.set_emergency_override(&actor, true).unwrap();
+
+        let commitment = protocol.prepare_audit_anchor(800_000).unwrap().unwrap();
+        assert_eq!(commitment.audit_head, protocol.audit_log.head().unwrap());
+        assert_eq!((commitment.total_vaults, commitment.block_height), (1, 800_000));
+        assert!((commitment.system_state.system_collateral_ratio - 2.5).abs() < 1e-9);
+        let txid = Txid::from_raw_hash(bitcoin::hashes::sha256d::Hash::hash(b"anchor"));
+        protocol.audit_log.record_anchor(commitment.clone(), txid).unwrap();
+
+        // The published digest checks out; anything else in the OP_RETURN does not
+        let digest = commitment.op_return_digest().to_vec();
+        assert!(protocol.audit_log.verify_on_chain(|_| Ok(Some(digest.clone()))).unwrap().is_ok());
+        let report = protocol.audit_log.verify_on_chain(|_| Ok(Some(vec![0; 32]))).unwrap();
+        assert_eq!(report.problems, vec![format!("OP_RETURN in {} does not match the anchored commitment", txid)]);
+    }
+}
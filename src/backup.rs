@@ -0,0 +1,536 @@
+//! Backup archives
+//! Gzip-compressed, checksummed snapshots of every tree, either full or incremental
+//! from the storage change log, and a restore that verifies before it overwrites.
+//! An archive is a gzip member holding the manifest line, followed by one holding the
+//! operations as JSON lines; both sides stream the operations rather than buffer them.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write as _};
+use std::path::Path;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use flate2::read::{GzDecoder, MultiGzDecoder};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::database::{DatabaseManager, Subsystem};
+use crate::schema::{self, MigrationMode, VerifyReport, SCHEMA_VERSION};
+use crate::stable::MultiCurrencyStableManager;
+use crate::storage::{ChangeLogStorage, KeyRange, MemoryStorage, Storage, Write, CHANGE_LOG_TREE};
+use crate::{BitStableError, Result};
+
+/// Archive layout version, bumped when the manifest or payload format changes
+pub const BACKUP_FORMAT_VERSION: u32 = 2;
+
+/// Keys read per scan while snapshotting a tree, and writes per batch while restoring
+const BATCH_SIZE: usize = 1024;
+
+/// Stable supply and vault debt may drift by float rounding, but no further
+const SUPPLY_TOLERANCE: f64 = 1e-6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupKind {
+    Full,
+    Incremental,
+}
+
+/// First line of an archive, describing the payload that follows it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format_version: u32,
+    pub kind: BackupKind,
+    pub created_at: DateTime<Utc>,
+    pub schema_version: u32,
+    pub base_checksum: Option<String>,  // Archive an incremental applies on top of
+    pub from_sequence: u64,             // First change log entry covered
+    pub next_sequence: u64,             // Where the next incremental starts
+    pub id_floor: u64,                  // Restored stores must not reissue ids below this
+    pub operations: usize,
+    pub checksum: String,               // SHA-256 of the uncompressed operation lines
+}
+
+/// One step of a restore; keys and values are hex encoded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum BackupOp {
+    Clear { tree: String },
+    Put { tree: String, key: String, value: String },
+    Remove { tree: String, key: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreReport {
+    pub archives: usize,
+    pub operations: usize,
+    pub from_schema_version: u32,
+    pub schema: VerifyReport,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupVerifyReport {
+    pub restore: RestoreReport,
+    pub violations: Vec<String>,
+}
+
+impl BackupVerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.restore.schema.is_ok() && self.violations.is_empty()
+    }
+}
+
+/// Snapshot every tree into a new archive at `path`. The change log before it is
+/// pruned, so later incrementals must build on this backup or its successors.
+pub fn write_full<P: AsRef<Path>>(db: &dyn Storage, path: P) -> Result<BackupManifest> {
+    // Read the log position first: writes racing the snapshot land in the next incremental too
+    let next_sequence = ChangeLogStorage::next_sequence(db)?;
+    let mut payload = PayloadWriter::create(path.as_ref())?;
+    for tree in db.tree_names()?.into_iter().filter(|tree| tree != CHANGE_LOG_TREE) {
+        snapshot_tree(db, &tree, &mut payload)?;
+    }
+    let manifest = payload.finish(db, path, BackupKind::Full, None, 0, next_sequence)?;
+    let pruned = ChangeLogStorage::prune(db, next_sequence)?;
+    log::debug!("Pruned {} change log entries covered by the full backup", pruned);
+    Ok(manifest)
+}
+
+/// Archive the keys changed since `base` was taken, to be restored on top of it
+pub fn write_incremental<P: AsRef<Path>>(db: &dyn Storage, path: P, base: &BackupManifest) -> Result<BackupManifest> {
+    let changes = ChangeLogStorage::changes_since(db, base.next_sequence)?;
+    let next_sequence = changes.last().map_or(base.next_sequence, |(sequence, _)| sequence + 1);
+
+    // A cleared tree is copied whole; otherwise each changed key's current value is
+    let mut cleared = BTreeSet::new();
+    let mut keys = BTreeSet::new();
+    for (_, change) in changes {
+        match change.key {
+            None => {
+                keys.retain(|(tree, _): &(String, Vec<u8>)| *tree != change.tree);
+                cleared.insert(change.tree);
+            }
+            Some(key) if !cleared.contains(&change.tree) => {
+                keys.insert((change.tree, key));
+            }
+            Some(_) => {}
+        }
+    }
+
+    let mut payload = PayloadWriter::create(path.as_ref())?;
+    for tree in &cleared {
+        snapshot_tree(db, tree, &mut payload)?;
+    }
+    for (tree, key) in keys {
+        payload.push(&match db.get(&tree, &key)? {
+            Some(value) => BackupOp::Put { tree, key: hex::encode(key), value: hex::encode(value) },
+            None => BackupOp::Remove { tree, key: hex::encode(key) },
+        })?;
+    }
+    payload.finish(db, path, BackupKind::Incremental, Some(base.checksum.clone()), base.next_sequence, next_sequence)
+}
+
+/// Read an archive's manifest without checking its payload
+pub fn read_manifest<P: AsRef<Path>>(path: P) -> Result<BackupManifest> {
+    let mut reader = BufReader::new(GzDecoder::new(open(path.as_ref())?));
+    let mut line = String::new();
+    reader.read_line(&mut line)
+        .map_err(|e| BitStableError::InvalidConfig(format!("Failed to read backup {}: {}", path.as_ref().display(), e)))?;
+    parse_manifest(path.as_ref(), &line)
+}
+
+/// Replace everything in `db` with the state a full backup and its incrementals
+/// describe. Every archive is checked and the result verified before `db` is touched.
+pub fn restore<P: AsRef<Path>>(db: &dyn Storage, paths: &[P]) -> Result<RestoreReport> {
+    let (staged, report) = stage_restore(paths)?;
+    if !report.schema.is_ok() {
+        return Err(BitStableError::InvalidConfig(format!(
+            "Backup does not verify, database left untouched: {}", report.schema.errors.join("; ")
+        )));
+    }
+
+    // One batch swaps the whole store over, so a failure part way leaves it as it was.
+    // The swap is logged like any write, keeping incrementals from earlier bases valid.
+    let trees: BTreeSet<String> = db.tree_names()?.into_iter()
+        .chain(staged.tree_names()?)
+        .filter(|tree| tree != CHANGE_LOG_TREE)
+        .collect();
+    let mut writes = Vec::new();
+    for tree in &trees {
+        let restored: BTreeMap<Vec<u8>, Vec<u8>> = staged.scan(tree, &KeyRange::all(), false, None)?.into_iter().collect();
+        for (key, _) in db.scan(tree, &KeyRange::all(), false, None)? {
+            if !restored.contains_key(&key) {
+                writes.push(Write::remove(tree, key));
+            }
+        }
+        writes.extend(restored.into_iter().map(|(key, value)| Write::insert(tree, key, value)));
+    }
+    db.apply(&writes)?;
+    db.advance_ids(staged.generate_id()?)?;
+    db.flush()?;
+    log::info!("Restored {} backup archives ({} operations)", report.archives, report.operations);
+    Ok(report)
+}
+
+/// Restore a backup chain into memory and check protocol invariants on the result
+pub fn verify_backup<P: AsRef<Path>>(paths: &[P]) -> Result<BackupVerifyReport> {
+    let (staged, restore) = stage_restore(paths)?;
+    let database = DatabaseManager::with_storage(staged)?;
+    let mut violations = Vec::new();
+
+    // Stable value in circulation must equal the debt vaults were issued against
+    let mut debt: BTreeMap<String, f64> = BTreeMap::new();
+    for vault in database.list_vaults()? {
+        for (currency, amount) in &vault.debts.debts {
+            *debt.entry(currency.to_string()).or_insert(0.0) += amount;
+        }
+    }
+    let stable_manager = database.load_subsystem::<MultiCurrencyStableManager>(Subsystem::StableBalances)?
+        .unwrap_or_default();
+    let mut supply: BTreeMap<String, f64> = BTreeMap::new();
+    for (currency, amount) in stable_manager.get_all_supplies() {
+        supply.insert(currency.to_string(), *amount);
+    }
+    let currencies: BTreeSet<&String> = debt.keys().chain(supply.keys()).collect();
+    for currency in currencies {
+        let owed = debt.get(currency).copied().unwrap_or(0.0);
+        let issued = supply.get(currency).copied().unwrap_or(0.0);
+        if (owed - issued).abs() > SUPPLY_TOLERANCE * owed.abs().max(1.0) {
+            violations.push(format!("{} supply {} does not equal vault debt {}", currency, issued, owed));
+        }
+    }
+
+    // Every escrow contract secures a vault that exists
+    for contract in database.load_escrow_contracts()? {
+        if database.get_vault(contract.vault_id)?.is_none() {
+            violations.push(format!("Escrow contract for missing vault {}", contract.vault_id));
+        }
+    }
+
+    Ok(BackupVerifyReport { restore, violations })
+}
+
+/// Apply a checked archive chain to an in-memory store, migrated to the current schema
+fn stage_restore<P: AsRef<Path>>(paths: &[P]) -> Result<(Arc<dyn Storage>, RestoreReport)> {
+    // Check the chain from the manifests before reading any payload
+    let manifests = paths.iter()
+        .map(read_manifest)
+        .collect::<Result<Vec<_>>>()?;
+    let Some(first) = manifests.first() else {
+        return Err(BitStableError::InvalidConfig("No backup archives given".to_string()));
+    };
+    if first.kind != BackupKind::Full {
+        return Err(BitStableError::InvalidConfig("A restore must start from a full backup".to_string()));
+    }
+    for pair in manifests.windows(2) {
+        let (base, next) = (&pair[0], &pair[1]);
+        if next.kind != BackupKind::Incremental || next.base_checksum.as_deref() != Some(base.checksum.as_str()) {
+            return Err(BitStableError::InvalidConfig(format!(
+                "Backup {} does not follow {}", short(&next.checksum), short(&base.checksum)
+            )));
+        }
+    }
+
+    let staged: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+    let mut report = RestoreReport {
+        archives: manifests.len(),
+        operations: 0,
+        from_schema_version: first.schema_version,
+        schema: VerifyReport { schema_version: first.schema_version, records_checked: 0, errors: Vec::new() },
+    };
+    for (path, manifest) in paths.iter().zip(&manifests) {
+        report.operations += apply_archive(staged.as_ref(), path.as_ref(), manifest)?;
+        staged.advance_ids(manifest.id_floor)?;
+        report.from_schema_version = manifest.schema_version;
+    }
+
+    schema::migrate(staged.as_ref(), MigrationMode::Apply)?;
+    report.schema = schema::verify(staged.as_ref())?;
+    Ok((staged, report))
+}
+
+/// Stream an archive's operations into `db`, checking them against `manifest` as they
+/// are read. `db` is a staging store: a corrupt archive leaves it partly written.
+fn apply_archive(db: &dyn Storage, path: &Path, manifest: &BackupManifest) -> Result<usize> {
+    let read_error = |e: std::io::Error| BitStableError::InvalidConfig(format!("Failed to read backup {}: {}", path.display(), e));
+    let mut reader = BufReader::new(MultiGzDecoder::new(open(path)?));
+    let mut line = Vec::new();
+    reader.read_until(b'\n', &mut line).map_err(read_error)?;   // The manifest
+
+    let mut hasher = Sha256::new();
+    let mut operations = 0;
+    let mut writes = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line).map_err(read_error)? == 0 {
+            break;
+        }
+        hasher.update(&line);
+        let op: BackupOp = serde_json::from_slice(&line)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to parse backup {}: {}", path.display(), e)))?;
+        operations += 1;
+        match op {
+            BackupOp::Clear { tree } => {
+                db.apply(&std::mem::take(&mut writes))?;
+                db.clear(&tree)?;
+            }
+            BackupOp::Put { tree, key, value } => writes.push(Write::insert(&tree, decode_hex(&key)?, decode_hex(&value)?)),
+            BackupOp::Remove { tree, key } => writes.push(Write::remove(&tree, decode_hex(&key)?)),
+        }
+        if writes.len() >= BATCH_SIZE {
+            db.apply(&std::mem::take(&mut writes))?;
+        }
+    }
+    db.apply(&writes)?;
+
+    let checksum = hex::encode(hasher.finalize());
+    if checksum != manifest.checksum {
+        return Err(BitStableError::InvalidConfig(format!(
+            "Backup {} is corrupt: checksum {} does not match manifest {}", path.display(), short(&checksum), short(&manifest.checksum)
+        )));
+    }
+    if operations != manifest.operations {
+        return Err(BitStableError::InvalidConfig(format!(
+            "Backup {} holds {} operations, manifest says {}", path.display(), operations, manifest.operations
+        )));
+    }
+    Ok(operations)
+}
+
+/// Copy a tree a page at a time, so only one page is held in memory
+fn snapshot_tree(db: &dyn Storage, tree: &str, payload: &mut PayloadWriter) -> Result<()> {
+    payload.push(&BackupOp::Clear { tree: tree.to_string() })?;
+    let mut range = KeyRange::all();
+    loop {
+        let page = db.scan(tree, &range, false, Some(BATCH_SIZE))?;
+        let Some((last, _)) = page.last() else { break };
+        // The first key after `last` is `last` with a zero byte appended
+        let mut next = last.clone();
+        next.push(0);
+        for (key, value) in &page {
+            payload.push(&BackupOp::Put { tree: tree.to_string(), key: hex::encode(key), value: hex::encode(value) })?;
+        }
+        if page.len() < BATCH_SIZE {
+            break;
+        }
+        range = KeyRange::starting_at(next);
+    }
+    Ok(())
+}
+
+/// Writes operations as JSON lines into a gzip payload beside the archive, hashing
+/// them as they go; `finish` puts the manifest in front and moves the archive into place
+struct PayloadWriter {
+    path: std::path::PathBuf,
+    encoder: GzEncoder<File>,
+    hasher: Sha256,
+    operations: usize,
+}
+
+impl PayloadWriter {
+    fn create(archive: &Path) -> Result<Self> {
+        let path = archive.with_extension("payload");
+        let file = File::create(&path).map_err(|e| write_error(&path, e))?;
+        Ok(Self { path, encoder: GzEncoder::new(file, Compression::default()), hasher: Sha256::new(), operations: 0 })
+    }
+
+    fn push(&mut self, op: &BackupOp) -> Result<()> {
+        let mut line = serde_json::to_vec(op)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize backup: {}", e)))?;
+        line.push(b'\n');
+        self.hasher.update(&line);
+        self.encoder.write_all(&line).map_err(|e| write_error(&self.path, e))?;
+        self.operations += 1;
+        Ok(())
+    }
+
+    fn finish<P: AsRef<Path>>(
+        self,
+        db: &dyn Storage,
+        path: P,
+        kind: BackupKind,
+        base_checksum: Option<String>,
+        from_sequence: u64,
+        next_sequence: u64,
+    ) -> Result<BackupManifest> {
+        let manifest = BackupManifest {
+            format_version: BACKUP_FORMAT_VERSION,
+            kind,
+            created_at: Utc::now(),
+            schema_version: schema::schema_version(db)?,
+            base_checksum,
+            from_sequence,
+            next_sequence,
+            id_floor: db.generate_id()?,
+            operations: self.operations,
+            checksum: hex::encode(self.hasher.finalize()),
+        };
+        let mut header = serde_json::to_vec(&manifest)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize backup manifest: {}", e)))?;
+        header.push(b'\n');
+
+        // Write beside the target and rename, so a crash never leaves a truncated archive
+        let path = path.as_ref();
+        let partial = path.with_extension("partial");
+        let payload_path = self.path;
+        let encoder = self.encoder;
+        let write = || -> std::io::Result<()> {
+            encoder.finish()?.sync_all()?;
+            let mut out = GzEncoder::new(File::create(&partial)?, Compression::default());
+            out.write_all(&header)?;
+            let mut out = out.finish()?;
+            std::io::copy(&mut File::open(&payload_path)?, &mut out)?;
+            out.sync_all()?;
+            std::fs::rename(&partial, path)?;
+            std::fs::remove_file(&payload_path)
+        };
+        write().map_err(|e| write_error(path, e))?;
+
+        log::info!("Wrote {:?} backup {} ({} operations)", manifest.kind, path.display(), manifest.operations);
+        Ok(manifest)
+    }
+}
+
+fn write_error(path: &Path, e: std::io::Error) -> BitStableError {
+    BitStableError::InvalidConfig(format!("Failed to write backup {}: {}", path.display(), e))
+}
+
+fn parse_manifest(path: &Path, line: &str) -> Result<BackupManifest> {
+    let manifest: BackupManifest = serde_json::from_str(line.trim_end())
+        .map_err(|e| BitStableError::InvalidConfig(format!("Failed to parse backup manifest in {}: {}", path.display(), e)))?;
+    if manifest.format_version != BACKUP_FORMAT_VERSION {
+        return Err(BitStableError::InvalidConfig(format!(
+            "Backup {} has format {}, this build reads {}", path.display(), manifest.format_version, BACKUP_FORMAT_VERSION
+        )));
+    }
+    if manifest.schema_version > SCHEMA_VERSION {
+        return Err(BitStableError::InvalidConfig(format!(
+            "Backup {} has schema version {}, newer than this build supports ({})", path.display(), manifest.schema_version, SCHEMA_VERSION
+        )));
+    }
+    Ok(manifest)
+}
+
+fn open(path: &Path) -> Result<File> {
+    File::open(path)
+        .map_err(|e| BitStableError::InvalidConfig(format!("Failed to open backup {}: {}", path.display(), e)))
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>> {
+    hex::decode(value)
+        .map_err(|e| BitStableError::InvalidConfig(format!("Failed to decode backup record: {}", e)))
+}
+
+fn short(checksum: &str) -> &str {
+    &checksum[..checksum.len().min(12)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Currency, Vault};
+    use std::io::Read;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{Amount, PublicKey, Txid};
+
+    fn vault(byte: u8, owner: PublicKey, debt: f64) -> Vault {
+        let id = Txid::from_raw_hash(bitcoin::hashes::sha256d::Hash::from_byte_array([byte; 32]));
+        let mut vault = Vault::new(id, owner, Amount::from_btc(1.0).unwrap());
+        vault.debts.add_debt(Currency::USD, debt).unwrap();
+        vault
+    }
+
+    #[tokio::test]
+    async fn test_incremental_chain_restores_and_verifies() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let owner: PublicKey = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".parse().unwrap();
+        let mut config = crate::ProtocolConfig::testnet();
+        config.database_path = format!("sled://{}", dir.join("source").display());
+        let mut protocol = crate::BitStableProtocol::new(config).unwrap();
+        protocol.oracle_network.apply_btc_prices(&std::collections::HashMap::from([(Currency::USD, 100000.0)]), Utc::now()).unwrap();
+        protocol.vault_manager.update_exchange_rates(protocol.oracle_network.get_exchange_rates().clone());
+        let source = protocol.vault_manager.database().unwrap();
+
+        let first = protocol.open_vault(owner, Amount::from_btc(1.0).unwrap(), Currency::USD, 1000.0).await.unwrap().vault_id;
+        let full = source.backup(dir.join("full.bak")).unwrap();
+
+        // Minting and repaying move balances and vault debt together
+        let second = protocol.open_vault(owner, Amount::from_btc(1.0).unwrap(), Currency::USD, 500.0).await.unwrap().vault_id;
+        protocol.mint_stable(second, Currency::USD, 250.0).await.unwrap();
+        protocol.burn_stable(first, Currency::USD, 1000.0).await.unwrap();
+        assert_eq!(protocol.stable_manager.get_balance(owner, &Currency::USD), 750.0);
+        let incremental = source.backup_incremental(dir.join("incr.bak"), &full).unwrap();
+        assert_eq!(read_manifest(dir.join("incr.bak")).unwrap().base_checksum, Some(full.checksum.clone()));
+        assert_eq!(incremental.from_sequence, full.next_sequence);
+
+        let chain = [dir.join("full.bak"), dir.join("incr.bak")];
+        let verified = verify_backup(&chain).unwrap();
+        assert!(verified.is_ok(), "{:?}", verified);
+
+        let target = DatabaseManager::open("memory://").unwrap();
+        target.save_vault(&vault(3, owner, 1.0)).unwrap();
+        restore(target.storage().as_ref(), &chain).unwrap();
+        let mut ids: Vec<Txid> = target.list_vaults().unwrap().into_iter().map(|vault| vault.id).collect();
+        ids.sort();
+        let mut expected = vec![first, second];
+        expected.sort();
+        assert_eq!(ids, expected);
+        assert_eq!(target.load_vault(second).unwrap().debts.get_debt(&Currency::USD), 750.0);
+        let restored: MultiCurrencyStableManager = target.load_subsystem(Subsystem::StableBalances).unwrap().unwrap();
+        assert_eq!(restored.get_total_supply(&Currency::USD), 750.0);
+
+        // A newer full backup prunes the log the first chain would extend from
+        let newer = source.backup(dir.join("newer.bak")).unwrap();
+        assert!(source.storage().len(CHANGE_LOG_TREE).unwrap() <= 1);
+        assert!(source.backup_incremental(dir.join("stale.bak"), &full).is_err());
+        protocol.mint_stable(second, Currency::USD, 10.0).await.unwrap();
+        let next = source.backup_incremental(dir.join("next.bak"), &newer).unwrap();
+        assert!(next.operations > 0);
+        assert!(verify_backup(&[dir.join("newer.bak"), dir.join("next.bak")]).unwrap().is_ok());
+
+        // The incremental alone, or out of order, is not a restorable chain
+        assert!(verify_backup(&[dir.join("incr.bak")]).is_err());
+        assert!(verify_backup(&[dir.join("incr.bak"), dir.join("full.bak")]).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_archive_is_rejected_before_restore() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let owner: PublicKey = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".parse().unwrap();
+        let source = DatabaseManager::open("memory://").unwrap();
+        source.save_vault(&vault(1, owner, 1000.0)).unwrap();
+        for i in 0..(BATCH_SIZE * 2 + 5) as u32 {
+            source.storage().insert("bulk", &i.to_be_bytes(), b"v").unwrap();
+        }
+        let path = temp_dir.path().join("full.bak");
+        let manifest = write_full(source.storage().as_ref(), &path).unwrap();
+        assert!(!path.with_extension("payload").exists());
+
+        // Trees larger than one scan page come back whole
+        let copy = DatabaseManager::open("memory://").unwrap();
+        restore(copy.storage().as_ref(), &[&path]).unwrap();
+        assert_eq!(copy.storage().len("bulk").unwrap(), BATCH_SIZE * 2 + 5);
+
+        // Debt that no stable supply was issued against is an invariant violation
+        let report = verify_backup(&[&path]).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.violations.len(), 1);
+
+        // Re-pack the archive with one payload byte changed
+        let mut contents = Vec::new();
+        MultiGzDecoder::new(File::open(&path).unwrap()).read_to_end(&mut contents).unwrap();
+        let last = contents.len() - 3;
+        contents[last] ^= 1;
+        let mut encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(&contents).unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(read_manifest(&path).unwrap().checksum, manifest.checksum);
+
+        let target = DatabaseManager::open("memory://").unwrap();
+        let kept = vault(2, owner, 1.0);
+        target.save_vault(&kept).unwrap();
+        assert!(restore(target.storage().as_ref(), &[&path]).is_err());
+        assert_eq!(target.load_vault(kept.id).unwrap().id, kept.id);
+    }
+}
@@ -1,7 +1,19 @@
 use clap::{Parser, Subcommand};
 use bitcoin::{Amount, PublicKey, Txid};
 use bitcoin::hashes::Hash;
+use bitstable::address_book::{AddressBook, AddressSource};
+use bitstable::audit::AuditLog;
+use bitstable::backup;
+use bitstable::export::{self, Dataset, ExportFilter, ExportFormat};
+use bitstable::database::DatabaseManager;
+use bitstable::schema::MigrationMode;
+use bitstable::storage;
+use bitstable::network::{self, BitStableNetwork, ServiceType};
+use bitstable::raft::{NodeId, RaftConfig};
+use bitstable::replication::{ReplicaPeer, ReplicatedProtocol};
+use bitstable::vault_sync::VaultSync;
 use bitstable::{BitStableProtocol, ProtocolConfig, BitcoinConfig, Result, Currency};
+use std::collections::HashMap;
 use std::str::FromStr;
 
 #[derive(Parser)]
@@ -54,6 +66,45 @@ enum Commands {
         #[command(subcommand)]
         action: CustodyCommands,
     },
+    /// Database schema maintenance
+    Database {
+        #[command(subcommand)]
+        action: DatabaseCommands,
+    },
+    /// Inspect the audit log of privileged actions
+    Audit {
+        #[command(subcommand)]
+        action: AuditCommands,
+    },
+    /// Export protocol history for analysis
+    Export {
+        /// vaults, liquidations, redemptions, transfers, insurance-contributions,
+        /// insurance-payouts or consensus-prices
+        dataset: Dataset,
+        /// csv or jsonl
+        #[arg(long, default_value = "csv")]
+        format: ExportFormat,
+        /// Earliest time to include, RFC 3339 or YYYY-MM-DD
+        #[arg(long)]
+        from: Option<String>,
+        /// Time to stop before, RFC 3339 or YYYY-MM-DD
+        #[arg(long)]
+        to: Option<String>,
+        /// Only rows in this currency, for datasets that have one
+        #[arg(long)]
+        currency: Option<String>,
+        /// File to write instead of stdout
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Print stored protocol events as JSON lines
+    Events {
+        /// Sequence number to replay from
+        #[arg(long, default_value = "0")]
+        from: u64,
+        #[arg(long)]
+        limit: Option<usize>,
+    },
     /// Show protocol status
     Status,
 }
@@ -222,10 +273,48 @@ enum NetworkCommands {
         #[arg(long)]
         pubkey: String,
     },
-    /// List connected peers
+    /// List peers in the address book
     Peers,
     /// Show network statistics
     Stats,
+    /// Ban a peer by public key
+    Ban {
+        /// Peer public key
+        pubkey: String,
+        
+        /// Why the peer is banned
+        #[arg(long, default_value = "manual ban")]
+        reason: String,
+        
+        /// Ban length in hours (permanent if omitted)
+        #[arg(long)]
+        hours: Option<i64>,
+    },
+    /// Lift a peer ban
+    Unban {
+        /// Peer public key
+        pubkey: String,
+    },
+    /// List active bans
+    Bans,
+    /// Run this node as a replica of the protocol state, ordered by Raft with its peers
+    Replica {
+        /// This replica's id, unique among the replicas
+        #[arg(long)]
+        id: u64,
+
+        /// Listening address
+        #[arg(long, default_value = "127.0.0.1:8335")]
+        listen: String,
+
+        /// Another replica as ID=PUBKEY@ADDRESS, once per peer
+        #[arg(long = "peer")]
+        peers: Vec<String>,
+
+        /// Milliseconds between Raft ticks
+        #[arg(long, default_value = "100")]
+        tick_ms: u64,
+    },
 }
 
 #[derive(Subcommand)]
@@ -247,6 +336,56 @@ enum CustodyCommands {
     },
 }
 
+#[derive(Subcommand)]
+enum DatabaseCommands {
+    /// Migrate the database to the current schema
+    Migrate {
+        /// Report pending migrations without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check the schema version and that every record decodes
+    Verify,
+    /// Copy every record into an empty store, e.g. to move from sled to SQLite
+    Copy {
+        /// Destination such as sqlite://./bitstable.sqlite or sled://./copy.db
+        #[arg(long)]
+        to: String,
+    },
+    /// Write a compressed, checksummed backup archive
+    Backup {
+        /// Archive file to write
+        #[arg(long)]
+        to: String,
+        /// Earlier archive to back up changes since, for an incremental backup
+        #[arg(long)]
+        base: Option<String>,
+    },
+    /// Replace the database with a full backup and any incrementals, in order
+    Restore {
+        #[arg(required = true)]
+        archives: Vec<String>,
+    },
+    /// Restore backups into memory and check they are consistent
+    VerifyBackup {
+        #[arg(required = true)]
+        archives: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AuditCommands {
+    /// Check the hash chain, signatures and on-chain anchors for gaps or edits
+    Verify,
+    /// Show the most recent entries
+    List {
+        #[arg(long, default_value = "20")]
+        limit: usize,
+    },
+    /// Publish the current head in a proof-of-reserves OP_RETURN
+    Anchor,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
@@ -271,6 +410,22 @@ async fn main() -> Result<()> {
 
     config.validate()?;
 
+    // Opening the protocol migrates the database, so schema commands run first
+    if let Commands::Database { action } = cli.command {
+        return handle_database_command(&config, action);
+    }
+    if let Commands::Audit { action } = &cli.command {
+        if !matches!(action, AuditCommands::Anchor) {
+            return handle_audit_command(&config, action);
+        }
+    }
+    if let Commands::Events { from, limit } = cli.command {
+        return handle_events_command(&config, from, limit);
+    }
+    if let Commands::Export { dataset, format, from, to, currency, output } = cli.command {
+        return handle_export_command(&config, dataset, format, from, to, currency, output);
+    }
+
     // Initialize protocol
     let protocol = BitStableProtocol::new(config)?;
 
@@ -282,16 +437,27 @@ async fn main() -> Result<()> {
             BitStableProtocol::new(ProtocolConfig::testnet()).unwrap()
         });
 
-    // Execute command
-    match cli.command {
+    // A replica owns the protocol and commits its own state as it applies the log
+    if let Commands::Network { action: NetworkCommands::Replica { id, listen, peers, tick_ms } } = cli.command {
+        return run_replica(protocol, id, &listen, &peers, tick_ms).await;
+    }
+
+    // Execute command, then save subsystem state for the next run
+    let result = match cli.command {
         Commands::Vault { action } => handle_vault_command(&mut protocol, action).await,
         Commands::Oracle { action } => handle_oracle_command(&mut protocol, action).await,
         Commands::Liquidate { action } => handle_liquidation_command(&mut protocol, action).await,
         Commands::Stable { action } => handle_stable_command(&mut protocol, action).await,
         Commands::Network { action } => handle_network_command(&mut protocol, action).await,
         Commands::Custody { action } => handle_custody_command(&mut protocol, action).await,
-        Commands::Status => handle_status_command(&protocol).await,
-    }
+        Commands::Status => handle_status_command(&mut protocol).await,
+        Commands::Audit { .. } => handle_audit_anchor(&mut protocol),
+        Commands::Database { .. } | Commands::Export { .. } | Commands::Events { .. } => {
+            unreachable!("handled before the protocol is opened")
+        }
+    };
+    result?;
+    protocol.flush()
 }
 
 async fn handle_vault_command(protocol: &mut BitStableProtocol, action: VaultCommands) -> Result<()> {
@@ -312,30 +478,34 @@ async fn handle_vault_command(protocol: &mut BitStableProtocol, action: VaultCom
         }
         
         VaultCommands::List { owner, liquidatable } => {
-            let vaults = protocol.vault_manager.list_vaults();
+            let database = protocol.vault_manager.database()?;
+            let exchange_rates = protocol.oracle_network.get_exchange_rates();
+            
+            // Narrow through the owner or liquidation price index instead of scanning every vault
+            let vaults = match (&owner, liquidatable) {
+                (Some(owner), _) => {
+                    let owner = PublicKey::from_str(owner)
+                        .map_err(|e| bitstable::BitStableError::PublicKeyParseError(e.to_string()))?;
+                    database.get_vaults_by_owner(&owner)?
+                }
+                (None, true) => database.get_liquidatable_vaults(exchange_rates, protocol.vault_manager.get_currency_configs())?,
+                (None, false) => database.list_vaults()?,
+            };
             
             println!("📦 Active Vaults:");
             println!("{:<66} {:<34} {:<12} {:<12} {:<8}", "Vault ID", "Owner", "Collateral", "Debt (USD)", "Ratio");
             println!("{}", "-".repeat(140));
             
             for vault in vaults {
-                // Filter by owner if specified
-                if let Some(ref owner_filter) = owner {
-                    if vault.owner.to_string() != *owner_filter {
-                        continue;
-                    }
-                }
-                
-                // Get current price for ratio calculation
-                let exchange_rates = protocol.oracle_network.get_exchange_rates();
                 let ratio = vault.collateral_ratio(&exchange_rates);
+                let at_risk = vault.is_liquidatable(exchange_rates, protocol.vault_manager.get_currency_configs());
                 
                 // Filter liquidatable if specified
-                if liquidatable && ratio >= protocol.config.liquidation_threshold {
+                if liquidatable && !at_risk {
                     continue;
                 }
                 
-                let status = if ratio < protocol.config.liquidation_threshold {
+                let status = if at_risk {
                     "🔴"
                 } else if ratio < protocol.config.min_collateral_ratio {
                     "🟡"
@@ -450,9 +620,9 @@ async fn handle_oracle_command(protocol: &mut BitStableProtocol, action: OracleC
         OracleCommands::Price => {
             println!("🔍 Fetching Bitcoin price consensus...");
             
-            match protocol.oracle_network.get_consensus_prices().await {
-                Ok(exchange_rates) => {
-                    if let Some(btc_price) = exchange_rates.get_btc_price(&Currency::USD) {
+            match protocol.refresh_prices().await {
+                Ok(alerts) => {
+                    if let Some(btc_price) = protocol.oracle_network.get_exchange_rates().get_btc_price(&Currency::USD) {
                         println!("💰 Current BTC Price: ${:.2}", btc_price);
                     }
                     
@@ -463,6 +633,12 @@ async fn handle_oracle_command(protocol: &mut BitStableProtocol, action: OracleC
                         );
                         println!("   Last updated: {}", consensus.timestamp.format("%H:%M:%S UTC"));
                     }
+                    for (currency, peg) in protocol.oracle_network.get_peg_prices(chrono::Utc::now()) {
+                        println!("   {} peg: {:.4} ({})", currency.to_string(), peg.price, peg.source);
+                    }
+                    for alert in alerts {
+                        println!("   ⚠️  {:?}: {}", alert.severity, alert.message);
+                    }
                 }
                 Err(e) => {
                     println!("❌ Failed to get price consensus: {}", e);
@@ -537,6 +713,7 @@ async fn handle_liquidation_command(protocol: &mut BitStableProtocol, action: Li
         LiquidationCommands::Scan => {
             println!("🔍 Scanning for liquidation opportunities...");
             
+            protocol.sync_circuit_breakers();
             let exchange_rates = protocol.oracle_network.get_exchange_rates();
             let vaults = protocol.vault_manager.list_vaults();
             
@@ -644,39 +821,174 @@ async fn handle_stable_command(_protocol: &mut BitStableProtocol, action: Stable
     Ok(())
 }
 
-async fn handle_network_command(_protocol: &mut BitStableProtocol, action: NetworkCommands) -> Result<()> {
+async fn handle_network_command(protocol: &mut BitStableProtocol, action: NetworkCommands) -> Result<()> {
+    let database = protocol.vault_manager.database()?;
+    let mut address_book = AddressBook::with_database(database.clone())?;
+    let now = chrono::Utc::now();
+
     match action {
         NetworkCommands::Start { listen } => {
+            let node_key = network::load_or_create_node_key(&database)?;
+            let mut network = BitStableNetwork::new(node_key, 125, protocol.config.oracle_keys()?)
+                .with_address_book(address_book)
+                .with_seeds(&protocol.config.seed_peers)?;
             println!("🌐 Starting BitStable network node on {}", listen);
+            println!("   Node key: {}", network.local_pubkey());
             println!("📡 Node is running. Press Ctrl+C to stop.");
             
-            // In a real implementation, this would start the network node
-            tokio::signal::ctrl_c().await.unwrap();
+            // Vault sync needs a Bitcoin node to check synced escrows against
+            match protocol.bitcoin_client.take() {
+                Some(bitcoin_client) => {
+                    // Records this node vouches for are signed with its node key, which
+                    // peers must list in custody_pubkeys to accept them
+                    let sync = VaultSync::new(bitcoin_client, protocol.config.custody_keys()?)
+                        .with_signing_key(node_key);
+                    tokio::select! {
+                        result = sync.run(&mut network, &mut protocol.vault_manager, &listen) => result?,
+                        _ = tokio::signal::ctrl_c() => {}
+                    }
+                }
+                None => {
+                    println!("⚠️  No Bitcoin node configured, vault sync disabled");
+                    tokio::select! {
+                        result = network.start(&listen) => result?,
+                        _ = tokio::signal::ctrl_c() => {}
+                    }
+                }
+            }
             println!("🛑 Shutting down network node...");
         }
         
         NetworkCommands::Connect { address, pubkey } => {
+            let pubkey = parse_peer_pubkey(&pubkey)?;
+            address_book.record(pubkey, address.clone(), Vec::new(), AddressSource::Manual, now);
+            let node_key = network::load_or_create_node_key(&database)?;
+            let mut network = BitStableNetwork::new(node_key, 8, protocol.config.oracle_keys()?)
+                .with_address_book(address_book);
+
             println!("🔗 Connecting to peer at {} ({})", address, pubkey);
-            println!("✅ Connected to peer successfully!");
+            network.connect_to_peer(&address, pubkey).await?;
+            println!("✅ Connected to peer successfully (protocol v{})", network.peer_version(&pubkey).unwrap_or_default());
         }
         
         NetworkCommands::Peers => {
-            println!("👥 Connected Peers:");
-            println!("   No peers connected");
+            println!("👥 Known Peers:");
+            let entries = address_book.entries();
+            if entries.is_empty() {
+                println!("   No peers known");
+            }
+            for entry in entries {
+                let banned = if address_book.is_banned(&entry.pubkey, now) { " [banned]" } else { "" };
+                println!("   {} @ {} {:?} via {:?}, {} failures{}",
+                    entry.pubkey, entry.address, entry.services, entry.source, entry.failures, banned);
+            }
         }
         
         NetworkCommands::Stats => {
+            let known = address_book.len();
+            let seeds = address_book.seeds().len();
+            let bans = address_book.bans(now).len();
+            let count_service = |service: ServiceType| {
+                address_book.entries().iter().filter(|entry| entry.services.contains(&service)).count()
+            };
+            let (oracles, liquidators) = (count_service(ServiceType::Oracle), count_service(ServiceType::Liquidator));
+
+            // Dial seeds and fill outbound slots from the address book, as a starting node would
+            let node_key = network::load_or_create_node_key(&database)?;
+            let mut network = BitStableNetwork::new(node_key, 125, protocol.config.oracle_keys()?)
+                .with_address_book(address_book)
+                .with_seeds(&protocol.config.seed_peers)?;
+            if tokio::time::timeout(std::time::Duration::from_secs(15), network.start_peer_discovery()).await.is_err() {
+                println!("⚠️  Peer discovery timed out, showing connections made so far");
+            }
+
             println!("📊 Network Statistics:");
-            println!("   Connected Peers: 0");
-            println!("   Oracle Nodes: 0");
-            println!("   Liquidator Nodes: 0");
-            println!("   Network Health: Unknown");
+            println!("   Connected Peers: {}", network.connected_peers().len());
+            println!("   Known Addresses: {} ({} seeds)", known, seeds);
+            println!("   Active Bans: {}", bans);
+            println!("   Known Oracle Nodes: {}", oracles);
+            println!("   Known Liquidator Nodes: {}", liquidators);
+            println!("   Outbound Slots:");
+            for (service, filled, wanted) in network.slot_usage() {
+                println!("     {:?}: {}/{}", service, filled, wanted);
+            }
         }
+        
+        NetworkCommands::Ban { pubkey, reason, hours } => {
+            let pubkey = parse_peer_pubkey(&pubkey)?;
+            address_book.ban(pubkey, &reason, hours.map(chrono::Duration::hours), now)?;
+            match hours {
+                Some(hours) => println!("🚫 Banned {} for {} hours: {}", pubkey, hours, reason),
+                None => println!("🚫 Banned {} permanently: {}", pubkey, reason),
+            }
+        }
+        
+        NetworkCommands::Unban { pubkey } => {
+            let pubkey = parse_peer_pubkey(&pubkey)?;
+            if address_book.unban(&pubkey)? {
+                println!("✅ Lifted ban on {}", pubkey);
+            } else {
+                println!("   {} was not banned", pubkey);
+            }
+        }
+        
+        NetworkCommands::Bans => {
+            address_book.purge_expired_bans(now)?;
+            println!("🚫 Active Bans:");
+            let bans = address_book.bans(now);
+            if bans.is_empty() {
+                println!("   No peers banned");
+            }
+            for ban in bans {
+                let expires = ban.expires_at
+                    .map(|expires_at| expires_at.format("%Y-%m-%d %H:%M UTC").to_string())
+                    .unwrap_or_else(|| "never".to_string());
+                println!("   {} (expires {}): {}", ban.pubkey, expires, ban.reason);
+            }
+        }
+
+        NetworkCommands::Replica { .. } => unreachable!("replicas run before the protocol is borrowed"),
     }
     
     Ok(())
 }
 
+async fn run_replica(protocol: BitStableProtocol, id: NodeId, listen: &str, peers: &[String], tick_ms: u64) -> Result<()> {
+    let peers = peers.iter().map(|peer| parse_replica_peer(peer)).collect::<Result<HashMap<_, _>>>()?;
+    if peers.contains_key(&id) {
+        return Err(bitstable::BitStableError::InvalidConfig(format!("Replica {} is listed as its own peer", id)));
+    }
+    let database = protocol.vault_manager.database()?;
+    let node_key = network::load_or_create_node_key(&database)?;
+    let mut network = BitStableNetwork::new(node_key, 125, protocol.config.oracle_keys()?)
+        .with_address_book(AddressBook::with_database(database)?);
+    let mut replica = ReplicatedProtocol::new(id, peers.keys().copied().collect(), protocol, RaftConfig::default())?;
+
+    println!("🗳️  Starting replica {} on {} with {} peers", id, listen, peers.len());
+    println!("   Node key: {}", network.local_pubkey());
+    println!("📡 Replica is running. Press Ctrl+C to stop.");
+    tokio::select! {
+        result = replica.run(&mut network, &peers, listen, std::time::Duration::from_millis(tick_ms)) => result?,
+        _ = tokio::signal::ctrl_c() => {}
+    }
+    println!("🛑 Shutting down replica...");
+    Ok(())
+}
+
+/// A replica peer given as ID=PUBKEY@ADDRESS
+fn parse_replica_peer(peer: &str) -> Result<(NodeId, ReplicaPeer)> {
+    let invalid = || bitstable::BitStableError::InvalidConfig(format!("Expected ID=PUBKEY@ADDRESS, got {}", peer));
+    let (id, rest) = peer.split_once('=').ok_or_else(invalid)?;
+    let (pubkey, address) = rest.split_once('@').ok_or_else(invalid)?;
+    let id = id.parse().map_err(|_| invalid())?;
+    Ok((id, ReplicaPeer { pubkey: parse_peer_pubkey(pubkey)?, address: address.to_string() }))
+}
+
+fn parse_peer_pubkey(pubkey: &str) -> Result<PublicKey> {
+    PublicKey::from_str(pubkey)
+        .map_err(|e| bitstable::BitStableError::InvalidConfig(format!("Invalid peer public key: {}", e)))
+}
+
 async fn handle_custody_command(protocol: &mut BitStableProtocol, action: CustodyCommands) -> Result<()> {
     match action {
         CustodyCommands::Stats => {
@@ -699,13 +1011,23 @@ async fn handle_custody_command(protocol: &mut BitStableProtocol, action: Custod
             println!("   No active contracts found");
         }
         
-        CustodyCommands::Settlements { limit: _ } => {
+        CustodyCommands::Settlements { limit } => {
             println!("⚖️  Recent Settlements:");
             println!("{:<66} {:<34} {:<12} {:<12}", "Vault ID", "Liquidator", "Amount", "Date");
             println!("{}", "-".repeat(130));
             
-            // This would show settlement history from custody manager
-            println!("   No settlements found");
+            let settlements = protocol.vault_manager.database()?.get_settlement_history(limit)?;
+            if settlements.is_empty() {
+                println!("   No settlements found");
+            }
+            for settlement in settlements {
+                println!("{:<66} {:<34} {:<12} {:<12}",
+                    settlement.vault_id,
+                    settlement.liquidator.to_string()[..34].to_string(),
+                    format!("{:.8}", settlement.collateral_seized.to_btc()),
+                    settlement.settled_at.format("%Y-%m-%d %H:%M")
+                );
+            }
         }
         
         CustodyCommands::Monitor { address } => {
@@ -722,7 +1044,192 @@ async fn handle_custody_command(protocol: &mut BitStableProtocol, action: Custod
     Ok(())
 }
 
-async fn handle_status_command(protocol: &BitStableProtocol) -> Result<()> {
+fn handle_database_command(config: &ProtocolConfig, action: DatabaseCommands) -> Result<()> {
+    // Copying moves raw records, so it runs before anything writes a schema version
+    if let DatabaseCommands::Copy { to } = action {
+        let source = storage::open_storage(&config.database_path)?;
+        let destination = storage::open_storage(&to)?;
+        let report = storage::copy_storage(source.as_ref(), destination.as_ref())?;
+        
+        println!("🗄️  Copied {} records in {} trees", report.records, report.trees);
+        println!("   {} ({}) → {} ({})", config.database_path, source.backend(), to, destination.backend());
+        return Ok(());
+    }
+    
+    // Verifying a backup never touches the configured database
+    if let DatabaseCommands::VerifyBackup { archives } = action {
+        let report = backup::verify_backup(&archives)?;
+        
+        println!("🗄️  Restored {} archives ({} operations) from schema {}",
+            report.restore.archives, report.restore.operations, report.restore.from_schema_version);
+        println!("   Records checked: {}", report.restore.schema.records_checked);
+        for problem in report.restore.schema.errors.iter().chain(&report.violations) {
+            println!("   ❌ {}", problem);
+        }
+        if !report.is_ok() {
+            return Err(bitstable::BitStableError::InvalidConfig(format!(
+                "Backup verification found {} problems", report.restore.schema.errors.len() + report.violations.len()
+            )));
+        }
+        println!("✅ Backup restores cleanly and supply matches vault debt");
+        return Ok(());
+    }
+    
+    let database = DatabaseManager::open_unmigrated(&config.database_path)?;
+    
+    match action {
+        DatabaseCommands::Migrate { dry_run } => {
+            let mode = if dry_run { MigrationMode::DryRun } else { MigrationMode::Apply };
+            let report = database.migrate(mode)?;
+            
+            if report.steps.is_empty() {
+                println!("✅ Database schema is current (version {})", report.to_version);
+                return Ok(());
+            }
+            
+            let verb = if report.applied { "Applied" } else { "Would apply" };
+            println!("🗄️  Schema version {} → {}", report.from_version, report.to_version);
+            for step in &report.steps {
+                println!("   {} v{}: {} ({} records)", verb, step.version, step.description, step.records);
+            }
+        }
+        
+        DatabaseCommands::Verify => {
+            let report = database.verify()?;
+            
+            println!("🗄️  Schema version: {}", report.schema_version);
+            println!("   Records checked: {}", report.records_checked);
+            for error in &report.errors {
+                println!("   ❌ {}", error);
+            }
+            if !report.is_ok() {
+                return Err(bitstable::BitStableError::InvalidConfig(
+                    format!("Database verification found {} problems", report.errors.len())
+                ));
+            }
+            println!("✅ All records decode at the current schema");
+        }
+        
+        DatabaseCommands::Backup { to, base } => {
+            let manifest = match base {
+                Some(base) => database.backup_incremental(&to, &backup::read_manifest(&base)?)?,
+                None => database.backup(&to)?,
+            };
+            
+            println!("🗄️  Wrote {:?} backup {} ({} operations)", manifest.kind, to, manifest.operations);
+            println!("   Schema version: {}", manifest.schema_version);
+            println!("   Checksum: {}", manifest.checksum);
+        }
+        
+        DatabaseCommands::Restore { archives } => {
+            let report = database.restore(&archives)?;
+            
+            println!("✅ Restored {} archives ({} operations)", report.archives, report.operations);
+            println!("   Schema version {} → {}", report.from_schema_version, report.schema.schema_version);
+        }
+        
+        DatabaseCommands::Copy { .. } => unreachable!("copied before the database is opened"),
+        DatabaseCommands::VerifyBackup { .. } => unreachable!("verified without opening the database"),
+    }
+    
+    Ok(())
+}
+
+fn handle_audit_command(config: &ProtocolConfig, action: &AuditCommands) -> Result<()> {
+    let audit_log = AuditLog::new(DatabaseManager::open(&config.database_path)?);
+    
+    match action {
+        AuditCommands::Verify => {
+            // Anchors are checked against the OP_RETURN of the transaction that published them
+            let bitcoin_client = BitcoinConfig::default().create_client()?;
+            let report = audit_log.verify_on_chain(|txid| bitcoin_client.get_op_return_data(txid))?;
+            
+            println!("📜 Audit entries: {} ({} anchored heads)", report.entries, report.anchors);
+            if let Some(head) = &report.head {
+                println!("   Head: #{} {}", head.sequence, head.hash);
+            }
+            for problem in &report.problems {
+                println!("   ❌ {}", problem);
+            }
+            if !report.is_ok() {
+                return Err(bitstable::BitStableError::InvalidConfig(
+                    format!("Audit log verification found {} problems", report.problems.len())
+                ));
+            }
+            println!("✅ Audit log chain is intact");
+        }
+        
+        AuditCommands::List { limit } => {
+            let entries = audit_log.entries()?;
+            
+            println!("📜 Audit log ({} entries)", entries.len());
+            for entry in entries.iter().rev().take(*limit) {
+                println!("   #{} {} by {}", entry.sequence, entry.timestamp.format("%Y-%m-%d %H:%M:%S"), entry.actor);
+                println!("      {:?}", entry.action);
+            }
+        }
+        
+        AuditCommands::Anchor => unreachable!("anchoring needs the protocol's Bitcoin client"),
+    }
+    
+    Ok(())
+}
+
+fn handle_audit_anchor(protocol: &mut BitStableProtocol) -> Result<()> {
+    match protocol.anchor_audit_log()? {
+        Some(anchor) => {
+            println!("⚓ Anchored audit entry #{} in {}", anchor.head.sequence, anchor.txid);
+            println!("   Head: {}", anchor.head.hash);
+        }
+        None => println!("📜 Audit log is empty, nothing to anchor"),
+    }
+    Ok(())
+}
+
+fn handle_events_command(config: &ProtocolConfig, from: u64, limit: Option<usize>) -> Result<()> {
+    let database = DatabaseManager::open(&config.database_path)?;
+    let records = database.get_events(from, limit)?;
+    
+    // One record per line on stdout, so the summary goes to stderr
+    for record in &records {
+        println!("{}", serde_json::to_string(record)?);
+    }
+    eprintln!("📣 Replayed {} events from sequence {}", records.len(), from);
+    
+    Ok(())
+}
+
+fn handle_export_command(
+    config: &ProtocolConfig,
+    dataset: Dataset,
+    format: ExportFormat,
+    from: Option<String>,
+    to: Option<String>,
+    currency: Option<String>,
+    output: Option<String>,
+) -> Result<()> {
+    let filter = ExportFilter {
+        from: from.as_deref().map(export::parse_time).transpose()?,
+        to: to.as_deref().map(export::parse_time).transpose()?,
+        currency: currency.as_deref().map(Currency::from_str),
+    };
+    let database = DatabaseManager::open(&config.database_path)?;
+    
+    // Rows go to stdout unless a file is named, so the summary goes to stderr
+    let rows = match &output {
+        Some(path) => {
+            let file = std::fs::File::create(path)?;
+            export::export(&database, dataset, &filter, format, std::io::BufWriter::new(file))?
+        }
+        None => export::export(&database, dataset, &filter, format, std::io::stdout().lock())?,
+    };
+    eprintln!("📤 Exported {} {} rows{}", rows, dataset.name(),
+        output.map(|path| format!(" to {}", path)).unwrap_or_default());
+    
+    Ok(())
+}
+
+async fn handle_status_command(protocol: &mut BitStableProtocol) -> Result<()> {
     println!("🚀 BitStable Protocol Status");
     println!("============================");
     
@@ -770,6 +1277,20 @@ async fn handle_status_command(protocol: &BitStableProtocol) -> Result<()> {
     println!("   Escrow Contracts: {}", custody_stats.active_escrow_contracts);
     println!("   Collateral Under Management: {} BTC", custody_stats.total_collateral_btc.to_btc());
     println!("   Completed Settlements: {}", custody_stats.completed_settlements);
+
+    // Risk metrics, with volatility taken from the daily candles the oracle daemon stores
+    println!("\n📊 Risk Metrics:");
+    match protocol.update_risk_metrics() {
+        Ok(alerts) => {
+            let metrics = &protocol.risk_metrics.current_metrics;
+            println!("   System Collateral Ratio: {:.1}%", metrics.system_collateral_ratio * 100.0);
+            println!("   BTC 30-day Volatility: {:.1}%", metrics.volatility_metrics.btc_30day_volatility * 100.0);
+            for alert in alerts {
+                println!("   ⚠️  {:?}: {}", alert.severity, alert.message);
+            }
+        }
+        Err(e) => println!("   Unavailable: {}", e),
+    }
     
     println!("\n✅ Protocol is operational!");
     
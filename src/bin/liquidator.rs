@@ -149,6 +149,17 @@ async fn run_liquidation_scan(
     max_liquidations: usize,
     dry_run: bool,
 ) -> Result<LiquidationResults> {
+    // Refresh prices, and let the peg monitor and emergency triggers see them
+    match protocol.refresh_prices().await {
+        Ok(alerts) => {
+            for alert in alerts {
+                log::warn!("{:?}: {}", alert.severity, alert.message);
+            }
+        }
+        Err(e) => log::warn!("Price refresh failed, scanning with the last known prices: {}", e),
+    }
+    protocol.sync_circuit_breakers();
+
     // Get current exchange rates
     let exchange_rates = protocol.oracle_network.get_exchange_rates();
     
@@ -1,6 +1,12 @@
 use clap::Parser;
-use bitstable::{ProtocolConfig, Result, oracle::MultiCurrencyOracleNetwork};
-use tokio::time::{sleep, Duration};
+use bitstable::{BitcoinConfig, ProtocolConfig, Result, TwapWindow, oracle::MultiCurrencyOracleNetwork};
+use bitstable::address_book::AddressBook;
+use bitstable::crypto::OracleKeyManager;
+use bitstable::database::DatabaseManager;
+use bitstable::network::{self, BitStableNetwork, ServiceType};
+use bitstable::candles::{CandleAggregator, RetentionPolicy};
+use bitstable::publisher::{OraclePublisher, PublicationPolicy};
+use tokio::time::{interval, interval_at, Duration, Instant};
 
 #[derive(Parser)]
 #[command(name = "oracle-node")]
@@ -19,9 +25,28 @@ struct Cli {
     #[arg(long)]
     oracle_key: Option<String>,
 
+    #[arg(long, default_value = "local_oracle")]
+    oracle_name: String,
+
     #[arg(long, default_value = "30")]
     update_interval: u64,
 
+    /// Relative price move that triggers a publish (0.005 = 0.5%)
+    #[arg(long, default_value = "0.005")]
+    deviation_threshold: f64,
+
+    /// Maximum seconds between publishes
+    #[arg(long, default_value = "3600")]
+    heartbeat: u64,
+
+    /// Database path, or a `sled://`, `sqlite://` or `memory://` location
+    #[arg(long, default_value = "./oracle-node.db")]
+    db_path: String,
+
+    /// Print the last N published updates and exit
+    #[arg(long)]
+    history: Option<usize>,
+
     #[arg(short, long)]
     verbose: bool,
 }
@@ -50,14 +75,72 @@ async fn main() -> Result<()> {
 
     config.validate()?;
 
+    let database = DatabaseManager::open(&cli.db_path)?;
+
+    if let Some(limit) = cli.history {
+        let history = database.get_publication_history(limit)?;
+        println!("📜 Publication History ({} updates)", history.len());
+        for update in history {
+            let usd = update.prices.get(&bitstable::Currency::USD).copied().unwrap_or(0.0);
+            println!("   #{} {} ${:.2} [{} currencies] {:?}",
+                update.sequence,
+                update.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                usd,
+                update.prices.len(),
+                update.reason
+            );
+        }
+        return Ok(());
+    }
+
+    let mut key_manager = OracleKeyManager::new();
+    let oracle_pubkey = if let Some(key_hex) = &cli.oracle_key {
+        key_manager.import_oracle_key(&cli.oracle_name, key_hex)?
+    } else {
+        log::warn!("No --oracle-key given, signing with an ephemeral key");
+        key_manager.generate_oracle_key(&cli.oracle_name)?
+    };
+
+    let policy = PublicationPolicy {
+        deviation_threshold: cli.deviation_threshold,
+        heartbeat_seconds: cli.heartbeat,
+    };
+    let mut publisher = OraclePublisher::new(&cli.oracle_name, policy, key_manager)?
+        .with_database(database.clone())?;
+
+    // Our own key is trusted too, so updates are checked before they go out
+    let oracle_keys = config.oracle_keys()?.into_iter().chain([oracle_pubkey]);
+    let mut network = BitStableNetwork::new(network::load_or_create_node_key(&database)?, 125, oracle_keys)
+        .with_services(vec![ServiceType::Oracle])
+        .with_address_book(AddressBook::with_database(database.clone())?)
+        .with_seeds(&config.seed_peers)?;
+    network.listen(&cli.listen).await?;
+    network.start_peer_discovery().await?;
+
     println!("🔮 BitStable Oracle Node Starting");
     println!("=================================");
     println!("Network: {:?}", config.network);
     println!("Listening on: {}", cli.listen);
     println!("Update interval: {}s", cli.update_interval);
+    println!("Publish on: {:.2}% deviation or {}s heartbeat",
+        cli.deviation_threshold * 100.0, cli.heartbeat);
+    println!("Oracle key: {}", oracle_pubkey);
+    println!("Node key: {}", network.local_pubkey());
 
     // Initialize oracle network
-    let mut oracle_network = MultiCurrencyOracleNetwork::new(&config)?;
+    let mut oracle_network = MultiCurrencyOracleNetwork::new(&config)?
+        .with_database(database.clone())?;
+    oracle_network.start_streams();
+
+    // A block-measured TWAP window needs each sample tagged with the chain tip
+    let bitcoin_client = match config.twap_window {
+        TwapWindow::Blocks(_) => Some(BitcoinConfig::default().create_client()?),
+        TwapWindow::Hours(_) => None,
+    };
+
+    // Downsample stored consensus rounds into candles once a minute
+    let _candle_task = CandleAggregator::new(database.clone(), RetentionPolicy::default())
+        .spawn(Duration::from_secs(60));
 
     println!("📡 Configured {} oracle endpoints", config.oracle_endpoints.len());
     for endpoint in &config.oracle_endpoints {
@@ -69,12 +152,21 @@ async fn main() -> Result<()> {
 
     // Main oracle loop
     let mut update_counter = 0u64;
+    let update_period = Duration::from_secs(cli.update_interval);
+    let mut updates = interval_at(Instant::now() + update_period, update_period);
+    let mut maintenance = interval(Duration::from_secs(30));
     
     loop {
         tokio::select! {
             // Periodic price updates
-            _ = sleep(Duration::from_secs(cli.update_interval)) => {
+            _ = updates.tick() => {
                 update_counter += 1;
+                if let Some(bitcoin_client) = &bitcoin_client {
+                    match bitcoin_client.get_block_height() {
+                        Ok(height) => oracle_network.set_block_height(height),
+                        Err(e) => log::warn!("Failed to read block height for TWAP samples: {}", e),
+                    }
+                }
                 
                 match oracle_network.get_consensus_prices().await {
                     Ok(exchange_rates) => {
@@ -92,16 +184,54 @@ async fn main() -> Result<()> {
                                     consensus.timestamp.format("%H:%M:%S")
                                 );
                             }
+
+                            match publisher.maybe_publish(consensus) {
+                                Ok(Some(update)) => {
+                                    println!("📣 Published update #{} ({:?})", update.sequence, update.reason);
+                                    let sent = match publisher.attestations(&update) {
+                                        Ok(attestations) => network.send_price_attestations(attestations).await,
+                                        Err(e) => Err(e),
+                                    };
+                                    if let Err(e) = sent {
+                                        log::error!("Failed to broadcast update #{}: {}", update.sequence, e);
+                                    }
+                                }
+                                Ok(None) => log::debug!("No deviation or heartbeat, skipping publish"),
+                                Err(e) => log::error!("Failed to publish update: {}", e),
+                            }
                         }
-                        
-                        // In a real implementation, broadcast this price to the network
-                        // network.broadcast_price_update(price, "local_oracle", signature).await?;
                     }
                     Err(e) => {
                         log::error!("Failed to get price consensus: {}", e);
                         println!("❌ Price update #{} failed: {}", update_counter, e);
                     }
                 }
+
+                // Market prices of the stable units themselves, for the peg monitor
+                if oracle_network.fetch_peg_prices().await > 0 {
+                    for (currency, peg) in oracle_network.get_peg_prices(chrono::Utc::now()) {
+                        log::info!("{} peg price {:.4} ({})", currency.to_string(), peg.price, peg.source);
+                    }
+                }
+
+                for event in oracle_network.drain_breaker_events() {
+                    println!("🚦 Circuit breaker {}: {:?} -> {:?}",
+                        event.currency.to_string(), event.from, event.to);
+                }
+            }
+
+
+            // Peer connections, incoming messages and relays
+            result = network.process_next_event() => {
+                if let Err(e) = result {
+                    log::warn!("Network event failed: {}", e);
+                }
+            }
+
+            _ = maintenance.tick() => {
+                if let Err(e) = network.maintenance_cycle().await {
+                    log::warn!("Network maintenance failed: {}", e);
+                }
             }
             
             // Handle shutdown signal
@@ -118,6 +248,9 @@ async fn main() -> Result<()> {
             println!("   Last Price: ${:.2}", btc_price);
         }
         println!("   Total Updates: {}", update_counter);
+        if let Some(last) = publisher.get_last_published() {
+            println!("   Last Published: #{} at {}", last.sequence, last.timestamp.format("%H:%M:%S"));
+        }
         println!("   Final Oracle Participation: {}/{}", 
             latest.participating_oracles, 
             latest.total_oracles
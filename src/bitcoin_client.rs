@@ -466,6 +466,26 @@ impl BitcoinClient {
         }
     }
 
+    /// Check whether an output has been spent, counting mempool spends
+    pub fn is_output_spent(&self, outpoint: bitcoin::OutPoint) -> Result<bool> {
+        let output = self.client.get_tx_out(&outpoint.txid, outpoint.vout, Some(true))
+            .map_err(|e| BitStableError::BitcoinRpcError(e.to_string()))?;
+        Ok(output.is_none())
+    }
+
+    /// Data pushed by the first OP_RETURN output of `txid`, if it has one
+    pub fn get_op_return_data(&self, txid: Txid) -> Result<Option<Vec<u8>>> {
+        let tx = self.client.get_raw_transaction(&txid, None)
+            .map_err(|e| BitStableError::BitcoinRpcError(e.to_string()))?;
+        let Some(output) = tx.output.iter().find(|output| output.script_pubkey.is_op_return()) else {
+            return Ok(None);
+        };
+        Ok(output.script_pubkey.instructions().find_map(|instruction| match instruction {
+            Ok(bitcoin::script::Instruction::PushBytes(bytes)) => Some(bytes.as_bytes().to_vec()),
+            _ => None,
+        }))
+    }
+
     /// Get current block height
     pub fn get_block_height(&self) -> Result<u64> {
         let info = self.client.get_blockchain_info()
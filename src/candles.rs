@@ -0,0 +1,279 @@
+//! OHLC candle downsampling of stored consensus rounds
+//! Buckets raw prices into 1m/1h/1d candles and prunes data past its retention
+
+use std::collections::{HashMap, VecDeque};
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use crate::{BitStableError, Result};
+use crate::database::DatabaseManager;
+use crate::multi_currency::Currency;
+
+/// Config key holding the timestamp aggregation has reached
+const CURSOR_KEY: &str = "candle_cursor";
+
+/// How far behind the cursor a consensus round may still be persisted
+const LATE_ROUND_GRACE_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CandleInterval {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl CandleInterval {
+    pub const ALL: [CandleInterval; 3] = [CandleInterval::Minute, CandleInterval::Hour, CandleInterval::Day];
+
+    pub fn duration(&self) -> Duration {
+        match self {
+            CandleInterval::Minute => Duration::minutes(1),
+            CandleInterval::Hour => Duration::hours(1),
+            CandleInterval::Day => Duration::days(1),
+        }
+    }
+
+    /// Start of the bucket containing `timestamp`
+    pub fn bucket_start(&self, timestamp: DateTime<Utc>) -> DateTime<Utc> {
+        timestamp.duration_trunc(self.duration()).unwrap_or(timestamp)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CandleInterval::Minute => "1m",
+            CandleInterval::Hour => "1h",
+            CandleInterval::Day => "1d",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Candle {
+    pub currency: Currency,
+    pub interval: CandleInterval,
+    pub open_time: DateTime<Utc>,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub samples: u64,
+}
+
+impl Candle {
+    fn new(currency: Currency, interval: CandleInterval, timestamp: DateTime<Utc>, price: f64) -> Self {
+        Self {
+            currency,
+            interval,
+            open_time: interval.bucket_start(timestamp),
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            samples: 1,
+        }
+    }
+
+    fn update(&mut self, price: f64) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.samples += 1;
+    }
+}
+
+/// How long each kind of price data is kept; `None` keeps it forever
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    pub raw_days: Option<i64>,      // Consensus rounds and oracle submissions
+    pub minute_days: Option<i64>,
+    pub hour_days: Option<i64>,
+    pub day_days: Option<i64>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            raw_days: Some(7),
+            minute_days: Some(30),
+            hour_days: Some(365),
+            day_days: None,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    fn for_interval(&self, interval: CandleInterval) -> Option<i64> {
+        match interval {
+            CandleInterval::Minute => self.minute_days,
+            CandleInterval::Hour => self.hour_days,
+            CandleInterval::Day => self.day_days,
+        }
+    }
+}
+
+/// Folds new consensus rounds into candles and enforces retention
+#[derive(Debug, Clone)]
+pub struct CandleAggregator {
+    database: DatabaseManager,
+    retention: RetentionPolicy,
+}
+
+impl CandleAggregator {
+    pub fn new(database: DatabaseManager, retention: RetentionPolicy) -> Self {
+        Self { database, retention }
+    }
+
+    /// Rebuild the candles still open since the last run from the stored rounds, then
+    /// prune; returns the rounds the minute candles were rebuilt from
+    pub fn run_once(&self, now: DateTime<Utc>) -> Result<usize> {
+        let cursor: DateTime<Utc> = self.database.load_config(CURSOR_KEY)?
+            .unwrap_or(DateTime::<Utc>::UNIX_EPOCH);
+        if now <= cursor {
+            return Ok(0);
+        }
+
+        // A round persisted after the last run may be timestamped before it, so each
+        // interval is rebuilt from the start of the bucket such a round could fall in
+        let rescan_from = (cursor - Duration::seconds(LATE_ROUND_GRACE_SECS)).max(DateTime::<Utc>::UNIX_EPOCH);
+        let mut processed = 0;
+        for interval in CandleInterval::ALL {
+            let rounds = self.database.get_consensus_rounds(interval.bucket_start(rescan_from), now)?;
+            let mut candles: HashMap<(Currency, DateTime<Utc>), Candle> = HashMap::new();
+            for round in &rounds {
+                for (currency, price) in &round.btc_prices {
+                    candles.entry((currency.clone(), interval.bucket_start(round.timestamp)))
+                        .and_modify(|candle| candle.update(*price))
+                        .or_insert_with(|| Candle::new(currency.clone(), interval, round.timestamp, *price));
+                }
+            }
+            for candle in candles.values() {
+                self.database.save_candle(candle)?;
+            }
+            if interval == CandleInterval::Minute {
+                processed = rounds.len();
+            }
+        }
+
+        // Retention runs after aggregation so raw rounds are never pruned unread
+        self.database.save_config(CURSOR_KEY, &now)?;
+        self.apply_retention(now)?;
+
+        if processed > 0 {
+            log::debug!("Aggregated {} consensus rounds into candles", processed);
+        }
+        Ok(processed)
+    }
+
+    /// Run `run_once` on a fixed period in the background
+    pub fn spawn(self, every: std::time::Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(every);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.run_once(Utc::now()) {
+                    log::error!("Candle aggregation failed: {}", e);
+                }
+            }
+        })
+    }
+
+    fn apply_retention(&self, now: DateTime<Utc>) -> Result<()> {
+        if let Some(days) = self.retention.raw_days {
+            // The next run rebuilds the open day from raw rounds, so those must stay
+            let rebuilt_from = CandleInterval::Day.bucket_start(now - Duration::seconds(LATE_ROUND_GRACE_SECS));
+            self.database.prune_raw_prices((now - Duration::days(days)).min(rebuilt_from))?;
+        }
+        for interval in CandleInterval::ALL {
+            if let Some(days) = self.retention.for_interval(interval) {
+                self.database.prune_candles(interval, now - Duration::days(days))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Candle closes over a range, shaped for `RiskMetricsSystem::update_risk_metrics`
+pub fn close_series(
+    database: &DatabaseManager,
+    currency: &Currency,
+    interval: CandleInterval,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<VecDeque<(DateTime<Utc>, f64)>> {
+    if from > to {
+        return Err(BitStableError::InvalidConfig("Candle range start is after its end".to_string()));
+    }
+
+    Ok(database.get_candles(currency, interval, from, to)?
+        .into_iter()
+        .map(|candle| (candle.open_time, candle.close))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oracle::ConsensusPrices;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn round(timestamp: DateTime<Utc>, usd: f64) -> ConsensusPrices {
+        let mut btc_prices = HashMap::new();
+        btc_prices.insert(Currency::USD, usd);
+        ConsensusPrices {
+            btc_prices,
+            exchange_rates: HashMap::new(),
+            timestamp,
+            participating_oracles: 3,
+            total_oracles: 5,
+        }
+    }
+
+    #[test]
+    fn test_candles_and_retention() {
+        let temp_dir = TempDir::new().unwrap();
+        let database = DatabaseManager::new(temp_dir.path()).unwrap();
+        let aggregator = CandleAggregator::new(database.clone(), RetentionPolicy {
+            raw_days: Some(1),
+            minute_days: Some(2),
+            hour_days: None,
+            day_days: None,
+        });
+
+        let day = CandleInterval::Day.bucket_start(Utc::now()) - Duration::days(3);
+        let t = |minutes: i64, seconds: i64| day + Duration::minutes(minutes) + Duration::seconds(seconds);
+
+        for (timestamp, price) in [(t(0, 5), 100.0), (t(0, 20), 120.0), (t(0, 40), 90.0), (t(1, 0), 95.0), (t(61, 0), 110.0)] {
+            database.save_consensus_round(&round(timestamp, price)).unwrap();
+        }
+
+        // Aggregate as of shortly after the last round so nothing is pruned yet
+        assert_eq!(aggregator.run_once(t(62, 0)).unwrap(), 5);
+        let minutes = database.get_candles(&Currency::USD, CandleInterval::Minute, day, t(120, 0)).unwrap();
+        assert_eq!(minutes.len(), 3);
+        assert_eq!((minutes[0].open, minutes[0].high, minutes[0].low, minutes[0].close), (100.0, 120.0, 90.0, 90.0));
+        assert_eq!(minutes[0].samples, 3);
+
+        let hours = close_series(&database, &Currency::USD, CandleInterval::Hour, day, t(120, 0)).unwrap();
+        assert_eq!(hours.iter().map(|(_, close)| *close).collect::<Vec<_>>(), vec![95.0, 110.0]);
+
+        // A round persisted after that run, timestamped before it, still reaches its candles
+        database.save_consensus_round(&round(t(61, 30), 130.0)).unwrap();
+        assert_eq!(aggregator.run_once(t(62, 10)).unwrap(), 2);
+        let minutes = database.get_candles(&Currency::USD, CandleInterval::Minute, day, t(120, 0)).unwrap();
+        assert_eq!((minutes[2].high, minutes[2].close, minutes[2].samples), (130.0, 130.0, 2));
+        let hours = close_series(&database, &Currency::USD, CandleInterval::Hour, day, t(120, 0)).unwrap();
+        assert_eq!(hours.iter().map(|(_, close)| *close).collect::<Vec<_>>(), vec![95.0, 130.0]);
+
+        // Rerunning later rebuilds only the open buckets, then prunes raw data and minute candles
+        database.save_consensus_round(&round(t(62, 30), 111.0)).unwrap();
+        assert_eq!(aggregator.run_once(Utc::now()).unwrap(), 3);
+
+        assert!(database.get_consensus_rounds(day, Utc::now()).unwrap().is_empty());
+        assert!(database.get_candles(&Currency::USD, CandleInterval::Minute, day, Utc::now()).unwrap().is_empty());
+        let daily = database.get_candles(&Currency::USD, CandleInterval::Day, day, Utc::now()).unwrap();
+        assert_eq!(daily.len(), 1);
+        assert_eq!((daily[0].high, daily[0].close, daily[0].samples), (130.0, 111.0, 7));
+    }
+}
@@ -0,0 +1,368 @@
+//! Per-currency circuit breaker state machine
+//! Tracks tripped, cooling-down and governance-override states between consensus rounds
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use chrono::{DateTime, Duration, Utc};
+use crate::Result;
+use crate::database::DatabaseManager;
+use crate::multi_currency::Currency;
+use crate::oracle::CircuitBreakerConfig;
+
+/// Largest move accepted while a breaker is cooling down
+const COOLDOWN_MAX_MOVE: f64 = 0.05;
+
+/// Which circuit breaker tier a price move failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BreakerTier {
+    Tier1,  // Move above tier1 without min_oracles_tier1
+    Tier2,  // Move above tier2 without min_oracles_tier2
+    Tier3,  // Move above tier3 without governance override
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BreakerState {
+    Closed,
+    Tripped(BreakerTier),
+    CoolingDown { until: DateTime<Utc> },
+    GovernanceOverride { until: DateTime<Utc> },
+}
+
+/// Breaker state for a single currency
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CurrencyBreaker {
+    pub currency: Currency,
+    pub state: BreakerState,
+    pub reference_price: Option<f64>,    // Last accepted consensus price
+    pub last_rejected_price: Option<f64>,
+    pub state_since: DateTime<Utc>,
+    pub trip_count: u64,
+}
+
+/// Emitted whenever a breaker changes state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BreakerEvent {
+    pub currency: Currency,
+    pub from: BreakerState,
+    pub to: BreakerState,
+    pub price: Option<f64>,
+    pub change: Option<f64>,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct CircuitBreakerSystem {
+    config: CircuitBreakerConfig,
+    breakers: HashMap<Currency, CurrencyBreaker>,
+    pending_events: Vec<BreakerEvent>,
+    database: Option<DatabaseManager>,
+}
+
+impl CircuitBreakerSystem {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            breakers: HashMap::new(),
+            pending_events: Vec::new(),
+            database: None,
+        }
+    }
+
+    /// Persist breaker state and restore whatever was saved previously
+    pub fn with_database(mut self, database: DatabaseManager) -> Result<Self> {
+        for breaker in database.load_circuit_breakers()? {
+            if matches!(breaker.state, BreakerState::Tripped(_)) {
+                log::warn!("Restored tripped circuit breaker for {}", breaker.currency.to_string());
+            }
+            self.breakers.insert(breaker.currency.clone(), breaker);
+        }
+        self.database = Some(database);
+        Ok(self)
+    }
+
+    pub fn config(&self) -> &CircuitBreakerConfig {
+        &self.config
+    }
+
+    pub fn set_emergency_override(&mut self, enabled: bool) {
+        self.config.emergency_override = enabled;
+    }
+
+    /// Run a new consensus price through the breaker; returns whether it may be used
+    pub fn observe(&mut self, currency: &Currency, price: f64, successful_oracles: usize, now: DateTime<Utc>) -> Result<bool> {
+        self.expire_timed_states(currency, now)?;
+
+        let breaker = self.breakers.entry(currency.clone()).or_insert_with(|| CurrencyBreaker {
+            currency: currency.clone(),
+            state: BreakerState::Closed,
+            reference_price: None,
+            last_rejected_price: None,
+            state_since: now,
+            trip_count: 0,
+        });
+
+        let reference = match breaker.reference_price {
+            Some(reference) if reference > 0.0 => reference,
+            _ => {
+                // First price always valid
+                breaker.reference_price = Some(price);
+                let breaker = breaker.clone();
+                self.persist(&breaker)?;
+                return Ok(true);
+            }
+        };
+
+        let change = ((price - reference) / reference).abs();
+        let failed_tier = self.failed_tier(change, successful_oracles);
+        let state = self.breakers[currency].state.clone();
+
+        let (accepted, next_state) = match state {
+            BreakerState::GovernanceOverride { .. } => (true, None),
+            BreakerState::Closed => match failed_tier {
+                Some(tier) => (false, Some(BreakerState::Tripped(tier))),
+                None if change > self.config.tier1_threshold => (true, Some(self.cooling_down(now))),
+                None => (true, None),
+            },
+            BreakerState::CoolingDown { .. } => match failed_tier {
+                Some(tier) => (false, Some(BreakerState::Tripped(tier))),
+                None if change > COOLDOWN_MAX_MOVE => {
+                    log::warn!("Price update for {} in cooldown period", currency.to_string());
+                    (false, None)
+                }
+                None => (true, None),
+            },
+            BreakerState::Tripped(current) => match failed_tier {
+                // Move is now confirmed by enough oracles or the price came back
+                None => (true, Some(self.cooling_down(now))),
+                Some(tier) if tier != current => (false, Some(BreakerState::Tripped(tier))),
+                Some(_) => (false, None),
+            },
+        };
+
+        if let Some(breaker) = self.breakers.get_mut(currency) {
+            if accepted {
+                breaker.reference_price = Some(price);
+                breaker.last_rejected_price = None;
+            } else {
+                breaker.last_rejected_price = Some(price);
+            }
+        }
+
+        match next_state {
+            Some(to) => self.transition(currency, to, Some(price), Some(change), now)?,
+            None => {
+                let breaker = self.breakers[currency].clone();
+                self.persist(&breaker)?;
+            }
+        }
+
+        if accepted {
+            log::info!("Price movement for {} validated: {:.2}% with {} oracles",
+                     currency.to_string(), change * 100.0, successful_oracles);
+        }
+
+        Ok(accepted)
+    }
+
+    /// Let governance bypass the breakers; `None` applies to every tracked currency
+    pub fn apply_governance_override(&mut self, currency: Option<&Currency>, duration: Duration, now: DateTime<Utc>) -> Result<()> {
+        let currencies: Vec<Currency> = match currency {
+            Some(currency) => vec![currency.clone()],
+            None => self.breakers.keys().cloned().collect(),
+        };
+
+        for currency in currencies {
+            self.breakers.entry(currency.clone()).or_insert_with(|| CurrencyBreaker {
+                currency: currency.clone(),
+                state: BreakerState::Closed,
+                reference_price: None,
+                last_rejected_price: None,
+                state_since: now,
+                trip_count: 0,
+            });
+            self.transition(&currency, BreakerState::GovernanceOverride { until: now + duration }, None, None, now)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_state(&self, currency: &Currency) -> BreakerState {
+        self.breakers.get(currency)
+            .map(|b| b.state.clone())
+            .unwrap_or(BreakerState::Closed)
+    }
+
+    pub fn get_breaker(&self, currency: &Currency) -> Option<&CurrencyBreaker> {
+        self.breakers.get(currency)
+    }
+
+    pub fn is_tripped(&self, currency: &Currency) -> bool {
+        matches!(self.get_state(currency), BreakerState::Tripped(_))
+    }
+
+    pub fn tripped_currencies(&self) -> HashSet<Currency> {
+        self.breakers.values()
+            .filter(|b| matches!(b.state, BreakerState::Tripped(_)))
+            .map(|b| b.currency.clone())
+            .collect()
+    }
+
+    /// Take the state changes emitted since the last call
+    pub fn drain_events(&mut self) -> Vec<BreakerEvent> {
+        std::mem::take(&mut self.pending_events)
+    }
+
+    /// Stored state change history, oldest first
+    pub fn get_event_history(&self, limit: usize) -> Result<Vec<BreakerEvent>> {
+        match &self.database {
+            Some(database) => database.get_circuit_breaker_events(limit),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn failed_tier(&self, change: f64, successful_oracles: usize) -> Option<BreakerTier> {
+        if change > self.config.tier3_threshold {
+            if self.config.emergency_override { None } else { Some(BreakerTier::Tier3) }
+        } else if change > self.config.tier2_threshold {
+            (successful_oracles < self.config.min_oracles_tier2).then_some(BreakerTier::Tier2)
+        } else if change > self.config.tier1_threshold {
+            (successful_oracles < self.config.min_oracles_tier1).then_some(BreakerTier::Tier1)
+        } else {
+            None
+        }
+    }
+
+    fn cooling_down(&self, now: DateTime<Utc>) -> BreakerState {
+        BreakerState::CoolingDown { until: now + Duration::minutes(self.config.cooldown_minutes as i64) }
+    }
+
+    fn expire_timed_states(&mut self, currency: &Currency, now: DateTime<Utc>) -> Result<()> {
+        let next = match self.breakers.get(currency).map(|b| &b.state) {
+            Some(BreakerState::CoolingDown { until }) if now >= *until => BreakerState::Closed,
+            Some(BreakerState::GovernanceOverride { until }) if now >= *until => self.cooling_down(now),
+            _ => return Ok(()),
+        };
+        self.transition(currency, next, None, None, now)
+    }
+
+    fn transition(&mut self, currency: &Currency, to: BreakerState, price: Option<f64>, change: Option<f64>, now: DateTime<Utc>) -> Result<()> {
+        let breaker = match self.breakers.get_mut(currency) {
+            Some(breaker) => breaker,
+            None => return Ok(()),
+        };
+
+        let event = BreakerEvent {
+            currency: currency.clone(),
+            from: breaker.state.clone(),
+            to: to.clone(),
+            price,
+            change,
+            timestamp: now,
+        };
+
+        if matches!(to, BreakerState::Tripped(_)) && !matches!(breaker.state, BreakerState::Tripped(_)) {
+            breaker.trip_count += 1;
+        }
+        breaker.state = to;
+        breaker.state_since = now;
+        let breaker = breaker.clone();
+
+        match &event.to {
+            BreakerState::Tripped(tier) => log::error!(
+                "Circuit breaker for {} tripped at {:?} ({:.2}% move)",
+                currency.to_string(), tier, change.unwrap_or(0.0) * 100.0
+            ),
+            state => log::info!("Circuit breaker for {} now {:?}", currency.to_string(), state),
+        }
+
+        self.persist(&breaker)?;
+        if let Some(database) = &self.database {
+            database.save_circuit_breaker_event(&event)?;
+        }
+        self.pending_events.push(event);
+        Ok(())
+    }
+
+    fn persist(&self, breaker: &CurrencyBreaker) -> Result<()> {
+        if let Some(database) = &self.database {
+            database.save_circuit_breaker(breaker)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn breaker_config() -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            tier1_threshold: 0.10,
+            tier2_threshold: 0.20,
+            tier3_threshold: 0.30,
+            min_oracles_tier1: 15,
+            min_oracles_tier2: 18,
+            emergency_override: false,
+            cooldown_minutes: 15,
+        }
+    }
+
+    #[test]
+    fn test_trip_recover_and_cooldown() {
+        let mut breakers = CircuitBreakerSystem::new(breaker_config());
+        let now = Utc::now();
+        let usd = Currency::USD;
+
+        assert!(breakers.observe(&usd, 100000.0, 20, now).unwrap());
+
+        // 12% drop reported by too few oracles trips tier 1
+        assert!(!breakers.observe(&usd, 88000.0, 10, now).unwrap());
+        assert_eq!(breakers.get_state(&usd), BreakerState::Tripped(BreakerTier::Tier1));
+        assert!(breakers.tripped_currencies().contains(&usd));
+
+        // Same move confirmed by enough oracles releases into cooldown
+        assert!(breakers.observe(&usd, 88000.0, 16, now).unwrap());
+        assert!(matches!(breakers.get_state(&usd), BreakerState::CoolingDown { .. }));
+
+        // Another 6% move during cooldown is held back, small moves pass
+        assert!(!breakers.observe(&usd, 82700.0, 20, now + Duration::minutes(5)).unwrap());
+        assert!(breakers.observe(&usd, 88500.0, 20, now + Duration::minutes(6)).unwrap());
+
+        // Cooldown expiry closes the breaker
+        assert!(breakers.observe(&usd, 88600.0, 20, now + Duration::minutes(16)).unwrap());
+        assert_eq!(breakers.get_state(&usd), BreakerState::Closed);
+
+        let transitions: Vec<BreakerState> = breakers.drain_events().into_iter().map(|e| e.to).collect();
+        assert_eq!(transitions.len(), 3);
+        assert_eq!(transitions[2], BreakerState::Closed);
+    }
+
+    #[test]
+    fn test_governance_override_and_persistence() {
+        let temp_dir = TempDir::new().unwrap();
+        let database = DatabaseManager::new(temp_dir.path()).unwrap();
+        let mut breakers = CircuitBreakerSystem::new(breaker_config())
+            .with_database(database.clone())
+            .unwrap();
+        let now = Utc::now();
+        let eur = Currency::EUR;
+
+        breakers.observe(&eur, 90000.0, 20, now).unwrap();
+        assert!(!breakers.observe(&eur, 50000.0, 20, now).unwrap());
+        assert_eq!(breakers.get_state(&eur), BreakerState::Tripped(BreakerTier::Tier3));
+
+        // State survives a restart
+        let restored = CircuitBreakerSystem::new(breaker_config())
+            .with_database(database)
+            .unwrap();
+        assert!(restored.is_tripped(&eur));
+        assert_eq!(restored.get_event_history(10).unwrap().len(), 1);
+
+        // Governance override accepts the move, then decays into cooldown
+        breakers.apply_governance_override(None, Duration::hours(1), now).unwrap();
+        assert!(breakers.observe(&eur, 50000.0, 20, now).unwrap());
+        breakers.observe(&eur, 50100.0, 20, now + Duration::hours(2)).unwrap();
+        assert!(matches!(breakers.get_state(&eur), BreakerState::CoolingDown { .. }));
+    }
+}
@@ -10,7 +10,7 @@ pub struct ProtocolConfig {
     pub stability_fee_apr: f64,
     pub oracle_threshold: usize,
     pub oracle_timeout_seconds: u64,
-    pub database_path: String,
+    pub database_path: String,  // Path or `sled://`, `sqlite://` or `memory://` location
     pub oracle_endpoints: Vec<OracleEndpoint>,
     // Progressive liquidation thresholds
     pub progressive_liquidation_threshold: f64,  // 130%
@@ -18,6 +18,44 @@ pub struct ProtocolConfig {
     pub partial_liquidation_50: f64,            // 127.5%
     pub partial_liquidation_75: f64,            // 125%
     pub insurance_fund_fee_rate: f64,           // 1% of fees to insurance
+    #[serde(default)]
+    pub twap_window: TwapWindow,                // Window for consensus TWAP
+    #[serde(default = "default_max_venue_weight")]
+    pub max_venue_weight: f64,                  // Largest share of volume weight any one venue gets
+    #[serde(default)]
+    pub seed_peers: Vec<String>,                // "pubkey@host:port" nodes dialled on startup
+    #[serde(default)]
+    pub custody_pubkeys: Vec<String>,           // Keys allowed to sign vault records other than the owner
+    #[serde(default)]
+    pub peg_price_endpoints: Vec<PegPriceEndpoint>,  // Market quotes of stable units against their pegs
+}
+
+fn default_max_venue_weight() -> f64 {
+    0.4
+}
+
+/// Length of the consensus TWAP window, in wall-clock hours or Bitcoin blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TwapWindow {
+    Hours(u64),
+    Blocks(u64),
+}
+
+impl Default for TwapWindow {
+    fn default() -> Self {
+        TwapWindow::Hours(24)
+    }
+}
+
+impl TwapWindow {
+    /// Approximate wall-clock length, assuming 10 minute blocks
+    pub fn duration(&self) -> chrono::Duration {
+        match self {
+            TwapWindow::Hours(hours) => chrono::Duration::hours(*hours as i64),
+            TwapWindow::Blocks(blocks) => chrono::Duration::minutes(*blocks as i64 * 10),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +63,16 @@ pub struct OracleEndpoint {
     pub name: String,
     pub url: String,
     pub pubkey: String,
+    #[serde(default)]
+    pub stream_url: Option<String>,  // WebSocket ticker; `url` remains the REST failover
+}
+
+/// Market quoting a stable unit in its own fiat currency, as JSON `{"price": 0.998}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PegPriceEndpoint {
+    pub name: String,
+    pub currency: crate::Currency,
+    pub url: String,
 }
 
 impl Default for ProtocolConfig {
@@ -43,26 +91,31 @@ impl Default for ProtocolConfig {
                     name: "Coinbase".to_string(),
                     url: "https://api.coinbase.com/v2/exchange-rates?currency=BTC".to_string(),
                     pubkey: "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".to_string(),
+                    stream_url: Some("wss://ws-feed.exchange.coinbase.com".to_string()),
                 },
                 OracleEndpoint {
                     name: "Binance".to_string(),
                     url: "https://api.binance.com/api/v3/ticker/price?symbol=BTCUSDT".to_string(),
                     pubkey: "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9".to_string(),
+                    stream_url: Some("wss://stream.binance.com:9443/ws/btcusdt@ticker".to_string()),
                 },
                 OracleEndpoint {
                     name: "Kraken".to_string(),
                     url: "https://api.kraken.com/0/public/Ticker?pair=XBTUSD".to_string(),
                     pubkey: "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".to_string(),
+                    stream_url: Some("wss://ws.kraken.com/v2".to_string()),
                 },
                 OracleEndpoint {
                     name: "Bitstamp".to_string(),
                     url: "https://www.bitstamp.net/api/v2/ticker/btcusd/".to_string(),
                     pubkey: "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9".to_string(),
+                    stream_url: None,
                 },
                 OracleEndpoint {
                     name: "CoinGecko".to_string(),
                     url: "https://api.coingecko.com/api/v3/simple/price?ids=bitcoin&vs_currencies=usd".to_string(),
                     pubkey: "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".to_string(),
+                    stream_url: None,
                 },
             ],
             // Progressive liquidation configuration
@@ -71,6 +124,11 @@ impl Default for ProtocolConfig {
             partial_liquidation_50: 1.275,            // 50% at 127.5%
             partial_liquidation_75: 1.25,             // 75% at 125%
             insurance_fund_fee_rate: 0.01,            // 1% of fees
+            twap_window: TwapWindow::default(),
+            max_venue_weight: default_max_venue_weight(),
+            seed_peers: Vec::new(),
+            custody_pubkeys: Vec::new(),
+            peg_price_endpoints: Vec::new(),
         }
     }
 }
@@ -87,6 +145,25 @@ impl ProtocolConfig {
         config
     }
 
+    pub fn custody_keys(&self) -> crate::Result<Vec<bitcoin::PublicKey>> {
+        self.custody_pubkeys
+            .iter()
+            .map(|key| key.parse().map_err(|e| crate::BitStableError::InvalidConfig(
+                format!("Invalid custody public key '{}': {}", key, e)
+            )))
+            .collect()
+    }
+
+    /// Keys of the configured oracles, whose price attestations peers accept
+    pub fn oracle_keys(&self) -> crate::Result<Vec<bitcoin::secp256k1::PublicKey>> {
+        self.oracle_endpoints
+            .iter()
+            .map(|endpoint| endpoint.pubkey.parse().map_err(|e| crate::BitStableError::InvalidConfig(
+                format!("Invalid public key for oracle '{}': {}", endpoint.name, e)
+            )))
+            .collect()
+    }
+
     pub fn validate(&self) -> crate::Result<()> {
         if self.min_collateral_ratio <= 1.0 {
             return Err(crate::BitStableError::InvalidConfig(
@@ -100,6 +177,24 @@ impl ProtocolConfig {
             ));
         }
 
+        if matches!(self.twap_window, TwapWindow::Hours(0) | TwapWindow::Blocks(0)) {
+            return Err(crate::BitStableError::InvalidConfig(
+                "twap_window must be non-zero".to_string()
+            ));
+        }
+
+        if self.max_venue_weight <= 0.0 || self.max_venue_weight > 1.0 {
+            return Err(crate::BitStableError::InvalidConfig(
+                "max_venue_weight must be in (0, 1]".to_string()
+            ));
+        }
+
+        for seed in &self.seed_peers {
+            crate::address_book::parse_peer_uri(seed)?;
+        }
+
+        self.custody_keys()?;
+
         if self.oracle_threshold > self.oracle_endpoints.len() {
             return Err(crate::BitStableError::InvalidConfig(
                 "oracle_threshold cannot exceed number of oracle endpoints".to_string()
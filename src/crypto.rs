@@ -6,6 +6,7 @@ use bitcoin::secp256k1::ecdsa::Signature;
 use sha2::{Sha256, Digest};
 use serde::{Deserialize, Serialize};
 use crate::{BitStableError, Result};
+use crate::multi_currency::Currency;
 use std::collections::HashMap;
 
 /// Oracle key manager for secure key storage and signing
@@ -76,11 +77,20 @@ impl OracleKeyManager {
 
     /// Sign price data with an oracle's private key
     pub fn sign_price_data(&self, oracle_name: &str, price: f64, timestamp: i64) -> Result<OracleSignature> {
+        self.sign_price(oracle_name, None, price, timestamp)
+    }
+
+    /// Sign a BTC price in a specific currency, so it can't be replayed as another currency's price
+    pub fn sign_currency_price(&self, oracle_name: &str, currency: Currency, price: f64, timestamp: i64) -> Result<OracleSignature> {
+        self.sign_price(oracle_name, Some(currency), price, timestamp)
+    }
+
+    fn sign_price(&self, oracle_name: &str, currency: Option<Currency>, price: f64, timestamp: i64) -> Result<OracleSignature> {
         let key_pair = self.oracle_keys.get(oracle_name)
             .ok_or_else(|| BitStableError::InvalidConfig(format!("Oracle key not found: {}", oracle_name)))?;
         
         // Create deterministic message from price and timestamp
-        let message_data = format!("{}:{}:{}", oracle_name, price, timestamp);
+        let message_data = price_message(oracle_name, currency.as_ref(), price, timestamp);
         
         // Hash the message
         let mut hasher = Sha256::new();
@@ -96,6 +106,7 @@ impl OracleKeyManager {
         
         Ok(OracleSignature {
             oracle_name: oracle_name.to_string(),
+            currency,
             price,
             timestamp,
             signature: hex::encode(signature.serialize_compact()),
@@ -106,7 +117,7 @@ impl OracleKeyManager {
     /// Verify an oracle signature
     pub fn verify_oracle_signature(&self, signature: &OracleSignature) -> Result<bool> {
         // Recreate the message
-        let message_data = format!("{}:{}:{}", signature.oracle_name, signature.price, signature.timestamp);
+        let message_data = price_message(&signature.oracle_name, signature.currency.as_ref(), signature.price, signature.timestamp);
         
         let mut hasher = Sha256::new();
         hasher.update(message_data.as_bytes());
@@ -132,6 +143,38 @@ impl OracleKeyManager {
         Ok(self.secp.verify_ecdsa(&message, &sig, &pubkey).is_ok())
     }
 
+    /// Sign an arbitrary message with an oracle's private key, returning a hex compact signature
+    pub fn sign_message(&self, oracle_name: &str, message_data: &str) -> Result<String> {
+        let key_pair = self.oracle_keys.get(oracle_name)
+            .ok_or_else(|| BitStableError::InvalidConfig(format!("Oracle key not found: {}", oracle_name)))?;
+
+        let hash = Sha256::digest(message_data.as_bytes());
+        let message = Message::from_digest_slice(&hash)
+            .map_err(|_e| BitStableError::OracleSignatureVerificationFailed)?;
+
+        let signature = self.secp.sign_ecdsa(&message, &key_pair.private_key);
+        Ok(hex::encode(signature.serialize_compact()))
+    }
+
+    /// Verify a hex compact signature over an arbitrary message
+    pub fn verify_message(&self, public_key_hex: &str, message_data: &str, signature_hex: &str) -> Result<bool> {
+        let hash = Sha256::digest(message_data.as_bytes());
+        let message = Message::from_digest_slice(&hash)
+            .map_err(|_e| BitStableError::OracleSignatureVerificationFailed)?;
+
+        let sig_bytes = hex::decode(signature_hex)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Invalid signature hex: {}", e)))?;
+        let sig = Signature::from_compact(&sig_bytes)
+            .map_err(|_e| BitStableError::OracleSignatureVerificationFailed)?;
+
+        let pubkey_bytes = hex::decode(public_key_hex)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Invalid public key hex: {}", e)))?;
+        let pubkey = PublicKey::from_slice(&pubkey_bytes)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Invalid public key: {}", e)))?;
+
+        Ok(self.secp.verify_ecdsa(&message, &sig, &pubkey).is_ok())
+    }
+
     /// Get public key for an oracle
     pub fn get_oracle_public_key(&self, oracle_name: &str) -> Option<PublicKey> {
         self.oracle_keys.get(oracle_name).map(|kp| kp.public_key)
@@ -143,10 +186,20 @@ impl OracleKeyManager {
     }
 }
 
+/// Signed text for a price; currency-bound signatures include the currency code
+fn price_message(oracle_name: &str, currency: Option<&Currency>, price: f64, timestamp: i64) -> String {
+    match currency {
+        Some(currency) => format!("{}:{}:{}:{}", oracle_name, currency.to_string(), price, timestamp),
+        None => format!("{}:{}:{}", oracle_name, price, timestamp),
+    }
+}
+
 /// Oracle signature with metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OracleSignature {
     pub oracle_name: String,
+    #[serde(default)]
+    pub currency: Option<Currency>,   // None for legacy USD-only signatures
     pub price: f64,
     pub timestamp: i64,
     pub signature: String,
@@ -339,6 +392,12 @@ mod tests {
         
         // Verify the signature
         assert!(key_manager.verify_oracle_signature(&signature).unwrap());
+
+        // Currency-bound signatures don't verify once relabelled as another currency
+        let mut eur = key_manager.sign_currency_price("test_oracle", Currency::EUR, 46000.0, timestamp).unwrap();
+        assert!(key_manager.verify_oracle_signature(&eur).unwrap());
+        eur.currency = Some(Currency::USD);
+        assert!(!key_manager.verify_oracle_signature(&eur).unwrap());
     }
     
     #[test]
@@ -11,6 +11,7 @@ use std::collections::HashMap;
 use chrono::{DateTime, Utc};
 use crate::{BitStableError, Result, ProtocolConfig, BitcoinClient};
 use crate::governance::{GovernanceSystem, Keyholder, KeyholderRole};
+use crate::multi_currency::btc_amount;
 
 /// Bitcoin custody manager that handles trustless collateral locking and liquidation settlements
 #[derive(Debug)]
@@ -25,7 +26,6 @@ pub struct CustodyManager {
     liquidator_privkey: Option<PrivateKey>,
     
     // Enhanced governance-controlled key management
-    #[allow(dead_code)]
     governance_system: GovernanceSystem,
     #[allow(dead_code)]
     key_rotation_in_progress: bool,
@@ -45,6 +45,14 @@ pub struct CustodyManager {
     bitcoin_client: Option<BitcoinClient>,
 }
 
+/// Custody state taken by `CustodyManager::snapshot`
+#[derive(Debug, Clone)]
+pub struct CustodySnapshot {
+    escrow_contracts: HashMap<Txid, EscrowContract>,
+    pending_txs: HashMap<Txid, PendingTransaction>,
+    settlements: HashMap<Txid, LiquidationSettlement>,
+}
+
 /// Represents a multisig escrow contract for vault collateral
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EscrowContract {
@@ -220,6 +228,62 @@ impl CustodyManager {
         self
     }
 
+    /// Restore escrow contracts persisted by an earlier run
+    pub fn with_escrow_contracts(mut self, contracts: Vec<EscrowContract>) -> Self {
+        self.escrow_contracts.extend(contracts.into_iter().map(|contract| (contract.vault_id, contract)));
+        self
+    }
+
+    /// Restore settlements from history, oldest first so each vault keeps its latest
+    pub fn with_settlements(mut self, settlements: Vec<LiquidationSettlement>) -> Self {
+        self.settlements.extend(settlements.into_iter().map(|settlement| (settlement.vault_id, settlement)));
+        self
+    }
+
+    /// Restore governance state persisted by an earlier run
+    pub fn with_governance_system(mut self, governance_system: GovernanceSystem) -> Self {
+        self.governance_system = governance_system;
+        self
+    }
+
+    pub fn governance_system(&self) -> &GovernanceSystem {
+        &self.governance_system
+    }
+
+    pub fn governance_system_mut(&mut self) -> &mut GovernanceSystem {
+        &mut self.governance_system
+    }
+
+    pub fn escrow_contracts(&self) -> impl Iterator<Item = &EscrowContract> {
+        self.escrow_contracts.values()
+    }
+
+    pub fn settlements(&self) -> impl Iterator<Item = &LiquidationSettlement> {
+        self.settlements.values()
+    }
+
+    /// Replace the escrow contracts and settlements outright, e.g. with a replicated
+    /// snapshot's; pending transactions are kept
+    pub fn replace_contracts(&mut self, contracts: Vec<EscrowContract>, settlements: Vec<LiquidationSettlement>) {
+        self.escrow_contracts = contracts.into_iter().map(|contract| (contract.vault_id, contract)).collect();
+        self.settlements = settlements.into_iter().map(|settlement| (settlement.vault_id, settlement)).collect();
+    }
+
+    /// Copy of the escrow, settlement and pending transaction state, for undoing a failed change
+    pub fn snapshot(&self) -> CustodySnapshot {
+        CustodySnapshot {
+            escrow_contracts: self.escrow_contracts.clone(),
+            pending_txs: self.pending_txs.clone(),
+            settlements: self.settlements.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: CustodySnapshot) {
+        self.escrow_contracts = snapshot.escrow_contracts;
+        self.pending_txs = snapshot.pending_txs;
+        self.settlements = snapshot.settlements;
+    }
+
     /// Generate the protocol's multisig keys (3-of-5 setup)
     fn generate_protocol_keys(secp: &Secp256k1<bitcoin::secp256k1::All>) -> Result<Vec<PublicKey>> {
         let mut keys = Vec::new();
@@ -241,6 +305,7 @@ impl CustodyManager {
         owner_pubkey: PublicKey,
         collateral_amount: Amount,
         liquidation_price: f64,
+        created_at: DateTime<Utc>,
     ) -> Result<EscrowContract> {
         // Use the Bitcoin client to create a real 2-of-3 multisig escrow
         let (multisig_address, redeem_script) = if let Some(bitcoin_client) = &self.bitcoin_client {
@@ -268,7 +333,7 @@ impl CustodyManager {
             redeem_script,
             funding_txid: Txid::from_raw_hash(bitcoin::hashes::sha256d::Hash::all_zeros()), // Will be set when funded
             funding_vout: 0,
-            created_at: Utc::now(),
+            created_at,
             liquidation_threshold_price: liquidation_price,
             required_sigs: 2,
             protocol_pubkeys: self.protocol_keys[0..2].to_vec(),
@@ -350,13 +415,14 @@ impl CustodyManager {
         Ok(())
     }
 
-    /// Execute liquidation settlement
+    /// Execute liquidation settlement, recorded as settled at `settled_at`
     pub fn execute_liquidation(
         &mut self,
         vault_id: Txid,
         liquidator: PublicKey,
         btc_price: f64,
         debt_amount: f64,
+        settled_at: DateTime<Utc>,
     ) -> Result<Transaction> {
         let contract = self.escrow_contracts.get(&vault_id)
             .ok_or_else(|| BitStableError::InvalidConfig("Escrow contract not found".to_string()))?;
@@ -364,12 +430,10 @@ impl CustodyManager {
         // Calculate liquidation amounts
         let debt_in_btc = debt_amount / btc_price;
         let liquidation_bonus = debt_in_btc * self.config.liquidation_penalty;
-        let total_seized = Amount::from_btc(debt_in_btc + liquidation_bonus)
-            .map_err(|e| BitStableError::InvalidConfig(format!("Invalid amount: {}", e)))?;
+        let total_seized = btc_amount(debt_in_btc + liquidation_bonus)?;
         
         // Protocol fee (1% of liquidated amount)
-        let protocol_fee = Amount::from_btc(debt_in_btc * 0.01)
-            .map_err(|e| BitStableError::InvalidConfig(format!("Invalid amount: {}", e)))?;
+        let protocol_fee = btc_amount(debt_in_btc * 0.01)?;
 
         // Create liquidation transaction
         let liquidation_tx = self.create_liquidation_transaction(
@@ -385,10 +449,9 @@ impl CustodyManager {
             liquidator,
             settlement_txid: liquidation_tx.compute_txid(),
             collateral_seized: total_seized,
-            liquidator_bonus: Amount::from_btc(liquidation_bonus)
-                .map_err(|e| BitStableError::InvalidConfig(format!("Invalid amount: {}", e)))?,
+            liquidator_bonus: btc_amount(liquidation_bonus)?,
             protocol_fee,
-            settled_at: Utc::now(),
+            settled_at,
         };
 
         self.settlements.insert(vault_id, settlement);
@@ -398,7 +461,7 @@ impl CustodyManager {
             tx: liquidation_tx.clone(),
             vault_id,
             tx_type: TransactionType::Liquidation,
-            created_at: Utc::now(),
+            created_at: settled_at,
             broadcast: false,
         };
 
@@ -878,6 +941,7 @@ mod tests {
             owner_key,
             Amount::from_btc(1.0).unwrap(),
             100000.0,
+            Utc::now(),
         ).unwrap();
 
         assert_eq!(contract.vault_id, vault_id);
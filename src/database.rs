@@ -1,66 +1,261 @@
 /// Database persistence layer for BitStable protocol
-/// Uses sled for embedded key-value storage
+/// Typed records over a pluggable storage backend (sled, SQLite or memory)
 
-use sled::{Db, Tree};
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use bitcoin::{Txid, PublicKey, Amount};
-use crate::{BitStableError, Result, Vault};
+use bitcoin::hashes::Hash;
+use crate::{BitStableError, Result, Vault, VaultState};
+use crate::publisher::PublishedUpdate;
+use crate::circuit_breaker::{BreakerEvent, CurrencyBreaker};
+use crate::multi_currency::{Currency, CurrencyConfig, ExchangeRates};
+use crate::candles::{Candle, CandleInterval};
+use crate::address_book::{AddressEntry, BanEntry};
+use crate::audit::{AuditAnchor, AuditEntry};
+use crate::events::EventRecord;
+use crate::backup::{self, BackupManifest, RestoreReport};
+use crate::custody::{EscrowContract, LiquidationSettlement};
+use crate::insurance::InsuranceContribution;
+use crate::redemption::RedemptionRecord;
+use crate::oracle::{ConsensusPrices, OracleState, OracleSubmission, SlashRecord};
+use crate::schema::{self, MigrationMode, MigrationReport, VerifyReport, Versioned};
+use crate::storage::{self, ChangeLogStorage, Entry, KeyRange, SledStorage, Storage, Write, DEFAULT_TREE};
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::Arc;
 use chrono::{DateTime, Utc};
 
 /// Database manager for persistent storage
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DatabaseManager {
-    db: Db,
-    vaults_tree: Tree,
-    liquidations_tree: Tree,
-    settlements_tree: Tree,
-    oracle_prices_tree: Tree,
-    config_tree: Tree,
+    storage: Arc<dyn Storage>,
+}
+
+const OWNER_INDEX_TREE: &str = "vault_owner_index";
+const STATE_INDEX_TREE: &str = "vault_state_index";
+const LIQUIDATION_PRICE_INDEX_TREE: &str = "vault_liquidation_price_index";
+const LIQUIDATIONS_TREE: &str = "liquidations";
+const SETTLEMENTS_TREE: &str = "settlements";
+const ORACLE_PRICES_TREE: &str = "oracle_prices";
+const CONFIG_TREE: &str = "config";
+const PUBLICATIONS_TREE: &str = "oracle_publications";
+const CIRCUIT_BREAKERS_TREE: &str = "circuit_breakers";
+const BREAKER_EVENTS_TREE: &str = "circuit_breaker_events";
+const CONSENSUS_ROUNDS_TREE: &str = "consensus_rounds";
+const ORACLE_SUBMISSIONS_TREE: &str = "oracle_submissions";
+const ORACLE_STATES_TREE: &str = "oracle_states";
+const ORACLE_SLASHES_TREE: &str = "oracle_slashes";
+const CANDLES_TREE: &str = "price_candles";
+const PEER_ADDRESSES_TREE: &str = "peer_addresses";
+const PEER_BANS_TREE: &str = "peer_bans";
+const REDEMPTIONS_TREE: &str = "redemptions";
+const INSURANCE_CONTRIBUTIONS_TREE: &str = "insurance_contributions";
+const ESCROW_CONTRACTS_TREE: &str = "escrow_contracts";
+const AUDIT_LOG_TREE: &str = "audit_log";
+const AUDIT_ANCHORS_TREE: &str = "audit_anchors";
+const EVENTS_TREE: &str = "protocol_events";
+const RAFT_LOG_TREE: &str = "raft_log";
+
+/// Subsystem held whole in memory and persisted as one record in its own tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    Governance,
+    InsuranceFund,
+    StabilityPool,
+    EmergencyShutdown,
+    RedemptionEngine,
+    StableBalances,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 6] = [
+        Subsystem::Governance,
+        Subsystem::InsuranceFund,
+        Subsystem::StabilityPool,
+        Subsystem::EmergencyShutdown,
+        Subsystem::RedemptionEngine,
+        Subsystem::StableBalances,
+    ];
+
+    pub fn tree(self) -> &'static str {
+        match self {
+            Subsystem::Governance => "governance",
+            Subsystem::InsuranceFund => "insurance_fund",
+            Subsystem::StabilityPool => "stability_pool",
+            Subsystem::EmergencyShutdown => "emergency_shutdown",
+            Subsystem::RedemptionEngine => "redemption_engine",
+            Subsystem::StableBalances => "stable_balances",
+        }
+    }
+}
+
+const SUBSYSTEM_KEY: &[u8] = b"state";
+
+#[derive(Debug)]
+struct StagedWrite {
+    tree: &'static str,
+    key: Vec<u8>,
+    unique: bool,   // Append a fresh id at commit so equal keys don't collide
+    value: Vec<u8>,
+}
+
+/// Writes staged across state trees and committed in one storage transaction, so a
+/// crash leaves either all of a state change on disk or none of it
+#[derive(Debug, Default)]
+pub struct UnitOfWork {
+    vaults: Vec<Vault>,   // Expanded into record and index writes at commit
+    writes: Vec<StagedWrite>,
+}
+
+impl UnitOfWork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vaults.is_empty() && self.writes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.vaults.len() + self.writes.len()
+    }
+
+    /// Write a vault to the live vault store, replacing any earlier copy in this unit
+    pub fn put_vault(&mut self, vault: &Vault) -> Result<()> {
+        self.vaults.retain(|staged| staged.id != vault.id);
+        self.vaults.push(vault.clone());
+        Ok(())
+    }
+
+    pub fn put_liquidation(&mut self, liquidation: &LiquidationRecord) -> Result<()> {
+        let key = format!("{}:{}", liquidation.vault_id, liquidation.liquidated_at.timestamp());
+        self.stage(LIQUIDATIONS_TREE, key.into_bytes(), false, schema::encode_record(liquidation)?);
+        Ok(())
+    }
+
+    pub fn put_settlement(&mut self, settlement: &LiquidationSettlement) -> Result<()> {
+        self.stage(SETTLEMENTS_TREE, time_prefix(settlement.settled_at).to_vec(), true, schema::encode_record(settlement)?);
+        Ok(())
+    }
+
+    pub fn put_redemption(&mut self, redemption: &RedemptionRecord) -> Result<()> {
+        self.stage(REDEMPTIONS_TREE, time_prefix(redemption.timestamp).to_vec(), true, schema::encode_record(redemption)?);
+        Ok(())
+    }
+
+    pub fn put_insurance_contribution(&mut self, contribution: &InsuranceContribution) -> Result<()> {
+        self.stage(INSURANCE_CONTRIBUTIONS_TREE, time_prefix(contribution.timestamp).to_vec(), true, schema::encode_record(contribution)?);
+        Ok(())
+    }
+
+    pub fn put_escrow_contract(&mut self, contract: &EscrowContract) -> Result<()> {
+        self.stage(ESCROW_CONTRACTS_TREE, contract.vault_id.to_string().into_bytes(), false, schema::encode_record(contract)?);
+        Ok(())
+    }
+
+    pub fn put_event(&mut self, record: &EventRecord) -> Result<()> {
+        self.stage(EVENTS_TREE, record.sequence.to_be_bytes().to_vec(), false, schema::encode_record(record)?);
+        Ok(())
+    }
+
+    pub fn put_audit_entry(&mut self, entry: &AuditEntry) -> Result<()> {
+        self.stage(AUDIT_LOG_TREE, entry.sequence.to_be_bytes().to_vec(), false, schema::encode_record(entry)?);
+        Ok(())
+    }
+
+    /// Write a config value, as `save_config` does, in this unit
+    pub fn put_config<T: Serialize>(&mut self, key: &str, value: &T) -> Result<()> {
+        let serialized = serde_json::to_vec(value)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize config: {}", e)))?;
+        self.stage(CONFIG_TREE, key.as_bytes().to_vec(), false, serialized);
+        Ok(())
+    }
+
+    /// Replace a subsystem's persisted state
+    pub fn put_subsystem<T: Versioned>(&mut self, subsystem: Subsystem, state: &T) -> Result<()> {
+        self.stage(subsystem.tree(), SUBSYSTEM_KEY.to_vec(), false, schema::encode_record(state)?);
+        Ok(())
+    }
+
+    fn stage(&mut self, tree: &'static str, key: Vec<u8>, unique: bool, value: Vec<u8>) {
+        self.writes.push(StagedWrite { tree, key, unique, value });
+    }
 }
 
 impl DatabaseManager {
-    /// Create a new database manager
+    /// Create a new database manager over a sled database at `path`
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let db = sled::open(path)
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to open database: {}", e)))?;
-        
-        let vaults_tree = db.open_tree("vaults")
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to open vaults tree: {}", e)))?;
-        
-        let liquidations_tree = db.open_tree("liquidations")
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to open liquidations tree: {}", e)))?;
+        Self::with_storage(Arc::new(ChangeLogStorage::new(Arc::new(SledStorage::open(path)?))))
+    }
+
+    /// Open the backend named by a location such as `sqlite://./bitstable.sqlite`
+    pub fn open(location: &str) -> Result<Self> {
+        Self::with_storage(storage::open_storage(location)?)
+    }
+
+    /// Open without running migrations, for inspecting or migrating by hand
+    pub fn open_unmigrated(location: &str) -> Result<Self> {
+        Ok(Self { storage: storage::open_storage(location)? })
+    }
+
+    /// Wrap a store that is already open elsewhere in the process,
+    /// migrating it to the current schema first
+    pub fn with_storage(storage: Arc<dyn Storage>) -> Result<Self> {
+        let manager = Self { storage };
+        manager.migrate(MigrationMode::Apply)?;
+        Ok(manager)
+    }
+
+    /// The underlying store, for subsystems that keep their own trees
+    pub fn storage(&self) -> Arc<dyn Storage> {
+        self.storage.clone()
+    }
+
+    /// Run pending schema migrations, or with `DryRun` report what they would do
+    pub fn migrate(&self, mode: MigrationMode) -> Result<MigrationReport> {
+        schema::migrate(self.storage.as_ref(), mode)
+    }
+
+    /// Check the schema version and that every versioned record decodes
+    pub fn verify(&self) -> Result<VerifyReport> {
+        schema::verify(self.storage.as_ref())
+    }
+
+    /// Commit every staged write atomically, then flush
+    pub fn commit(&self, work: UnitOfWork) -> Result<()> {
+        if work.is_empty() {
+            return Ok(());
+        }
         
-        let settlements_tree = db.open_tree("settlements")
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to open settlements tree: {}", e)))?;
+        let mut writes = Vec::with_capacity(work.len());
+        for vault in &work.vaults {
+            writes.extend(self.vault_writes(vault)?);
+        }
         
-        let oracle_prices_tree = db.open_tree("oracle_prices")
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to open oracle prices tree: {}", e)))?;
+        // Ids are allocated up front so the batch is fixed before it is applied
+        for write in work.writes {
+            let mut key = write.key;
+            if write.unique {
+                let id = self.storage.generate_id()
+                    .map_err(|e| BitStableError::InvalidConfig(format!("Failed to allocate record id: {}", e)))?;
+                key.extend_from_slice(&id.to_be_bytes());
+            }
+            writes.push(Write::insert(write.tree, key, write.value));
+        }
         
-        let config_tree = db.open_tree("config")
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to open config tree: {}", e)))?;
+        self.storage.apply(&writes)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to commit unit of work: {}", e)))?;
         
-        Ok(Self {
-            db,
-            vaults_tree,
-            liquidations_tree,
-            settlements_tree,
-            oracle_prices_tree,
-            config_tree,
-        })
+        self.flush()
     }
 
-    /// Save a vault to the database
+    /// Save a vault to the live vault store, updating its indexes in the same transaction
     pub fn save_vault(&self, vault: &Vault) -> Result<()> {
-        let key = vault.id.to_string();
-        let value = serde_json::to_vec(vault)
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize vault: {}", e)))?;
+        let writes = self.vault_writes(vault)?;
         
-        self.vaults_tree.insert(key, value)
+        self.storage.apply(&writes)
             .map_err(|e| BitStableError::InvalidConfig(format!("Failed to save vault: {}", e)))?;
         
-        self.db.flush()
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to flush database: {}", e)))?;
+        self.flush()?;
         
         log::debug!("Saved vault {} to database", vault.id);
         Ok(())
@@ -68,101 +263,273 @@ impl DatabaseManager {
 
     /// Load a vault from the database
     pub fn load_vault(&self, vault_id: Txid) -> Result<Vault> {
+        self.get_vault(vault_id)?
+            .ok_or(BitStableError::VaultNotFound(vault_id))
+    }
+
+    pub fn get_vault(&self, vault_id: Txid) -> Result<Option<Vault>> {
         let key = vault_id.to_string();
         
-        let value = self.vaults_tree.get(key)
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to read vault: {}", e)))?
-            .ok_or_else(|| BitStableError::VaultNotFound(vault_id))?;
-        
-        let vault: Vault = serde_json::from_slice(&value)
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to deserialize vault: {}", e)))?;
+        let value = self.storage.get(DEFAULT_TREE, key.as_bytes())
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to read vault: {}", e)))?;
         
-        Ok(vault)
+        value.map(|value| schema::decode_record(&value)).transpose()
     }
 
     /// List all vaults
     pub fn list_vaults(&self) -> Result<Vec<Vault>> {
-        let mut vaults = Vec::new();
-        
-        for item in self.vaults_tree.iter() {
-            let (_, value) = item
-                .map_err(|e| BitStableError::InvalidConfig(format!("Failed to iterate vaults: {}", e)))?;
-            
-            let vault: Vault = serde_json::from_slice(&value)
-                .map_err(|e| BitStableError::InvalidConfig(format!("Failed to deserialize vault: {}", e)))?;
-            
-            vaults.push(vault);
-        }
-        
-        Ok(vaults)
+        self.scan(DEFAULT_TREE, &KeyRange::all(), false, None, "vaults")?
+            .iter()
+            .map(|(_, value)| schema::decode_record(value))
+            .collect()
     }
 
-    /// Delete a vault
+    /// Delete a vault and its index entries
     pub fn delete_vault(&self, vault_id: Txid) -> Result<()> {
-        let key = vault_id.to_string();
+        let mut writes = match self.get_vault(vault_id)? {
+            Some(old) => vault_index_writes(Some(&old), None),
+            None => Vec::new(),
+        };
+        writes.push(Write::remove(DEFAULT_TREE, vault_id.to_string()));
         
-        self.vaults_tree.remove(key)
+        self.storage.apply(&writes)
             .map_err(|e| BitStableError::InvalidConfig(format!("Failed to delete vault: {}", e)))?;
         
-        self.db.flush()
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to flush database: {}", e)))?;
+        self.flush()?;
         
         log::debug!("Deleted vault {} from database", vault_id);
         Ok(())
     }
 
+    /// Replace every live vault and rebuild the indexes, in one transaction
+    pub fn replace_vaults(&self, vaults: &[Vault]) -> Result<()> {
+        let mut writes = Vec::new();
+        for tree in [DEFAULT_TREE, OWNER_INDEX_TREE, STATE_INDEX_TREE, LIQUIDATION_PRICE_INDEX_TREE] {
+            for (key, _) in self.scan(tree, &KeyRange::all(), false, None, "vaults")? {
+                writes.push(Write::remove(tree, key));
+            }
+        }
+        for vault in vaults {
+            writes.extend(vault_index_writes(None, Some(vault)));
+            writes.push(Write::insert(DEFAULT_TREE, vault.id.to_string(), schema::encode_record(vault)?));
+        }
+        
+        self.storage.apply(&writes)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to replace vaults: {}", e)))?;
+        
+        self.flush()
+    }
+
+    /// Vaults owned by `owner`, via the owner index
+    pub fn get_vaults_by_owner(&self, owner: &PublicKey) -> Result<Vec<Vault>> {
+        self.load_indexed(OWNER_INDEX_TREE, owner.to_bytes())
+    }
+
+    /// Vaults in `state`, via the state index
+    pub fn get_vaults_by_state(&self, state: &VaultState) -> Result<Vec<Vault>> {
+        self.load_indexed(STATE_INDEX_TREE, state_prefix(state))
+    }
+
+    /// Active vaults with any debt currency's collateral ratio below that currency's
+    /// liquidation threshold, priced in its liquidation price mode. Each currency reads
+    /// only the index buckets at or above its cut-off price.
+    pub fn get_liquidatable_vaults(
+        &self,
+        exchange_rates: &ExchangeRates,
+        currency_configs: &HashMap<Currency, CurrencyConfig>,
+    ) -> Result<Vec<Vault>> {
+        let mut vaults: HashMap<Txid, Vault> = HashMap::new();
+        for (currency, config) in currency_configs {
+            let btc_price = exchange_rates.price_for_mode(currency, config.liquidation_price_mode);
+            if btc_price <= 0.0 {
+                continue;
+            }
+            for vault in self.liquidatable_in(currency, btc_price, config.liquidation_threshold)? {
+                vaults.insert(vault.id, vault);
+            }
+        }
+        Ok(vaults.into_values().collect())
+    }
+
+    /// Active vaults whose `currency` collateral ratio is below `liquidation_threshold` at `btc_price`
+    fn liquidatable_in(&self, currency: &Currency, btc_price: f64, liquidation_threshold: f64) -> Result<Vec<Vault>> {
+        // ratio < threshold  <=>  debt / collateral > btc_price / threshold
+        let cutoff = btc_price / liquidation_threshold;
+        let mut start = currency_prefix(currency);
+        start.extend_from_slice(&price_bucket(cutoff).to_be_bytes());
+        let range = KeyRange { start: Some(start), end: KeyRange::prefix(currency_prefix(currency)).end };
+        
+        let vaults = self.load_vault_ids(self.scan(LIQUIDATION_PRICE_INDEX_TREE, &range, false, None, "liquidation price index")?)?;
+        Ok(vaults
+            .into_iter()
+            .filter(|vault| vault.state == VaultState::Active)
+            .filter(|vault| vault.collateral_btc.to_btc() * btc_price < vault.debts.get_debt(currency) * liquidation_threshold)
+            .collect())
+    }
+
+    /// The live record plus index changes for writing `vault` over its stored copy
+    fn vault_writes(&self, vault: &Vault) -> Result<Vec<Write>> {
+        let old = self.get_vault(vault.id)?;
+        let mut writes = vault_index_writes(old.as_ref(), Some(vault));
+        writes.push(Write::insert(DEFAULT_TREE, vault.id.to_string(), schema::encode_record(vault)?));
+        Ok(writes)
+    }
+
+    fn load_indexed(&self, tree: &str, prefix: Vec<u8>) -> Result<Vec<Vault>> {
+        self.load_vault_ids(self.scan(tree, &KeyRange::prefix(prefix), false, None, "vault index")?)
+    }
+
+    /// Index keys end with the vault id
+    fn load_vault_ids(&self, entries: Vec<Entry>) -> Result<Vec<Vault>> {
+        let mut vaults = Vec::new();
+        for (key, _) in entries {
+            let Some(id) = key.len().checked_sub(32).map(|start| &key[start..]) else {
+                continue;
+            };
+            let vault_id = Txid::from_slice(id)
+                .map_err(|e| BitStableError::InvalidConfig(format!("Corrupt vault index key: {}", e)))?;
+            // An index entry without its vault is skipped rather than failing the query
+            if let Some(vault) = self.get_vault(vault_id)? {
+                vaults.push(vault);
+            }
+        }
+        Ok(vaults)
+    }
+
+    /// A subsystem's state as of its last commit, if it was ever saved
+    pub fn load_subsystem<T: Versioned>(&self, subsystem: Subsystem) -> Result<Option<T>> {
+        let value = self.storage.get(subsystem.tree(), SUBSYSTEM_KEY)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to read {} state: {}", subsystem.tree(), e)))?;
+        
+        value.map(|value| schema::decode_record(&value)).transpose()
+    }
+
     /// Save liquidation record
     pub fn save_liquidation(&self, liquidation: &LiquidationRecord) -> Result<()> {
         let key = format!("{}:{}", liquidation.vault_id, liquidation.liquidated_at.timestamp());
-        let value = serde_json::to_vec(liquidation)
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize liquidation: {}", e)))?;
+        let value = schema::encode_record(liquidation)?;
         
-        self.liquidations_tree.insert(key, value)
+        self.storage.insert(LIQUIDATIONS_TREE, key.as_bytes(), &value)
             .map_err(|e| BitStableError::InvalidConfig(format!("Failed to save liquidation: {}", e)))?;
         
-        self.db.flush()
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to flush database: {}", e)))?;
-        
-        Ok(())
+        self.flush()
     }
 
     /// Get liquidation history
     pub fn get_liquidation_history(&self, limit: Option<usize>) -> Result<Vec<LiquidationRecord>> {
-        let mut liquidations = Vec::new();
-        
-        for item in self.liquidations_tree.iter().rev() {
-            let (_, value) = item
-                .map_err(|e| BitStableError::InvalidConfig(format!("Failed to iterate liquidations: {}", e)))?;
-            
-            let liquidation: LiquidationRecord = serde_json::from_slice(&value)
-                .map_err(|e| BitStableError::InvalidConfig(format!("Failed to deserialize liquidation: {}", e)))?;
-            
-            liquidations.push(liquidation);
-            
-            if let Some(limit) = limit {
-                if liquidations.len() >= limit {
-                    break;
-                }
-            }
-        }
-        
-        Ok(liquidations)
+        self.scan(LIQUIDATIONS_TREE, &KeyRange::all(), true, limit, "liquidations")?
+            .iter()
+            .map(|(_, value)| schema::decode_record(value))
+            .collect()
+    }
+
+    /// Liquidation settlements, newest first
+    pub fn get_settlement_history(&self, limit: usize) -> Result<Vec<LiquidationSettlement>> {
+        self.scan(SETTLEMENTS_TREE, &KeyRange::all(), true, Some(limit), "settlements")?
+            .iter()
+            .map(|(_, value)| schema::decode_record(value))
+            .collect()
+    }
+
+    /// Redemptions, newest first
+    pub fn get_redemption_history(&self, limit: usize) -> Result<Vec<RedemptionRecord>> {
+        self.scan(REDEMPTIONS_TREE, &KeyRange::all(), true, Some(limit), "redemptions")?
+            .iter()
+            .map(|(_, value)| schema::decode_record(value))
+            .collect()
+    }
+
+    /// Insurance fund contributions, newest first
+    pub fn get_insurance_contributions(&self, limit: usize) -> Result<Vec<InsuranceContribution>> {
+        self.scan(INSURANCE_CONTRIBUTIONS_TREE, &KeyRange::all(), true, Some(limit), "insurance contributions")?
+            .iter()
+            .map(|(_, value)| schema::decode_record(value))
+            .collect()
+    }
+
+    /// Redemptions with `from <= timestamp < to`, oldest first
+    pub fn get_redemptions_between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<RedemptionRecord>> {
+        let range = KeyRange::between(time_prefix(from), time_prefix(to));
+        self.scan(REDEMPTIONS_TREE, &range, false, None, "redemptions")?
+            .iter()
+            .map(|(_, value)| schema::decode_record(value))
+            .collect()
+    }
+
+    /// Insurance fund contributions with `from <= timestamp < to`, oldest first
+    pub fn get_insurance_contributions_between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<InsuranceContribution>> {
+        let range = KeyRange::between(time_prefix(from), time_prefix(to));
+        self.scan(INSURANCE_CONTRIBUTIONS_TREE, &range, false, None, "insurance contributions")?
+            .iter()
+            .map(|(_, value)| schema::decode_record(value))
+            .collect()
+    }
+
+    pub fn load_escrow_contracts(&self) -> Result<Vec<EscrowContract>> {
+        self.scan(ESCROW_CONTRACTS_TREE, &KeyRange::all(), false, None, "escrow contracts")?
+            .iter()
+            .map(|(_, value)| schema::decode_record(value))
+            .collect()
+    }
+
+    /// Protocol events from `from_sequence` on, in sequence order
+    pub fn get_events(&self, from_sequence: u64, limit: Option<usize>) -> Result<Vec<EventRecord>> {
+        self.scan(EVENTS_TREE, &KeyRange::starting_at(from_sequence.to_be_bytes()), false, limit, "protocol events")?
+            .iter()
+            .map(|(_, value)| schema::decode_record(value))
+            .collect()
+    }
+
+    pub fn last_event(&self) -> Result<Option<EventRecord>> {
+        self.scan(EVENTS_TREE, &KeyRange::all(), true, Some(1), "protocol events")?
+            .first()
+            .map(|(_, value)| schema::decode_record(value))
+            .transpose()
+    }
+
+    /// The whole audit log, in sequence order
+    pub fn get_audit_entries(&self) -> Result<Vec<AuditEntry>> {
+        self.scan(AUDIT_LOG_TREE, &KeyRange::all(), false, None, "audit log")?
+            .iter()
+            .map(|(_, value)| schema::decode_record(value))
+            .collect()
+    }
+
+    pub fn last_audit_entry(&self) -> Result<Option<AuditEntry>> {
+        self.scan(AUDIT_LOG_TREE, &KeyRange::all(), true, Some(1), "audit log")?
+            .first()
+            .map(|(_, value)| schema::decode_record(value))
+            .transpose()
+    }
+
+    /// Save an on-chain anchor of the audit log, keyed by the sequence it covers
+    pub fn save_audit_anchor(&self, anchor: &AuditAnchor) -> Result<()> {
+        let value = schema::encode_record(anchor)?;
+        self.storage.insert(AUDIT_ANCHORS_TREE, &anchor.head.sequence.to_be_bytes(), &value)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to save audit anchor: {}", e)))?;
+        self.flush()
+    }
+
+    pub fn get_audit_anchors(&self) -> Result<Vec<AuditAnchor>> {
+        self.scan(AUDIT_ANCHORS_TREE, &KeyRange::all(), false, None, "audit anchors")?
+            .iter()
+            .map(|(_, value)| schema::decode_record(value))
+            .collect()
     }
 
     /// Save oracle price data
     pub fn save_oracle_price(&self, price_data: &OraclePriceRecord) -> Result<()> {
         let key = format!("{}", price_data.timestamp.timestamp());
-        let value = serde_json::to_vec(price_data)
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize price data: {}", e)))?;
+        let value = schema::encode_record(price_data)?;
         
-        self.oracle_prices_tree.insert(key, value)
+        self.storage.insert(ORACLE_PRICES_TREE, key.as_bytes(), &value)
             .map_err(|e| BitStableError::InvalidConfig(format!("Failed to save price data: {}", e)))?;
         
         // Keep only last 10000 price records
-        let count = self.oracle_prices_tree.len();
+        let count = self.storage.len(ORACLE_PRICES_TREE)?;
         if count > 10000 {
-            if let Some(Ok((key, _))) = self.oracle_prices_tree.iter().next() {
-                self.oracle_prices_tree.remove(key)
+            if let Some((key, _)) = self.scan(ORACLE_PRICES_TREE, &KeyRange::all(), false, Some(1), "prices")?.first() {
+                self.storage.remove(ORACLE_PRICES_TREE, key)
                     .map_err(|e| BitStableError::InvalidConfig(format!("Failed to remove old price: {}", e)))?;
             }
         }
@@ -172,20 +539,356 @@ impl DatabaseManager {
 
     /// Get oracle price history
     pub fn get_price_history(&self, limit: usize) -> Result<Vec<OraclePriceRecord>> {
-        let mut prices = Vec::new();
+        let mut prices = self.scan(ORACLE_PRICES_TREE, &KeyRange::all(), true, Some(limit), "prices")?
+            .iter()
+            .map(|(_, value)| schema::decode_record(value))
+            .collect::<Result<Vec<OraclePriceRecord>>>()?;
         
-        for item in self.oracle_prices_tree.iter().rev().take(limit) {
-            let (_, value) = item
-                .map_err(|e| BitStableError::InvalidConfig(format!("Failed to iterate prices: {}", e)))?;
-            
-            let price: OraclePriceRecord = serde_json::from_slice(&value)
-                .map_err(|e| BitStableError::InvalidConfig(format!("Failed to deserialize price: {}", e)))?;
+        prices.reverse(); // Return in chronological order
+        Ok(prices)
+    }
+
+    /// Save a published oracle update, keyed by sequence number
+    pub fn save_publication(&self, update: &PublishedUpdate) -> Result<()> {
+        let value = serde_json::to_vec(update)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize publication: {}", e)))?;
+        
+        self.storage.insert(PUBLICATIONS_TREE, &update.sequence.to_be_bytes(), &value)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to save publication: {}", e)))?;
+        
+        self.flush()
+    }
+
+    /// Get the most recently published oracle update
+    pub fn get_latest_publication(&self) -> Result<Option<PublishedUpdate>> {
+        Ok(self.get_publication_history(1)?.pop())
+    }
+
+    /// Get published oracle updates in chronological order
+    pub fn get_publication_history(&self, limit: usize) -> Result<Vec<PublishedUpdate>> {
+        let mut updates: Vec<PublishedUpdate> = Self::decode_all(
+            self.scan(PUBLICATIONS_TREE, &KeyRange::all(), true, Some(limit), "publications")?,
+            "publication",
+        )?;
+        
+        updates.reverse();
+        Ok(updates)
+    }
+
+    /// Save the breaker state for one currency
+    pub fn save_circuit_breaker(&self, breaker: &CurrencyBreaker) -> Result<()> {
+        let value = serde_json::to_vec(breaker)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize circuit breaker: {}", e)))?;
+        
+        self.storage.insert(CIRCUIT_BREAKERS_TREE, breaker.currency.to_string().as_bytes(), &value)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to save circuit breaker: {}", e)))?;
+        
+        self.flush()
+    }
+
+    /// Load every persisted circuit breaker
+    pub fn load_circuit_breakers(&self) -> Result<Vec<CurrencyBreaker>> {
+        Self::decode_all(self.scan(CIRCUIT_BREAKERS_TREE, &KeyRange::all(), false, None, "circuit breakers")?, "circuit breaker")
+    }
+
+    /// Append a circuit breaker state change
+    pub fn save_circuit_breaker_event(&self, event: &BreakerEvent) -> Result<()> {
+        let id = self.storage.generate_id()
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to allocate event id: {}", e)))?;
+        let value = serde_json::to_vec(event)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize circuit breaker event: {}", e)))?;
+        
+        self.storage.insert(BREAKER_EVENTS_TREE, &id.to_be_bytes(), &value)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to save circuit breaker event: {}", e)))?;
+        
+        Ok(())
+    }
+
+    /// Get circuit breaker state changes in chronological order
+    pub fn get_circuit_breaker_events(&self, limit: usize) -> Result<Vec<BreakerEvent>> {
+        let mut events: Vec<BreakerEvent> = Self::decode_all(
+            self.scan(BREAKER_EVENTS_TREE, &KeyRange::all(), true, Some(limit), "circuit breaker events")?,
+            "circuit breaker event",
+        )?;
+        
+        events.reverse();
+        Ok(events)
+    }
+
+    /// Save a multi-currency consensus round, keyed by time
+    pub fn save_consensus_round(&self, round: &ConsensusPrices) -> Result<()> {
+        let key = self.time_key(round.timestamp)?;
+        let value = serde_json::to_vec(round)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize consensus round: {}", e)))?;
+        
+        self.storage.insert(CONSENSUS_ROUNDS_TREE, &key, &value)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to save consensus round: {}", e)))?;
+        
+        Ok(())
+    }
+
+    /// Most recent consensus rounds in chronological order
+    pub fn get_recent_consensus_rounds(&self, limit: usize) -> Result<Vec<ConsensusPrices>> {
+        let mut rounds: Vec<ConsensusPrices> = Self::decode_all(
+            self.scan(CONSENSUS_ROUNDS_TREE, &KeyRange::all(), true, Some(limit), "consensus rounds")?,
+            "consensus round",
+        )?;
+        rounds.reverse();
+        Ok(rounds)
+    }
+
+    /// Consensus rounds with `from <= timestamp < to`
+    pub fn get_consensus_rounds(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<ConsensusPrices>> {
+        let range = KeyRange::between(time_prefix(from), time_prefix(to));
+        Self::decode_all(self.scan(CONSENSUS_ROUNDS_TREE, &range, false, None, "consensus rounds")?, "consensus round")
+    }
+
+    /// Consensus BTC price in one currency over a time range
+    pub fn get_consensus_price_range(
+        &self,
+        currency: &Currency,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<(DateTime<Utc>, f64)>> {
+        Ok(self.get_consensus_rounds(from, to)?
+            .into_iter()
+            .filter_map(|round| round.btc_prices.get(currency).map(|price| (round.timestamp, *price)))
+            .collect())
+    }
+
+    /// Save one oracle's price for a currency, keyed by currency then time
+    pub fn save_oracle_submission(&self, submission: &OracleSubmission) -> Result<()> {
+        let mut key = currency_prefix(&submission.currency);
+        key.extend_from_slice(&self.time_key(submission.timestamp)?);
+        let value = serde_json::to_vec(submission)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize oracle submission: {}", e)))?;
+        
+        self.storage.insert(ORACLE_SUBMISSIONS_TREE, &key, &value)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to save oracle submission: {}", e)))?;
+        
+        Ok(())
+    }
+
+    /// Oracle submissions for a currency with `from <= timestamp < to`
+    pub fn get_oracle_submissions(
+        &self,
+        currency: &Currency,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<OracleSubmission>> {
+        let mut start = currency_prefix(currency);
+        start.extend_from_slice(&time_prefix(from));
+        let mut end = currency_prefix(currency);
+        end.extend_from_slice(&time_prefix(to));
+        
+        let range = KeyRange::between(start, end);
+        Self::decode_all(self.scan(ORACLE_SUBMISSIONS_TREE, &range, false, None, "oracle submissions")?, "oracle submission")
+    }
+
+    /// Save an oracle's metrics and bond state
+    pub fn save_oracle_state(&self, state: &OracleState) -> Result<()> {
+        let value = serde_json::to_vec(state)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize oracle state: {}", e)))?;
+        
+        self.storage.insert(ORACLE_STATES_TREE, state.name.as_bytes(), &value)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to save oracle state: {}", e)))?;
+        
+        self.flush()
+    }
+
+    /// Load every persisted oracle state
+    pub fn load_oracle_states(&self) -> Result<Vec<OracleState>> {
+        Self::decode_all(self.scan(ORACLE_STATES_TREE, &KeyRange::all(), false, None, "oracle states")?, "oracle state")
+    }
+
+    /// Append a bond slash
+    pub fn save_slash_record(&self, record: &SlashRecord) -> Result<()> {
+        let key = self.time_key(record.timestamp)?;
+        let value = serde_json::to_vec(record)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize slash record: {}", e)))?;
+        
+        self.storage.insert(ORACLE_SLASHES_TREE, &key, &value)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to save slash record: {}", e)))?;
+        
+        self.flush()
+    }
+
+    /// Slashes with `from <= timestamp < to`, optionally for a single oracle
+    pub fn get_slash_history(
+        &self,
+        oracle_name: Option<&str>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<SlashRecord>> {
+        let range = KeyRange::between(time_prefix(from), time_prefix(to));
+        let records: Vec<SlashRecord> = Self::decode_all(self.scan(ORACLE_SLASHES_TREE, &range, false, None, "slash records")?, "slash record")?;
+        
+        Ok(records
+            .into_iter()
+            .filter(|record| oracle_name.is_none_or(|name| record.oracle_name == name))
+            .collect())
+    }
+
+    /// Insert or replace the candle for its bucket
+    pub fn save_candle(&self, candle: &Candle) -> Result<()> {
+        let mut key = candle_prefix(candle.interval, &candle.currency);
+        key.extend_from_slice(&time_prefix(candle.open_time));
+        let value = serde_json::to_vec(candle)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize candle: {}", e)))?;
+        
+        self.storage.insert(CANDLES_TREE, &key, &value)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to save candle: {}", e)))?;
+        
+        Ok(())
+    }
+
+    /// Candle opening at exactly `open_time`, if any
+    pub fn get_candle(&self, currency: &Currency, interval: CandleInterval, open_time: DateTime<Utc>) -> Result<Option<Candle>> {
+        let mut key = candle_prefix(interval, currency);
+        key.extend_from_slice(&time_prefix(open_time));
+        
+        match self.storage.get(CANDLES_TREE, &key) {
+            Ok(Some(value)) => {
+                let candle = serde_json::from_slice(&value)
+                    .map_err(|e| BitStableError::InvalidConfig(format!("Failed to deserialize candle: {}", e)))?;
+                Ok(Some(candle))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(BitStableError::InvalidConfig(format!("Failed to read candle: {}", e))),
+        }
+    }
+
+    /// Candles opening within `from <= open_time < to`, oldest first
+    pub fn get_candles(
+        &self,
+        currency: &Currency,
+        interval: CandleInterval,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Candle>> {
+        let mut start = candle_prefix(interval, currency);
+        start.extend_from_slice(&time_prefix(from));
+        let mut end = candle_prefix(interval, currency);
+        end.extend_from_slice(&time_prefix(to));
+        
+        Self::decode_all(self.scan(CANDLES_TREE, &KeyRange::between(start, end), false, None, "candles")?, "candle")
+    }
+
+    /// Drop candles of one interval that opened before `before`
+    pub fn prune_candles(&self, interval: CandleInterval, before: DateTime<Utc>) -> Result<usize> {
+        let mut prefix = interval.as_str().as_bytes().to_vec();
+        prefix.push(b'/');
+        
+        // Candle keys end with the open time
+        let candles = self.scan(CANDLES_TREE, &KeyRange::prefix(prefix), false, None, "candles")?;
+        self.prune_where(CANDLES_TREE, candles, before, 8)
+    }
+
+    /// Drop consensus rounds and oracle submissions older than `before`
+    pub fn prune_raw_prices(&self, before: DateTime<Utc>) -> Result<usize> {
+        let mut removed = 0;
+        
+        for (key, _) in self.scan(CONSENSUS_ROUNDS_TREE, &KeyRange::before(time_prefix(before)), false, None, "consensus rounds")? {
+            self.storage.remove(CONSENSUS_ROUNDS_TREE, &key)
+                .map_err(|e| BitStableError::InvalidConfig(format!("Failed to prune consensus round: {}", e)))?;
+            removed += 1;
+        }
+        
+        // Submission keys end with the time followed by an 8 byte id
+        let submissions = self.scan(ORACLE_SUBMISSIONS_TREE, &KeyRange::all(), false, None, "oracle submissions")?;
+        removed += self.prune_where(ORACLE_SUBMISSIONS_TREE, submissions, before, 16)?;
+        Ok(removed)
+    }
+
+    fn prune_where(&self, tree: &str, entries: Vec<Entry>, before: DateTime<Utc>, time_offset_from_end: usize) -> Result<usize> {
+        let cutoff = time_prefix(before);
+        let mut removed = 0;
+        
+        for (key, _) in entries {
+            if key.len() < time_offset_from_end {
+                continue;
+            }
             
-            prices.push(price);
+            let start = key.len() - time_offset_from_end;
+            if key[start..start + 8] < cutoff[..] {
+                self.storage.remove(tree, &key)
+                    .map_err(|e| BitStableError::InvalidConfig(format!("Failed to prune record: {}", e)))?;
+                removed += 1;
+            }
         }
         
-        prices.reverse(); // Return in chronological order
-        Ok(prices)
+        Ok(removed)
+    }
+
+    /// Save or replace a peer's address book entry
+    pub fn save_peer_address(&self, entry: &AddressEntry) -> Result<()> {
+        let value = serde_json::to_vec(entry)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize peer address: {}", e)))?;
+        
+        self.storage.insert(PEER_ADDRESSES_TREE, &entry.pubkey.to_bytes(), &value)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to save peer address: {}", e)))?;
+        
+        Ok(())
+    }
+
+    pub fn remove_peer_address(&self, pubkey: &PublicKey) -> Result<()> {
+        self.storage.remove(PEER_ADDRESSES_TREE, &pubkey.to_bytes())
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to remove peer address: {}", e)))?;
+        
+        Ok(())
+    }
+
+    pub fn load_peer_addresses(&self) -> Result<Vec<AddressEntry>> {
+        Self::decode_all(self.scan(PEER_ADDRESSES_TREE, &KeyRange::all(), false, None, "peer addresses")?, "peer address")
+    }
+
+    /// Save or replace a peer ban
+    pub fn save_peer_ban(&self, ban: &BanEntry) -> Result<()> {
+        let value = serde_json::to_vec(ban)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize peer ban: {}", e)))?;
+        
+        self.storage.insert(PEER_BANS_TREE, &ban.pubkey.to_bytes(), &value)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to save peer ban: {}", e)))?;
+        
+        self.flush()
+    }
+
+    pub fn remove_peer_ban(&self, pubkey: &PublicKey) -> Result<()> {
+        self.storage.remove(PEER_BANS_TREE, &pubkey.to_bytes())
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to remove peer ban: {}", e)))?;
+        
+        self.flush()
+    }
+
+    pub fn load_peer_bans(&self) -> Result<Vec<BanEntry>> {
+        Self::decode_all(self.scan(PEER_BANS_TREE, &KeyRange::all(), false, None, "peer bans")?, "peer ban")
+    }
+
+    /// Time-ordered key with a unique suffix so equal timestamps don't collide
+    fn time_key(&self, timestamp: DateTime<Utc>) -> Result<Vec<u8>> {
+        let id = self.storage.generate_id()
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to allocate record id: {}", e)))?;
+        
+        let mut key = time_prefix(timestamp).to_vec();
+        key.extend_from_slice(&id.to_be_bytes());
+        Ok(key)
+    }
+
+    fn scan(&self, tree: &str, range: &KeyRange, reverse: bool, limit: Option<usize>, what: &str) -> Result<Vec<Entry>> {
+        self.storage.scan(tree, range, reverse, limit)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to iterate {}: {}", what, e)))
+    }
+
+    fn decode_all<T: DeserializeOwned>(entries: Vec<Entry>, what: &str) -> Result<Vec<T>> {
+        entries.iter()
+            .map(|(_, value)| serde_json::from_slice(value)
+                .map_err(|e| BitStableError::InvalidConfig(format!("Failed to deserialize {}: {}", what, e))))
+            .collect()
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.storage.flush()
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to flush database: {}", e)))
     }
 
     /// Save configuration value
@@ -193,18 +896,15 @@ impl DatabaseManager {
         let serialized = serde_json::to_vec(value)
             .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize config: {}", e)))?;
         
-        self.config_tree.insert(key, serialized)
+        self.storage.insert(CONFIG_TREE, key.as_bytes(), &serialized)
             .map_err(|e| BitStableError::InvalidConfig(format!("Failed to save config: {}", e)))?;
         
-        self.db.flush()
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to flush database: {}", e)))?;
-        
-        Ok(())
+        self.flush()
     }
 
     /// Load configuration value
     pub fn load_config<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
-        match self.config_tree.get(key) {
+        match self.storage.get(CONFIG_TREE, key.as_bytes()) {
             Ok(Some(value)) => {
                 let deserialized = serde_json::from_slice(&value)
                     .map_err(|e| BitStableError::InvalidConfig(format!("Failed to deserialize config: {}", e)))?;
@@ -215,87 +915,162 @@ impl DatabaseManager {
         }
     }
 
+    /// Persist a step of Raft state in one transaction: `meta` replaces the config value
+    /// under `meta_key` if given, `entries` are written under their log indexes and the
+    /// `removed` indexes are dropped
+    pub fn save_raft_log<M: Serialize, E: Serialize>(
+        &self,
+        meta_key: &str,
+        meta: Option<&M>,
+        entries: &[(u64, &E)],
+        removed: &[u64],
+    ) -> Result<()> {
+        let mut writes = Vec::with_capacity(entries.len() + removed.len() + 1);
+        if let Some(meta) = meta {
+            let value = serde_json::to_vec(meta)
+                .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize raft state: {}", e)))?;
+            writes.push(Write::insert(CONFIG_TREE, meta_key, value));
+        }
+        for index in removed {
+            writes.push(Write::remove(RAFT_LOG_TREE, index.to_be_bytes().to_vec()));
+        }
+        for (index, entry) in entries {
+            let value = serde_json::to_vec(entry)
+                .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize raft entry: {}", e)))?;
+            writes.push(Write::insert(RAFT_LOG_TREE, index.to_be_bytes().to_vec(), value));
+        }
+        if writes.is_empty() {
+            return Ok(());
+        }
+        
+        self.storage.apply(&writes)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to save raft log: {}", e)))?;
+        
+        self.flush()
+    }
+
+    /// Stored Raft log entries, in index order
+    pub fn load_raft_log<E: DeserializeOwned>(&self) -> Result<Vec<E>> {
+        Self::decode_all(self.scan(RAFT_LOG_TREE, &KeyRange::all(), false, None, "raft log")?, "raft entry")
+    }
+
     /// Get database statistics
     pub fn get_stats(&self) -> DatabaseStats {
+        let count = |tree| self.storage.len(tree).unwrap_or(0);
         DatabaseStats {
-            total_vaults: self.vaults_tree.len(),
-            total_liquidations: self.liquidations_tree.len(),
-            total_settlements: self.settlements_tree.len(),
-            total_price_records: self.oracle_prices_tree.len(),
-            database_size_bytes: self.db.size_on_disk().unwrap_or(0),
+            total_vaults: count(DEFAULT_TREE),
+            total_liquidations: count(LIQUIDATIONS_TREE),
+            total_settlements: count(SETTLEMENTS_TREE),
+            total_price_records: count(ORACLE_PRICES_TREE),
+            database_size_bytes: self.storage.size_on_disk(),
         }
     }
 
     /// Clear all data (use with caution!)
     pub fn clear_all(&self) -> Result<()> {
-        self.vaults_tree.clear()
+        self.replace_vaults(&[])
             .map_err(|e| BitStableError::InvalidConfig(format!("Failed to clear vaults: {}", e)))?;
         
-        self.liquidations_tree.clear()
+        self.storage.clear(LIQUIDATIONS_TREE)
             .map_err(|e| BitStableError::InvalidConfig(format!("Failed to clear liquidations: {}", e)))?;
         
-        self.settlements_tree.clear()
+        self.storage.clear(SETTLEMENTS_TREE)
             .map_err(|e| BitStableError::InvalidConfig(format!("Failed to clear settlements: {}", e)))?;
         
-        self.oracle_prices_tree.clear()
+        self.storage.clear(ORACLE_PRICES_TREE)
             .map_err(|e| BitStableError::InvalidConfig(format!("Failed to clear prices: {}", e)))?;
         
-        self.db.flush()
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to flush database: {}", e)))?;
+        self.flush()?;
         
         log::warn!("Cleared all database data");
         Ok(())
     }
 
-    /// Backup database to a file
-    pub fn backup<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        // Export all data to JSON
-        let backup_data = DatabaseBackup {
-            timestamp: Utc::now(),
-            vaults: self.list_vaults()?,
-            liquidations: self.get_liquidation_history(None)?,
-            prices: self.get_price_history(1000)?,
-        };
-        
-        let json = serde_json::to_string_pretty(&backup_data)
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize backup: {}", e)))?;
-        
-        std::fs::write(path, json)
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to write backup: {}", e)))?;
-        
-        log::info!("Database backed up successfully");
-        Ok(())
+    /// Write a full, checksummed backup archive of every tree
+    pub fn backup<P: AsRef<Path>>(&self, path: P) -> Result<BackupManifest> {
+        backup::write_full(self.storage.as_ref(), path)
     }
 
-    /// Restore database from a backup file
-    pub fn restore<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let json = std::fs::read_to_string(path)
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to read backup: {}", e)))?;
-        
-        let backup_data: DatabaseBackup = serde_json::from_str(&json)
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to parse backup: {}", e)))?;
-        
-        // Clear existing data
-        self.clear_all()?;
-        
-        // Restore vaults
-        for vault in backup_data.vaults {
-            self.save_vault(&vault)?;
-        }
-        
-        // Restore liquidations
-        for liquidation in backup_data.liquidations {
-            self.save_liquidation(&liquidation)?;
-        }
-        
-        // Restore prices
-        for price in backup_data.prices {
-            self.save_oracle_price(&price)?;
+    /// Write an archive of what changed since `base`, restorable on top of it
+    pub fn backup_incremental<P: AsRef<Path>>(&self, path: P, base: &BackupManifest) -> Result<BackupManifest> {
+        backup::write_incremental(self.storage.as_ref(), path, base)
+    }
+
+    /// Replace all data with a full backup followed by its incrementals, after
+    /// checking their checksums, order and schema versions
+    pub fn restore<P: AsRef<Path>>(&self, paths: &[P]) -> Result<RestoreReport> {
+        backup::restore(self.storage.as_ref(), paths)
+    }
+}
+
+/// Big-endian millisecond timestamp; pre-1970 times clamp to zero
+fn time_prefix(timestamp: DateTime<Utc>) -> [u8; 8] {
+    (timestamp.timestamp_millis().max(0) as u64).to_be_bytes()
+}
+
+fn candle_prefix(interval: CandleInterval, currency: &Currency) -> Vec<u8> {
+    let mut prefix = interval.as_str().as_bytes().to_vec();
+    prefix.push(b'/');
+    prefix.extend_from_slice(&currency_prefix(currency));
+    prefix
+}
+
+/// Index entries for `new` replacing `old`. Each vault is indexed by owner, by state
+/// and, while active, by the BTC price in each debt currency at which its collateral
+/// only just covers that debt. Liquidation at threshold `t` happens below `t` times that price.
+pub(crate) fn vault_index_writes(old: Option<&Vault>, new: Option<&Vault>) -> Vec<Write> {
+    let old_keys = old.map(vault_index_keys).unwrap_or_default();
+    let new_keys = new.map(vault_index_keys).unwrap_or_default();
+    
+    let mut writes: Vec<Write> = old_keys.iter()
+        .filter(|key| !new_keys.contains(key))
+        .map(|(tree, key)| Write::remove(tree, key.clone()))
+        .collect();
+    writes.extend(new_keys.iter()
+        .filter(|key| !old_keys.contains(key))
+        .map(|(tree, key)| Write::insert(tree, key.clone(), Vec::new())));
+    writes
+}
+
+fn vault_index_keys(vault: &Vault) -> Vec<(&'static str, Vec<u8>)> {
+    let id = vault.id.to_byte_array();
+    let with_id = |mut key: Vec<u8>| {
+        key.extend_from_slice(&id);
+        key
+    };
+    
+    let mut keys = vec![
+        (OWNER_INDEX_TREE, with_id(vault.owner.to_bytes())),
+        (STATE_INDEX_TREE, with_id(state_prefix(&vault.state))),
+    ];
+    if vault.state == VaultState::Active {
+        for (currency, debt) in &vault.debts.debts {
+            if *debt <= 0.0 {
+                continue;
+            }
+            let collateral = vault.collateral_btc.to_btc();
+            let price = if collateral > 0.0 { debt / collateral } else { f64::INFINITY };
+            let mut key = currency_prefix(currency);
+            key.extend_from_slice(&price_bucket(price).to_be_bytes());
+            keys.push((LIQUIDATION_PRICE_INDEX_TREE, with_id(key)));
         }
-        
-        log::info!("Database restored from backup (timestamp: {})", backup_data.timestamp);
-        Ok(())
     }
+    keys
+}
+
+/// Whole currency units; queries re-check the exact ratio within a bucket
+fn price_bucket(price: f64) -> u64 {
+    price.max(0.0).floor() as u64
+}
+
+fn state_prefix(state: &VaultState) -> Vec<u8> {
+    format!("{:?}/", state).into_bytes()
+}
+
+fn currency_prefix(currency: &Currency) -> Vec<u8> {
+    let mut prefix = currency.to_string().into_bytes();
+    prefix.push(b'/');
+    prefix
 }
 
 /// Liquidation record for database storage
@@ -329,21 +1104,13 @@ pub struct DatabaseStats {
     pub database_size_bytes: u64,
 }
 
-/// Database backup structure
-#[derive(Debug, Serialize, Deserialize)]
-pub struct DatabaseBackup {
-    pub timestamp: DateTime<Utc>,
-    pub vaults: Vec<Vault>,
-    pub liquidations: Vec<LiquidationRecord>,
-    pub prices: Vec<OraclePriceRecord>,
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use tempfile::TempDir;
     use bitcoin::hashes::Hash;
     use crate::Currency;
+    use crate::multi_currency::PriceMode;
     
     #[test]
     fn test_database_operations() {
@@ -400,6 +1167,233 @@ mod tests {
         }
     }
     
+    #[test]
+    fn test_unit_of_work_commits_across_trees() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = DatabaseManager::new(temp_dir.path()).unwrap();
+        
+        let vault_id = Txid::from_raw_hash(bitcoin::hashes::sha256d::Hash::all_zeros());
+        let owner: PublicKey = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".parse().unwrap();
+        let mut vault = Vault::new(vault_id, owner, Amount::from_btc(1.0).unwrap());
+        vault.state = crate::VaultState::Liquidated;
+        let now = Utc::now();
+        
+        let mut work = UnitOfWork::new();
+        work.put_vault(&vault).unwrap();
+        work.put_liquidation(&LiquidationRecord {
+            vault_id,
+            liquidator: owner,
+            collateral_seized: Amount::from_btc(0.5).unwrap(),
+            debt_covered: 40000.0,
+            bonus_paid: Amount::from_btc(0.05).unwrap(),
+            liquidated_at: now,
+            btc_price: 80000.0,
+        }).unwrap();
+        // Settlements at the same instant get distinct keys
+        for _ in 0..2 {
+            work.put_settlement(&LiquidationSettlement {
+                vault_id,
+                liquidator: owner,
+                settlement_txid: vault_id,
+                collateral_seized: Amount::from_btc(0.5).unwrap(),
+                liquidator_bonus: Amount::from_btc(0.05).unwrap(),
+                protocol_fee: Amount::from_sat(1000),
+                settled_at: now,
+            }).unwrap();
+        }
+        assert_eq!(work.len(), 4);
+        db.commit(work).unwrap();
+        
+        let stored = db.storage.get(DEFAULT_TREE, vault_id.to_string().as_bytes()).unwrap().unwrap();
+        let stored: Vault = schema::decode_record(&stored).unwrap();
+        assert_eq!(stored.state, crate::VaultState::Liquidated);
+        assert_eq!(db.get_liquidation_history(None).unwrap().len(), 1);
+        assert_eq!(db.get_settlement_history(10).unwrap().len(), 2);
+        assert!(db.verify().unwrap().is_ok());
+    }
+    
+    #[test]
+    fn test_subsystems_survive_reopen() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().to_str().unwrap();
+        let holder: PublicKey = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".parse().unwrap();
+        let vault_id = Txid::from_raw_hash(bitcoin::hashes::sha256d::Hash::all_zeros());
+        
+        {
+            let mut protocol = crate::BitStableProtocol::open(path).unwrap();
+            protocol.stable_manager.mint_stable(holder, Currency::USD, 1000.0, vault_id).unwrap();
+            protocol.insurance_fund.contribute_from_fees(Amount::from_sat(100_000), crate::insurance::ContributionSource::ProtocolFees).unwrap();
+            protocol.flush().unwrap();
+        }
+        
+        let protocol = crate::BitStableProtocol::open(path).unwrap();
+        assert_eq!(protocol.stable_manager.get_balance(holder, &Currency::USD), 1000.0);
+        assert!(protocol.insurance_fund.balance_btc > Amount::ZERO);
+        assert_eq!(protocol.insurance_fund.contribution_history.len(), 1);
+        assert!(protocol.vault_manager.database().unwrap().verify().unwrap().is_ok());
+    }
+    
+    /// In-memory store whose writes can be made to fail
+    #[derive(Debug, Default)]
+    struct FailingStorage {
+        inner: crate::storage::MemoryStorage,
+        fail_writes: std::sync::atomic::AtomicBool,
+    }
+
+    impl Storage for FailingStorage {
+        fn backend(&self) -> &'static str {
+            "failing"
+        }
+        fn get(&self, tree: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            self.inner.get(tree, key)
+        }
+        fn insert(&self, tree: &str, key: &[u8], value: &[u8]) -> Result<()> {
+            self.apply(&[Write::insert(tree, key, value)])
+        }
+        fn remove(&self, tree: &str, key: &[u8]) -> Result<()> {
+            self.apply(&[Write::remove(tree, key)])
+        }
+        fn scan(&self, tree: &str, range: &KeyRange, reverse: bool, limit: Option<usize>) -> Result<Vec<Entry>> {
+            self.inner.scan(tree, range, reverse, limit)
+        }
+        fn len(&self, tree: &str) -> Result<usize> {
+            self.inner.len(tree)
+        }
+        fn clear(&self, tree: &str) -> Result<()> {
+            self.inner.clear(tree)
+        }
+        fn apply(&self, writes: &[Write]) -> Result<()> {
+            if self.fail_writes.load(std::sync::atomic::Ordering::SeqCst) {
+                return Err(BitStableError::InvalidConfig("Disk full".to_string()));
+            }
+            self.inner.apply(writes)
+        }
+        fn generate_id(&self) -> Result<u64> {
+            self.inner.generate_id()
+        }
+        fn advance_ids(&self, floor: u64) -> Result<()> {
+            self.inner.advance_ids(floor)
+        }
+        fn flush(&self) -> Result<()> {
+            self.inner.flush()
+        }
+        fn tree_names(&self) -> Result<Vec<String>> {
+            self.inner.tree_names()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit_rolls_back_liquidation() {
+        let storage = Arc::new(FailingStorage::default());
+        let database = DatabaseManager::with_storage(storage.clone()).unwrap();
+        let mut protocol = crate::BitStableProtocol::with_database(crate::ProtocolConfig::testnet(), database).unwrap();
+        let owner: PublicKey = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".parse().unwrap();
+        let liquidator: PublicKey = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5".parse().unwrap();
+        let set_price = |protocol: &mut crate::BitStableProtocol, price: f64| {
+            protocol.oracle_network.apply_btc_prices(&HashMap::from([(Currency::USD, price)]), Utc::now()).unwrap();
+            protocol.vault_manager.update_exchange_rates(protocol.oracle_network.get_exchange_rates().clone());
+        };
+
+        // A failed open leaves no escrow contract behind
+        set_price(&mut protocol, 100000.0);
+        storage.fail_writes.store(true, std::sync::atomic::Ordering::SeqCst);
+        assert!(protocol.open_vault(owner, Amount::from_btc(1.0).unwrap(), Currency::USD, 40000.0).await.is_err());
+        assert_eq!(protocol.custody_manager.escrow_contracts().count(), 0);
+        assert!(protocol.vault_manager.list_vaults().is_empty());
+
+        storage.fail_writes.store(false, std::sync::atomic::Ordering::SeqCst);
+        let vault_id = protocol.open_vault(owner, Amount::from_btc(1.0).unwrap(), Currency::USD, 40000.0).await.unwrap().vault_id;
+        // The liquidator repays covered debt out of their own balance
+        protocol.stable_manager.transfer_stable(owner, liquidator, Currency::USD, 40000.0).unwrap();
+
+        // At 128% the vault is partially liquidatable
+        set_price(&mut protocol, 51200.0);
+        let vaults: Vec<Vault> = protocol.vault_manager.list_vaults().into_iter().cloned().collect();
+        let exchange_rates = protocol.oracle_network.get_exchange_rates().clone();
+        protocol.liquidation_engine.update_system_collateral(10_000_000.0);
+        protocol.liquidation_engine.scan_for_liquidations(&vaults.iter().collect::<Vec<_>>(), &exchange_rates);
+
+        storage.fail_writes.store(true, std::sync::atomic::Ordering::SeqCst);
+        assert!(protocol.liquidate_vault(vault_id, liquidator).await.is_err());
+        assert_eq!(protocol.insurance_fund.balance_btc, Amount::ZERO);
+        assert!(protocol.insurance_fund.contribution_history.is_empty());
+        assert!(protocol.custody_manager.get_settlement(vault_id).is_none());
+        assert!(protocol.custody_manager.get_pending_transactions().is_empty());
+        assert!(protocol.liquidation_engine.get_liquidation_history(None).is_empty());
+        assert_eq!(protocol.vault_manager.get_vault(vault_id).unwrap().state, VaultState::Active);
+        assert_eq!(protocol.stable_manager.get_balance(liquidator, &Currency::USD), 40000.0);
+
+        // The restored queue lets the same liquidation go through once writes succeed
+        storage.fail_writes.store(false, std::sync::atomic::Ordering::SeqCst);
+        protocol.liquidate_vault(vault_id, liquidator).await.unwrap();
+        assert!(protocol.insurance_fund.balance_btc > Amount::ZERO);
+        assert!(protocol.custody_manager.get_settlement(vault_id).is_some());
+        assert_eq!(protocol.liquidation_engine.get_liquidation_history(None).len(), 1);
+        assert_eq!(protocol.vault_manager.database().unwrap().get_liquidation_history(None).unwrap().len(), 1);
+        let remaining_debt = protocol.vault_manager.get_vault(vault_id).unwrap().debts.get_debt(&Currency::USD);
+        assert!(remaining_debt < 40000.0);
+        assert!((protocol.stable_manager.get_total_supply(&Currency::USD) - remaining_debt).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_vault_indexes_follow_writes() {
+        let db = DatabaseManager::open("memory://").unwrap();
+        let alice: PublicKey = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".parse().unwrap();
+        let bob: PublicKey = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5".parse().unwrap();
+
+        // Debt per BTC of 30k, 50k and 70k
+        let mut vaults = Vec::new();
+        for (i, (owner, debt)) in [(alice, 30000.0), (alice, 50000.0), (bob, 70000.0)].into_iter().enumerate() {
+            let vault_id = Txid::from_raw_hash(bitcoin::hashes::sha256d::Hash::hash(&[i as u8]));
+            let mut vault = Vault::new(vault_id, owner, Amount::from_btc(1.0).unwrap());
+            vault.debts.add_debt(Currency::USD, debt).unwrap();
+            db.save_vault(&vault).unwrap();
+            vaults.push(vault);
+        }
+
+        let ids = |found: Vec<Vault>| -> Vec<Txid> {
+            let mut ids: Vec<Txid> = found.into_iter().map(|vault| vault.id).collect();
+            ids.sort();
+            ids
+        };
+        let mut alice_ids = vec![vaults[0].id, vaults[1].id];
+        alice_ids.sort();
+        assert_eq!(ids(db.get_vaults_by_owner(&alice).unwrap()), alice_ids);
+
+        // At 1.2x, a 60k BTC price liquidates vaults with more than 50k debt per BTC
+        let configs = HashMap::from([(Currency::USD, CurrencyConfig { liquidation_threshold: 1.2, ..CurrencyConfig::default() })]);
+        let rates = |spot: f64, twap: f64| {
+            let mut rates = ExchangeRates::new();
+            rates.update_btc_price(Currency::USD, spot);
+            rates.update_twap_price(Currency::USD, twap);
+            rates
+        };
+        let liquidatable = db.get_liquidatable_vaults(&rates(60000.0, 40000.0), &configs).unwrap();
+        assert_eq!(ids(liquidatable), vec![vaults[2].id]);
+        assert_eq!(db.get_liquidatable_vaults(&rates(40000.0, 60000.0), &configs).unwrap().len(), 2);
+
+        // Each currency uses its own threshold and liquidation price mode
+        let mut twap_configs = configs.clone();
+        twap_configs.get_mut(&Currency::USD).unwrap().liquidation_price_mode = PriceMode::Twap;
+        assert_eq!(db.get_liquidatable_vaults(&rates(60000.0, 40000.0), &twap_configs).unwrap().len(), 2);
+        twap_configs.get_mut(&Currency::USD).unwrap().liquidation_threshold = 1.5;
+        assert_eq!(db.get_liquidatable_vaults(&rates(40000.0, 60000.0), &twap_configs).unwrap().len(), 2);
+
+        // Closing a vault moves it between state indexes and out of the price index
+        let mut closed = vaults[2].clone();
+        closed.state = VaultState::Closed;
+        let mut work = UnitOfWork::new();
+        work.put_vault(&closed).unwrap();
+        db.commit(work).unwrap();
+        assert!(db.get_liquidatable_vaults(&rates(60000.0, 60000.0), &configs).unwrap().is_empty());
+        assert_eq!(ids(db.get_vaults_by_state(&VaultState::Closed).unwrap()), vec![closed.id]);
+        assert_eq!(db.get_vaults_by_state(&VaultState::Active).unwrap().len(), 2);
+
+        db.delete_vault(vaults[0].id).unwrap();
+        assert_eq!(ids(db.get_vaults_by_owner(&alice).unwrap()), vec![vaults[1].id]);
+        assert_eq!(db.storage.len(OWNER_INDEX_TREE).unwrap(), 2);
+    }
+
     #[test]
     fn test_backup_restore() {
         let temp_dir = TempDir::new().unwrap();
@@ -414,7 +1408,7 @@ mod tests {
         db.save_vault(&vault).unwrap();
         
         // Backup
-        let backup_path = temp_dir.path().join("backup.json");
+        let backup_path = temp_dir.path().join("backup.bak");
         db.backup(&backup_path).unwrap();
         
         // Clear database
@@ -422,11 +1416,22 @@ mod tests {
         assert_eq!(db.list_vaults().unwrap().len(), 0);
         
         // Restore
-        db.restore(&backup_path).unwrap();
+        db.restore(&[&backup_path]).unwrap();
         
         // Verify restoration
         let vaults = db.list_vaults().unwrap();
         assert_eq!(vaults.len(), 1);
         assert_eq!(vaults[0].id, vault.id);
+
+        // A restore that fails to write leaves the existing data in place
+        let storage = Arc::new(FailingStorage::default());
+        let failing = DatabaseManager::with_storage(storage.clone()).unwrap();
+        let mut kept = vault.clone();
+        kept.id = Txid::from_raw_hash(bitcoin::hashes::sha256d::Hash::hash(b"kept"));
+        failing.save_vault(&kept).unwrap();
+        storage.fail_writes.store(true, std::sync::atomic::Ordering::SeqCst);
+        assert!(failing.restore(&[&backup_path]).is_err());
+        let ids: Vec<Txid> = failing.list_vaults().unwrap().into_iter().map(|vault| vault.id).collect();
+        assert_eq!(ids, vec![kept.id]);
     }
 }
@@ -4,6 +4,7 @@ use std::collections::HashMap;
 use chrono::{DateTime, Utc, Duration};
 use crate::{BitStableError, Result, ProtocolConfig};
 use crate::multi_currency::{Currency, ExchangeRates};
+use crate::events::{EventBus, EventEmitter, ProtocolEvent};
 use crate::governance::{GovernanceSystem, ProposalType};
 
 /// Emergency shutdown system for protocol-wide crisis management
@@ -18,6 +19,10 @@ pub struct EmergencyShutdownSystem {
     pub user_claims: HashMap<PublicKey, UserClaim>,
     pub governance_override_active: bool,
     pub last_health_check: DateTime<Utc>,
+    #[serde(default)]
+    pub last_system_state: Option<SystemStateSnapshot>,  // State seen by the latest health check
+    #[serde(skip)]
+    events: EventEmitter,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -51,6 +56,7 @@ pub enum TriggerType {
     SecurityBreach,
     RegulatoryShutdown,
     BlackSwanEvent,
+    StablecoinDepeg,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -94,6 +100,8 @@ pub struct SystemStateSnapshot {
     pub oracle_failures: usize,
     pub insurance_fund_balance: Amount,
     pub stability_pool_size: f64,
+    #[serde(default)]
+    pub sustained_peg_deviation: f64,  // Worst stable unit deviation held past the depeg window
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -149,6 +157,14 @@ impl EmergencyShutdownSystem {
                 triggered_at: None,
                 alert_level: 2,
             },
+            ShutdownTrigger {
+                trigger_type: TriggerType::StablecoinDepeg,
+                threshold: 0.05, // Stable unit 5% off peg past the sustained window
+                current_value: 0.0,
+                triggered: false,
+                triggered_at: None,
+                alert_level: 2,
+            },
         ];
 
         Self {
@@ -161,6 +177,8 @@ impl EmergencyShutdownSystem {
             user_claims: HashMap::new(),
             governance_override_active: false,
             last_health_check: Utc::now(),
+            last_system_state: None,
+            events: EventEmitter::default(),
         }
     }
 
@@ -171,6 +189,7 @@ impl EmergencyShutdownSystem {
         governance_system: &mut GovernanceSystem,
     ) -> Result<Vec<AlertAction>> {
         self.last_health_check = Utc::now();
+        self.last_system_state = Some(system_state.clone());
         let mut actions = Vec::new();
 
         // Collect trigger changes first
@@ -198,6 +217,7 @@ impl EmergencyShutdownSystem {
                     // Would need integration with liquidation engine
                     0.0 // Placeholder
                 },
+                TriggerType::StablecoinDepeg => system_state.sustained_peg_deviation,
                 _ => trigger.current_value, // Keep existing values for other types
             };
 
@@ -206,7 +226,9 @@ impl EmergencyShutdownSystem {
                 TriggerType::SystemCollateralizationRatio => {
                     trigger.current_value < trigger.threshold
                 },
-                TriggerType::OracleFailureRate | TriggerType::InsuranceFundDepletion => {
+                TriggerType::OracleFailureRate
+                | TriggerType::InsuranceFundDepletion
+                | TriggerType::StablecoinDepeg => {
                     trigger.current_value > trigger.threshold
                 },
                 _ => false,
@@ -288,7 +310,7 @@ impl EmergencyShutdownSystem {
         _system_state: &SystemStateSnapshot,
     ) -> Result<AlertAction> {
         if self.shutdown_state == ShutdownState::Normal {
-            self.shutdown_state = ShutdownState::AlertLevel1;
+            self.transition(ShutdownState::AlertLevel1, format!("{:?} threshold breached", trigger.trigger_type));
         }
 
         Ok(AlertAction::AlertLevel1 {
@@ -309,7 +331,7 @@ impl EmergencyShutdownSystem {
         _system_state: &SystemStateSnapshot,
     ) -> Result<AlertAction> {
         if matches!(self.shutdown_state, ShutdownState::Normal | ShutdownState::AlertLevel1) {
-            self.shutdown_state = ShutdownState::AlertLevel2;
+            self.transition(ShutdownState::AlertLevel2, format!("{:?} critical threshold breached", trigger.trigger_type));
         }
 
         Ok(AlertAction::AlertLevel2 {
@@ -371,7 +393,7 @@ impl EmergencyShutdownSystem {
         system_state: Option<SystemStateSnapshot>,
         triggered_by: Option<PublicKey>,
     ) -> Result<()> {
-        self.shutdown_state = ShutdownState::EmergencyShutdown;
+        self.transition(ShutdownState::EmergencyShutdown, reason.clone());
 
         let event = ShutdownEvent {
             event_type: ShutdownEventType::EmergencyShutdownTriggered,
@@ -394,7 +416,7 @@ impl EmergencyShutdownSystem {
 
     /// Prepare settlement mode for user claims
     fn prepare_settlement_mode(&mut self) -> Result<()> {
-        self.shutdown_state = ShutdownState::SettlementMode;
+        self.transition(ShutdownState::SettlementMode, "Settlement mode initiated");
 
         let event = ShutdownEvent {
             event_type: ShutdownEventType::SettlementBegan,
@@ -531,32 +553,56 @@ impl EmergencyShutdownSystem {
             return Ok(());
         }
 
-        if active_level_3 {
-            self.shutdown_state = ShutdownState::AlertLevel3;
+        let state = if active_level_3 {
+            ShutdownState::AlertLevel3
         } else if active_level_2 {
-            self.shutdown_state = ShutdownState::AlertLevel2;
+            ShutdownState::AlertLevel2
         } else if active_level_1 {
-            self.shutdown_state = ShutdownState::AlertLevel1;
+            ShutdownState::AlertLevel1
         } else {
-            self.shutdown_state = ShutdownState::Normal;
-        }
+            ShutdownState::Normal
+        };
+        let active: Vec<_> = self.shutdown_triggers.iter()
+            .filter(|t| t.triggered)
+            .map(|t| format!("{:?}", t.trigger_type))
+            .collect();
+        let reason = if active.is_empty() {
+            "No triggers active".to_string()
+        } else {
+            format!("Active triggers: {}", active.join(", "))
+        };
+        self.transition(state, reason);
 
         Ok(())
     }
 
-    /// Create current system snapshot
+    /// Move to `state`, emitting an event if it differs from the current one
+    fn transition(&mut self, state: ShutdownState, reason: impl Into<String>) {
+        if self.shutdown_state == state {
+            return;
+        }
+        let from = std::mem::replace(&mut self.shutdown_state, state.clone());
+        self.events.emit(ProtocolEvent::EmergencyStateChanged { from, to: state, reason: reason.into() });
+    }
+
+    pub fn set_event_bus(&mut self, bus: EventBus) {
+        self.events.attach(bus);
+    }
+
+    /// Snapshot of the state seen by the latest health check, or an empty one
+    /// stamped now if no check has run yet
     fn create_current_snapshot(&self) -> SystemStateSnapshot {
-        // This would integrate with actual system state
-        SystemStateSnapshot {
+        self.last_system_state.clone().unwrap_or_else(|| SystemStateSnapshot {
             timestamp: Utc::now(),
-            system_collateral_ratio: 1.5,
-            total_debt_usd: 1_000_000.0,
-            total_collateral_btc: 20.0,
-            active_vaults: 100,
+            system_collateral_ratio: 0.0,
+            total_debt_usd: 0.0,
+            total_collateral_btc: 0.0,
+            active_vaults: 0,
             oracle_failures: 0,
-            insurance_fund_balance: Amount::from_btc(5.0).unwrap_or(Amount::ZERO),
-            stability_pool_size: 500_000.0,
-        }
+            insurance_fund_balance: Amount::ZERO,
+            stability_pool_size: 0.0,
+            sustained_peg_deviation: 0.0,
+        })
     }
 
     /// Get emergency system status
@@ -656,7 +702,7 @@ mod tests {
         let emergency_system = EmergencyShutdownSystem::new(&config);
         
         assert_eq!(emergency_system.shutdown_state, ShutdownState::Normal);
-        assert_eq!(emergency_system.shutdown_triggers.len(), 3);
+        assert_eq!(emergency_system.shutdown_triggers.len(), 4);
         assert!(emergency_system.user_claims.is_empty());
     }
 
@@ -674,6 +720,7 @@ mod tests {
             oracle_failures: 0,
             insurance_fund_balance: Amount::ZERO,
             stability_pool_size: 500_000.0,
+            sustained_peg_deviation: 0.0,
         };
 
         let mut governance = crate::governance::GovernanceSystem::new();
@@ -682,4 +729,60 @@ mod tests {
         assert!(!actions.is_empty());
         assert_eq!(emergency_system.shutdown_state, ShutdownState::AlertLevel1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_sustained_depeg_trigger() {
+        let config = ProtocolConfig::testnet();
+        let mut emergency_system = EmergencyShutdownSystem::new(&config);
+        let mut governance = crate::governance::GovernanceSystem::new();
+
+        let mut system_state = SystemStateSnapshot {
+            timestamp: Utc::now(),
+            system_collateral_ratio: 1.5,
+            total_debt_usd: 1_000_000.0,
+            total_collateral_btc: 20.0,
+            active_vaults: 100,
+            oracle_failures: 0,
+            insurance_fund_balance: Amount::ZERO,
+            stability_pool_size: 500_000.0,
+            sustained_peg_deviation: 0.08,
+        };
+        let actions = emergency_system.check_system_health(system_state.clone(), &mut governance).unwrap();
+
+        assert!(matches!(actions[0], AlertAction::AlertLevel2 { trigger_type: TriggerType::StablecoinDepeg, .. }));
+        assert_eq!(emergency_system.shutdown_state, ShutdownState::AlertLevel2);
+
+        // Back on peg resolves the trigger
+        system_state.sustained_peg_deviation = 0.0;
+        emergency_system.check_system_health(system_state, &mut governance).unwrap();
+        assert_eq!(emergency_system.shutdown_state, ShutdownState::Normal);
+    }
+
+    #[test]
+    fn test_peg_monitor_feeds_shutdown_triggers() {
+        let database = crate::database::DatabaseManager::open("memory://").unwrap();
+        let mut protocol = crate::BitStableProtocol::with_database(ProtocolConfig::testnet(), database.clone()).unwrap();
+        protocol.oracle_network.submit_peg_price(crate::PegPrice {
+            currency: Currency::EUR,
+            price: 0.92,
+            source: "dex".to_string(),
+            timestamp: Utc::now(),
+        }).unwrap();
+
+        // Just off peg: no alert and nothing sustained yet
+        assert!(protocol.monitor_peg().unwrap().is_empty());
+        assert_eq!(protocol.emergency_system.shutdown_state, ShutdownState::Normal);
+
+        // Off peg past the sustained window escalates from the real system state
+        let status = protocol.risk_metrics.peg_status.get_mut(&Currency::EUR).unwrap();
+        status.outside_band_since = Some(Utc::now() - Duration::hours(1));
+        assert_eq!(protocol.monitor_peg().unwrap().len(), 1);
+        assert_eq!(protocol.emergency_system.shutdown_state, ShutdownState::AlertLevel2);
+        let seen = protocol.emergency_system.last_system_state.as_ref().unwrap();
+        assert!((seen.sustained_peg_deviation - 0.08).abs() < 1e-9);
+        assert_eq!(seen.active_vaults, 0);
+
+        let stored: EmergencyShutdownSystem = database.load_subsystem(crate::database::Subsystem::EmergencyShutdown).unwrap().unwrap();
+        assert_eq!(stored.shutdown_state, ShutdownState::AlertLevel2);
+    }
+}
@@ -23,6 +23,9 @@ pub enum BitStableError {
     #[error("Database error: {0}")]
     DatabaseError(#[from] sled::Error),
 
+    #[error("SQLite error: {0}")]
+    SqliteError(#[from] rusqlite::Error),
+
     #[error("Bitcoin error: {0}")]
     BitcoinError(#[from] bitcoin::consensus::encode::Error),
 
@@ -61,6 +64,15 @@ pub enum BitStableError {
 
     #[error("Insufficient oracle consensus: got {got}, required {required}")]
     InsufficientOracleConsensus { got: usize, required: usize },
+
+    #[error("Circuit breaker tripped for {0}")]
+    CircuitBreakerTripped(String),
+
+    #[error("Peer protocol error: {0}")]
+    PeerProtocolError(String),
+
+    #[error("Not the Raft leader (leader: {0:?})")]
+    NotLeader(Option<u64>),
 }
 
 pub type Result<T> = std::result::Result<T, BitStableError>;
\ No newline at end of file
@@ -0,0 +1,330 @@
+//! Protocol events
+//! Typed state changes emitted by the subsystems that make them. Events are held back
+//! until the change commits, then stored under a sequence number and broadcast.
+
+use bitcoin::{Amount, PublicKey, Txid};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use crate::database::{DatabaseManager, UnitOfWork};
+use crate::emergency::ShutdownState;
+use crate::governance::{ExecutionResult, ProposalStatus, VoteDecision};
+use crate::liquidation::LiquidationRecord;
+use crate::multi_currency::Currency;
+use crate::redemption::RedemptionRecord;
+use crate::stability_pool::{StabilityLiquidation, WithdrawalResult};
+use crate::vault::VaultState;
+use crate::{BitStableError, Result};
+
+/// Events a live subscriber can fall behind by before it is caught up from storage
+const CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ProtocolEvent {
+    VaultCreated {
+        vault_id: Txid,
+        owner: PublicKey,
+        collateral: Amount,
+        currency: Currency,
+        amount: f64,
+    },
+    StableMinted {
+        vault_id: Txid,
+        currency: Currency,
+        amount: f64,
+    },
+    StableBurned {
+        vault_id: Txid,
+        currency: Currency,
+        amount: f64,
+    },
+    VaultClosed {
+        vault_id: Txid,
+        owner: PublicKey,
+        collateral_returned: Amount,
+    },
+    VaultLiquidated {
+        vault_id: Txid,
+        collateral_seized: Amount,
+        debt_covered_usd: f64,
+        state: VaultState,   // Still active after a partial liquidation
+    },
+    LiquidationExecuted {
+        record: LiquidationRecord,
+    },
+    RedemptionExecuted {
+        record: RedemptionRecord,
+    },
+    StabilityDeposit {
+        depositor: PublicKey,
+        currency: Currency,
+        amount: f64,
+    },
+    StabilityWithdrawal {
+        withdrawal: WithdrawalResult,
+    },
+    StabilityLiquidation {
+        liquidation: StabilityLiquidation,
+    },
+    StabilityRewardsClaimed {
+        depositor: PublicKey,
+        currency: Currency,
+        rewards: Amount,
+    },
+    ProposalCreated {
+        proposal_id: u64,
+        proposer: PublicKey,
+        title: String,
+        emergency: bool,
+    },
+    VoteCast {
+        proposal_id: u64,
+        voter: PublicKey,
+        decision: VoteDecision,
+    },
+    ProposalStatusChanged {
+        proposal_id: u64,
+        status: ProposalStatus,
+    },
+    ProposalExecuted {
+        proposal_id: u64,
+        result: ExecutionResult,
+    },
+    EmergencyStateChanged {
+        from: ShutdownState,
+        to: ShutdownState,
+        reason: String,
+    },
+}
+
+impl ProtocolEvent {
+    /// The vault whose record this event changed, if any
+    pub fn vault_id(&self) -> Option<Txid> {
+        match self {
+            ProtocolEvent::VaultCreated { vault_id, .. }
+            | ProtocolEvent::StableMinted { vault_id, .. }
+            | ProtocolEvent::StableBurned { vault_id, .. }
+            | ProtocolEvent::VaultClosed { vault_id, .. }
+            | ProtocolEvent::VaultLiquidated { vault_id, .. } => Some(*vault_id),
+            _ => None,
+        }
+    }
+}
+
+/// A committed event and its place in the stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventRecord {
+    pub sequence: u64,
+    pub timestamp: DateTime<Utc>,
+    pub event: ProtocolEvent,
+}
+
+/// Shared handle to the protocol's event stream. Emitted events wait in a pending
+/// buffer until `stage` writes them into the unit of work that commits the change.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    database: DatabaseManager,
+    sender: broadcast::Sender<EventRecord>,
+    pending: Arc<Mutex<Pending>>,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    records: Vec<EventRecord>,   // Numbered when staged
+    operation_start: Option<usize>,   // Index of the first event from the running operation
+}
+
+impl EventBus {
+    pub fn new(database: DatabaseManager) -> Self {
+        Self {
+            database,
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            pending: Arc::new(Mutex::new(Pending::default())),
+        }
+    }
+
+    pub fn emit(&self, event: ProtocolEvent) {
+        self.pending.lock().unwrap().records.push(EventRecord { sequence: 0, timestamp: Utc::now(), event });
+    }
+
+    /// Mark the start of an operation, so `abandon` drops only the events it emits
+    pub fn begin(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.operation_start = Some(pending.records.len());
+    }
+
+    /// Number the pending events after the last stored one and stage them in `work`.
+    /// Pass the records to `publish` once the work commits.
+    pub fn stage(&self, work: &mut UnitOfWork) -> Result<Vec<EventRecord>> {
+        let mut records = std::mem::take(&mut self.pending.lock().unwrap().records);
+        for (sequence, record) in (self.next_sequence()?..).zip(&mut records) {
+            record.sequence = sequence;
+            work.put_event(record)?;
+        }
+        Ok(records)
+    }
+
+    /// Return staged records to the front of the pending buffer after a failed commit
+    pub fn unstage(&self, mut records: Vec<EventRecord>) {
+        let mut pending = self.pending.lock().unwrap();
+        records.append(&mut pending.records);
+        pending.records = records;
+    }
+
+    /// Broadcast committed records to live subscribers
+    pub fn publish(&self, records: Vec<EventRecord>) {
+        self.pending.lock().unwrap().operation_start = None;
+        for record in records {
+            // No subscribers is not an error; the record is already stored
+            let _ = self.sender.send(record);
+        }
+    }
+
+    /// Drop the events emitted since `begin` by an operation that failed, keeping
+    /// earlier ones for the next commit
+    pub fn abandon(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(start) = pending.operation_start.take() {
+            pending.records.truncate(start);
+        }
+    }
+
+    pub fn next_sequence(&self) -> Result<u64> {
+        Ok(self.database.last_event()?.map_or(0, |record| record.sequence + 1))
+    }
+
+    /// Committed events as they are published, starting now
+    pub fn subscribe(&self) -> broadcast::Receiver<EventRecord> {
+        self.sender.subscribe()
+    }
+
+    /// Stored events from `from_sequence` on
+    pub fn replay(&self, from_sequence: u64) -> Result<Vec<EventRecord>> {
+        self.database.get_events(from_sequence, None)
+    }
+
+    /// Replay stored events from `from_sequence`, then continue with live ones,
+    /// without gaps or repeats
+    pub fn subscribe_from(&self, from_sequence: u64) -> Result<EventSubscription> {
+        // Subscribe before replaying, so nothing committed in between is missed
+        let receiver = self.sender.subscribe();
+        Ok(EventSubscription {
+            database: self.database.clone(),
+            receiver,
+            backlog: self.replay(from_sequence)?.into(),
+            next_sequence: from_sequence,
+        })
+    }
+}
+
+/// An ordered event stream from a chosen sequence number. A subscriber that falls
+/// behind the live channel is caught up from storage rather than skipping events.
+#[derive(Debug)]
+pub struct EventSubscription {
+    database: DatabaseManager,
+    receiver: broadcast::Receiver<EventRecord>,
+    backlog: VecDeque<EventRecord>,
+    next_sequence: u64,
+}
+
+impl EventSubscription {
+    /// The next event, waiting for one to be published if none is stored yet.
+    /// Fails once the bus has been dropped.
+    pub async fn recv(&mut self) -> Result<EventRecord> {
+        loop {
+            if let Some(record) = self.backlog.pop_front() {
+                if record.sequence < self.next_sequence {
+                    continue;
+                }
+                self.next_sequence = record.sequence + 1;
+                return Ok(record);
+            }
+
+            match self.receiver.recv().await {
+                Ok(record) if record.sequence < self.next_sequence => {}
+                Ok(record) if record.sequence == self.next_sequence => {
+                    self.next_sequence += 1;
+                    return Ok(record);
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                    self.backlog = self.database.get_events(self.next_sequence, None)?.into();
+                }
+                Err(broadcast::error::RecvError::Closed) => {
+                    return Err(BitStableError::InvalidConfig("Event bus closed".to_string()));
+                }
+            }
+        }
+    }
+
+    /// Sequence number of the next event this subscription will return
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+}
+
+/// A subsystem's connection to the bus; emitting does nothing until one is attached
+#[derive(Debug, Clone, Default)]
+pub struct EventEmitter(Option<EventBus>);
+
+impl EventEmitter {
+    pub fn attach(&mut self, bus: EventBus) {
+        self.0 = Some(bus);
+    }
+
+    pub fn emit(&self, event: ProtocolEvent) {
+        if let Some(bus) = &self.0 {
+            bus.emit(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BitStableProtocol;
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::SecretKey;
+
+    #[tokio::test]
+    async fn test_subscription_replays_then_follows_without_gaps() {
+        let mut protocol = BitStableProtocol::open("memory://").unwrap();
+        let depositor: PublicKey = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".parse().unwrap();
+        let mut live = protocol.events.subscribe_from(0).unwrap();
+
+        // A failed operation doesn't drop events from earlier direct calls
+        protocol.stability_pool.deposit(depositor, Currency::USD, 500.0).unwrap();
+        let missing = Txid::from_raw_hash(bitcoin::hashes::sha256d::Hash::all_zeros());
+        assert!(protocol.close_vault(missing, depositor).await.is_err());
+        protocol.flush().unwrap();
+        let actor = SecretKey::from_slice(&[7u8; 32]).unwrap();
+        protocol.emergency_shutdown(&actor, "test".to_string()).unwrap();
+
+        let first = live.recv().await.unwrap();
+        assert_eq!(first.sequence, 0);
+        assert!(matches!(first.event, ProtocolEvent::StabilityDeposit { amount, .. } if amount == 500.0));
+        let shutdown = live.recv().await.unwrap();
+        assert!(matches!(shutdown.event, ProtocolEvent::EmergencyStateChanged { to: ShutdownState::EmergencyShutdown, .. }));
+        let settlement = live.recv().await.unwrap();
+        assert_eq!(settlement.sequence, 2);
+        assert!(matches!(settlement.event, ProtocolEvent::EmergencyStateChanged { to: ShutdownState::SettlementMode, .. }));
+
+        // A late subscriber replays from storage, then picks up live events
+        let mut late = protocol.events.subscribe_from(1).unwrap();
+        assert_eq!(protocol.events.replay(1).unwrap().len(), 2);
+        assert_eq!(late.recv().await.unwrap().sequence, 1);
+        assert_eq!(late.recv().await.unwrap().sequence, 2);
+
+        // Falling behind the live channel is caught up from storage
+        for _ in 0..CHANNEL_CAPACITY + 10 {
+            protocol.stability_pool.deposit(depositor, Currency::USD, 100.0).unwrap();
+        }
+        protocol.flush().unwrap();
+        for sequence in 3..CHANNEL_CAPACITY as u64 + 13 {
+            assert_eq!(late.recv().await.unwrap().sequence, sequence);
+        }
+        assert_eq!(protocol.events.next_sequence().unwrap(), CHANNEL_CAPACITY as u64 + 13);
+        assert!(protocol.vault_manager.database().unwrap().verify().unwrap().is_ok());
+    }
+}
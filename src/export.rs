@@ -0,0 +1,432 @@
+//! Analytics export
+//! Writes protocol history to CSV or JSON Lines with a fixed column schema per dataset.
+//! Time-keyed trees are read only over the filter's range; each dataset's matching
+//! records are loaded before the rows are written.
+
+use std::io::Write;
+use std::str::FromStr;
+use bitcoin::Amount;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::{Map, Value};
+use crate::database::{DatabaseManager, Subsystem};
+use crate::insurance::InsuranceFund;
+use crate::multi_currency::Currency;
+use crate::stable::MultiCurrencyStableManager;
+use crate::{BitStableError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+}
+
+impl FromStr for ExportFormat {
+    type Err = BitStableError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "jsonl" | "ndjson" => Ok(ExportFormat::Jsonl),
+            other => Err(BitStableError::InvalidConfig(format!("Unknown export format '{}' (csv or jsonl)", other))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dataset {
+    Vaults,
+    Liquidations,
+    Redemptions,
+    Transfers,
+    InsuranceContributions,
+    InsurancePayouts,
+    ConsensusPrices,
+}
+
+impl Dataset {
+    pub const ALL: [Dataset; 7] = [
+        Dataset::Vaults,
+        Dataset::Liquidations,
+        Dataset::Redemptions,
+        Dataset::Transfers,
+        Dataset::InsuranceContributions,
+        Dataset::InsurancePayouts,
+        Dataset::ConsensusPrices,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Dataset::Vaults => "vaults",
+            Dataset::Liquidations => "liquidations",
+            Dataset::Redemptions => "redemptions",
+            Dataset::Transfers => "transfers",
+            Dataset::InsuranceContributions => "insurance-contributions",
+            Dataset::InsurancePayouts => "insurance-payouts",
+            Dataset::ConsensusPrices => "consensus-prices",
+        }
+    }
+
+    /// Column names in output order. Columns are only ever appended, so
+    /// downstream readers can rely on position as well as name.
+    pub fn columns(self) -> &'static [&'static str] {
+        match self {
+            Dataset::Vaults => &[
+                "vault_id", "owner", "state", "created_at", "collateral_sats", "currency", "debt", "revision",
+            ],
+            Dataset::Liquidations => &[
+                "liquidated_at", "vault_id", "liquidator", "collateral_seized_sats", "debt_covered",
+                "bonus_paid_sats", "btc_price",
+            ],
+            Dataset::Redemptions => &[
+                "timestamp", "redeemer", "vault_id", "currency", "stable_amount", "btc_received_sats",
+                "fee_paid", "redemption_price",
+            ],
+            Dataset::Transfers => &[
+                "timestamp", "from", "to", "currency", "amount", "vaults",
+            ],
+            Dataset::InsuranceContributions => &[
+                "timestamp", "source", "amount_sats", "transaction_id",
+            ],
+            Dataset::InsurancePayouts => &[
+                "timestamp", "payout_type", "amount_sats", "recipient", "vault_id", "reason",
+            ],
+            Dataset::ConsensusPrices => &[
+                "timestamp", "currency", "btc_price", "usd_rate", "participating_oracles", "total_oracles",
+            ],
+        }
+    }
+
+    /// Whether rows carry a currency; the others ignore the currency filter
+    pub fn has_currency(self) -> bool {
+        self.columns().contains(&"currency")
+    }
+}
+
+impl FromStr for Dataset {
+    type Err = BitStableError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let name = s.to_lowercase().replace('_', "-");
+        Dataset::ALL.into_iter()
+            .find(|dataset| dataset.name() == name)
+            .ok_or_else(|| BitStableError::InvalidConfig(format!(
+                "Unknown dataset '{}' (one of {})",
+                s,
+                Dataset::ALL.map(Dataset::name).join(", ")
+            )))
+    }
+}
+
+/// Rows to keep: `from <= time < to`, and for datasets with a currency, that currency
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub currency: Option<Currency>,
+}
+
+impl ExportFilter {
+    fn includes_time(&self, time: DateTime<Utc>) -> bool {
+        self.from.is_none_or(|from| time >= from) && self.to.is_none_or(|to| time < to)
+    }
+
+    /// Bounds for range scans over time-keyed trees
+    fn time_range(&self) -> (DateTime<Utc>, DateTime<Utc>) {
+        (self.from.unwrap_or(DateTime::<Utc>::UNIX_EPOCH), self.to.unwrap_or(DateTime::<Utc>::MAX_UTC))
+    }
+
+    fn includes_currency(&self, currency: &Currency) -> bool {
+        self.currency.as_ref().is_none_or(|wanted| wanted == currency)
+    }
+}
+
+/// Parse an RFC 3339 timestamp or a bare `YYYY-MM-DD` date (midnight UTC)
+pub fn parse_time(value: &str) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).expect("midnight exists").and_utc())
+        .map_err(|_| BitStableError::InvalidConfig(format!("Invalid time '{}': expected RFC 3339 or YYYY-MM-DD", value)))
+}
+
+/// Write one dataset to `out` in chronological order, returning the number of rows
+pub fn export<W: Write>(
+    database: &DatabaseManager,
+    dataset: Dataset,
+    filter: &ExportFilter,
+    format: ExportFormat,
+    out: W,
+) -> Result<usize> {
+    let mut writer = RowWriter::new(out, dataset.columns(), format)?;
+
+    match dataset {
+        Dataset::Vaults => {
+            for vault in database.list_vaults()? {
+                if !filter.includes_time(vault.created_at) {
+                    continue;
+                }
+                // One row per debt currency, so amounts in different units never share a column
+                let mut debts: Vec<(&Currency, &f64)> = vault.debts.debts.iter().collect();
+                debts.sort_by_key(|(currency, _)| currency.to_string());
+                if debts.is_empty() && filter.currency.is_none() {
+                    writer.row(vec![
+                        vault.id.to_string().into(), vault.owner.to_string().into(), format!("{:?}", vault.state).into(),
+                        time(vault.created_at), sats(vault.collateral_btc), Value::Null, Value::Null, vault.revision.into(),
+                    ])?;
+                }
+                for (currency, debt) in debts.into_iter().filter(|(currency, _)| filter.includes_currency(currency)) {
+                    writer.row(vec![
+                        vault.id.to_string().into(), vault.owner.to_string().into(), format!("{:?}", vault.state).into(),
+                        time(vault.created_at), sats(vault.collateral_btc), currency.to_string().into(), (*debt).into(),
+                        vault.revision.into(),
+                    ])?;
+                }
+            }
+        }
+
+        Dataset::Liquidations => {
+            let mut liquidations = database.get_liquidation_history(None)?;
+            liquidations.sort_by_key(|liquidation| liquidation.liquidated_at);
+            for liquidation in liquidations.into_iter().filter(|liquidation| filter.includes_time(liquidation.liquidated_at)) {
+                writer.row(vec![
+                    time(liquidation.liquidated_at), liquidation.vault_id.to_string().into(),
+                    liquidation.liquidator.to_string().into(), sats(liquidation.collateral_seized),
+                    liquidation.debt_covered.into(), sats(liquidation.bonus_paid), liquidation.btc_price.into(),
+                ])?;
+            }
+        }
+
+        Dataset::Redemptions => {
+            let (from, to) = filter.time_range();
+            for redemption in database.get_redemptions_between(from, to)?.into_iter()
+                .filter(|redemption| filter.includes_time(redemption.timestamp) && filter.includes_currency(&redemption.currency))
+            {
+                writer.row(vec![
+                    time(redemption.timestamp), redemption.redeemer.to_string().into(), redemption.vault_id.to_string().into(),
+                    redemption.currency.to_string().into(), redemption.stable_amount.into(), sats(redemption.btc_received),
+                    redemption.fee_paid.into(), redemption.redemption_price.into(),
+                ])?;
+            }
+        }
+
+        Dataset::Transfers => {
+            let stable_manager: MultiCurrencyStableManager = database.load_subsystem(Subsystem::StableBalances)?
+                .unwrap_or_default();
+            let mut transfers = stable_manager.get_transfer_history(None);
+            transfers.sort_by_key(|transfer| transfer.timestamp);
+            for transfer in transfers.into_iter()
+                .filter(|transfer| filter.includes_time(transfer.timestamp) && filter.includes_currency(&transfer.currency))
+            {
+                writer.row(vec![
+                    time(transfer.timestamp), transfer.from.to_string().into(), transfer.to.to_string().into(),
+                    transfer.currency.to_string().into(), transfer.amount.into(), transfer.positions_transferred.len().into(),
+                ])?;
+            }
+        }
+
+        Dataset::InsuranceContributions => {
+            let (from, to) = filter.time_range();
+            for contribution in database.get_insurance_contributions_between(from, to)?.into_iter()
+                .filter(|contribution| filter.includes_time(contribution.timestamp))
+            {
+                writer.row(vec![
+                    time(contribution.timestamp), format!("{:?}", contribution.source).into(), sats(contribution.amount),
+                    contribution.transaction_id.map_or(Value::Null, |txid| txid.to_string().into()),
+                ])?;
+            }
+        }
+
+        Dataset::InsurancePayouts => {
+            let fund: Option<InsuranceFund> = database.load_subsystem(Subsystem::InsuranceFund)?;
+            let mut payouts = fund.map(|fund| fund.payout_history).unwrap_or_default();
+            payouts.sort_by_key(|payout| payout.timestamp);
+            for payout in payouts.into_iter().filter(|payout| filter.includes_time(payout.timestamp)) {
+                writer.row(vec![
+                    time(payout.timestamp), format!("{:?}", payout.payout_type).into(), sats(payout.amount),
+                    payout.recipient.map_or(Value::Null, |recipient| recipient.to_string().into()),
+                    payout.vault_id.map_or(Value::Null, |vault_id| vault_id.to_string().into()),
+                    payout.reason.into(),
+                ])?;
+            }
+        }
+
+        Dataset::ConsensusPrices => {
+            let (from, to) = filter.time_range();
+            for round in database.get_consensus_rounds(from, to)? {
+                let mut prices: Vec<(&Currency, &f64)> = round.btc_prices.iter()
+                    .filter(|(currency, _)| filter.includes_currency(currency))
+                    .collect();
+                prices.sort_by_key(|(currency, _)| currency.to_string());
+                for (currency, price) in prices {
+                    writer.row(vec![
+                        time(round.timestamp), currency.to_string().into(), (*price).into(),
+                        round.exchange_rates.get(currency).map_or(Value::Null, |rate| (*rate).into()),
+                        round.participating_oracles.into(), round.total_oracles.into(),
+                    ])?;
+                }
+            }
+        }
+    }
+
+    writer.finish()
+}
+
+fn time(timestamp: DateTime<Utc>) -> Value {
+    timestamp.to_rfc3339().into()
+}
+
+fn sats(amount: Amount) -> Value {
+    amount.to_sat().into()
+}
+
+/// Writes each row as it is formatted, so only the source records are held in memory
+struct RowWriter<W: Write> {
+    out: W,
+    columns: &'static [&'static str],
+    format: ExportFormat,
+    rows: usize,
+}
+
+impl<W: Write> RowWriter<W> {
+    fn new(mut out: W, columns: &'static [&'static str], format: ExportFormat) -> Result<Self> {
+        if format == ExportFormat::Csv {
+            writeln!(out, "{}", columns.join(",")).map_err(write_error)?;
+        }
+        Ok(Self { out, columns, format, rows: 0 })
+    }
+
+    fn row(&mut self, values: Vec<Value>) -> Result<()> {
+        debug_assert_eq!(values.len(), self.columns.len());
+        let line = match self.format {
+            ExportFormat::Csv => values.iter().map(csv_field).collect::<Vec<_>>().join(","),
+            ExportFormat::Jsonl => {
+                let object: Map<String, Value> = self.columns.iter()
+                    .map(|column| column.to_string())
+                    .zip(values)
+                    .collect();
+                Value::Object(object).to_string()
+            }
+        };
+        writeln!(self.out, "{}", line).map_err(write_error)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<usize> {
+        self.out.flush().map_err(write_error)?;
+        Ok(self.rows)
+    }
+}
+
+fn csv_field(value: &Value) -> String {
+    let text = match value {
+        Value::Null => return String::new(),
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+fn write_error(e: std::io::Error) -> BitStableError {
+    BitStableError::InvalidConfig(format!("Failed to write export: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{LiquidationRecord, UnitOfWork};
+    use crate::oracle::ConsensusPrices;
+    use crate::redemption::RedemptionRecord;
+    use crate::Vault;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{PublicKey, Txid};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_export_filters_and_formats() {
+        let database = DatabaseManager::open("memory://").unwrap();
+        let owner: PublicKey = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".parse().unwrap();
+        let vault_id = Txid::from_raw_hash(bitcoin::hashes::sha256d::Hash::all_zeros());
+        let mut vault = Vault::new(vault_id, owner, Amount::from_sat(100_000_000));
+        vault.debts.add_debt(Currency::USD, 30000.0).unwrap();
+        vault.debts.add_debt(Currency::EUR, 1000.0).unwrap();
+        database.save_vault(&vault).unwrap();
+
+        for day in [1, 2, 3] {
+            let timestamp = parse_time(&format!("2026-03-0{}", day)).unwrap();
+            database.save_liquidation(&LiquidationRecord {
+                vault_id,
+                liquidator: owner,
+                collateral_seized: Amount::from_sat(1000),
+                debt_covered: 10.0,
+                bonus_paid: Amount::from_sat(50),
+                liquidated_at: timestamp,
+                btc_price: 60000.0,
+            }).unwrap();
+            database.save_consensus_round(&ConsensusPrices {
+                btc_prices: HashMap::from([(Currency::USD, 60000.0), (Currency::EUR, 55000.0)]),
+                exchange_rates: HashMap::from([(Currency::USD, 1.0)]),
+                timestamp,
+                participating_oracles: 3,
+                total_oracles: 5,
+            }).unwrap();
+            let mut work = UnitOfWork::new();
+            work.put_redemption(&RedemptionRecord {
+                redeemer: owner,
+                currency: Currency::USD,
+                stable_amount: 100.0 * day as f64,
+                btc_received: Amount::from_sat(1000),
+                fee_paid: 0.5,
+                vault_id,
+                redemption_price: 60000.0,
+                timestamp: timestamp + chrono::Duration::hours(12),
+            }).unwrap();
+            database.commit(work).unwrap();
+        }
+
+        let run = |dataset, filter: &ExportFilter, format| {
+            let mut out = Vec::new();
+            let rows = export(&database, dataset, filter, format, &mut out).unwrap();
+            (rows, String::from_utf8(out).unwrap())
+        };
+
+        let eur = ExportFilter { currency: Some(Currency::EUR), ..Default::default() };
+        let (rows, csv) = run(Dataset::Vaults, &eur, ExportFormat::Csv);
+        assert_eq!(rows, 1);
+        assert_eq!(csv.lines().next().unwrap(), Dataset::Vaults.columns().join(","));
+        assert!(csv.lines().nth(1).unwrap().contains(",100000000,EUR,1000.0,"));
+
+        let window = ExportFilter {
+            from: Some(parse_time("2026-03-02").unwrap()),
+            to: Some(parse_time("2026-03-03T00:00:00Z").unwrap()),
+            currency: Some(Currency::USD),
+        };
+        let (rows, jsonl) = run(Dataset::ConsensusPrices, &window, ExportFormat::Jsonl);
+        assert_eq!(rows, 1);
+        let row: Value = serde_json::from_str(jsonl.trim()).unwrap();
+        assert_eq!(row["currency"], "USD");
+        assert_eq!(row["usd_rate"], 1.0);
+        assert_eq!(row.as_object().unwrap().len(), Dataset::ConsensusPrices.columns().len());
+
+        // Redemptions come from a range scan over the window
+        let (rows, jsonl) = run(Dataset::Redemptions, &window, ExportFormat::Jsonl);
+        assert_eq!(rows, 1);
+        let row: Value = serde_json::from_str(jsonl.trim()).unwrap();
+        assert_eq!(row["stable_amount"], 200.0);
+
+        // Liquidations have no currency, so only the time window applies
+        let (rows, _) = run(Dataset::Liquidations, &window, ExportFormat::Csv);
+        assert_eq!(rows, 1);
+        let (rows, csv) = run(Dataset::InsurancePayouts, &ExportFilter::default(), ExportFormat::Csv);
+        assert_eq!((rows, csv.lines().count()), (0, 1));
+
+        assert_eq!("insurance_contributions".parse::<Dataset>().unwrap(), Dataset::InsuranceContributions);
+        assert!("trades".parse::<Dataset>().is_err());
+        assert_eq!(csv_field(&Value::from("a,\"b\"")), "\"a,\"\"b\"\"\"");
+    }
+}
@@ -0,0 +1,184 @@
+//! Flood control for relayed network messages
+//! Seen-cache deduplication, hop limits and per-peer rate limiting
+
+use std::collections::{HashMap, VecDeque};
+use bitcoin::PublicKey;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use crate::{BitStableError, Result};
+
+/// Hash of a message's signed content, identical at every hop
+pub type MessageId = [u8; 32];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GossipConfig {
+    pub max_hops: u8,                   // TTL given to messages we originate
+    pub seen_capacity: usize,
+    pub message_expiry_secs: i64,       // Older messages are neither delivered nor relayed
+    pub max_clock_skew_secs: i64,       // How far in the future a timestamp may be
+    pub rate_limit_per_sec: f64,
+    pub rate_limit_burst: f64,
+    pub invalid_signature_penalty: f64,
+    pub rate_limit_penalty: f64,
+    pub stale_message_penalty: f64,
+    pub disconnect_below_reputation: f64,
+    pub misbehaviour_ban_secs: i64,     // How long peers are banned once below that floor
+}
+
+impl Default for GossipConfig {
+    fn default() -> Self {
+        Self {
+            max_hops: 6,
+            seen_capacity: 10_000,
+            message_expiry_secs: 600,
+            max_clock_skew_secs: 60,
+            rate_limit_per_sec: 20.0,
+            rate_limit_burst: 100.0,
+            invalid_signature_penalty: 0.2,
+            rate_limit_penalty: 0.05,
+            stale_message_penalty: 0.02,
+            disconnect_below_reputation: 0.1,
+            misbehaviour_ban_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+impl GossipConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.max_hops == 0 {
+            return Err(BitStableError::InvalidConfig("Gossip max_hops must be at least 1".to_string()));
+        }
+        if self.seen_capacity == 0 || self.message_expiry_secs <= 0 {
+            return Err(BitStableError::InvalidConfig("Gossip seen-cache must have capacity and expiry".to_string()));
+        }
+        if self.rate_limit_per_sec <= 0.0 || self.rate_limit_burst < 1.0 {
+            return Err(BitStableError::InvalidConfig("Gossip rate limit must allow at least one message".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Bounded record of recently seen message ids
+#[derive(Debug)]
+pub struct SeenCache {
+    seen: HashMap<MessageId, DateTime<Utc>>,
+    order: VecDeque<(MessageId, DateTime<Utc>)>,
+    capacity: usize,
+    expiry: Duration,
+}
+
+impl SeenCache {
+    pub fn new(capacity: usize, expiry: Duration) -> Self {
+        Self {
+            seen: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+            expiry,
+        }
+    }
+
+    /// Record `id`; returns false if it was already seen
+    pub fn insert(&mut self, id: MessageId, now: DateTime<Utc>) -> bool {
+        self.evict(now);
+        if self.seen.contains_key(&id) {
+            return false;
+        }
+
+        self.seen.insert(id, now);
+        self.order.push_back((id, now));
+        while self.order.len() > self.capacity {
+            if let Some((oldest, _)) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    pub fn contains(&self, id: &MessageId) -> bool {
+        self.seen.contains_key(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    fn evict(&mut self, now: DateTime<Utc>) {
+        while let Some((id, seen_at)) = self.order.front() {
+            if now.signed_duration_since(*seen_at) < self.expiry {
+                break;
+            }
+            self.seen.remove(id);
+            self.order.pop_front();
+        }
+    }
+}
+
+/// Token bucket per peer
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_second: f64,
+    burst: f64,
+    buckets: HashMap<PublicKey, (f64, DateTime<Utc>)>,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: f64, burst: f64) -> Self {
+        Self {
+            per_second,
+            burst,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Spend one token for `peer`; false means the peer is over its limit
+    pub fn allow(&mut self, peer: &PublicKey, now: DateTime<Utc>) -> bool {
+        let (tokens, last) = self.buckets.entry(*peer).or_insert((self.burst, now));
+        let elapsed = now.signed_duration_since(*last).num_milliseconds().max(0) as f64 / 1000.0;
+        *tokens = (*tokens + elapsed * self.per_second).min(self.burst);
+        *last = now;
+
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn forget(&mut self, peer: &PublicKey) {
+        self.buckets.remove(peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seen_cache_and_rate_limit() {
+        let now = Utc::now();
+        let mut seen = SeenCache::new(2, Duration::minutes(10));
+        assert!(seen.insert([1; 32], now));
+        assert!(!seen.insert([1; 32], now));
+
+        // Capacity evicts the oldest, expiry evicts by age
+        assert!(seen.insert([2; 32], now));
+        assert!(seen.insert([3; 32], now));
+        assert!(!seen.contains(&[1; 32]));
+        assert!(seen.insert([4; 32], now + Duration::minutes(11)));
+        assert_eq!(seen.len(), 1);
+
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let secret = bitcoin::secp256k1::SecretKey::from_slice(&[7; 32]).unwrap();
+        let peer = PublicKey::new(bitcoin::secp256k1::PublicKey::from_secret_key(&secp, &secret));
+
+        let mut limiter = RateLimiter::new(1.0, 3.0);
+        assert_eq!((0..5).filter(|_| limiter.allow(&peer, now)).count(), 3);
+        assert!(limiter.allow(&peer, now + Duration::seconds(1)));
+        assert!(!limiter.allow(&peer, now + Duration::seconds(1)));
+    }
+}
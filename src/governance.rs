@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use chrono::{DateTime, Utc, Duration};
 use crate::{BitStableError, Result};
+use crate::events::{EventBus, EventEmitter, ProtocolEvent};
 
 /// Governance system for protocol parameter updates and emergency responses
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,6 +15,8 @@ pub struct GovernanceSystem {
     pub next_proposal_id: u64,
     pub emergency_keyholders: Vec<PublicKey>,
     pub key_rotation_schedule: KeyRotationSchedule,
+    #[serde(skip)]
+    events: EventEmitter,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -162,9 +165,14 @@ impl GovernanceSystem {
             next_proposal_id: 1,
             emergency_keyholders: Vec::new(),
             key_rotation_schedule,
+            events: EventEmitter::default(),
         }
     }
 
+    pub fn set_event_bus(&mut self, bus: EventBus) {
+        self.events.attach(bus);
+    }
+
     /// Add a new keyholder with geographic and role distribution
     pub fn add_keyholder(&mut self, keyholder: Keyholder) -> Result<()> {
         // Check for duplicate
@@ -255,6 +263,7 @@ impl GovernanceSystem {
             proposal_id,
             proposal.title
         );
+        self.events.emit(ProtocolEvent::ProposalCreated { proposal_id, proposer, title: proposal.title, emergency });
 
         Ok(proposal_id)
     }
@@ -322,6 +331,7 @@ impl GovernanceSystem {
             decision,
             vote.weight
         );
+        self.events.emit(ProtocolEvent::VoteCast { proposal_id, voter, decision });
 
         Ok(())
     }
@@ -341,6 +351,7 @@ impl GovernanceSystem {
         // Check if voting deadline passed
         if Utc::now() > proposal.voting_deadline {
             proposal.status = ProposalStatus::Expired;
+            self.events.emit(ProtocolEvent::ProposalStatusChanged { proposal_id, status: ProposalStatus::Expired });
             return Ok(proposal.status.clone());
         }
 
@@ -371,34 +382,33 @@ impl GovernanceSystem {
             approval_rate * 100.0,
             participation * 100.0
         );
+        self.events.emit(ProtocolEvent::ProposalStatusChanged { proposal_id, status: proposal.status.clone() });
 
         Ok(proposal.status.clone())
     }
 
     /// Execute a passed proposal after timelock
     pub fn execute_proposal(&mut self, proposal_id: u64) -> Result<ExecutionResult> {
-        // Extract proposal data first to avoid borrowing conflicts
-        let (proposal_type, _execution_deadline) = {
-            let proposal = self.proposals.get(&proposal_id)
-                .ok_or_else(|| BitStableError::InvalidConfig("Proposal not found".to_string()))?;
-
-            if proposal.status != ProposalStatus::Passed {
-                return Err(BitStableError::InvalidConfig("Proposal has not passed".to_string()));
-            }
+        let now = Utc::now();
+        let result = self.proposal_outcome(proposal_id, now)?;
+        self.record_execution(proposal_id, &result, now);
+        Ok(result)
+    }
 
-            if Utc::now() < proposal.execution_deadline {
-                return Err(BitStableError::InvalidConfig("Execution timelock has not expired".to_string()));
-            }
+    /// What executing a proposal at `now` would do, without executing it
+    pub fn proposal_outcome(&self, proposal_id: u64, now: DateTime<Utc>) -> Result<ExecutionResult> {
+        let proposal = self.proposals.get(&proposal_id)
+            .ok_or_else(|| BitStableError::InvalidConfig("Proposal not found".to_string()))?;
 
-            (proposal.proposal_type.clone(), proposal.execution_deadline)
-        };
+        if proposal.status != ProposalStatus::Passed {
+            return Err(BitStableError::InvalidConfig("Proposal has not passed".to_string()));
+        }
 
-        // Now we can safely modify the proposal status
-        if let Some(proposal) = self.proposals.get_mut(&proposal_id) {
-            proposal.status = ProposalStatus::Executed;
+        if now < proposal.execution_deadline {
+            return Err(BitStableError::InvalidConfig("Execution timelock has not expired".to_string()));
         }
 
-        let result = match &proposal_type {
+        let result = match &proposal.proposal_type {
             ProposalType::ParameterChange { parameter, new_value, .. } => {
                 ExecutionResult::ParameterChanged {
                     parameter: parameter.clone(),
@@ -406,7 +416,10 @@ impl GovernanceSystem {
                 }
             },
             ProposalType::KeyRotation { keys_to_remove, keys_to_add } => {
-                self.execute_key_rotation(keys_to_remove.clone(), keys_to_add.clone())?
+                ExecutionResult::KeyRotationCompleted {
+                    removed_keys: keys_to_remove.clone(),
+                    added_keys: keys_to_add.clone(),
+                }
             },
             ProposalType::EmergencyShutdown { reason } => {
                 ExecutionResult::EmergencyShutdown {
@@ -436,19 +449,28 @@ impl GovernanceSystem {
                 }
             },
         };
+        Ok(result)
+    }
+
+    /// Record a proposal executed at `executed_at` with `result`, applying the key
+    /// changes it carries. The proposal itself need not be known here: replicas
+    /// record results decided on another node.
+    pub fn record_execution(&mut self, proposal_id: u64, result: &ExecutionResult, executed_at: DateTime<Utc>) {
+        if let Some(proposal) = self.proposals.get_mut(&proposal_id) {
+            proposal.status = ProposalStatus::Executed;
+        }
+        if let ExecutionResult::KeyRotationCompleted { removed_keys, .. } = result {
+            self.execute_key_rotation(removed_keys, executed_at);
+        }
 
         log::info!("Executed proposal {}: {:?}", proposal_id, result);
-        Ok(result)
+        self.events.emit(ProtocolEvent::ProposalExecuted { proposal_id, result: result.clone() });
     }
 
     /// Execute key rotation
-    fn execute_key_rotation(
-        &mut self,
-        keys_to_remove: Vec<PublicKey>,
-        keys_to_add: Vec<PublicKey>,
-    ) -> Result<ExecutionResult> {
+    fn execute_key_rotation(&mut self, keys_to_remove: &[PublicKey], executed_at: DateTime<Utc>) {
         // Remove keys
-        for key in &keys_to_remove {
+        for key in keys_to_remove {
             self.keyholders.retain(|k| k.pubkey != *key);
             self.emergency_keyholders.retain(|k| k != key);
         }
@@ -456,14 +478,9 @@ impl GovernanceSystem {
         // Add new keys would require separate keyholder creation
         // This is a placeholder for the actual key addition logic
 
-        self.key_rotation_schedule.last_rotation = Utc::now();
-        self.key_rotation_schedule.next_rotation = Utc::now() + 
+        self.key_rotation_schedule.last_rotation = executed_at;
+        self.key_rotation_schedule.next_rotation = executed_at + 
             Duration::days(30 * self.key_rotation_schedule.rotation_interval_months as i64);
-
-        Ok(ExecutionResult::KeyRotationCompleted {
-            removed_keys: keys_to_remove,
-            added_keys: keys_to_add,
-        })
     }
 
     /// Get governance statistics
@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use chrono::{DateTime, Utc};
 use crate::{BitStableError, Result, ProtocolConfig};
-use crate::multi_currency::{Currency, ExchangeRates};
+use crate::multi_currency::{btc_amount, Currency, ExchangeRates};
 
 /// Protocol insurance fund for handling black swan events and system recapitalization
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -74,8 +74,12 @@ impl InsuranceFund {
 
     /// Contribute protocol fees to the insurance fund
     pub fn contribute_from_fees(&mut self, protocol_fees: Amount, source: ContributionSource) -> Result<()> {
-        let contribution_amount = Amount::from_btc(protocol_fees.to_btc() * self.fee_percentage)
-            .map_err(|e| BitStableError::InvalidConfig(format!("Invalid contribution amount: {}", e)))?;
+        self.contribute_from_fees_at(protocol_fees, source, Utc::now())
+    }
+
+    /// Contribute fees collected at `timestamp`, e.g. by a liquidation settled then
+    pub fn contribute_from_fees_at(&mut self, protocol_fees: Amount, source: ContributionSource, timestamp: DateTime<Utc>) -> Result<()> {
+        let contribution_amount = btc_amount(protocol_fees.to_btc() * self.fee_percentage)?;
 
         self.balance_btc += contribution_amount;
         self.total_contributions += contribution_amount;
@@ -83,7 +87,7 @@ impl InsuranceFund {
         let contribution = InsuranceContribution {
             amount: contribution_amount,
             source,
-            timestamp: Utc::now(),
+            timestamp,
             transaction_id: None,
         };
 
@@ -19,27 +19,58 @@ pub mod stability_pool;
 pub mod emergency;
 pub mod risk_metrics;
 pub mod proof_of_reserves;
+pub mod publisher;
+pub mod circuit_breaker;
+pub mod price_stream;
+pub mod candles;
+pub mod wire;
+pub mod noise;
+pub mod gossip;
+pub mod address_book;
+pub mod vault_sync;
+pub mod simulation;
+pub mod raft;
+pub mod replication;
+pub mod schema;
+pub mod storage;
+pub mod backup;
+pub mod export;
+pub mod audit;
+pub mod events;
 
 use bitcoin::{Amount, PublicKey, Txid};
+use bitcoin::secp256k1::SecretKey;
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use audit::{AuditAction, AuditAnchor, AuditLog};
+use database::{DatabaseManager, Subsystem, UnitOfWork};
+use insurance::ContributionSource;
+use proof_of_reserves::SystemStateSnapshot;
+use redemption::RedemptionState;
 // Re-export for public use
 
 pub use error::{BitStableError, Result};
-pub use vault::{Vault, VaultState, VaultManager};
-pub use oracle::{Oracle, MultiCurrencyOracleNetwork, PriceConsensus};
+pub use vault::{Vault, VaultState, VaultManager, EscrowFunding};
+pub use oracle::{Oracle, MultiCurrencyOracleNetwork, PriceConsensus, PriceQuote, PegPrice};
 pub use liquidation::{LiquidationEngine, LiquidationOpportunity};
-pub use stable::StableTransfer;
-pub use config::ProtocolConfig;
+pub use stable::{MultiCurrencyStableManager, StableTransfer};
+pub use config::{ProtocolConfig, TwapWindow};
 pub use custody::{CustodyManager, EscrowContract, LiquidationSettlement};
 pub use bitcoin_client::{BitcoinClient, BitcoinConfig};
-pub use multi_currency::{Currency, CurrencyConfig, ExchangeRates, MultiCurrencyPosition};
+pub use multi_currency::{Currency, CurrencyConfig, ExchangeRates, MultiCurrencyPosition, PriceMode};
 pub use stability_controller::{StabilityController, RebalanceAction};
 pub use redemption::{RedemptionEngine, RedemptionRecord, RedemptionStats};
 pub use insurance::{InsuranceFund, EmergencyAction, InsuranceFundHealth};
 pub use governance::{GovernanceSystem, Proposal, ProposalType, ExecutionResult};
 pub use stability_pool::{StabilityPool, StabilityLiquidation, DepositorInfo};
 pub use emergency::{EmergencyShutdownSystem, ShutdownState, AlertAction};
-pub use risk_metrics::{RiskMetricsSystem, SystemRiskMetrics, RiskDashboard, SystemHealth};
+pub use risk_metrics::{RiskMetricsSystem, SystemRiskMetrics, RiskDashboard, SystemHealth, RiskAlert};
 pub use proof_of_reserves::{ProofOfReservesSystem, ReservesCommitment, MerkleProof, FraudProof};
+pub use publisher::{OraclePublisher, PublicationPolicy, PublishedUpdate};
+pub use circuit_breaker::{CircuitBreakerSystem, BreakerState, BreakerTier, BreakerEvent};
+pub use price_stream::{PriceStream, StreamConfig, StreamStats, StreamStatus};
+pub use candles::{Candle, CandleAggregator, CandleInterval, RetentionPolicy};
+pub use events::{EventBus, EventRecord, EventSubscription, ProtocolEvent};
 
 #[derive(Debug)]
 pub struct BitStableProtocol {
@@ -50,43 +81,545 @@ pub struct BitStableProtocol {
     pub custody_manager: CustodyManager,
     pub stability_controller: StabilityController,
     pub redemption_engine: RedemptionEngine,
+    pub stable_manager: MultiCurrencyStableManager,
     pub insurance_fund: InsuranceFund,
     pub stability_pool: StabilityPool,
     pub emergency_system: EmergencyShutdownSystem,
     pub risk_metrics: RiskMetricsSystem,
     pub proof_of_reserves: ProofOfReservesSystem,
+    pub events: EventBus,
+    pub audit_log: AuditLog,
     pub bitcoin_client: Option<BitcoinClient>,
+    rollback: Option<WorkSnapshot>,   // What the state change in progress may need to undo
+    pinned_circuit_breakers: Option<HashSet<Currency>>,   // Halted instead of the oracles' set, see `pin_circuit_breakers`
+    commit_marker: Option<(String, serde_json::Value)>,   // Config value written with every commit, see `mark_commits`
+}
+
+/// In-memory state a state change can modify before it commits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Touches {
+    Custody,
+    Liquidations,
+    Subsystem(Subsystem),
+}
+
+/// Copies taken by `begin_work` of the state a change touches, restored if it fails
+#[derive(Debug, Default)]
+struct WorkSnapshot {
+    subsystems: Vec<Subsystem>,   // Staged again when the change commits
+    custody: Option<custody::CustodySnapshot>,
+    liquidation_engine: Option<LiquidationEngine>,
+    governance: Option<GovernanceSystem>,
+    insurance_fund: Option<InsuranceFund>,
+    stability_pool: Option<StabilityPool>,
+    emergency_system: Option<EmergencyShutdownSystem>,
+    redemption: Option<RedemptionState>,
+    stable_manager: Option<MultiCurrencyStableManager>,
 }
 
 impl BitStableProtocol {
     pub fn new(config: ProtocolConfig) -> Result<Self> {
-        Ok(Self {
-            vault_manager: VaultManager::new(&config)?,
-            oracle_network: MultiCurrencyOracleNetwork::new(&config)?,
+        // Opening the database manager brings the stored schema up to date
+        let database = DatabaseManager::with_storage(storage::open_storage(&config.database_path)?)?;
+        Self::with_database(config, database)
+    }
+
+    /// Run over an already open database instead of `config.database_path`
+    pub fn with_database(config: ProtocolConfig, database: DatabaseManager) -> Result<Self> {
+        let vault_manager = VaultManager::with_database(&config, database.clone())?;
+        let oracle_network = MultiCurrencyOracleNetwork::new(&config)?
+            .with_database(database.clone())?;
+        let mut custody_manager = CustodyManager::new(&config)?
+            .with_escrow_contracts(database.load_escrow_contracts()?);
+        let mut settlements = database.get_settlement_history(usize::MAX)?;
+        settlements.reverse();
+        custody_manager = custody_manager.with_settlements(settlements);
+        if let Some(governance_system) = database.load_subsystem(Subsystem::Governance)? {
+            custody_manager = custody_manager.with_governance_system(governance_system);
+        }
+        let mut redemption_engine = RedemptionEngine::new(&config);
+        if let Some(state) = database.load_subsystem(Subsystem::RedemptionEngine)? {
+            redemption_engine.restore_state(state);
+        }
+
+        let mut protocol = Self {
+            vault_manager,
+            oracle_network,
             liquidation_engine: LiquidationEngine::new(&config)?,
-            custody_manager: CustodyManager::new(&config)?,
+            custody_manager,
             stability_controller: StabilityController::new(
                 bitcoin::PublicKey::from_slice(&[2; 33]).unwrap(),
                 Currency::USD,
                 0.0
             ),
-            redemption_engine: RedemptionEngine::new(&config),
-            insurance_fund: InsuranceFund::new(&config),
-            stability_pool: StabilityPool::new(&config),
-            emergency_system: EmergencyShutdownSystem::new(&config),
+            redemption_engine,
+            stable_manager: database.load_subsystem(Subsystem::StableBalances)?
+                .unwrap_or_default(),
+            insurance_fund: database.load_subsystem(Subsystem::InsuranceFund)?
+                .unwrap_or_else(|| InsuranceFund::new(&config)),
+            stability_pool: database.load_subsystem(Subsystem::StabilityPool)?
+                .unwrap_or_else(|| StabilityPool::new(&config)),
+            emergency_system: database.load_subsystem(Subsystem::EmergencyShutdown)?
+                .unwrap_or_else(|| EmergencyShutdownSystem::new(&config)),
             risk_metrics: RiskMetricsSystem::new(&config),
             proof_of_reserves: ProofOfReservesSystem::new(),
+            events: EventBus::new(database.clone()),
+            audit_log: AuditLog::new(database),
             bitcoin_client: None,
+            rollback: None,
+            pinned_circuit_breakers: None,
+            commit_marker: None,
             config,
+        };
+        protocol.attach_event_bus();
+        // Liquidation checks price each currency the way its configuration asks
+        let price_modes: Vec<_> = protocol.vault_manager.get_currency_configs().iter()
+            .map(|(currency, config)| (currency.clone(), config.liquidation_price_mode))
+            .collect();
+        for (currency, mode) in price_modes {
+            protocol.liquidation_engine.set_price_mode(currency, mode);
+        }
+        Ok(protocol)
+    }
+
+    /// Connect the subsystems that emit events; restored subsystems start detached
+    fn attach_event_bus(&mut self) {
+        self.vault_manager.set_event_bus(self.events.clone());
+        self.liquidation_engine.set_event_bus(self.events.clone());
+        self.redemption_engine.set_event_bus(self.events.clone());
+        self.stability_pool.set_event_bus(self.events.clone());
+        self.custody_manager.governance_system_mut().set_event_bus(self.events.clone());
+        self.emergency_system.set_event_bus(self.events.clone());
+    }
+
+    /// Open the protocol over the database at `path`, restoring every subsystem
+    /// saved by an earlier run
+    pub fn open(path: &str) -> Result<Self> {
+        Self::new(ProtocolConfig {
+            database_path: path.to_string(),
+            ..ProtocolConfig::default()
         })
     }
 
+    /// Persist every subsystem, escrow contract and pending event in one atomic commit
+    pub fn flush(&mut self) -> Result<()> {
+        self.flush_with(UnitOfWork::new())
+    }
+
+    /// Flush together with the records already staged in `work`
+    pub fn flush_with(&mut self, mut work: UnitOfWork) -> Result<()> {
+        if let Some((key, value)) = &self.commit_marker {
+            work.put_config(key, value)?;
+        }
+        for contract in self.custody_manager.escrow_contracts() {
+            work.put_escrow_contract(contract)?;
+        }
+        self.stage_subsystems(&mut work)?;
+        let events = self.events.stage(&mut work)?;
+        if let Err(e) = self.vault_manager.commit_work(work) {
+            self.events.unstage(events);
+            return Err(e);
+        }
+        self.events.publish(events);
+        Ok(())
+    }
+
+    /// Write `value` under the config `key` in every commit from now on, so what the
+    /// caller records there always describes the state committed with it. Replicas
+    /// record how far their log is applied.
+    pub fn mark_commits<T: serde::Serialize>(&mut self, key: &str, value: &T) -> Result<()> {
+        self.commit_marker = Some((key.to_string(), serde_json::to_value(value)?));
+        Ok(())
+    }
+
+    /// Snapshot the in-memory subsystems into `work`, so they are written in the
+    /// same transaction as the records of the change that produced them
+    fn stage_subsystems(&self, work: &mut UnitOfWork) -> Result<()> {
+        for subsystem in Subsystem::ALL {
+            self.stage_subsystem(work, subsystem)?;
+        }
+        Ok(())
+    }
+
+    fn stage_subsystem(&self, work: &mut UnitOfWork, subsystem: Subsystem) -> Result<()> {
+        match subsystem {
+            Subsystem::Governance => work.put_subsystem(subsystem, self.custody_manager.governance_system()),
+            Subsystem::InsuranceFund => work.put_subsystem(subsystem, &self.insurance_fund),
+            Subsystem::StabilityPool => work.put_subsystem(subsystem, &self.stability_pool),
+            Subsystem::EmergencyShutdown => work.put_subsystem(subsystem, &self.emergency_system),
+            Subsystem::RedemptionEngine => work.put_subsystem(subsystem, &self.redemption_engine.state()),
+            Subsystem::StableBalances => work.put_subsystem(subsystem, &self.stable_manager),
+        }
+    }
+
     /// Initialize with Bitcoin client for on-chain operations
     pub fn with_bitcoin_client(mut self, bitcoin_config: BitcoinConfig) -> Result<Self> {
         self.bitcoin_client = Some(bitcoin_config.create_client()?);
         Ok(self)
     }
 
+    /// Push tripped oracle circuit breakers to the subsystems they halt
+    pub fn sync_circuit_breakers(&mut self) {
+        let tripped = match &self.pinned_circuit_breakers {
+            Some(pinned) => pinned.clone(),
+            None => self.oracle_network.get_circuit_breakers().tripped_currencies(),
+        };
+        self.vault_manager.update_circuit_breakers(tripped.clone());
+        self.liquidation_engine.update_circuit_breakers(tripped);
+    }
+
+    /// Halt exactly `tripped` from now on, whatever this machine's oracles report;
+    /// replicas pin the set agreed through their log
+    pub fn pin_circuit_breakers(&mut self, tripped: HashSet<Currency>) {
+        self.pinned_circuit_breakers = Some(tripped);
+        self.sync_circuit_breakers();
+    }
+
+    /// Price everything at `exchange_rates` instead of this machine's oracle consensus
+    pub fn set_exchange_rates(&mut self, exchange_rates: ExchangeRates) {
+        self.oracle_network.set_exchange_rates(exchange_rates.clone());
+        self.vault_manager.update_exchange_rates(exchange_rates);
+    }
+
+    /// Add or reconfigure a currency, keeping liquidation price modes in step
+    pub fn configure_currency(&mut self, currency: Currency, config: CurrencyConfig) {
+        self.liquidation_engine.set_price_mode(currency.clone(), config.liquidation_price_mode);
+        self.vault_manager.add_currency(currency, config);
+    }
+
+    /// Tag consensus TWAP samples with the chain tip when a Bitcoin client is attached
+    pub fn sync_block_height(&mut self) -> Result<()> {
+        if let Some(bitcoin_client) = &self.bitcoin_client {
+            let height = bitcoin_client.get_block_height()?;
+            self.oracle_network.set_block_height(height);
+        }
+        Ok(())
+    }
+
+    /// The periodic price update: fetch consensus and peg prices, then run the peg
+    /// monitor and emergency triggers over them
+    pub async fn refresh_prices(&mut self) -> Result<Vec<RiskAlert>> {
+        if let Err(e) = self.sync_block_height() {
+            log::warn!("Failed to read block height for TWAP samples: {}", e);
+        }
+        self.oracle_network.get_consensus_prices().await?;
+        self.oracle_network.fetch_peg_prices().await;
+        self.monitor_peg()
+    }
+
+    /// Check stable unit market prices against their pegs, raising depeg alerts and
+    /// running the emergency shutdown triggers over the current system state
+    pub fn monitor_peg(&mut self) -> Result<Vec<RiskAlert>> {
+        let now = chrono::Utc::now();
+        let peg_prices = self.oracle_network.get_peg_prices(now);
+        let alerts = self.risk_metrics.check_peg_deviation(&peg_prices, now);
+
+        // A trigger may escalate the shutdown state or open an emergency proposal
+        self.begin_work(&[
+            Touches::Subsystem(Subsystem::EmergencyShutdown),
+            Touches::Subsystem(Subsystem::Governance),
+        ]);
+        let system_state = self.emergency_snapshot(now);
+        let result = self.emergency_system
+            .check_system_health(system_state, self.custody_manager.governance_system_mut())
+            .map(|_| ((), UnitOfWork::new()));
+        self.finish_work(result)?;
+        Ok(alerts)
+    }
+
+    /// Recompute risk metrics from the active vaults and the stored daily candles
+    pub fn update_risk_metrics(&mut self) -> Result<Vec<RiskAlert>> {
+        let database = self.vault_manager.database()?;
+        self.risk_metrics.update_risk_metrics_from_candles(
+            &self.vault_manager,
+            self.oracle_network.get_exchange_rates(),
+            self.oracle_network.get_oracles(),
+            &database,
+        )
+    }
+
+    /// System state as the emergency shutdown triggers see it
+    fn emergency_snapshot(&self, now: chrono::DateTime<chrono::Utc>) -> emergency::SystemStateSnapshot {
+        let exchange_rates = self.oracle_network.get_exchange_rates();
+        let vaults: Vec<&Vault> = self.vault_manager.list_vaults()
+            .into_iter()
+            .filter(|vault| vault.state == VaultState::Active)
+            .collect();
+        let total_collateral_btc: f64 = vaults.iter().map(|vault| vault.collateral_btc.to_btc()).sum();
+        let total_debt_usd: f64 = vaults.iter().map(|vault| vault.debts.total_debt_in_usd(exchange_rates)).sum();
+        let btc_price = exchange_rates.get_btc_price(&Currency::USD).unwrap_or(0.0);
+        let consensus = self.oracle_network.get_latest_consensus();
+
+        emergency::SystemStateSnapshot {
+            timestamp: now,
+            system_collateral_ratio: Self::system_collateral_ratio(total_collateral_btc, btc_price, total_debt_usd),
+            total_debt_usd,
+            total_collateral_btc,
+            active_vaults: vaults.len(),
+            oracle_failures: consensus.map_or(0, |round| round.total_oracles.saturating_sub(round.participating_oracles)),
+            insurance_fund_balance: self.insurance_fund.balance_btc,
+            stability_pool_size: self.stability_pool.total_deposited.iter()
+                .map(|(currency, amount)| amount * exchange_rates.get_rate_to_usd(currency).unwrap_or(1.0))
+                .sum(),
+            sustained_peg_deviation: self.risk_metrics.sustained_peg_deviation(now),
+        }
+    }
+
+    /// With no debt outstanding nothing is undercollateralized. `f64::MAX` rather than
+    /// infinity keeps the ratio serializable in reserve commitments.
+    fn system_collateral_ratio(collateral_btc: f64, btc_price: f64, debt_usd: f64) -> f64 {
+        if debt_usd > 0.0 { collateral_btc * btc_price / debt_usd } else { f64::MAX }
+    }
+
+    /// Start a state change finished by `finish_work`, snapshotting the state it touches
+    fn begin_work(&mut self, touches: &[Touches]) {
+        self.vault_manager.begin_work();
+        self.events.begin();
+        
+        let mut snapshot = WorkSnapshot::default();
+        for touched in touches {
+            match touched {
+                Touches::Custody => snapshot.custody = Some(self.custody_manager.snapshot()),
+                Touches::Liquidations => snapshot.liquidation_engine = Some(self.liquidation_engine.clone()),
+                Touches::Subsystem(Subsystem::Governance) => {
+                    snapshot.governance = Some(self.custody_manager.governance_system().clone());
+                }
+                Touches::Subsystem(Subsystem::InsuranceFund) => snapshot.insurance_fund = Some(self.insurance_fund.clone()),
+                Touches::Subsystem(Subsystem::StabilityPool) => snapshot.stability_pool = Some(self.stability_pool.clone()),
+                Touches::Subsystem(Subsystem::EmergencyShutdown) => snapshot.emergency_system = Some(self.emergency_system.clone()),
+                Touches::Subsystem(Subsystem::RedemptionEngine) => snapshot.redemption = Some(self.redemption_engine.state()),
+                Touches::Subsystem(Subsystem::StableBalances) => snapshot.stable_manager = Some(self.stable_manager.clone()),
+            }
+            if let Touches::Subsystem(subsystem) = touched {
+                snapshot.subsystems.push(*subsystem);
+            }
+        }
+        self.rollback = Some(snapshot);
+    }
+
+    /// Run a state change with vault writes staged, committing them together with
+    /// the change's other records and events, or restoring the vaults if it fails.
+    /// Events are only broadcast once committed.
+    fn finish_work<T>(&mut self, result: Result<(T, UnitOfWork)>) -> Result<T> {
+        let (value, mut work) = match result {
+            Ok(staged) => staged,
+            Err(e) => return Err(self.abandon_work(e)),
+        };
+        // Only the subsystems the change declared can differ from their stored copies
+        let touched = self.rollback.as_ref().map(|snapshot| snapshot.subsystems.clone()).unwrap_or_default();
+        for subsystem in touched {
+            if let Err(e) = self.stage_subsystem(&mut work, subsystem) {
+                return Err(self.abandon_work(e));
+            }
+        }
+        if let Some((key, value)) = &self.commit_marker {
+            if let Err(e) = work.put_config(key, value) {
+                return Err(self.abandon_work(e));
+            }
+        }
+        let events = match self.events.stage(&mut work) {
+            Ok(events) => events,
+            Err(e) => return Err(self.abandon_work(e)),
+        };
+        if let Err(e) = self.vault_manager.commit_work(work) {
+            // Earlier events go back to wait for the next commit
+            self.events.unstage(events);
+            return Err(self.abandon_work(e));
+        }
+        self.rollback = None;
+        self.events.publish(events);
+        Ok(value)
+    }
+
+    /// Undo a failed state change, returning the error that failed it
+    fn abandon_work(&mut self, error: BitStableError) -> BitStableError {
+        self.events.abandon();
+        if let Some(snapshot) = self.rollback.take() {
+            self.restore(snapshot);
+        }
+        match self.vault_manager.rollback_work() {
+            Ok(()) => error,
+            Err(e) => e,
+        }
+    }
+
+    fn restore(&mut self, snapshot: WorkSnapshot) {
+        if let Some(custody) = snapshot.custody {
+            self.custody_manager.restore(custody);
+        }
+        if let Some(liquidation_engine) = snapshot.liquidation_engine {
+            self.liquidation_engine = liquidation_engine;
+        }
+        if let Some(governance) = snapshot.governance {
+            *self.custody_manager.governance_system_mut() = governance;
+        }
+        if let Some(insurance_fund) = snapshot.insurance_fund {
+            self.insurance_fund = insurance_fund;
+        }
+        if let Some(stability_pool) = snapshot.stability_pool {
+            self.stability_pool = stability_pool;
+        }
+        if let Some(emergency_system) = snapshot.emergency_system {
+            self.emergency_system = emergency_system;
+        }
+        if let Some(redemption) = snapshot.redemption {
+            self.redemption_engine.restore_state(redemption);
+        }
+        if let Some(stable_manager) = snapshot.stable_manager {
+            self.stable_manager = stable_manager;
+        }
+    }
+
+    /// Run a privileged action and record it in the audit log, signed by `actor`;
+    /// the entry commits atomically with the subsystem state the action changed
+    fn audited<T>(
+        &mut self,
+        actor: &SecretKey,
+        touches: &[Touches],
+        action: impl FnOnce(&mut Self) -> Result<(T, AuditAction)>,
+    ) -> Result<T> {
+        self.begin_work(touches);
+        let result = action(self).and_then(|(value, audit_action)| {
+            let mut work = UnitOfWork::new();
+            self.audit_log.stage(&mut work, actor, audit_action)?;
+            Ok((value, work))
+        });
+        self.finish_work(result)
+    }
+
+    /// Execute a passed governance proposal
+    pub fn execute_proposal(&mut self, actor: &SecretKey, proposal_id: u64) -> Result<ExecutionResult> {
+        let now = Utc::now();
+        let result = self.custody_manager.governance_system().proposal_outcome(proposal_id, now)?;
+        self.apply_proposal_result(actor, proposal_id, result.clone(), now)?;
+        Ok(result)
+    }
+
+    /// Record a proposal as executed at `executed_at` with `result` and carry the result
+    /// out, audited as `actor`. Replicas use this for results decided on the leader,
+    /// whose proposals they need not know.
+    pub fn apply_proposal_result(
+        &mut self,
+        actor: &SecretKey,
+        proposal_id: u64,
+        result: ExecutionResult,
+        executed_at: DateTime<Utc>,
+    ) -> Result<()> {
+        let secp = bitcoin::secp256k1::Secp256k1::signing_only();
+        let triggered_by = PublicKey::new(actor.public_key(&secp));
+        let touches = [
+            Touches::Subsystem(Subsystem::Governance),
+            Touches::Subsystem(Subsystem::EmergencyShutdown),
+        ];
+        self.audited(actor, &touches, |protocol| {
+            protocol.custody_manager.governance_system_mut().record_execution(proposal_id, &result, executed_at);
+            match &result {
+                ExecutionResult::EmergencyShutdown { reason } => {
+                    protocol.emergency_system.execute_emergency_shutdown(reason.clone(), None, Some(triggered_by))?;
+                }
+                ExecutionResult::CircuitBreakerOverride { duration } => {
+                    protocol.oracle_network.apply_governance_override(None, *duration)?;
+                }
+                // Key rotations were applied by `record_execution`; nothing in the
+                // protocol acts on the other results yet
+                ExecutionResult::KeyRotationCompleted { .. }
+                | ExecutionResult::ParameterChanged { .. }
+                | ExecutionResult::OracleAdded { .. }
+                | ExecutionResult::OracleRemoved { .. }
+                | ExecutionResult::InsuranceFundAllocated { .. } => {}
+            }
+            Ok(((), AuditAction::governance(proposal_id, &result)))
+        })
+    }
+
+    pub fn emergency_shutdown(&mut self, actor: &SecretKey, reason: String) -> Result<()> {
+        let secp = bitcoin::secp256k1::Secp256k1::signing_only();
+        let triggered_by = PublicKey::new(actor.public_key(&secp));
+        self.audited(actor, &[Touches::Subsystem(Subsystem::EmergencyShutdown)], |protocol| {
+            protocol.emergency_system.execute_emergency_shutdown(reason.clone(), None, Some(triggered_by))?;
+            Ok(((), AuditAction::EmergencyShutdown { reason }))
+        })
+    }
+
+    /// Switch the circuit breakers' emergency override on or off
+    pub fn set_emergency_override(&mut self, actor: &SecretKey, enabled: bool) -> Result<()> {
+        self.audited(actor, &[], |protocol| {
+            protocol.oracle_network.enable_emergency_override(enabled);
+            Ok(((), AuditAction::EmergencyOverride { enabled }))
+        })
+    }
+
+    /// Bypass the circuit breakers for one currency, or all when `currency` is `None`
+    pub fn override_circuit_breakers(&mut self, actor: &SecretKey, currency: Option<Currency>, duration: chrono::Duration) -> Result<()> {
+        self.audited(actor, &[], |protocol| {
+            protocol.oracle_network.apply_governance_override(currency.as_ref(), duration)?;
+            let until = chrono::Utc::now() + duration;
+            Ok(((), AuditAction::CircuitBreakerOverride { currency, until }))
+        })
+    }
+
+    /// Pay a vault's bad debt from the insurance fund
+    pub fn cover_bad_debt(&mut self, actor: &SecretKey, vault_id: Txid, amount: Amount) -> Result<insurance::InsurancePayout> {
+        let owner = self.vault_manager.get_vault(vault_id)?.owner;
+        self.audited(actor, &[Touches::Subsystem(Subsystem::InsuranceFund)], |protocol| {
+            let payout = protocol.insurance_fund.cover_bad_debt(vault_id, amount, owner)?;
+            Ok((payout.clone(), AuditAction::InsurancePayout { payouts: vec![payout] }))
+        })
+    }
+
+    /// Cover a system-wide deficit from the insurance fund
+    pub fn emergency_recapitalization(&mut self, actor: &SecretKey, deficit: Amount, reason: String) -> Result<insurance::InsurancePayout> {
+        self.audited(actor, &[Touches::Subsystem(Subsystem::InsuranceFund)], |protocol| {
+            let payout = protocol.insurance_fund.execute_emergency_recapitalization(deficit, reason)?;
+            Ok((payout.clone(), AuditAction::InsurancePayout { payouts: vec![payout] }))
+        })
+    }
+
+    /// Publish the audit log's head in a fresh proof-of-reserves commitment's
+    /// OP_RETURN, so later edits or truncation of the log are detectable
+    pub fn anchor_audit_log(&mut self) -> Result<Option<AuditAnchor>> {
+        if self.audit_log.head()?.is_none() {
+            return Ok(None);
+        }
+        let no_client = || BitStableError::InvalidConfig("Anchoring the audit log needs a Bitcoin client".to_string());
+        let block_height = self.bitcoin_client.as_ref().ok_or_else(no_client)?.get_block_height()?;
+        let Some(commitment) = self.prepare_audit_anchor(block_height)? else {
+            return Ok(None);
+        };
+        let bitcoin_client = self.bitcoin_client.as_ref().ok_or_else(no_client)?;
+        let txid = self.proof_of_reserves.submit_to_bitcoin(&commitment, bitcoin_client)?;
+        Ok(Some(self.audit_log.record_anchor(commitment, txid)?))
+    }
+
+    /// Commit to the active vaults and system state at `block_height`, carrying the
+    /// audit log's head, ready to be published
+    pub fn prepare_audit_anchor(&mut self, block_height: u64) -> Result<Option<ReservesCommitment>> {
+        let Some(head) = self.audit_log.head()? else {
+            return Ok(None);
+        };
+        let exchange_rates = self.oracle_network.get_exchange_rates();
+        let vaults: Vec<&Vault> = self.vault_manager.list_vaults()
+            .into_iter()
+            .filter(|vault| vault.state == VaultState::Active)
+            .collect();
+        let total_collateral_btc: Amount = vaults.iter().map(|vault| vault.collateral_btc).sum();
+        let total_debt_usd: f64 = vaults.iter().map(|vault| vault.debts.total_debt_in_usd(exchange_rates)).sum();
+        let btc_price = exchange_rates.get_btc_price(&Currency::USD).unwrap_or(0.0);
+        let consensus = self.oracle_network.get_latest_consensus();
+
+        let system_state = SystemStateSnapshot {
+            system_collateral_ratio: Self::system_collateral_ratio(total_collateral_btc.to_btc(), btc_price, total_debt_usd),
+            total_debt_all_currencies: total_debt_usd,
+            total_collateral_btc,
+            oracle_health: consensus.map_or(0.0, |round| round.participating_oracles as f64 / round.total_oracles.max(1) as f64),
+            insurance_balance: self.insurance_fund.balance_btc,
+            active_oracles: consensus.map_or(0, |round| round.participating_oracles),
+            emergency_state: self.emergency_system.shutdown_state != ShutdownState::Normal,
+        };
+        self.proof_of_reserves.generate_commitment(&vaults, system_state, block_height)?;
+        self.proof_of_reserves.attach_audit_head(head).map(Some)
+    }
+
     pub async fn open_vault(
         &mut self,
         owner: PublicKey,
@@ -94,15 +627,47 @@ impl BitStableProtocol {
         currency: Currency,
         stable_amount: f64,
     ) -> Result<EscrowContract> {
+        let vault_id = self.vault_manager.generate_vault_id();
+        self.open_vault_at(vault_id, owner, collateral, currency, stable_amount, Utc::now()).await
+    }
+
+    /// Open a vault with a caller-chosen id and creation time, as replicas do
+    pub async fn open_vault_at(
+        &mut self,
+        vault_id: Txid,
+        owner: PublicKey,
+        collateral: Amount,
+        currency: Currency,
+        stable_amount: f64,
+        created_at: DateTime<Utc>,
+    ) -> Result<EscrowContract> {
+        self.sync_circuit_breakers();
+        self.begin_work(&[Touches::Custody, Touches::Subsystem(Subsystem::StableBalances)]);
+        let result = self.open_vault_staged(vault_id, owner, collateral, currency, stable_amount, created_at).await;
+        self.finish_work(result)
+    }
+
+    async fn open_vault_staged(
+        &mut self,
+        vault_id: Txid,
+        owner: PublicKey,
+        collateral: Amount,
+        currency: Currency,
+        stable_amount: f64,
+        created_at: DateTime<Utc>,
+    ) -> Result<(EscrowContract, UnitOfWork)> {
         let exchange_rates = self.oracle_network.get_exchange_rates();
         
         // Create vault in the vault manager
-        let vault_id = self.vault_manager.create_vault(
+        self.vault_manager.create_vault_at(
+            vault_id,
             owner,
             collateral,
             currency.clone(),
             stable_amount,
+            created_at,
         ).await?;
+        self.stable_manager.mint_stable(owner, currency.clone(), stable_amount, vault_id)?;
         
         // Calculate liquidation threshold price
         let vault = self.vault_manager.get_vault(vault_id)?;
@@ -114,6 +679,7 @@ impl BitStableProtocol {
             owner,
             collateral,
             liquidation_price,
+            created_at,
         )?;
 
         log::info!(
@@ -124,7 +690,9 @@ impl BitStableProtocol {
             currency
         );
 
-        Ok(escrow_contract)
+        let mut work = UnitOfWork::new();
+        work.put_escrow_contract(&escrow_contract)?;
+        Ok((escrow_contract, work))
     }
 
     /// Fund a vault's escrow contract with actual Bitcoin
@@ -151,15 +719,84 @@ impl BitStableProtocol {
             }
         }
 
-        // Record the funding in the custody manager
-        self.custody_manager.process_vault_funding(vault_id, funding_txid, vout, amount)?;
+        // Record the funding in the custody manager and on the vault together
+        self.begin_work(&[Touches::Custody]);
+        let result = self.custody_manager.process_vault_funding(vault_id, funding_txid, vout, amount)
+            .and_then(|_| {
+                let mut work = UnitOfWork::new();
+                if let Some(contract) = self.custody_manager.get_escrow_contract(vault_id).cloned() {
+                    self.vault_manager.record_funding(vault_id, EscrowFunding {
+                        outpoint: bitcoin::OutPoint::new(funding_txid, vout),
+                        address: contract.multisig_address.to_string(),
+                    })?;
+                    work.put_escrow_contract(&contract)?;
+                }
+                Ok(((), work))
+            });
+        self.finish_work(result)?;
         
         log::info!("Vault {} funded with {} BTC", vault_id, amount.to_btc());
         Ok(())
     }
 
+    /// Mint more stable value against a vault, credited to its owner
+    pub async fn mint_stable(&mut self, vault_id: Txid, currency: Currency, amount: f64) -> Result<()> {
+        self.sync_circuit_breakers();
+        let owner = self.vault_manager.get_vault(vault_id)?.owner;
+        self.begin_work(&[Touches::Subsystem(Subsystem::StableBalances)]);
+        let result = match self.vault_manager.mint_additional(vault_id, currency.clone(), amount).await {
+            Ok(()) => self.stable_manager.mint_stable(owner, currency, amount, vault_id)
+                .map(|_| ((), UnitOfWork::new())),
+            Err(e) => Err(e),
+        };
+        self.finish_work(result)
+    }
+
+    /// Repay a vault's debt with stable value burned from its owner's balance
+    pub async fn burn_stable(&mut self, vault_id: Txid, currency: Currency, amount: f64) -> Result<()> {
+        let owner = self.vault_manager.get_vault(vault_id)?.owner;
+        self.begin_work(&[Touches::Subsystem(Subsystem::StableBalances)]);
+        let result = match self.stable_manager.burn_stable(owner, currency.clone(), amount) {
+            Ok(_) => self.vault_manager.burn_stable(vault_id, currency, amount).await
+                .map(|_| ((), UnitOfWork::new())),
+            Err(e) => Err(e),
+        };
+        self.finish_work(result)
+    }
+
+    /// Liquidate a vault. The vault update, liquidation record, settlement and
+    /// insurance contribution are committed together before anything is broadcast.
     pub async fn liquidate_vault(&mut self, vault_id: Txid, liquidator: PublicKey) -> Result<Txid> {
-        let exchange_rates = self.oracle_network.get_exchange_rates();
+        let liquidation_tx = self.prepare_liquidation(vault_id, liquidator, Utc::now()).await?;
+
+        // Broadcast the transaction if we have a Bitcoin client
+        if let Some(bitcoin_client) = &self.bitcoin_client {
+            let txid = bitcoin_client.broadcast_transaction(&liquidation_tx)?;
+            self.custody_manager.mark_transaction_broadcast(txid)?;
+            
+            log::info!("Liquidation transaction broadcast: {}", txid);
+            Ok(txid)
+        } else {
+            Ok(liquidation_tx.compute_txid())
+        }
+    }
+
+    /// Liquidate a vault at `now`, committed as `liquidate_vault` does, and return
+    /// the settlement transaction without broadcasting it
+    pub async fn prepare_liquidation(&mut self, vault_id: Txid, liquidator: PublicKey, now: DateTime<Utc>) -> Result<bitcoin::Transaction> {
+        self.sync_circuit_breakers();
+        self.begin_work(&[
+            Touches::Custody,
+            Touches::Liquidations,
+            Touches::Subsystem(Subsystem::InsuranceFund),
+            Touches::Subsystem(Subsystem::StableBalances),
+        ]);
+        let result = self.liquidate_vault_staged(vault_id, liquidator, now).await;
+        self.finish_work(result)
+    }
+
+    async fn liquidate_vault_staged(&mut self, vault_id: Txid, liquidator: PublicKey, now: DateTime<Utc>) -> Result<(bitcoin::Transaction, UnitOfWork)> {
+        let exchange_rates = self.oracle_network.get_exchange_rates().clone();
         
         // Get vault information for liquidation calculation
         let vault = self.vault_manager.get_vault(vault_id)?;
@@ -168,15 +805,16 @@ impl BitStableProtocol {
         let btc_price = exchange_rates.get_btc_price(&Currency::USD).unwrap_or(0.0);
         if !self.custody_manager.can_liquidate_vault(vault_id, btc_price) {
             return Err(BitStableError::LiquidationNotPossible {
-                ratio: vault.collateral_ratio(exchange_rates)
+                ratio: vault.collateral_ratio(&exchange_rates)
             });
         }
 
-        // Execute liquidation in the liquidation engine
-        self.liquidation_engine.liquidate(vault_id, liquidator, btc_price).await?;
-        
         // Calculate total debt in USD for liquidation settlement
-        let total_debt_usd = vault.debts.total_debt_in_usd(exchange_rates);
+        let total_debt_usd = vault.debts.total_debt_in_usd(&exchange_rates);
+        let debts_before = vault.debts.debts.clone();
+
+        // Execute liquidation in the liquidation engine
+        let record = self.liquidation_engine.liquidate(vault_id, liquidator, btc_price, now).await?;
         
         // Create and sign liquidation settlement transaction
         let liquidation_tx = self.custody_manager.execute_liquidation(
@@ -184,27 +822,43 @@ impl BitStableProtocol {
             liquidator,
             btc_price,
             total_debt_usd,
+            now,
         )?;
+        self.vault_manager.apply_liquidation(vault_id, record.collateral_seized, record.debt_covered, &exchange_rates)?;
 
-        // Broadcast the transaction if we have a Bitcoin client
-        if let Some(bitcoin_client) = &self.bitcoin_client {
-            let txid = bitcoin_client.broadcast_transaction(&liquidation_tx)?;
-            self.custody_manager.mark_transaction_broadcast(txid)?;
-            
-            log::info!("Liquidation transaction broadcast: {}", txid);
-            Ok(txid)
-        } else {
-            Ok(liquidation_tx.compute_txid())
+        // The liquidator repays the covered debt out of their own stable balance
+        let debts_after = &self.vault_manager.get_vault(vault_id)?.debts;
+        for (currency, before) in debts_before {
+            let repaid = before - debts_after.get_debt(&currency);
+            if repaid > 0.0 {
+                self.stable_manager.burn_stable(liquidator, currency, repaid)?;
+            }
+        }
+
+        let mut work = UnitOfWork::new();
+        work.put_liquidation(&database::LiquidationRecord {
+            vault_id,
+            liquidator,
+            collateral_seized: record.collateral_seized,
+            debt_covered: record.debt_covered,
+            bonus_paid: record.bonus_paid,
+            liquidated_at: record.liquidated_at,
+            btc_price,
+        })?;
+        if let Some(settlement) = self.custody_manager.get_settlement(vault_id) {
+            work.put_settlement(settlement)?;
+            self.insurance_fund.contribute_from_fees_at(settlement.protocol_fee, ContributionSource::LiquidationPenalties, now)?;
+            if let Some(contribution) = self.insurance_fund.get_recent_contributions(1).first() {
+                work.put_insurance_contribution(contribution)?;
+            }
         }
+
+        Ok((liquidation_tx, work))
     }
 
     /// Close a vault and return collateral to owner (when debt is repaid)
     pub async fn close_vault(&mut self, vault_id: Txid, owner: PublicKey) -> Result<Txid> {
-        // Close vault in the vault manager
-        let returned_collateral = self.vault_manager.close_vault(vault_id, owner).await?;
-        
-        // Create vault closure transaction
-        let closure_tx = self.custody_manager.create_vault_closure_transaction(vault_id)?;
+        let (returned_collateral, closure_tx) = self.prepare_vault_closure(vault_id, owner).await?;
         
         // Broadcast the transaction if we have a Bitcoin client
         if let Some(bitcoin_client) = &self.bitcoin_client {
@@ -223,6 +877,45 @@ impl BitStableProtocol {
         }
     }
 
+    /// Close a vault as `close_vault` does, returning the collateral released and the
+    /// closure transaction without broadcasting it
+    pub async fn prepare_vault_closure(&mut self, vault_id: Txid, owner: PublicKey) -> Result<(Amount, bitcoin::Transaction)> {
+        // Close vault in the vault manager, keeping it open if no closure transaction can be built
+        self.begin_work(&[]);
+        let result = match self.vault_manager.close_vault(vault_id, owner).await {
+            Ok(returned_collateral) => self.custody_manager.create_vault_closure_transaction(vault_id)
+                .map(|closure_tx| ((returned_collateral, closure_tx), UnitOfWork::new())),
+            Err(e) => Err(e),
+        };
+        self.finish_work(result)
+    }
+
+    /// Redeem stable value for BTC from the lowest-ratio vault, committing the
+    /// vault change and redemption record together
+    pub async fn redeem_stablecoins(
+        &mut self,
+        redeemer: PublicKey,
+        currency: Currency,
+        stable_amount: f64,
+    ) -> Result<RedemptionRecord> {
+        self.sync_circuit_breakers();
+        let exchange_rates = self.oracle_network.get_exchange_rates().clone();
+        self.begin_work(&[
+            Touches::Subsystem(Subsystem::RedemptionEngine),
+            Touches::Subsystem(Subsystem::StableBalances),
+        ]);
+        let result = self.redemption_engine
+            .redeem_stablecoins(redeemer, currency, stable_amount, &mut self.vault_manager, &exchange_rates)
+            .await
+            .and_then(|record| {
+                self.stable_manager.burn_stable(redeemer, record.currency.clone(), record.stable_amount)?;
+                let mut work = UnitOfWork::new();
+                work.put_redemption(&record)?;
+                Ok((record, work))
+            });
+        self.finish_work(result)
+    }
+
     pub async fn get_vault_health(&mut self, vault_id: Txid) -> Result<f64> {
         let vault = self.vault_manager.get_vault(vault_id)?;
         let exchange_rates = self.oracle_network.get_exchange_rates();
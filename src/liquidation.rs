@@ -1,9 +1,11 @@
 use bitcoin::{Amount, PublicKey, Txid};
 use serde::{Deserialize, Serialize};
-use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
 use std::cmp::Ordering;
 use chrono::{DateTime, Utc};
-use crate::{BitStableError, Result, ProtocolConfig, Vault, ExchangeRates};
+use crate::{BitStableError, Result, ProtocolConfig, Vault, ExchangeRates, Currency};
+use crate::multi_currency::{btc_amount, PriceMode};
+use crate::events::{EventBus, EventEmitter, ProtocolEvent};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LiquidationOpportunity {
@@ -16,6 +18,8 @@ pub struct LiquidationOpportunity {
     pub discovered_at: DateTime<Utc>,
     pub liquidation_type: LiquidationType,
     pub liquidation_percentage: f64,
+    #[serde(default)]
+    pub debt_currencies: Vec<Currency>,
 }
 
 /// Progressive liquidation types
@@ -58,7 +62,7 @@ pub struct LiquidationRecord {
     pub final_collateral_ratio: f64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LiquidationEngine {
     config: ProtocolConfig,
     liquidation_queue: BinaryHeap<LiquidationOpportunity>,
@@ -70,6 +74,9 @@ pub struct LiquidationEngine {
     last_block_reset: DateTime<Utc>,               // when block volume was last reset
     emergency_halt_until: Option<DateTime<Utc>>,   // emergency trading halt timestamp
     cascade_detection: CascadeDetectionSystem,
+    tripped_currencies: HashSet<Currency>,        // currencies halted by the oracle circuit breaker
+    price_modes: HashMap<Currency, PriceMode>,     // spot/TWAP choice for liquidation checks
+    events: EventEmitter,
 }
 
 #[derive(Debug, Clone)]
@@ -110,19 +117,59 @@ impl LiquidationEngine {
                 max_block_liquidation: 0.10, // 10%
                 max_vault_liquidation_per_hour: 0.50, // 50%
             },
+            tripped_currencies: HashSet::new(),
+            price_modes: HashMap::new(),
+            events: EventEmitter::default(),
         })
     }
 
+    pub fn set_event_bus(&mut self, bus: EventBus) {
+        self.events.attach(bus);
+    }
+
+    /// Update which currencies are halted by a tripped circuit breaker
+    pub fn update_circuit_breakers(&mut self, tripped: HashSet<Currency>) {
+        self.tripped_currencies = tripped;
+    }
+
+    /// First halted currency among the debts, USD included since collateral is valued in it
+    fn halted_currency<'a>(&self, currencies: impl IntoIterator<Item = &'a Currency>) -> Option<Currency> {
+        std::iter::once(&Currency::USD)
+            .chain(currencies)
+            .find(|c| self.tripped_currencies.contains(*c))
+            .cloned()
+    }
+
+    /// Choose which price liquidation checks use for a currency (spot by default)
+    pub fn set_price_mode(&mut self, currency: Currency, mode: PriceMode) {
+        self.price_modes.insert(currency, mode);
+    }
+
+    /// Worst collateral ratio across a vault's debts, each valued in its currency's price mode
+    fn liquidation_ratio(&self, vault: &Vault, exchange_rates: &ExchangeRates) -> f64 {
+        vault.debts.debts.keys()
+            .map(|currency| {
+                let mode = self.price_modes.get(currency).copied().unwrap_or(PriceMode::Spot);
+                vault.collateral_ratio_with_mode(currency, exchange_rates, mode)
+            })
+            .fold(f64::INFINITY, f64::min)
+    }
+
     pub fn scan_for_liquidations(&mut self, vaults: &[&Vault], exchange_rates: &ExchangeRates) {
         self.liquidation_queue.clear();
         
         for vault in vaults {
-            let collateral_ratio = vault.collateral_ratio(exchange_rates);
+            if let Some(currency) = self.halted_currency(vault.debts.debts.keys()) {
+                log::warn!("Skipping vault {}: circuit breaker tripped for {}", vault.id, currency.to_string());
+                continue;
+            }
+
+            let collateral_ratio = self.liquidation_ratio(vault, exchange_rates);
             let (liquidation_type, _) = self.determine_liquidation_type(collateral_ratio);
             
             // Include vaults that need any type of liquidation (including partial)
             if !matches!(liquidation_type, LiquidationType::None) {
-                let opportunity = self.create_liquidation_opportunity(vault, exchange_rates);
+                let opportunity = self.create_liquidation_opportunity(vault, collateral_ratio, exchange_rates);
                 self.liquidation_queue.push(opportunity);
             }
         }
@@ -130,9 +177,10 @@ impl LiquidationEngine {
         log::info!("Found {} liquidation opportunities (including progressive)", self.liquidation_queue.len());
     }
 
-    fn create_liquidation_opportunity(&self, vault: &Vault, exchange_rates: &ExchangeRates) -> LiquidationOpportunity {
-        let collateral_ratio = vault.collateral_ratio(exchange_rates);
+    fn create_liquidation_opportunity(&self, vault: &Vault, collateral_ratio: f64, exchange_rates: &ExchangeRates) -> LiquidationOpportunity {
         let (liquidation_type, liquidation_percentage) = self.determine_liquidation_type(collateral_ratio);
+        // Size the bonus and debt at the same mode prices the decision used
+        let exchange_rates = &exchange_rates.with_price_modes(&self.price_modes);
         let bonus = vault.liquidation_bonus(exchange_rates, self.config.liquidation_penalty * liquidation_percentage);
         
         LiquidationOpportunity {
@@ -145,6 +193,7 @@ impl LiquidationEngine {
             discovered_at: Utc::now(),
             liquidation_type,
             liquidation_percentage,
+            debt_currencies: vault.debts.debts.keys().cloned().collect(),
         }
     }
     
@@ -169,15 +218,18 @@ impl LiquidationEngine {
         }
     }
 
+    /// Liquidate a queued vault at `now`, which stamps the record and drives the
+    /// rate limits, so replicas liquidating at the same time agree
     pub async fn liquidate(
         &mut self,
         vault_id: Txid,
         liquidator: PublicKey,
         btc_price: f64,
+        now: DateTime<Utc>,
     ) -> Result<LiquidationRecord> {
         // Check emergency halt
         if let Some(halt_until) = self.emergency_halt_until {
-            if Utc::now() < halt_until {
+            if now < halt_until {
                 return Err(BitStableError::InvalidConfig(
                     "Trading halted due to liquidation cascade".to_string()
                 ));
@@ -193,6 +245,11 @@ impl LiquidationEngine {
             .ok_or(BitStableError::LiquidationThresholdNotReached)?
             .clone();
 
+        // Queue may predate a breaker trip
+        if let Some(currency) = self.halted_currency(&opportunity.debt_currencies) {
+            return Err(BitStableError::CircuitBreakerTripped(currency.to_string()));
+        }
+
         // Verify liquidation is still valid based on progressive thresholds
         let (liquidation_type, liquidation_percentage) = self.determine_liquidation_type(opportunity.collateral_ratio);
         if matches!(liquidation_type, LiquidationType::None) {
@@ -205,7 +262,7 @@ impl LiquidationEngine {
         let liquidation_volume_usd = opportunity.debt_usd * liquidation_percentage;
         
         // Check rate limiting constraints
-        self.check_rate_limits(vault_id, liquidation_volume_usd, liquidation_percentage)?;
+        self.check_rate_limits(vault_id, liquidation_volume_usd, liquidation_percentage, now)?;
 
         // Apply dynamic liquidation penalty (smoothing function)
         let dynamic_penalty = self.calculate_dynamic_penalty(liquidation_volume_usd);
@@ -213,12 +270,12 @@ impl LiquidationEngine {
         // Calculate progressive liquidation amounts
         let debt_in_btc = opportunity.debt_usd / btc_price;
         let debt_to_cover = debt_in_btc * liquidation_percentage;
-        let collateral_needed = Amount::from_btc(debt_to_cover)?;
-        let bonus = Amount::from_btc(debt_to_cover * dynamic_penalty)?;
+        let collateral_needed = btc_amount(debt_to_cover)?;
+        let bonus = btc_amount(debt_to_cover * dynamic_penalty)?;
         let total_seized = collateral_needed + bonus;
 
         // Ensure we don't seize more than available collateral
-        let max_seizeable = Amount::from_btc(opportunity.collateral.to_btc() * liquidation_percentage)?;
+        let max_seizeable = btc_amount(opportunity.collateral.to_btc() * liquidation_percentage)?;
         let actual_seized = std::cmp::min(total_seized, max_seizeable);
         let actual_bonus = if actual_seized > collateral_needed {
             actual_seized - collateral_needed
@@ -230,7 +287,7 @@ impl LiquidationEngine {
         let record = LiquidationRecord {
             vault_id,
             liquidator,
-            liquidated_at: Utc::now(),
+            liquidated_at: now,
             collateral_seized: actual_seized,
             debt_covered: debt_to_cover * btc_price,  // In USD
             bonus_paid: actual_bonus,
@@ -238,16 +295,16 @@ impl LiquidationEngine {
         };
 
         // Update cascade detection and tracking
-        self.update_cascade_tracking(liquidation_volume_usd)?;
+        self.update_cascade_tracking(liquidation_volume_usd, now)?;
         
         // Update liquidator stats
-        self.update_liquidator_stats(liquidator, actual_bonus);
+        self.update_liquidator_stats(liquidator, actual_bonus, now);
 
         // Store liquidation record
         self.liquidation_history.push(record.clone());
         
         // Check for cascade trigger after this liquidation
-        self.check_cascade_emergency_trigger()?;
+        self.check_cascade_emergency_trigger(now)?;
 
         // Remove from queue if fully liquidated, otherwise update
         if matches!(liquidation_type, LiquidationType::Full) {
@@ -265,11 +322,12 @@ impl LiquidationEngine {
             actual_seized.to_btc(),
             actual_bonus.to_btc()
         );
+        self.events.emit(ProtocolEvent::LiquidationExecuted { record: record.clone() });
 
         Ok(record)
     }
 
-    fn update_liquidator_stats(&mut self, liquidator: PublicKey, bonus: Amount) {
+    fn update_liquidator_stats(&mut self, liquidator: PublicKey, bonus: Amount, now: DateTime<Utc>) {
         let stats = self.active_liquidators
             .entry(liquidator)
             .or_insert_with(|| LiquidatorInfo {
@@ -281,7 +339,7 @@ impl LiquidationEngine {
 
         stats.total_liquidations += 1;
         stats.total_bonus_earned += bonus;
-        stats.last_liquidation = Some(Utc::now());
+        stats.last_liquidation = Some(now);
     }
 
     pub fn get_liquidation_opportunities(&self) -> Vec<&LiquidationOpportunity> {
@@ -446,9 +504,10 @@ impl LiquidationEngine {
         vault_id: Txid,
         liquidation_volume_usd: f64,
         liquidation_percentage: f64,
+        now: DateTime<Utc>,
     ) -> Result<()> {
         // Update block volume tracking
-        self.update_block_tracking();
+        self.update_block_tracking(now);
         
         // Check block-level liquidation limit (10% of system collateral)
         let proposed_block_volume = self.block_liquidation_volume + liquidation_volume_usd;
@@ -488,9 +547,7 @@ impl LiquidationEngine {
     }
     
     /// Update block-level liquidation tracking
-    fn update_block_tracking(&mut self) {
-        let now = Utc::now();
-        
+    fn update_block_tracking(&mut self, now: DateTime<Utc>) {
         // Reset block volume every minute (simplified block time)
         if now.signed_duration_since(self.last_block_reset).num_minutes() >= 1 {
             self.block_liquidation_volume = 0.0;
@@ -499,9 +556,7 @@ impl LiquidationEngine {
     }
     
     /// Update cascade detection tracking
-    fn update_cascade_tracking(&mut self, liquidation_volume_usd: f64) -> Result<()> {
-        let now = Utc::now();
-        
+    fn update_cascade_tracking(&mut self, liquidation_volume_usd: f64, now: DateTime<Utc>) -> Result<()> {
         // Add to block volume
         self.block_liquidation_volume += liquidation_volume_usd;
         
@@ -534,10 +589,10 @@ impl LiquidationEngine {
     }
     
     /// Check if emergency halt should be triggered
-    fn check_cascade_emergency_trigger(&mut self) -> Result<()> {
+    fn check_cascade_emergency_trigger(&mut self, now: DateTime<Utc>) -> Result<()> {
         if self.cascade_detection.liquidation_rate_10min > self.cascade_detection.emergency_threshold {
             // Trigger 1-hour emergency halt
-            self.emergency_halt_until = Some(Utc::now() + chrono::Duration::hours(1));
+            self.emergency_halt_until = Some(now + chrono::Duration::hours(1));
             
             log::error!(
                 "EMERGENCY LIQUIDATION HALT: {:.2}% of system collateral liquidated in 10 minutes",
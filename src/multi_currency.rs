@@ -127,6 +127,25 @@ impl MultiCurrencyDebt {
     }
 }
 
+/// Which BTC price a risk check should use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceMode {
+    #[default]
+    Spot,
+    Twap,
+    Min,   // Lower of spot and TWAP
+    Max,   // Higher of spot and TWAP
+}
+
+/// A BTC quantity computed in floating point, rounded to the nearest satoshi
+pub fn btc_amount(btc: f64) -> Result<bitcoin::Amount> {
+    if !btc.is_finite() || btc < 0.0 {
+        return Err(BitStableError::InvalidConfig(format!("Invalid BTC amount: {}", btc)));
+    }
+    Ok(bitcoin::Amount::from_sat((btc * 100_000_000.0).round() as u64))
+}
+
 /// Exchange rate tracking
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExchangeRates {
@@ -134,6 +153,9 @@ pub struct ExchangeRates {
     pub btc_prices: HashMap<Currency, f64>,
     /// Exchange rates to USD (e.g., EUR/USD)
     pub to_usd_rates: HashMap<Currency, f64>,
+    /// Consensus TWAP of the BTC price in each currency
+    #[serde(default)]
+    pub twap_prices: HashMap<Currency, f64>,
     pub timestamp: DateTime<Utc>,
 }
 
@@ -145,6 +167,7 @@ impl ExchangeRates {
         Self {
             btc_prices: HashMap::new(),
             to_usd_rates,
+            twap_prices: HashMap::new(),
             timestamp: Utc::now(),
         }
     }
@@ -167,6 +190,45 @@ impl ExchangeRates {
         self.to_usd_rates.get(currency).copied()
     }
 
+    pub fn update_twap_price(&mut self, currency: Currency, twap: f64) {
+        self.twap_prices.insert(currency, twap);
+    }
+
+    /// TWAP of BTC in a currency, derived from the USD TWAP when not tracked directly
+    pub fn get_twap_price(&self, currency: &Currency) -> Option<f64> {
+        if let Some(twap) = self.twap_prices.get(currency) {
+            return Some(*twap);
+        }
+
+        let usd_twap = self.twap_prices.get(&Currency::USD)?;
+        let rate_to_usd = self.to_usd_rates.get(currency)?;
+        Some(usd_twap / rate_to_usd)
+    }
+
+    /// BTC price in a currency under the given mode; falls back to spot without TWAP data
+    pub fn price_for_mode(&self, currency: &Currency, mode: PriceMode) -> f64 {
+        let spot = self.calculate_btc_price(currency, self.get_btc_price(&Currency::USD).unwrap_or(0.0));
+
+        match (mode, self.get_twap_price(currency)) {
+            (PriceMode::Spot, _) | (_, None) => spot,
+            (PriceMode::Twap, Some(twap)) => twap,
+            (PriceMode::Min, Some(twap)) => spot.min(twap),
+            (PriceMode::Max, Some(twap)) => spot.max(twap),
+        }
+    }
+
+    /// Copy of these rates with each currency's BTC price replaced by its mode price
+    pub fn with_price_modes(&self, modes: &HashMap<Currency, PriceMode>) -> ExchangeRates {
+        let mut rates = self.clone();
+        for (currency, mode) in modes {
+            let price = self.price_for_mode(currency, *mode);
+            if price > 0.0 {
+                rates.btc_prices.insert(currency.clone(), price);
+            }
+        }
+        rates
+    }
+
     /// Calculate BTC price in a currency from USD price and exchange rate
     pub fn calculate_btc_price(&self, currency: &Currency, btc_usd_price: f64) -> f64 {
         if currency == &Currency::USD {
@@ -324,6 +386,10 @@ pub struct CurrencyConfig {
     pub liquidation_threshold: f64,
     pub min_mint_amount: f64,
     pub enabled: bool,
+    #[serde(default)]
+    pub liquidation_price_mode: PriceMode,
+    #[serde(default)]
+    pub mint_price_mode: PriceMode,
 }
 
 impl Default for CurrencyConfig {
@@ -335,6 +401,8 @@ impl Default for CurrencyConfig {
             liquidation_threshold: 1.1,    // 110% liquidation (matches whitepaper)
             min_mint_amount: 10.0,         // Minimum 10 units
             enabled: true,
+            liquidation_price_mode: PriceMode::Spot,
+            mint_price_mode: PriceMode::Spot,
         }
     }
 }
@@ -426,6 +494,28 @@ mod tests {
         assert_eq!(btc_eur, 100000.0 / 0.85); // ~117,647 EUR
     }
 
+    #[test]
+    fn test_price_modes() {
+        let mut rates = ExchangeRates::new();
+        rates.update_btc_price(Currency::USD, 90000.0);
+        rates.update_exchange_rate(Currency::EUR, 1.125);
+
+        // No TWAP yet, every mode falls back to spot
+        assert_eq!(rates.price_for_mode(&Currency::USD, PriceMode::Twap), 90000.0);
+
+        rates.update_twap_price(Currency::USD, 100000.0);
+        assert_eq!(rates.price_for_mode(&Currency::USD, PriceMode::Spot), 90000.0);
+        assert_eq!(rates.price_for_mode(&Currency::USD, PriceMode::Twap), 100000.0);
+        assert_eq!(rates.price_for_mode(&Currency::USD, PriceMode::Min), 90000.0);
+        assert_eq!(rates.price_for_mode(&Currency::USD, PriceMode::Max), 100000.0);
+
+        // EUR TWAP is derived from the USD TWAP
+        assert_eq!(rates.price_for_mode(&Currency::EUR, PriceMode::Max), 100000.0 / 1.125);
+
+        let modes = HashMap::from([(Currency::USD, PriceMode::Max)]);
+        assert_eq!(rates.with_price_modes(&modes).get_btc_price(&Currency::USD), Some(100000.0));
+    }
+
     #[test]
     fn test_multi_currency_position() {
         let secp = Secp256k1::new();
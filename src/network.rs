@@ -1,7 +1,25 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
 use bitcoin::PublicKey;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use sha2::{Digest, Sha256};
 use crate::{BitStableError, Result};
+use crate::crypto::{OracleKeyManager, OracleSignature};
+use crate::database::DatabaseManager;
+use crate::multi_currency::{Currency, MultiCurrencyDebt};
+use crate::vault::{Vault, VaultState};
+use crate::vault_sync::VaultSetSummary;
+use crate::address_book::{AddressBook, AddressSource, PeerAddress, MAX_ADDRESSES_PER_MESSAGE};
+use crate::gossip::{GossipConfig, MessageId, RateLimiter, SeenCache};
+use crate::noise::{self, SecureStream};
+use crate::raft::Envelope;
+use crate::replication::ProtocolOp;
+use crate::wire::{self, Frame, FrameKind, Hello, PeerHandshake, FEATURE_MULTI_CURRENCY, FEATURE_REPLICATION, FEATURE_VAULT_SYNC};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PeerInfo {
@@ -12,7 +30,7 @@ pub struct PeerInfo {
     pub reputation_score: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum ServiceType {
     Oracle,
     Liquidator,
@@ -20,13 +38,93 @@ pub enum ServiceType {
     StableHolder,
 }
 
+impl ServiceType {
+    pub const ALL: [ServiceType; 4] = [
+        ServiceType::Oracle,
+        ServiceType::Liquidator,
+        ServiceType::VaultProvider,
+        ServiceType::StableHolder,
+    ];
+
+    /// Bit advertised for this service in the handshake
+    pub fn flag(&self) -> u32 {
+        match self {
+            ServiceType::Oracle => 1,
+            ServiceType::Liquidator => 1 << 1,
+            ServiceType::VaultProvider => 1 << 2,
+            ServiceType::StableHolder => 1 << 3,
+        }
+    }
+
+    pub fn to_flags(services: &[ServiceType]) -> u32 {
+        services.iter().fold(0, |flags, service| flags | service.flag())
+    }
+
+    /// Unknown bits are ignored so newer peers can advertise services we don't know
+    pub fn from_flags(flags: u32) -> Vec<ServiceType> {
+        Self::ALL.into_iter().filter(|service| flags & service.flag() != 0).collect()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkMessage {
     pub message_type: MessageType,
     pub sender: PublicKey,
     pub timestamp: chrono::DateTime<chrono::Utc>,
     pub data: MessageData,
-    pub signature: Option<Vec<u8>>,
+    pub signature: Option<Vec<u8>>,   // Sender's compact ECDSA signature over `id()`
+    #[serde(default = "default_ttl")]
+    pub ttl: u8,                      // Hops left; not signed since relays decrement it
+}
+
+fn default_ttl() -> u8 {
+    1
+}
+
+impl NetworkMessage {
+    pub fn new(message_type: MessageType, sender: PublicKey, data: MessageData, ttl: u8) -> Self {
+        Self {
+            message_type,
+            sender,
+            timestamp: chrono::Utc::now(),
+            data,
+            signature: None,
+            ttl,
+        }
+    }
+
+    /// Hash of the signed content, the same at every hop
+    pub fn id(&self) -> Result<MessageId> {
+        let content = if self.data.required_features() == 0 {
+            serde_json::to_vec(&(&self.message_type, &self.sender, &self.timestamp, &self.data))?
+        } else {
+            // Map payloads hash their sorted-key form so the id doesn't depend on HashMap order
+            let data = serde_json::to_value(&self.data)?;
+            serde_json::to_vec(&(&self.message_type, &self.sender, &self.timestamp, &data))?
+        };
+        Ok(Sha256::digest(content).into())
+    }
+
+    pub fn sign(&mut self, secret_key: &bitcoin::secp256k1::SecretKey) -> Result<()> {
+        let secp = bitcoin::secp256k1::Secp256k1::signing_only();
+        let digest = bitcoin::secp256k1::Message::from_digest(self.id()?);
+        self.signature = Some(secp.sign_ecdsa(&digest, secret_key).serialize_compact().to_vec());
+        Ok(())
+    }
+
+    /// Check the sender signed this content; returns the message id
+    pub fn verify_signature(&self) -> Result<MessageId> {
+        let signature = self.signature.as_ref()
+            .ok_or_else(|| BitStableError::PeerProtocolError("Unsigned message".to_string()))?;
+        let signature = bitcoin::secp256k1::ecdsa::Signature::from_compact(signature)
+            .map_err(|e| BitStableError::PeerProtocolError(format!("Malformed signature: {}", e)))?;
+
+        let id = self.id()?;
+        let secp = bitcoin::secp256k1::Secp256k1::verification_only();
+        secp.verify_ecdsa(&bitcoin::secp256k1::Message::from_digest(id), &signature, &self.sender.inner)
+            .map_err(|_| BitStableError::PeerProtocolError(format!("Bad signature from {}", self.sender)))?;
+        Ok(id)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, Hash, PartialEq)]
@@ -37,6 +135,11 @@ pub enum MessageType {
     VaultLiquidated,
     PeerAnnouncement,
     StableTransfer,
+    GetAddresses,
+    Addresses,
+    VaultClosed,
+    VaultSync,   // Summary and range exchange between two peers
+    Replication, // Raft envelopes sent directly between replicas
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -70,13 +173,185 @@ pub enum MessageData {
         to: PublicKey,
         amount_usd: f64,
     },
+    GetAddresses,
+    Addresses {
+        peers: Vec<PeerAddress>,
+    },
+    // Payloads below need `FEATURE_MULTI_CURRENCY`
+    PriceAttestations {
+        prices: HashMap<Currency, f64>,
+        attestations: Vec<OracleSignature>,   // Currency-bound oracle signatures backing `prices`
+    },
+    VaultCreatedMultiCurrency {
+        vault_id: bitcoin::Txid,
+        collateral_amount: bitcoin::Amount,
+        debt: MultiCurrencyDebt,
+    },
+    CurrencyTransfer {
+        from: PublicKey,
+        to: PublicKey,
+        currency: Currency,
+        amount: f64,
+    },
+    // Payloads below need `FEATURE_VAULT_SYNC`
+    GetVaultSummary,
+    VaultSummary {
+        summary: VaultSetSummary,
+    },
+    GetVaults {
+        buckets: Vec<u8>,
+        #[serde(default)]
+        after: Option<bitcoin::Txid>,   // Continue a paged reply after this vault id
+    },
+    Vaults {
+        vaults: Vec<Vault>,
+        #[serde(default)]
+        buckets: Vec<u8>,
+        #[serde(default)]
+        resume_after: Option<bitcoin::Txid>,   // Set when more vaults in `buckets` follow
+    },
+    VaultRecord {
+        vault: Box<Vault>,   // Full record, gossiped on creation, liquidation and closure
+    },
+    // Payloads below need `FEATURE_REPLICATION`
+    Raft {
+        envelope: Box<Envelope<ProtocolOp>>,
+    },
+}
+
+impl MessageData {
+    /// Feature bits a peer must have negotiated to decode this payload
+    pub fn required_features(&self) -> u64 {
+        match self {
+            MessageData::PriceAttestations { .. }
+            | MessageData::VaultCreatedMultiCurrency { .. }
+            | MessageData::CurrencyTransfer { .. } => FEATURE_MULTI_CURRENCY,
+            MessageData::GetVaultSummary
+            | MessageData::VaultSummary { .. }
+            | MessageData::GetVaults { .. }
+            | MessageData::Vaults { .. }
+            | MessageData::VaultRecord { .. } => FEATURE_VAULT_SYNC,
+            MessageData::Raft { .. } => FEATURE_REPLICATION,
+            _ => 0,
+        }
+    }
+
+    /// Closest version 1 payload, if the content can be expressed in USD alone.
+    /// Prices have none: a v1 `PriceUpdate` can't carry a currency-bound attestation.
+    pub fn legacy_fallback(&self) -> Option<MessageData> {
+        match self {
+            MessageData::VaultCreatedMultiCurrency { vault_id, collateral_amount, debt } => {
+                if debt.debts.keys().any(|currency| *currency != Currency::USD) {
+                    return None;
+                }
+                Some(MessageData::VaultCreated {
+                    vault_id: *vault_id,
+                    collateral_amount: *collateral_amount,
+                    stable_debt: debt.debts.get(&Currency::USD).copied().unwrap_or(0.0),
+                })
+            }
+            MessageData::CurrencyTransfer { from, to, currency: Currency::USD, amount } => {
+                Some(MessageData::StableTransfer { from: *from, to: *to, amount_usd: *amount })
+            }
+            MessageData::VaultRecord { vault } if vault.state == VaultState::Active => {
+                MessageData::VaultCreatedMultiCurrency {
+                    vault_id: vault.id,
+                    collateral_amount: vault.collateral_btc,
+                    debt: vault.debts.clone(),
+                }.legacy_fallback()
+            }
+            _ => None,
+        }
+    }
+
+    /// Every price must be backed by a valid attestation for that currency and value,
+    /// signed by one of the `oracle_keys` this node trusts
+    pub fn verify_attestations(&self, oracle_keys: &HashSet<bitcoin::secp256k1::PublicKey>) -> Result<()> {
+        let (prices, attestations) = match self {
+            MessageData::PriceAttestations { prices, attestations } => (prices, attestations),
+            MessageData::PriceUpdate { .. } => {
+                return Err(BitStableError::PeerProtocolError("v1 price updates carry no verifiable attestation".to_string()));
+            }
+            _ => return Ok(()),
+        };
+
+        let verifier = OracleKeyManager::new();
+        // The embedded key only proves the signature is self-consistent, not who made it
+        let trusted = |attestation: &OracleSignature| hex::decode(&attestation.public_key).ok()
+            .and_then(|bytes| bitcoin::secp256k1::PublicKey::from_slice(&bytes).ok())
+            .is_some_and(|key| oracle_keys.contains(&key));
+        for (currency, price) in prices {
+            let attested = attestations.iter().any(|attestation| {
+                attestation.currency.as_ref() == Some(currency)
+                    && attestation.price == *price
+                    && trusted(attestation)
+                    && verifier.verify_oracle_signature(attestation).unwrap_or(false)
+            });
+            if !attested {
+                return Err(BitStableError::PeerProtocolError(format!(
+                    "No valid attestation for {} price {}", currency.to_string(), price
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_outbound_slots() -> HashMap<ServiceType, usize> {
+    HashMap::from([
+        (ServiceType::Oracle, 3),
+        (ServiceType::Liquidator, 2),
+        (ServiceType::VaultProvider, 2),
+        (ServiceType::StableHolder, 1),
+    ])
+}
+
+/// Byte stream a peer connection runs over
+pub trait PeerStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> PeerStream for T {}
+
+/// Reported by listener and per-connection tasks back to the network
+enum PeerEvent {
+    Connected {
+        handshake: PeerHandshake,
+        address: String,
+        stream: Box<dyn PeerStream>,
+    },
+    Frame {
+        peer: PublicKey,
+        generation: u64,
+        frame: Frame,
+    },
+    Disconnected {
+        peer: PublicKey,
+        generation: u64,
+        reason: String,
+    },
 }
 
 pub struct BitStableNetwork {
+    local_key: bitcoin::secp256k1::SecretKey,
     local_pubkey: PublicKey,
+    local_services: Vec<ServiceType>,
+    local_features: u64,
+    listen_address: Option<SocketAddr>,
     peers: HashMap<PublicKey, PeerInfo>,
     message_handlers: HashMap<MessageType, Box<dyn Fn(&NetworkMessage) -> Result<()>>>,
     connection_pool: ConnectionPool,
+    event_sender: mpsc::UnboundedSender<PeerEvent>,
+    events: mpsc::UnboundedReceiver<PeerEvent>,
+    listener_task: Option<JoinHandle<()>>,
+    address_book: AddressBook,
+    outbound_slots: HashMap<ServiceType, usize>,
+    oracle_keys: HashSet<bitcoin::secp256k1::PublicKey>,   // Oracles whose price attestations are accepted
+    gossip: GossipConfig,
+    seen_messages: Mutex<SeenCache>,
+    rate_limiter: RateLimiter,
+    messages_sent: AtomicU64,
+    messages_received: u64,
+    next_generation: u64,   // Tags connections so events from a replaced one are ignored
+    last_address_exchange: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 pub struct ConnectionPool {
@@ -84,88 +359,360 @@ pub struct ConnectionPool {
     max_connections: usize,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionDirection {
+    Inbound,
+    Outbound,
+}
+
 pub struct Connection {
-    _peer: PublicKey,
-    _stream: Option<tokio::net::TcpStream>,
+    peer: PublicKey,
+    generation: u64,
+    direction: ConnectionDirection,
+    version: u16,
+    features: u64,
+    outbound: mpsc::UnboundedSender<Frame>,
     last_activity: chrono::DateTime<chrono::Utc>,
     is_connected: bool,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Connection {
+    fn send(&self, mut frame: Frame) -> Result<()> {
+        // Stamp frames with the version this peer negotiated
+        frame.version = self.version;
+        self.outbound.send(frame)
+            .map_err(|_| BitStableError::PeerProtocolError(format!("Connection to {} is closed", self.peer)))
+    }
+
+    fn supports(&self, features: u64) -> bool {
+        self.features & features == features
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for BitStableNetwork {
+    fn drop(&mut self) {
+        if let Some(task) = &self.listener_task {
+            task.abort();
+        }
+    }
+}
+
+/// Config key the node's identity key is kept under
+const NODE_KEY_CONFIG: &str = "node_key";
+
+/// How often maintenance asks peers for addresses even when well connected
+const ADDRESS_EXCHANGE_MINUTES: i64 = 10;
+
+/// This node's identity key, generated on first use and stored in `database` so the
+/// node keeps the pubkey peers pinned, and its bans and address book, across restarts
+pub fn load_or_create_node_key(database: &DatabaseManager) -> Result<bitcoin::secp256k1::SecretKey> {
+    if let Some(key) = database.load_config::<String>(NODE_KEY_CONFIG)? {
+        return key.parse()
+            .map_err(|e| BitStableError::InvalidConfig(format!("Corrupt node key: {}", e)));
+    }
+    let key = bitcoin::secp256k1::SecretKey::new(&mut rand::thread_rng());
+    database.save_config(NODE_KEY_CONFIG, &key.display_secret().to_string())?;
+    Ok(key)
 }
 
 impl BitStableNetwork {
-    pub fn new(local_pubkey: PublicKey, max_connections: usize) -> Self {
+    /// The node key both identifies us to peers and authenticates our connections.
+    /// Price attestations are only accepted from `oracle_keys`, typically `ProtocolConfig::oracle_keys`.
+    pub fn new(
+        local_key: bitcoin::secp256k1::SecretKey,
+        max_connections: usize,
+        oracle_keys: impl IntoIterator<Item = bitcoin::secp256k1::PublicKey>,
+    ) -> Self {
+        let (event_sender, events) = mpsc::unbounded_channel();
+        let secp = bitcoin::secp256k1::Secp256k1::signing_only();
+        let gossip = GossipConfig::default();
         Self {
-            local_pubkey,
+            local_key,
+            local_pubkey: PublicKey::new(bitcoin::secp256k1::PublicKey::from_secret_key(&secp, &local_key)),
+            local_services: vec![ServiceType::VaultProvider, ServiceType::StableHolder],
+            local_features: wire::SUPPORTED_FEATURES,
+            listen_address: None,
             peers: HashMap::new(),
             message_handlers: HashMap::new(),
             connection_pool: ConnectionPool {
                 connections: HashMap::new(),
                 max_connections,
             },
+            event_sender,
+            events,
+            listener_task: None,
+            address_book: AddressBook::new(),
+            outbound_slots: default_outbound_slots(),
+            oracle_keys: oracle_keys.into_iter().collect(),
+            seen_messages: Mutex::new(SeenCache::new(
+                gossip.seen_capacity,
+                chrono::Duration::seconds(gossip.message_expiry_secs),
+            )),
+            rate_limiter: RateLimiter::new(gossip.rate_limit_per_sec, gossip.rate_limit_burst),
+            gossip,
+            messages_sent: AtomicU64::new(0),
+            messages_received: 0,
+            next_generation: 0,
+            last_address_exchange: None,
         }
     }
 
-    pub async fn start(&mut self, bind_address: &str) -> Result<()> {
-        // Start listening for incoming connections
-        let listener = tokio::net::TcpListener::bind(bind_address).await
-            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to bind: {}", e)))?;
+    /// Services advertised to peers in the handshake and announcements
+    pub fn with_services(mut self, services: Vec<ServiceType>) -> Self {
+        self.local_services = services;
+        self
+    }
+
+    /// Optional protocol features to advertise; a subset of `wire::SUPPORTED_FEATURES`
+    pub fn with_features(mut self, features: u64) -> Self {
+        self.local_features = features & wire::SUPPORTED_FEATURES;
+        self
+    }
+
+    pub fn with_gossip_config(mut self, gossip: GossipConfig) -> Result<Self> {
+        gossip.validate()?;
+        self.seen_messages = Mutex::new(SeenCache::new(
+            gossip.seen_capacity,
+            chrono::Duration::seconds(gossip.message_expiry_secs),
+        ));
+        self.rate_limiter = RateLimiter::new(gossip.rate_limit_per_sec, gossip.rate_limit_burst);
+        self.gossip = gossip;
+        Ok(self)
+    }
+
+    /// Persisted address book to discover peers from and record them in
+    pub fn with_address_book(mut self, address_book: AddressBook) -> Self {
+        self.address_book = address_book;
+        self
+    }
+
+    /// Add `pubkey@host:port` seeds, typically `ProtocolConfig::seed_peers`
+    pub fn with_seeds(mut self, seeds: &[String]) -> Result<Self> {
+        let now = chrono::Utc::now();
+        for seed in seeds {
+            self.address_book.add_seed(seed, now)?;
+        }
+        Ok(self)
+    }
 
-        log::info!("BitStable network node started on {}", bind_address);
+    /// How many outbound connections to keep to peers offering each service
+    pub fn with_outbound_slots(mut self, slots: HashMap<ServiceType, usize>) -> Self {
+        self.outbound_slots = slots;
+        self
+    }
+
+    pub fn address_book(&self) -> &AddressBook {
+        &self.address_book
+    }
+
+    pub fn local_pubkey(&self) -> PublicKey {
+        self.local_pubkey
+    }
+
+    fn local_hello(&self) -> Hello {
+        let mut hello = Hello::new(
+            self.local_pubkey,
+            &self.local_services,
+            self.listen_address.map(|addr| addr.to_string()),
+        );
+        hello.features = self.local_features;
+        hello
+    }
+
+    pub async fn start(&mut self, bind_address: &str) -> Result<()> {
+        let local_address = self.listen(bind_address).await?;
+        log::info!("BitStable network node started on {}", local_address);
 
         // Start peer discovery
         self.start_peer_discovery().await?;
 
         // Main network loop
+        let mut maintenance = tokio::time::interval(tokio::time::Duration::from_secs(30));
         loop {
             tokio::select! {
-                // Handle incoming connections
-                Ok((stream, addr)) = listener.accept() => {
-                    log::debug!("New connection from {}", addr);
-                    self.handle_incoming_connection(stream).await?;
+                // Handshaken connections, received frames and disconnects
+                Some(event) = self.events.recv() => {
+                    self.handle_event(event)?;
                 }
-                
+
                 // Periodic maintenance
-                _ = tokio::time::sleep(tokio::time::Duration::from_secs(30)) => {
+                _ = maintenance.tick() => {
                     self.maintenance_cycle().await?;
                 }
             }
         }
     }
 
-    async fn start_peer_discovery(&mut self) -> Result<()> {
-        // Connect to bootstrap nodes
-        let bootstrap_nodes = vec![
-            // In a real implementation, these would be well-known BitStable nodes
-            ("127.0.0.1:8333", "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9"),
-            ("127.0.0.1:8334", "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"),
-        ];
+    /// Bind a listener that handshakes inbound peers in the background
+    pub async fn listen(&mut self, bind_address: &str) -> Result<SocketAddr> {
+        let listener = tokio::net::TcpListener::bind(bind_address).await
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to bind: {}", e)))?;
+        let local_address = listener.local_addr()?;
+        self.listen_address = Some(local_address);
 
-        for (addr, pubkey_str) in bootstrap_nodes {
-            if let Ok(pubkey) = pubkey_str.parse() {
-                if let Err(e) = self.connect_to_peer(addr, pubkey).await {
-                    log::warn!("Failed to connect to bootstrap node {}: {}", addr, e);
+        let hello = self.local_hello();
+        let local_key = self.local_key;
+        let events = self.event_sender.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, addr)) => {
+                        log::debug!("New connection from {}", addr);
+                        tokio::spawn(handle_incoming_connection(stream, addr.to_string(), local_key, hello.clone(), events.clone()));
+                    }
+                    Err(e) => log::warn!("Failed to accept connection: {}", e),
                 }
             }
+        });
+
+        if let Some(previous) = self.listener_task.replace(task) {
+            previous.abort();
+        }
+        Ok(local_address)
+    }
+
+    /// Dial seeds, ask them for more addresses and fill outbound slots
+    pub async fn start_peer_discovery(&mut self) -> Result<()> {
+        // Seeds first, so a fresh node learns addresses before filling its slots
+        let seeds: Vec<_> = self.address_book.seeds()
+            .into_iter()
+            .map(|seed| (seed.address.clone(), seed.pubkey))
+            .collect();
+
+        for (addr, pubkey) in seeds {
+            if let Err(e) = self.connect_to_peer(&addr, pubkey).await {
+                log::warn!("Failed to connect to seed node {}: {}", addr, e);
+            }
         }
 
+        self.request_addresses()?;
+        self.fill_outbound_slots().await;
         Ok(())
     }
 
+    /// Outbound connections to peers offering `service`
+    fn outbound_filled(&self, service: &ServiceType) -> usize {
+        self.connection_pool.connections.values()
+            .filter(|connection| connection.direction == ConnectionDirection::Outbound)
+            .filter(|connection| self.peers.get(&connection.peer).is_some_and(|peer| peer.services.contains(service)))
+            .count()
+    }
+
+    /// Filled and configured outbound slots per service
+    pub fn slot_usage(&self) -> Vec<(ServiceType, usize, usize)> {
+        let mut usage: Vec<_> = self.outbound_slots.iter()
+            .map(|(service, wanted)| (service.clone(), self.outbound_filled(service), *wanted))
+            .collect();
+        usage.sort_by_key(|(service, _, _)| service.flag());
+        usage
+    }
+
+    /// Dial address book candidates until every service has its outbound slots; returns connections opened
+    pub async fn fill_outbound_slots(&mut self) -> usize {
+        let mut opened = 0;
+        let mut slots: Vec<_> = self.outbound_slots.iter().map(|(service, slots)| (service.clone(), *slots)).collect();
+        slots.sort_by_key(|(service, _)| service.flag());
+
+        for (service, wanted) in slots {
+            let filled = self.outbound_filled(&service);
+            if filled >= wanted {
+                continue;
+            }
+
+            let mut exclude: HashSet<_> = self.connection_pool.connections.keys().copied().collect();
+            exclude.insert(self.local_pubkey);
+            let candidates: Vec<_> = self.address_book.candidates(Some(&service), &exclude, chrono::Utc::now())
+                .into_iter()
+                .take(wanted - filled)
+                .map(|entry| (entry.address.clone(), entry.pubkey))
+                .collect();
+
+            for (address, pubkey) in candidates {
+                if self.connection_pool.connections.len() >= self.connection_pool.max_connections {
+                    return opened;
+                }
+                match self.connect_to_peer(&address, pubkey).await {
+                    Ok(()) => opened += 1,
+                    Err(e) => log::debug!("Failed to fill {:?} slot with {}: {}", service, pubkey, e),
+                }
+            }
+        }
+
+        opened
+    }
+
+    /// Ask connected peers for addresses they know
+    pub fn request_addresses(&mut self) -> Result<()> {
+        let mut message = self.new_message(MessageType::GetAddresses, MessageData::GetAddresses)?;
+        message.ttl = 1;
+        self.last_address_exchange = Some(chrono::Utc::now());
+
+        for peer in self.connected_peers() {
+            if let Err(e) = self.send_to_peer(&peer, &message) {
+                log::debug!("Failed to request addresses from {}: {}", peer, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Ban a peer, dropping any connection to it; `None` bans permanently
+    pub fn ban_peer(&mut self, pubkey: PublicKey, reason: &str, duration: Option<chrono::Duration>) -> Result<()> {
+        self.address_book.ban(pubkey, reason, duration, chrono::Utc::now())?;
+        if self.connection_pool.connections.remove(&pubkey).is_some() {
+            log::info!("Disconnected banned peer {}: {}", pubkey, reason);
+        }
+        self.rate_limiter.forget(&pubkey);
+        Ok(())
+    }
+
+    pub fn unban_peer(&mut self, pubkey: &PublicKey) -> Result<bool> {
+        self.address_book.unban(pubkey)
+    }
+
     pub async fn connect_to_peer(&mut self, address: &str, pubkey: PublicKey) -> Result<()> {
         if self.connection_pool.connections.len() >= self.connection_pool.max_connections {
             return Err(BitStableError::InvalidConfig("Max connections reached".to_string()));
         }
+        let now = chrono::Utc::now();
+        if self.address_book.is_banned(&pubkey, now) {
+            return Err(BitStableError::PeerProtocolError(format!("Peer {} is banned", pubkey)));
+        }
+
+        self.address_book.record_attempt(&pubkey, now);
+        let result = self.dial(address, pubkey).await;
+        match &result {
+            Ok(()) => self.address_book.record_success(&pubkey, chrono::Utc::now()),
+            Err(_) => self.address_book.record_failure(&pubkey),
+        }
+        result
+    }
 
+    async fn dial(&mut self, address: &str, pubkey: PublicKey) -> Result<()> {
         let stream = tokio::net::TcpStream::connect(address).await
             .map_err(|e| BitStableError::InvalidConfig(format!("Connection failed: {}", e)))?;
+        self.connect_stream(stream, address, pubkey).await
+    }
 
-        let connection = Connection {
-            _peer: pubkey,
-            _stream: Some(stream),
-            last_activity: chrono::Utc::now(),
-            is_connected: true,
-        };
+    /// Run the outbound handshake over an already open stream, such as an in-memory link
+    pub async fn connect_stream<S: PeerStream + 'static>(&mut self, mut stream: S, address: &str, pubkey: PublicKey) -> Result<()> {
+        // Only the holder of `pubkey` can complete the encrypted handshake
+        let transport = tokio::time::timeout(
+            wire::HANDSHAKE_TIMEOUT,
+            noise::initiate(&mut stream, &self.local_key, &pubkey.inner),
+        ).await.map_err(|_| BitStableError::PeerProtocolError("Handshake timed out".to_string()))??;
+        let mut stream = SecureStream::new(stream, transport);
+        let handshake = wire::handshake(&mut stream, &self.local_hello(), Some(pubkey)).await?;
 
-        self.connection_pool.connections.insert(pubkey, connection);
+        self.attach_connection(handshake, address.to_string(), ConnectionDirection::Outbound, Box::new(stream))?;
 
         // Send peer announcement
         self.send_peer_announcement(pubkey).await?;
@@ -174,44 +721,276 @@ impl BitStableNetwork {
         Ok(())
     }
 
-    async fn handle_incoming_connection(&mut self, _stream: tokio::net::TcpStream) -> Result<()> {
-        // In a real implementation, this would:
-        // 1. Perform handshake to identify peer
-        // 2. Verify peer identity
-        // 3. Add to connection pool
-        // 4. Start message handling loop
+    /// Handshake an inbound stream in the background; the peer attaches once it completes
+    pub fn accept_stream<S: PeerStream + 'static>(&self, stream: S, address: String) {
+        tokio::spawn(handle_incoming_connection(stream, address, self.local_key, self.local_hello(), self.event_sender.clone()));
+    }
+
+    /// Register a handshaken peer and spawn its read and write tasks
+    fn attach_connection(
+        &mut self,
+        handshake: PeerHandshake,
+        address: String,
+        direction: ConnectionDirection,
+        stream: Box<dyn PeerStream>,
+    ) -> Result<()> {
+        // Key connections by the compressed form the transport authenticated
+        let peer = PublicKey::new(handshake.hello.pubkey.inner);
+        if self.address_book.is_banned(&peer, chrono::Utc::now()) {
+            return Err(BitStableError::PeerProtocolError(format!("Peer {} is banned", peer)));
+        }
+        if self.connection_pool.connections.len() >= self.connection_pool.max_connections
+            && !self.connection_pool.connections.contains_key(&peer)
+        {
+            return Err(BitStableError::InvalidConfig("Max connections reached".to_string()));
+        }
+
+        let (mut reader, mut writer) = tokio::io::split(stream);
+        let (outbound, mut outbound_rx) = mpsc::unbounded_channel::<Frame>();
+        let generation = self.next_generation;
+        self.next_generation += 1;
+
+        let events = self.event_sender.clone();
+        let read_task = tokio::spawn(async move {
+            let reason = loop {
+                match wire::read_frame(&mut reader).await {
+                    Ok(frame) => {
+                        if events.send(PeerEvent::Frame { peer, generation, frame }).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => break e.to_string(),
+                }
+            };
+            let _ = events.send(PeerEvent::Disconnected { peer, generation, reason });
+        });
+
+        let write_task = tokio::spawn(async move {
+            while let Some(frame) = outbound_rx.recv().await {
+                if let Err(e) = wire::write_frame(&mut writer, &frame).await {
+                    log::debug!("Write to {} failed: {}", peer, e);
+                    break;
+                }
+            }
+        });
+
+        let now = chrono::Utc::now();
+        let services = handshake.services();
 
-        log::debug!("Handling incoming connection");
+        // Only addresses we know accept connections go in the address book
+        let dialable = match direction {
+            ConnectionDirection::Outbound => Some(address.clone()),
+            ConnectionDirection::Inbound => handshake.hello.listen_address.clone(),
+        };
+        if let Some(dialable) = dialable {
+            self.address_book.record(peer, dialable, services.clone(), AddressSource::Handshake, now);
+        }
+
+        let address = handshake.hello.listen_address.clone().unwrap_or(address);
+        let info = self.peers.entry(peer).or_insert_with(|| PeerInfo {
+            pubkey: peer,
+            address: address.clone(),
+            last_seen: now,
+            services: services.clone(),
+            reputation_score: 0.5,
+        });
+        info.address = address;
+        info.services = services;
+        info.last_seen = now;
+
+        // Replacing an existing connection drops it, which aborts its tasks
+        self.connection_pool.connections.insert(peer, Connection {
+            peer,
+            generation,
+            direction,
+            version: handshake.version,
+            features: handshake.features,
+            outbound,
+            last_activity: now,
+            is_connected: true,
+            tasks: vec![read_task, write_task],
+        });
+
+        log::debug!("Peer {} attached ({} v{})", peer, handshake.hello.user_agent, handshake.version);
         Ok(())
     }
 
-    pub async fn broadcast_message(&self, message: NetworkMessage) -> Result<()> {
-        let _serialized = serde_json::to_vec(&message)?;
-        
-        for (peer_pubkey, connection) in &self.connection_pool.connections {
-            if connection.is_connected && *peer_pubkey != self.local_pubkey {
-                // In a real implementation, send over the TCP stream
-                log::debug!("Broadcasting message to peer: {}", peer_pubkey);
-            }
+    /// Wait for and handle the next connection, frame or disconnect
+    pub async fn process_next_event(&mut self) -> Result<()> {
+        match self.events.recv().await {
+            Some(event) => self.handle_event(event),
+            None => Err(BitStableError::PeerProtocolError("Event channel closed".to_string())),
         }
+    }
+
+    /// Handle every event already queued without waiting; returns how many ran
+    pub fn process_pending_events(&mut self) -> Result<usize> {
+        let mut handled = 0;
+        while let Ok(event) = self.events.try_recv() {
+            self.handle_event(event)?;
+            handled += 1;
+        }
+        Ok(handled)
+    }
 
+    fn handle_event(&mut self, event: PeerEvent) -> Result<()> {
+        match event {
+            PeerEvent::Connected { handshake, address, stream } => {
+                let peer = handshake.hello.pubkey;
+                if let Err(e) = self.attach_connection(handshake, address, ConnectionDirection::Inbound, stream) {
+                    log::warn!("Rejected inbound peer {}: {}", peer, e);
+                }
+            }
+            PeerEvent::Frame { peer, generation, frame } => {
+                if self.is_current_connection(&peer, generation) {
+                    self.handle_frame(peer, frame);
+                }
+            }
+            PeerEvent::Disconnected { peer, generation, reason } => {
+                // A late disconnect from a replaced connection must not drop its successor
+                if self.is_current_connection(&peer, generation) {
+                    self.connection_pool.connections.remove(&peer);
+                    log::info!("Peer {} disconnected: {}", peer, reason);
+                }
+            }
+        }
         Ok(())
     }
 
-    pub async fn send_price_update(&self, price: f64, source: String, signature: Vec<u8>) -> Result<()> {
-        let message = NetworkMessage {
-            message_type: MessageType::PriceUpdate,
-            sender: self.local_pubkey,
-            timestamp: chrono::Utc::now(),
-            data: MessageData::PriceUpdate {
-                price_usd: price,
-                oracle_source: source,
-                signature,
+    fn is_current_connection(&self, peer: &PublicKey, generation: u64) -> bool {
+        self.connection_pool.connections.get(peer)
+            .is_some_and(|connection| connection.generation == generation)
+    }
+
+    fn handle_frame(&mut self, peer: PublicKey, frame: Frame) {
+        let Some(connection) = self.connection_pool.connections.get_mut(&peer) else {
+            return;
+        };
+        connection.last_activity = chrono::Utc::now();
+
+        match frame.kind {
+            FrameKind::Message => match serde_json::from_slice::<NetworkMessage>(&frame.payload) {
+                Ok(message) => {
+                    self.messages_received += 1;
+                    self.handle_message(peer, message);
+                }
+                Err(e) => log::warn!("Undecodable message from {}: {}", peer, e),
             },
-            signature: None,
+            FrameKind::Ping => {
+                let _ = connection.send(Frame::new(FrameKind::Pong, frame.payload));
+            }
+            FrameKind::Pong => {}
+            FrameKind::Hello => {
+                log::warn!("Peer {} repeated its handshake, disconnecting", peer);
+                self.connection_pool.connections.remove(&peer);
+            }
+        }
+    }
+
+    /// Queue a message for one connected peer
+    pub fn send_to_peer(&self, peer: &PublicKey, message: &NetworkMessage) -> Result<()> {
+        let connection = self.connection_pool.connections.get(peer)
+            .ok_or_else(|| BitStableError::PeerProtocolError(format!("Not connected to {}", peer)))?;
+        if !connection.supports(message.data.required_features()) {
+            return Err(BitStableError::PeerProtocolError(format!(
+                "Peer {} can't decode {:?} payloads", peer, message.message_type
+            )));
+        }
+        connection.send(Frame::message(message)?)?;
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn connected_peers(&self) -> Vec<PublicKey> {
+        self.connection_pool.connections
+            .values()
+            .filter(|connection| connection.is_connected)
+            .map(|connection| connection.peer)
+            .collect()
+    }
+
+    /// Connected peers that negotiated every bit in `features`
+    pub fn peers_supporting(&self, features: u64) -> Vec<PublicKey> {
+        self.connection_pool.connections
+            .values()
+            .filter(|connection| connection.is_connected && connection.supports(features))
+            .map(|connection| connection.peer)
+            .collect()
+    }
+
+    /// Sign and send a single-hop message to one peer
+    pub fn send_direct(&self, peer: &PublicKey, message_type: MessageType, data: MessageData) -> Result<()> {
+        let mut message = self.new_message(message_type, data)?;
+        message.ttl = 1;
+        self.send_to_peer(peer, &message)
+    }
+
+    /// Negotiated protocol version for a connected peer
+    pub fn peer_version(&self, peer: &PublicKey) -> Option<u16> {
+        self.connection_pool.connections.get(peer).map(|connection| connection.version)
+    }
+
+    /// Signed message from this node, with a full hop budget
+    fn new_message(&self, message_type: MessageType, data: MessageData) -> Result<NetworkMessage> {
+        let mut message = NetworkMessage::new(message_type, self.local_pubkey, data, self.gossip.max_hops);
+        message.sign(&self.local_key)?;
+        Ok(message)
+    }
+
+    pub async fn broadcast_message(&self, message: NetworkMessage) -> Result<()> {
+        // Remember our own messages so echoes from peers are dropped
+        let id = message.verify_signature()?;
+        let now = chrono::Utc::now();
+        self.seen_messages.lock().unwrap().insert(id, now);
+
+        // Only the author can sign a v1 rendering for peers without the payload's features
+        let fallback = match message.data.legacy_fallback() {
+            Some(data) if message.sender == self.local_pubkey => {
+                let fallback = self.new_message(message.message_type.clone(), data)?;
+                self.seen_messages.lock().unwrap().insert(fallback.id()?, now);
+                Some(fallback)
+            }
+            _ => None,
         };
+        self.relay_message(&message, None, fallback.as_ref())
+    }
 
-        self.broadcast_message(message).await
+    /// Forward to every connection except the peer it came from and its author.
+    /// Peers that can't decode the payload get `fallback` instead, or nothing.
+    fn relay_message(
+        &self,
+        message: &NetworkMessage,
+        from: Option<&PublicKey>,
+        fallback: Option<&NetworkMessage>,
+    ) -> Result<()> {
+        let frame = Frame::message(message)?;
+        let fallback_frame = fallback.map(Frame::message).transpose()?;
+        let required = message.data.required_features();
+        
+        for (peer_pubkey, connection) in &self.connection_pool.connections {
+            if connection.is_connected
+                && *peer_pubkey != self.local_pubkey
+                && *peer_pubkey != message.sender
+                && Some(peer_pubkey) != from
+            {
+                let frame = if connection.supports(required) {
+                    frame.clone()
+                } else if let Some(fallback_frame) = &fallback_frame {
+                    fallback_frame.clone()
+                } else {
+                    log::debug!("Not relaying {:?} to {}: feature not negotiated", message.message_type, peer_pubkey);
+                    continue;
+                };
+
+                log::debug!("Broadcasting message to peer: {}", peer_pubkey);
+                // A closed connection is cleaned up by its disconnect event
+                if connection.send(frame).is_ok() {
+                    self.messages_sent.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        Ok(())
     }
 
     pub async fn send_liquidation_alert(
@@ -220,17 +999,14 @@ impl BitStableNetwork {
         collateral_ratio: f64,
         bonus: bitcoin::Amount,
     ) -> Result<()> {
-        let message = NetworkMessage {
-            message_type: MessageType::LiquidationAlert,
-            sender: self.local_pubkey,
-            timestamp: chrono::Utc::now(),
-            data: MessageData::LiquidationAlert {
+        let message = self.new_message(
+            MessageType::LiquidationAlert,
+            MessageData::LiquidationAlert {
                 vault_id,
                 collateral_ratio,
                 potential_bonus: bonus,
             },
-            signature: None,
-        };
+        )?;
 
         self.broadcast_message(message).await
     }
@@ -241,39 +1017,102 @@ impl BitStableNetwork {
         collateral: bitcoin::Amount,
         debt: f64,
     ) -> Result<()> {
-        let message = NetworkMessage {
-            message_type: MessageType::VaultCreated,
-            sender: self.local_pubkey,
-            timestamp: chrono::Utc::now(),
-            data: MessageData::VaultCreated {
+        let message = self.new_message(
+            MessageType::VaultCreated,
+            MessageData::VaultCreated {
                 vault_id,
                 collateral_amount: collateral,
                 stable_debt: debt,
             },
-            signature: None,
-        };
+        )?;
 
         self.broadcast_message(message).await
     }
 
-    async fn send_peer_announcement(&self, _target_peer: PublicKey) -> Result<()> {
-        let _message = NetworkMessage {
-            message_type: MessageType::PeerAnnouncement,
-            sender: self.local_pubkey,
-            timestamp: chrono::Utc::now(),
-            data: MessageData::PeerAnnouncement {
-                services: vec![ServiceType::VaultProvider, ServiceType::StableHolder],
-                endpoint: "127.0.0.1:8335".to_string(), // Our listening address
+    /// Per-currency prices, each backed by a currency-bound oracle attestation.
+    /// Peers without multi-currency support don't receive them.
+    pub async fn send_price_attestations(&self, attestations: Vec<OracleSignature>) -> Result<()> {
+        let mut prices = HashMap::new();
+        for attestation in &attestations {
+            let currency = attestation.currency.clone().ok_or_else(|| {
+                BitStableError::InvalidConfig(format!("Attestation from {} is not bound to a currency", attestation.oracle_name))
+            })?;
+            prices.insert(currency, attestation.price);
+        }
+
+        let data = MessageData::PriceAttestations { prices, attestations };
+        data.verify_attestations(&self.oracle_keys)?;
+        let message = self.new_message(MessageType::PriceUpdate, data)?;
+
+        self.broadcast_message(message).await
+    }
+
+    pub async fn announce_multi_currency_vault(
+        &self,
+        vault_id: bitcoin::Txid,
+        collateral: bitcoin::Amount,
+        debt: MultiCurrencyDebt,
+    ) -> Result<()> {
+        let message = self.new_message(
+            MessageType::VaultCreated,
+            MessageData::VaultCreatedMultiCurrency {
+                vault_id,
+                collateral_amount: collateral,
+                debt,
             },
-            signature: None,
+        )?;
+
+        self.broadcast_message(message).await
+    }
+
+    pub async fn send_currency_transfer(&self, to: PublicKey, currency: Currency, amount: f64) -> Result<()> {
+        let message = self.new_message(
+            MessageType::StableTransfer,
+            MessageData::CurrencyTransfer {
+                from: self.local_pubkey,
+                to,
+                currency,
+                amount,
+            },
+        )?;
+
+        self.broadcast_message(message).await
+    }
+
+    /// Gossip a vault's current record; the state picks the message type
+    pub async fn announce_vault(&self, vault: &Vault) -> Result<()> {
+        let message_type = match vault.state {
+            VaultState::Active => MessageType::VaultCreated,
+            VaultState::Liquidating | VaultState::Liquidated => MessageType::VaultLiquidated,
+            VaultState::Closed => MessageType::VaultClosed,
         };
+        let message = self.new_message(message_type, MessageData::VaultRecord { vault: Box::new(vault.clone()) })?;
+
+        self.broadcast_message(message).await
+    }
+
+    async fn send_peer_announcement(&self, target_peer: PublicKey) -> Result<()> {
+        // Outbound-only nodes have nothing to announce
+        let Some(endpoint) = self.listen_address else {
+            return Ok(());
+        };
+
+        let mut message = self.new_message(
+            MessageType::PeerAnnouncement,
+            MessageData::PeerAnnouncement {
+                services: self.local_services.clone(),
+                endpoint: endpoint.to_string(),
+            },
+        )?;
+        message.ttl = 1;
 
         // Send directly to specific peer instead of broadcasting
-        log::debug!("Sending peer announcement to {}", _target_peer);
-        Ok(())
+        log::debug!("Sending peer announcement to {}", target_peer);
+        self.send_to_peer(&target_peer, &message)
     }
 
-    async fn maintenance_cycle(&mut self) -> Result<()> {
+    /// Prune, ping and re-fill connections; `start` runs this every 30 seconds
+    pub async fn maintenance_cycle(&mut self) -> Result<()> {
         let now = chrono::Utc::now();
         
         // Remove stale connections
@@ -282,14 +1121,27 @@ impl BitStableNetwork {
             age.num_minutes() < 30 // Keep connections active for 30 minutes
         });
 
+        // Keep quiet links alive; pongs refresh last_activity
+        for connection in self.connection_pool.connections.values() {
+            let _ = connection.send(Frame::new(FrameKind::Ping, Vec::new()));
+        }
+
         // Update peer reputation scores
         self.update_peer_reputations().await?;
 
-        // Attempt to maintain minimum number of connections
-        if self.connection_pool.connections.len() < 3 {
+        self.address_book.purge_expired_bans(now)?;
+
+        // Refresh the address book periodically, and straight away when short of peers
+        let exchange_due = self.last_address_exchange
+            .is_none_or(|last| now.signed_duration_since(last) >= chrono::Duration::minutes(ADDRESS_EXCHANGE_MINUTES));
+        let low_on_peers = self.connection_pool.connections.len() < 3;
+        if low_on_peers {
             log::info!("Low peer count, attempting to find more peers");
-            // In a real implementation, this would trigger peer discovery
         }
+        if low_on_peers || exchange_due {
+            self.request_addresses()?;
+        }
+        self.fill_outbound_slots().await;
 
         Ok(())
     }
@@ -343,24 +1195,150 @@ impl BitStableNetwork {
         self.message_handlers.insert(message_type, Box::new(handler));
     }
 
-    #[allow(dead_code)]
-    async fn handle_message(&mut self, message: NetworkMessage) -> Result<()> {
-        // Verify message signature if present
-        if let Some(_signature) = &message.signature {
-            // In a real implementation, verify the signature
+    fn handle_message(&mut self, from: PublicKey, mut message: NetworkMessage) {
+        let now = chrono::Utc::now();
+        if !self.rate_limiter.allow(&from, now) {
+            self.penalize_peer(&from, self.gossip.rate_limit_penalty, "rate limit exceeded");
+            return;
         }
 
-        // Route to appropriate handler
+        let id = match message.verify_signature() {
+            Ok(id) => id,
+            Err(e) => {
+                self.penalize_peer(&from, self.gossip.invalid_signature_penalty, &e.to_string());
+                return;
+            }
+        };
+
+        let age = now.signed_duration_since(message.timestamp);
+        if age.num_seconds() > self.gossip.message_expiry_secs || -age.num_seconds() > self.gossip.max_clock_skew_secs {
+            self.penalize_peer(&from, self.gossip.stale_message_penalty, "message outside the accepted time window");
+            return;
+        }
+
+        // Duplicates are normal in a flood network, so they cost nothing
+        if !self.seen_messages.lock().unwrap().insert(id, now) {
+            return;
+        }
+
+        if let Err(e) = message.data.verify_attestations(&self.oracle_keys) {
+            self.penalize_peer(&from, self.gossip.invalid_signature_penalty, &e.to_string());
+            return;
+        }
+
+        match &message.data {
+            // Announcements are signed, so they describe their author wherever they came from
+            MessageData::PeerAnnouncement { services, endpoint } => {
+                if let Some(peer) = self.peers.get_mut(&message.sender) {
+                    peer.services = services.clone();
+                    peer.address = endpoint.clone();
+                }
+                if message.sender != self.local_pubkey {
+                    self.address_book.record(message.sender, endpoint.clone(), services.clone(), AddressSource::Announcement, now);
+                }
+            }
+            MessageData::GetAddresses => {
+                let peers = self.address_book.sample(MAX_ADDRESSES_PER_MESSAGE, now)
+                    .into_iter()
+                    .filter(|address| address.pubkey != from)
+                    .collect();
+                let reply = self.new_message(MessageType::Addresses, MessageData::Addresses { peers })
+                    .and_then(|mut reply| {
+                        reply.ttl = 1;
+                        self.send_to_peer(&from, &reply)
+                    });
+                if let Err(e) = reply {
+                    log::debug!("Failed to send addresses to {}: {}", from, e);
+                }
+            }
+            MessageData::Addresses { peers } => {
+                if peers.len() > MAX_ADDRESSES_PER_MESSAGE {
+                    self.penalize_peer(&from, self.gossip.rate_limit_penalty, "oversized address list");
+                    return;
+                }
+                for address in peers {
+                    if address.pubkey != self.local_pubkey {
+                        self.address_book.record(
+                            address.pubkey,
+                            address.address.clone(),
+                            address.services.clone(),
+                            AddressSource::Exchange,
+                            now,
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        // Route to appropriate handler; a failing handler doesn't drop the peer
         if let Some(handler) = self.message_handlers.get(&message.message_type) {
-            handler(&message)?;
+            if let Err(e) = handler(&message) {
+                log::warn!("{:?} handler failed for message from {}: {}", message.message_type, from, e);
+            }
         }
 
         // Update peer info
-        if let Some(peer) = self.peers.get_mut(&message.sender) {
-            peer.last_seen = chrono::Utc::now();
+        if let Some(peer) = self.peers.get_mut(&from) {
+            peer.last_seen = now;
         }
 
-        Ok(())
+        if message.ttl > 1 {
+            message.ttl -= 1;
+            if let Err(e) = self.relay_message(&message, Some(&from), None) {
+                log::warn!("Failed to relay message from {}: {}", message.sender, e);
+            }
+        }
+    }
+
+    /// Lower a misbehaving peer's reputation, disconnecting it below the floor
+    fn penalize_peer(&mut self, peer: &PublicKey, penalty: f64, reason: &str) {
+        let Some(info) = self.peers.get_mut(peer) else {
+            return;
+        };
+        info.reputation_score = (info.reputation_score - penalty).max(0.0);
+        log::warn!("Penalized peer {} ({}): reputation now {:.2}", peer, reason, info.reputation_score);
+
+        if info.reputation_score < self.gossip.disconnect_below_reputation {
+            log::warn!("Disconnecting peer {} for misbehaviour", peer);
+            let ban = chrono::Duration::seconds(self.gossip.misbehaviour_ban_secs);
+            if let Err(e) = self.ban_peer(*peer, reason, Some(ban)) {
+                log::warn!("Failed to ban peer {}: {}", peer, e);
+            }
+        }
+    }
+
+    pub fn peer_info(&self, peer: &PublicKey) -> Option<&PeerInfo> {
+        self.peers.get(peer)
+    }
+}
+
+/// Handshake an accepted socket off the main loop, then hand it to the network
+async fn handle_incoming_connection<S: PeerStream + 'static>(
+    mut stream: S,
+    addr: String,
+    local_key: bitcoin::secp256k1::SecretKey,
+    hello: Hello,
+    events: mpsc::UnboundedSender<PeerEvent>,
+) {
+    let responded = tokio::time::timeout(wire::HANDSHAKE_TIMEOUT, noise::respond(&mut stream, &local_key)).await;
+    let (remote_key, transport) = match responded {
+        Ok(Ok(authenticated)) => authenticated,
+        Ok(Err(e)) => return log::debug!("Encrypted handshake with {} failed: {}", addr, e),
+        Err(_) => return log::debug!("Encrypted handshake with {} timed out", addr),
+    };
+
+    // The hello must come from the key the peer just proved it controls
+    let mut stream = SecureStream::new(stream, transport);
+    match wire::handshake(&mut stream, &hello, Some(PublicKey::new(remote_key))).await {
+        Ok(handshake) => {
+            let _ = events.send(PeerEvent::Connected {
+                handshake,
+                address: addr,
+                stream: Box::new(stream),
+            });
+        }
+        Err(e) => log::debug!("Handshake with {} failed: {}", addr, e),
     }
 }
 
@@ -386,8 +1364,8 @@ impl BitStableNetwork {
 
         NetworkStats {
             connected_peers: self.connection_pool.connections.len(),
-            total_messages_sent: 0, // Would track in real implementation
-            total_messages_received: 0,
+            total_messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            total_messages_received: self.messages_received,
             oracle_nodes: oracle_count,
             liquidator_nodes: liquidator_count,
             vault_nodes: vault_count,
@@ -406,4 +1384,365 @@ impl BitStableNetwork {
 
         (oracle_health + liquidator_health + peer_health) / 3.0
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn secret(byte: u8) -> bitcoin::secp256k1::SecretKey {
+        bitcoin::secp256k1::SecretKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    fn pubkey(byte: u8) -> PublicKey {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        PublicKey::new(bitcoin::secp256k1::PublicKey::from_secret_key(&secp, &secret(byte)))
+    }
+
+    fn vault_id() -> bitcoin::Txid {
+        use bitcoin::hashes::Hash;
+        bitcoin::Txid::from_byte_array([7; 32])
+    }
+
+    #[tokio::test]
+    async fn test_peers_exchange_messages() {
+        let mut alice = BitStableNetwork::new(secret(1), 8, []).with_services(vec![ServiceType::Oracle]);
+        let mut bob = BitStableNetwork::new(secret(2), 8, []);
+        let bob_address = bob.listen("127.0.0.1:0").await.unwrap();
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        bob.register_message_handler(MessageType::VaultCreated, move |message| {
+            if let MessageData::VaultCreated { stable_debt, .. } = message.data {
+                sink.lock().unwrap().push(stable_debt);
+            }
+            Ok(())
+        });
+
+        // Bob can't prove he holds a key he doesn't, so claiming one is refused
+        let err = alice.connect_to_peer(&bob_address.to_string(), pubkey(3)).await.unwrap_err();
+        assert!(matches!(err, BitStableError::PeerProtocolError(_) | BitStableError::IoError(_)));
+        assert!(alice.connected_peers().is_empty());
+
+        alice.connect_to_peer(&bob_address.to_string(), pubkey(2)).await.unwrap();
+        alice.announce_vault_creation(vault_id(), bitcoin::Amount::from_sat(100_000), 64_000.0).await.unwrap();
+
+        // Bob sees the inbound connection, then the announcement
+        let deadline = std::time::Duration::from_secs(5);
+        while received.lock().unwrap().is_empty() {
+            tokio::time::timeout(deadline, bob.process_next_event()).await.unwrap().unwrap();
+        }
+
+        assert_eq!(*received.lock().unwrap(), vec![64_000.0]);
+        assert_eq!(bob.connected_peers(), vec![pubkey(1)]);
+        assert_eq!(bob.get_available_liquidators().len(), 0);
+        assert_eq!(bob.get_peers_by_service(ServiceType::Oracle)[0].pubkey, pubkey(1));
+        assert_eq!(alice.get_network_stats().total_messages_sent, 1);
+
+        // Dropping Alice closes the socket and Bob forgets the connection
+        drop(alice);
+        while !bob.connected_peers().is_empty() {
+            tokio::time::timeout(deadline, bob.process_next_event()).await.unwrap().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_node_key_persists() {
+        let database = DatabaseManager::open("memory://").unwrap();
+        let key = load_or_create_node_key(&database).unwrap();
+        assert_eq!(load_or_create_node_key(&database).unwrap(), key);
+    }
+
+    #[tokio::test]
+    async fn test_late_disconnect_keeps_replacement_connection() {
+        let mut alice = BitStableNetwork::new(secret(1), 8, []);
+        let mut bob = BitStableNetwork::new(secret(2), 8, []);
+        let bob_address = bob.listen("127.0.0.1:0").await.unwrap();
+
+        // Reconnecting replaces the first connection
+        alice.connect_to_peer(&bob_address.to_string(), pubkey(2)).await.unwrap();
+        alice.connect_to_peer(&bob_address.to_string(), pubkey(2)).await.unwrap();
+
+        let disconnect = |generation| PeerEvent::Disconnected { peer: pubkey(2), generation, reason: "closed".to_string() };
+        alice.event_sender.send(disconnect(0)).unwrap();
+        alice.process_pending_events().unwrap();
+        assert_eq!(alice.connected_peers(), vec![pubkey(2)]);
+
+        alice.event_sender.send(disconnect(1)).unwrap();
+        alice.process_pending_events().unwrap();
+        assert!(alice.connected_peers().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_gossip_relays_once_and_penalizes_bad_signatures() {
+        let mut alice = BitStableNetwork::new(secret(1), 8, []);
+        let mut bob = BitStableNetwork::new(secret(2), 8, []);
+        let mut carol = BitStableNetwork::new(secret(3), 8, []);
+        let bob_address = bob.listen("127.0.0.1:0").await.unwrap().to_string();
+
+        let counter = |network: &mut BitStableNetwork| {
+            let count = Arc::new(Mutex::new(0));
+            let sink = count.clone();
+            network.register_message_handler(MessageType::VaultCreated, move |_| {
+                *sink.lock().unwrap() += 1;
+                Ok(())
+            });
+            count
+        };
+        let bob_count = counter(&mut bob);
+        let carol_count = counter(&mut carol);
+
+        alice.connect_to_peer(&bob_address, pubkey(2)).await.unwrap();
+        carol.connect_to_peer(&bob_address, pubkey(2)).await.unwrap();
+        let deadline = std::time::Duration::from_secs(5);
+        while bob.connected_peers().len() < 2 {
+            tokio::time::timeout(deadline, bob.process_next_event()).await.unwrap().unwrap();
+        }
+
+        // The same signed message arriving twice is delivered once, and Bob relays it to Carol
+        let mut message = NetworkMessage::new(
+            MessageType::VaultCreated,
+            pubkey(1),
+            MessageData::VaultCreated { vault_id: vault_id(), collateral_amount: bitcoin::Amount::from_sat(100_000), stable_debt: 64_000.0 },
+            alice.gossip.max_hops,
+        );
+        message.sign(&secret(1)).unwrap();
+        alice.send_to_peer(&pubkey(2), &message).unwrap();
+        alice.send_to_peer(&pubkey(2), &message).unwrap();
+
+        // A forged copy claiming Carol wrote it costs Alice reputation
+        let mut forged = message.clone();
+        forged.sender = pubkey(3);
+        alice.send_to_peer(&pubkey(2), &forged).unwrap();
+
+        let starting_reputation = bob.peer_info(&pubkey(1)).unwrap().reputation_score;
+        while bob.peer_info(&pubkey(1)).unwrap().reputation_score == starting_reputation {
+            tokio::time::timeout(deadline, bob.process_next_event()).await.unwrap().unwrap();
+        }
+        while *carol_count.lock().unwrap() == 0 {
+            tokio::time::timeout(deadline, carol.process_next_event()).await.unwrap().unwrap();
+        }
+
+        assert_eq!(*bob_count.lock().unwrap(), 1);
+        assert_eq!(*carol_count.lock().unwrap(), 1);
+        assert!(bob.peer_info(&pubkey(1)).unwrap().reputation_score < starting_reputation);
+
+        // Carol doesn't echo the message back through Bob
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        carol.process_pending_events().unwrap();
+        bob.process_pending_events().unwrap();
+        assert_eq!(*bob_count.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_address_exchange_fills_slots_and_bans() {
+        let mut alice = BitStableNetwork::new(secret(1), 8, [])
+            .with_outbound_slots(HashMap::from([(ServiceType::Oracle, 1)]));
+        let mut bob = BitStableNetwork::new(secret(2), 8, []);
+        let mut carol = BitStableNetwork::new(secret(3), 8, []).with_services(vec![ServiceType::Oracle]);
+        let bob_address = bob.listen("127.0.0.1:0").await.unwrap().to_string();
+        let carol_address = carol.listen("127.0.0.1:0").await.unwrap().to_string();
+
+        // Bob learns Carol's address from her connection, Alice knows only Bob as a seed
+        carol.connect_to_peer(&bob_address, pubkey(2)).await.unwrap();
+        alice = alice.with_seeds(&[format!("{}@{}", pubkey(2), bob_address)]).unwrap();
+        alice.start_peer_discovery().await.unwrap();
+
+        let step = std::time::Duration::from_millis(20);
+        for _ in 0..250 {
+            if alice.address_book().get(&pubkey(3)).is_some() {
+                break;
+            }
+            bob.process_pending_events().unwrap();
+            let _ = tokio::time::timeout(step, alice.process_next_event()).await;
+        }
+        assert_eq!(alice.address_book().get(&pubkey(3)).unwrap().address, carol_address);
+
+        // The empty oracle slot is filled from the address book
+        assert_eq!(alice.fill_outbound_slots().await, 1);
+        assert_eq!(alice.fill_outbound_slots().await, 0);
+        assert!(alice.connected_peers().contains(&pubkey(3)));
+        assert_eq!(alice.slot_usage(), vec![(ServiceType::Oracle, 1, 1)]);
+
+        alice.ban_peer(pubkey(3), "test", Some(chrono::Duration::hours(1))).unwrap();
+        assert!(!alice.connected_peers().contains(&pubkey(3)));
+        assert!(alice.connect_to_peer(&carol_address, pubkey(3)).await.is_err());
+        assert_eq!(alice.fill_outbound_slots().await, 0);
+
+        assert!(alice.unban_peer(&pubkey(3)).unwrap());
+        alice.connect_to_peer(&carol_address, pubkey(3)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_well_connected_node_still_exchanges_addresses() {
+        let mut alice = BitStableNetwork::new(secret(1), 8, []);
+        let mut peers = Vec::new();
+        for byte in 2..=4 {
+            let mut peer = BitStableNetwork::new(secret(byte), 8, []);
+            let address = peer.listen("127.0.0.1:0").await.unwrap().to_string();
+            alice.connect_to_peer(&address, pubkey(byte)).await.unwrap();
+            peers.push(peer);
+        }
+        assert_eq!(alice.connected_peers().len(), 3);
+
+        // Not yet due: maintenance leaves the last exchange alone
+        let recent = chrono::Utc::now() - chrono::Duration::minutes(1);
+        alice.last_address_exchange = Some(recent);
+        alice.maintenance_cycle().await.unwrap();
+        assert_eq!(alice.last_address_exchange, Some(recent));
+
+        let stale = chrono::Utc::now() - chrono::Duration::minutes(ADDRESS_EXCHANGE_MINUTES + 1);
+        alice.last_address_exchange = Some(stale);
+        alice.maintenance_cycle().await.unwrap();
+        assert!(alice.last_address_exchange.unwrap() > recent);
+    }
+
+    #[tokio::test]
+    async fn test_prices_reach_only_multi_currency_peers() {
+        let mut oracles = OracleKeyManager::new();
+        let oracle_key = oracles.generate_oracle_key("oracle").unwrap();
+        let mut alice = BitStableNetwork::new(secret(1), 8, [oracle_key]);
+        let mut bob = BitStableNetwork::new(secret(2), 8, [oracle_key]);
+        let mut carol = BitStableNetwork::new(secret(3), 8, []).with_features(0);
+        let mut dave = BitStableNetwork::new(secret(4), 8, []).with_features(0);
+        let bob_address = bob.listen("127.0.0.1:0").await.unwrap().to_string();
+        let carol_address = carol.listen("127.0.0.1:0").await.unwrap().to_string();
+        let dave_address = dave.listen("127.0.0.1:0").await.unwrap().to_string();
+        alice.connect_to_peer(&bob_address, pubkey(2)).await.unwrap();
+        alice.connect_to_peer(&carol_address, pubkey(3)).await.unwrap();
+        bob.connect_to_peer(&dave_address, pubkey(4)).await.unwrap();
+
+        let recorder = |network: &mut BitStableNetwork| {
+            let received = Arc::new(Mutex::new(Vec::new()));
+            let sink = received.clone();
+            for message_type in [MessageType::PriceUpdate, MessageType::VaultCreated] {
+                let sink = sink.clone();
+                network.register_message_handler(message_type, move |message| {
+                    sink.lock().unwrap().push(message.data.clone());
+                    Ok(())
+                });
+            }
+            received
+        };
+        let bob_received = recorder(&mut bob);
+        let carol_received = recorder(&mut carol);
+        let dave_received = recorder(&mut dave);
+
+        let now = chrono::Utc::now().timestamp();
+        let attestations = vec![
+            oracles.sign_currency_price("oracle", Currency::USD, 64_000.0, now).unwrap(),
+            oracles.sign_currency_price("oracle", Currency::EUR, 59_000.0, now).unwrap(),
+        ];
+        alice.send_price_attestations(attestations.clone()).await.unwrap();
+        // Connections are ordered, so whoever sees the announcement has seen any price before it
+        alice.announce_vault_creation(vault_id(), bitcoin::Amount::from_sat(100_000), 1_000.0).await.unwrap();
+
+        let deadline = std::time::Duration::from_secs(5);
+        while bob_received.lock().unwrap().len() < 2 {
+            tokio::time::timeout(deadline, bob.process_next_event()).await.unwrap().unwrap();
+        }
+        while carol_received.lock().unwrap().is_empty() {
+            tokio::time::timeout(deadline, carol.process_next_event()).await.unwrap().unwrap();
+        }
+        while dave_received.lock().unwrap().is_empty() {
+            tokio::time::timeout(deadline, dave.process_next_event()).await.unwrap().unwrap();
+        }
+
+        // Bob negotiated multi-currency and gets every attested price
+        match &bob_received.lock().unwrap()[0] {
+            MessageData::PriceAttestations { prices, .. } => assert_eq!(prices[&Currency::EUR], 59_000.0),
+            other => panic!("unexpected payload {:?}", other),
+        }
+        // Legacy peers, direct or behind Bob, get no unattested v1 price
+        for received in [&carol_received, &dave_received] {
+            assert!(matches!(received.lock().unwrap()[..], [MessageData::VaultCreated { .. }]));
+        }
+
+        // Non-USD transfers have no v1 form, so they can't be sent to Carol at all
+        let mut transfer = NetworkMessage::new(
+            MessageType::StableTransfer,
+            pubkey(1),
+            MessageData::CurrencyTransfer { from: pubkey(1), to: pubkey(3), currency: Currency::EUR, amount: 10.0 },
+            1,
+        );
+        transfer.sign(&secret(1)).unwrap();
+        assert!(alice.send_to_peer(&pubkey(3), &transfer).is_err());
+
+        // A price relabelled after signing fails attestation and costs the sender reputation
+        let prices = HashMap::from([(Currency::USD, 64_000.0), (Currency::GBP, 59_000.0)]);
+        let mut tampered = attestations;
+        tampered[1].currency = Some(Currency::GBP);
+        let mut forged = NetworkMessage::new(
+            MessageType::PriceUpdate,
+            pubkey(1),
+            MessageData::PriceAttestations { prices, attestations: tampered },
+            1,
+        );
+        forged.sign(&secret(1)).unwrap();
+        alice.send_to_peer(&pubkey(2), &forged).unwrap();
+
+        let starting_reputation = bob.peer_info(&pubkey(1)).unwrap().reputation_score;
+        while bob.peer_info(&pubkey(1)).unwrap().reputation_score == starting_reputation {
+            tokio::time::timeout(deadline, bob.process_next_event()).await.unwrap().unwrap();
+        }
+        assert_eq!(bob_received.lock().unwrap().len(), 2);
+
+        // A well-formed attestation from an oracle Bob doesn't trust is rejected too
+        let mut rogue = OracleKeyManager::new();
+        rogue.generate_oracle_key("oracle").unwrap();
+        let attestations = vec![rogue.sign_currency_price("oracle", Currency::USD, 1.0, now).unwrap()];
+        let mut untrusted = NetworkMessage::new(
+            MessageType::PriceUpdate,
+            pubkey(1),
+            MessageData::PriceAttestations { prices: HashMap::from([(Currency::USD, 1.0)]), attestations },
+            1,
+        );
+        untrusted.sign(&secret(1)).unwrap();
+        alice.send_to_peer(&pubkey(2), &untrusted).unwrap();
+
+        let starting_reputation = bob.peer_info(&pubkey(1)).unwrap().reputation_score;
+        while bob.peer_info(&pubkey(1)).unwrap().reputation_score == starting_reputation {
+            tokio::time::timeout(deadline, bob.process_next_event()).await.unwrap().unwrap();
+        }
+        assert_eq!(bob_received.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_unattested_v1_prices_are_rejected() {
+        let mut oracles = OracleKeyManager::new();
+        let oracle_key = oracles.generate_oracle_key("oracle").unwrap();
+        let mut alice = BitStableNetwork::new(secret(1), 8, [oracle_key]);
+        let mut bob = BitStableNetwork::new(secret(2), 8, [oracle_key]);
+        let bob_address = bob.listen("127.0.0.1:0").await.unwrap().to_string();
+        alice.connect_to_peer(&bob_address, pubkey(2)).await.unwrap();
+
+        let received = Arc::new(Mutex::new(0));
+        let sink = received.clone();
+        bob.register_message_handler(MessageType::PriceUpdate, move |_| {
+            *sink.lock().unwrap() += 1;
+            Ok(())
+        });
+
+        let deadline = std::time::Duration::from_secs(5);
+        while bob.peer_info(&pubkey(1)).is_none() {
+            tokio::time::timeout(deadline, bob.process_next_event()).await.unwrap().unwrap();
+        }
+
+        // A v1 price carries no attestation to check, so it costs the sender like a forged one
+        let mut message = NetworkMessage::new(
+            MessageType::PriceUpdate,
+            pubkey(1),
+            MessageData::PriceUpdate { price_usd: 1.0, oracle_source: "oracle".to_string(), signature: Vec::new() },
+            1,
+        );
+        message.sign(&secret(1)).unwrap();
+        alice.send_to_peer(&pubkey(2), &message).unwrap();
+
+        let starting_reputation = bob.peer_info(&pubkey(1)).unwrap().reputation_score;
+        while bob.peer_info(&pubkey(1)).unwrap().reputation_score == starting_reputation {
+            tokio::time::timeout(deadline, bob.process_next_event()).await.unwrap().unwrap();
+        }
+        assert_eq!(*received.lock().unwrap(), 0);
+    }
+}
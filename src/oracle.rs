@@ -4,7 +4,11 @@ use std::collections::{HashMap, VecDeque};
 use chrono::{DateTime, Utc, Duration};
 use bitcoin::secp256k1::PublicKey;
 use crate::{BitStableError, Result, ProtocolConfig};
+use crate::config::TwapWindow;
 use crate::multi_currency::{Currency, ExchangeRates};
+use crate::circuit_breaker::{BreakerEvent, CircuitBreakerSystem};
+use crate::database::DatabaseManager;
+use crate::price_stream::{self, PriceStream, StreamConfig, StreamStats};
 
 /// Types of oracle slashing offenses
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,6 +24,108 @@ pub struct PriceData {
     pub timestamp: DateTime<Utc>,
     pub source: String,
     pub signature: Option<String>,
+    #[serde(default)]
+    pub volumes: HashMap<Currency, f64>,  // 24h BTC volume behind each price, when reported
+}
+
+/// A price from one venue along with the BTC volume traded there, if the venue reports it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PriceQuote {
+    pub price: f64,
+    pub volume: Option<f64>,
+}
+
+impl PriceQuote {
+    pub fn new(price: f64, volume: Option<f64>) -> Self {
+        Self {
+            price,
+            volume: volume.filter(|v| v.is_finite() && *v >= 0.0),
+        }
+    }
+
+    /// Reject a quote whose price is not a positive finite number
+    pub fn validate(self) -> Result<Self> {
+        if self.price.is_finite() && self.price > 0.0 {
+            Ok(self)
+        } else {
+            Err(BitStableError::PriceFeedError(format!("Invalid price {}", self.price)))
+        }
+    }
+}
+
+/// Median of a set of prices, `None` when there are none
+pub fn median_price(prices: &mut [f64]) -> Option<f64> {
+    if prices.is_empty() {
+        return None;
+    }
+    prices.sort_by(|a, b| a.total_cmp(b));
+    let middle = prices.len() / 2;
+    if prices.len().is_multiple_of(2) {
+        Some((prices[middle - 1] + prices[middle]) / 2.0)
+    } else {
+        Some(prices[middle])
+    }
+}
+
+/// Volume-weighted price with no venue above `max_weight` of the total weight.
+/// Falls back to the median when any venue is missing volume or none has traded.
+/// Quotes without a positive finite price are ignored.
+pub fn volume_weighted_price(quotes: &[PriceQuote], max_weight: f64) -> Option<f64> {
+    let quotes: Vec<PriceQuote> = quotes.iter().filter_map(|q| q.validate().ok()).collect();
+    if quotes.is_empty() {
+        return None;
+    }
+
+    let volumes: Option<Vec<f64>> = quotes.iter().map(|q| q.volume).collect();
+    let volumes = match volumes {
+        Some(volumes) if volumes.iter().sum::<f64>() > 0.0 => volumes,
+        _ => {
+            let mut prices: Vec<f64> = quotes.iter().map(|q| q.price).collect();
+            return median_price(&mut prices);
+        }
+    };
+
+    let weights = capped_weights(&volumes, max_weight);
+    Some(quotes.iter().zip(weights).map(|(q, w)| q.price * w).sum())
+}
+
+/// Normalise volumes to weights, capping each at `max_weight` and spreading the
+/// excess over the remaining venues in proportion to their volume
+fn capped_weights(volumes: &[f64], max_weight: f64) -> Vec<f64> {
+    let n = volumes.len();
+    // A cap this tight cannot sum to one, so every venue gets the same say
+    if max_weight * n as f64 <= 1.0 {
+        return vec![1.0 / n as f64; n];
+    }
+
+    let mut weights = vec![0.0; n];
+    let mut capped = vec![false; n];
+    let mut remaining = 1.0;
+
+    loop {
+        let uncapped: Vec<usize> = (0..n).filter(|i| !capped[*i]).collect();
+        let uncapped_volume: f64 = uncapped.iter().map(|i| volumes[*i]).sum();
+        let uncapped_count = uncapped.len() as f64;
+        let share = |i: usize| if uncapped_volume > 0.0 {
+            remaining * volumes[i] / uncapped_volume
+        } else {
+            remaining / uncapped_count
+        };
+
+        let over: Vec<usize> = uncapped.iter().copied().filter(|i| share(*i) > max_weight).collect();
+        if over.is_empty() {
+            for i in uncapped {
+                weights[i] = share(i);
+            }
+            return weights;
+        }
+
+        for i in over {
+            capped[i] = true;
+            weights[i] = max_weight;
+            remaining -= max_weight;
+        }
+    }
 }
 
 /// Graduated circuit breaker configuration
@@ -42,6 +148,54 @@ pub struct TimeWeightedPrice {
     pub last_twap: f64,
 }
 
+/// Market price of a BitStable unit in its own fiat currency; 1.0 is on peg
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PegPrice {
+    pub currency: Currency,
+    pub price: f64,
+    pub source: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl PegPrice {
+    /// Absolute distance from the peg
+    pub fn deviation(&self) -> f64 {
+        (self.price - 1.0).abs()
+    }
+}
+
+/// Peg quotes older than this are left out of the combined peg price
+const PEG_PRICE_MAX_AGE_MINUTES: i64 = 10;
+
+/// Price from a peg endpoint's `{"price": ...}` reply, as a number or a decimal string
+fn parse_peg_price(text: &str) -> Result<f64> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Price {
+        Number(f64),
+        Text(String),
+    }
+    #[derive(Deserialize)]
+    struct PegResponse {
+        price: Price,
+    }
+
+    let response: PegResponse = serde_json::from_str(text)?;
+    match response.price {
+        Price::Number(price) => Ok(price),
+        Price::Text(price) => price.parse()
+            .map_err(|e| BitStableError::PriceFeedError(format!("Invalid peg price '{}': {}", price, e))),
+    }
+}
+
+/// Consensus price sample feeding the network TWAP
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwapSample {
+    pub timestamp: DateTime<Utc>,
+    pub block_height: Option<u64>,
+    pub price: f64,
+}
+
 /// Oracle bonding and slashing configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OracleBond {
@@ -67,6 +221,35 @@ pub struct OracleMetrics {
     pub last_price_timestamp: Option<DateTime<Utc>>,
 }
 
+/// One oracle's price for a currency in a consensus round
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OracleSubmission {
+    pub oracle_name: String,
+    pub currency: Currency,
+    pub price: f64,
+    pub volume: Option<f64>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A bond slash applied to an oracle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlashRecord {
+    pub oracle_name: String,
+    pub slash_type: SlashType,
+    pub amount: Amount,
+    pub remaining_bond: Amount,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Metrics and bond state persisted for an oracle between restarts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OracleState {
+    pub name: String,
+    pub metrics: OracleMetrics,
+    pub quality_score: f64,
+    pub is_bonded: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct Oracle {
     pub name: String,
@@ -79,6 +262,7 @@ pub struct Oracle {
     pub quality_score: f64,
     pub is_bonded: bool,
     pub backup_urls: HashMap<Currency, Vec<String>>, // Backup price sources
+    pub streams: HashMap<Currency, PriceStream>,       // Live WebSocket tickers, REST stays as failover
 }
 
 impl Oracle {
@@ -111,9 +295,25 @@ impl Oracle {
             quality_score: 1.0,
             is_bonded: false,
             backup_urls: HashMap::new(),
+            streams: HashMap::new(),
         }
     }
 
+    pub fn state(&self) -> OracleState {
+        OracleState {
+            name: self.name.clone(),
+            metrics: self.metrics.clone(),
+            quality_score: self.quality_score,
+            is_bonded: self.is_bonded,
+        }
+    }
+
+    pub fn restore_state(&mut self, state: OracleState) {
+        self.metrics = state.metrics;
+        self.quality_score = state.quality_score;
+        self.is_bonded = state.is_bonded;
+    }
+
     pub fn add_price_feed(&mut self, currency: Currency, url: String) {
         self.urls.insert(currency, url);
     }
@@ -124,12 +324,22 @@ impl Oracle {
 
         // Clone the URLs to avoid borrowing issues
         let urls = self.urls.clone();
+        self.sync_streams();
         
         for (currency, url) in urls {
+            // A live stream already keeps this price current
+            if self.is_price_fresh(&currency) {
+                if let Some(price) = self.last_prices.get(&currency).and_then(|p| p.prices.get(&currency)) {
+                    prices.insert(currency.clone(), *price);
+                    continue;
+                }
+            }
+
             match self.fetch_single_price(&url, &currency).await {
-                Ok(price) => {
+                Ok(quote) => {
+                    let price = quote.price;
                     prices.insert(currency.clone(), price);
-                    self.update_twap(currency.clone(), price);
+                    self.record_quote(currency.clone(), quote, Utc::now());
                     log::debug!("Oracle {} fetched {}/{}: {}", self.name, "BTC", currency.to_string(), price);
                 }
                 Err(e) => {
@@ -161,15 +371,55 @@ impl Oracle {
         Ok(prices)
     }
 
+    /// Subscribe to a WebSocket ticker for one currency, replacing any existing stream
+    pub fn start_stream(&mut self, config: StreamConfig) {
+        let currency = config.currency.clone();
+        self.streams.insert(currency, PriceStream::spawn(&self.name, config));
+    }
+
+    /// Apply ticks received from streams to `last_prices` and TWAP; returns how many were applied
+    pub fn sync_streams(&mut self) -> usize {
+        let ticks: Vec<_> = self.streams.values().flat_map(|stream| stream.drain_ticks()).collect();
+
+        for tick in &ticks {
+            self.metrics.last_price_timestamp = Some(tick.timestamp);
+            self.record_quote(tick.currency.clone(), tick.quote, tick.timestamp);
+        }
+
+        ticks.len()
+    }
+
+    /// Keep a fetched or streamed quote, with its volume, in `last_prices` and TWAP
+    fn record_quote(&mut self, currency: Currency, quote: PriceQuote, timestamp: DateTime<Utc>) {
+        let entry = self.last_prices.entry(currency.clone()).or_insert_with(|| PriceData {
+            prices: HashMap::new(),
+            timestamp,
+            source: self.name.clone(),
+            signature: None,
+            volumes: HashMap::new(),
+        });
+        entry.prices.insert(currency.clone(), quote.price);
+        match quote.volume {
+            Some(volume) => entry.volumes.insert(currency.clone(), volume),
+            None => entry.volumes.remove(&currency),
+        };
+        entry.timestamp = timestamp;
+
+        self.update_twap(currency, quote.price, timestamp);
+    }
+
+    pub fn stream_stats(&self, currency: &Currency) -> Option<StreamStats> {
+        self.streams.get(currency).map(|stream| stream.stats())
+    }
+
     /// Update TWAP data for a currency
-    fn update_twap(&mut self, currency: Currency, price: f64) {
+    fn update_twap(&mut self, currency: Currency, price: f64, now: DateTime<Utc>) {
         let twap = self.twap_data.entry(currency).or_insert_with(|| TimeWeightedPrice {
             prices: VecDeque::new(),
             window_hours: 24, // 24-hour TWAP
             last_twap: price,
         });
         
-        let now = Utc::now();
         twap.prices.push_back((now, price));
         
         // Remove prices older than window
@@ -281,7 +531,7 @@ impl Oracle {
         // Try primary URL first
         if let Some(primary_url) = self.urls.get(currency) {
             match self.fetch_single_price(primary_url, currency).await {
-                Ok(price) => {
+                Ok(PriceQuote { price, .. }) => {
                     self.metrics.successful_submissions += 1;
                     self.metrics.last_price_timestamp = Some(Utc::now());
                     return Ok(price);
@@ -296,7 +546,7 @@ impl Oracle {
         if let Some(backup_urls) = self.backup_urls.get(currency) {
             for backup_url in backup_urls {
                 match self.fetch_single_price(backup_url, currency).await {
-                    Ok(price) => {
+                    Ok(PriceQuote { price, .. }) => {
                         self.metrics.successful_submissions += 1;
                         self.metrics.last_price_timestamp = Some(Utc::now());
                         log::info!("Oracle {} failed over to backup for {}", self.name, currency.to_string());
@@ -313,7 +563,38 @@ impl Oracle {
         Err(BitStableError::PriceFeedError(format!("All oracle sources failed for {}", currency.to_string())))
     }
 
-    async fn fetch_single_price(&self, url: &str, currency: &Currency) -> Result<f64> {
+    /// Query the primary and every backup source and combine them by traded volume
+    pub async fn fetch_volume_weighted_quote(&mut self, currency: &Currency, max_venue_weight: f64) -> Result<PriceQuote> {
+        let sources: Vec<String> = self.urls.get(currency).into_iter()
+            .chain(self.backup_urls.get(currency).into_iter().flatten())
+            .cloned()
+            .collect();
+
+        let mut quotes = Vec::new();
+        for url in &sources {
+            match self.fetch_single_price(url, currency).await {
+                Ok(quote) => quotes.push(quote),
+                Err(e) => log::warn!("Oracle {} source {} failed for {}: {}", self.name, url, currency.to_string(), e),
+            }
+        }
+
+        let price = match volume_weighted_price(&quotes, max_venue_weight) {
+            Some(price) => price,
+            None => {
+                self.metrics.total_failures += 1;
+                return Err(BitStableError::PriceFeedError(format!("All oracle sources failed for {}", currency.to_string())));
+            }
+        };
+
+        self.metrics.successful_submissions += 1;
+        self.metrics.last_price_timestamp = Some(Utc::now());
+
+        // The oracle's volume is only meaningful if every source reported one
+        let volume = quotes.iter().map(|q| q.volume).sum::<Option<f64>>();
+        Ok(PriceQuote::new(price, volume))
+    }
+
+    async fn fetch_single_price(&self, url: &str, currency: &Currency) -> Result<PriceQuote> {
         let response = self.client
             .get(url)
             .timeout(std::time::Duration::from_secs(10))
@@ -321,10 +602,10 @@ impl Oracle {
             .await?;
 
         let text = response.text().await?;
-        self.parse_price_response(&text, currency)
+        self.parse_price_response(&text, currency)?.validate()
     }
 
-    fn parse_price_response(&self, text: &str, currency: &Currency) -> Result<f64> {
+    fn parse_price_response(&self, text: &str, currency: &Currency) -> Result<PriceQuote> {
         // Parse different exchange formats based on oracle name and currency
         match self.name.as_str() {
             "Coinbase" => self.parse_coinbase(text, currency),
@@ -335,7 +616,7 @@ impl Oracle {
         }
     }
 
-    fn parse_coinbase(&self, text: &str, currency: &Currency) -> Result<f64> {
+    fn parse_coinbase(&self, text: &str, currency: &Currency) -> Result<PriceQuote> {
         #[derive(Deserialize)]
         struct CoinbaseResponse {
             data: CoinbaseData,
@@ -349,26 +630,36 @@ impl Oracle {
         let response: CoinbaseResponse = serde_json::from_str(text)
             .map_err(|e| BitStableError::PriceFeedError(format!("Coinbase parse error: {}", e)))?;
         
-        response.data.rates.get(&currency.to_string())
+        // The exchange-rates endpoint carries no volume
+        let price = response.data.rates.get(&currency.to_string())
             .ok_or_else(|| BitStableError::PriceFeedError(format!("{} rate not found", currency.to_string())))?
             .parse()
-            .map_err(|e| BitStableError::PriceFeedError(format!("Price parse error: {}", e)))
+            .map_err(|e| BitStableError::PriceFeedError(format!("Price parse error: {}", e)))?;
+        Ok(PriceQuote::new(price, None))
     }
 
-    fn parse_binance(&self, text: &str, _currency: &Currency) -> Result<f64> {
+    fn parse_binance(&self, text: &str, _currency: &Currency) -> Result<PriceQuote> {
+        // Accepts both /ticker/price and /ticker/24hr, which adds base asset volume
         #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
         struct BinanceResponse {
-            price: String,
+            price: Option<String>,
+            last_price: Option<String>,
+            volume: Option<String>,
         }
 
         let response: BinanceResponse = serde_json::from_str(text)
             .map_err(|e| BitStableError::PriceFeedError(format!("Binance parse error: {}", e)))?;
         
-        response.price.parse()
-            .map_err(|e| BitStableError::PriceFeedError(format!("Price parse error: {}", e)))
+        let price = response.price.or(response.last_price)
+            .ok_or_else(|| BitStableError::PriceFeedError("No price found".to_string()))?
+            .parse()
+            .map_err(|e| BitStableError::PriceFeedError(format!("Price parse error: {}", e)))?;
+        let volume = response.volume.and_then(|v| v.parse().ok());
+        Ok(PriceQuote::new(price, volume))
     }
 
-    fn parse_kraken(&self, text: &str, currency: &Currency) -> Result<f64> {
+    fn parse_kraken(&self, text: &str, currency: &Currency) -> Result<PriceQuote> {
         #[derive(Deserialize)]
         struct KrakenResponse {
             result: HashMap<String, KrakenTicker>,
@@ -377,6 +668,8 @@ impl Oracle {
         #[derive(Deserialize)]
         struct KrakenTicker {
             c: Vec<String>, // last trade closed array
+            #[serde(default)]
+            v: Vec<String>, // volume [today, last 24 hours]
         }
 
         let response: KrakenResponse = serde_json::from_str(text)
@@ -396,13 +689,15 @@ impl Oracle {
         let ticker = response.result.get(pair)
             .ok_or_else(|| BitStableError::PriceFeedError(format!("{} pair not found", pair)))?;
         
-        ticker.c.first()
+        let price = ticker.c.first()
             .ok_or_else(|| BitStableError::PriceFeedError("No last price found".to_string()))?
             .parse()
-            .map_err(|e| BitStableError::PriceFeedError(format!("Price parse error: {}", e)))
+            .map_err(|e| BitStableError::PriceFeedError(format!("Price parse error: {}", e)))?;
+        let volume = ticker.v.get(1).and_then(|v| v.parse().ok());
+        Ok(PriceQuote::new(price, volume))
     }
 
-    fn parse_coingecko(&self, text: &str, currency: &Currency) -> Result<f64> {
+    fn parse_coingecko(&self, text: &str, currency: &Currency) -> Result<PriceQuote> {
         #[derive(Deserialize)]
         struct CoinGeckoResponse {
             bitcoin: HashMap<String, f64>,
@@ -412,9 +707,15 @@ impl Oracle {
             .map_err(|e| BitStableError::PriceFeedError(format!("CoinGecko parse error: {}", e)))?;
         
         let currency_key = currency.to_string().to_lowercase();
-        response.bitcoin.get(&currency_key)
+        let price = response.bitcoin.get(&currency_key)
             .copied()
-            .ok_or_else(|| BitStableError::PriceFeedError(format!("{} price not found", currency.to_string())))
+            .ok_or_else(|| BitStableError::PriceFeedError(format!("{} price not found", currency.to_string())))?;
+
+        // With include_24hr_vol the volume is quoted in fiat; convert to BTC
+        let volume = response.bitcoin.get(&format!("{}_24h_vol", currency_key))
+            .filter(|_| price > 0.0)
+            .map(|vol| vol / price);
+        Ok(PriceQuote::new(price, volume))
     }
 }
 
@@ -424,8 +725,11 @@ pub struct MultiCurrencyOracleNetwork {
     config: ProtocolConfig,
     price_history: Vec<ConsensusPrices>,
     exchange_rates: ExchangeRates,
-    circuit_breaker: CircuitBreakerConfig,
-    last_price_update: HashMap<Currency, DateTime<Utc>>,
+    circuit_breakers: CircuitBreakerSystem,
+    consensus_twap: HashMap<Currency, VecDeque<TwapSample>>,
+    block_height: Option<u64>,
+    peg_prices: HashMap<Currency, HashMap<String, PegPrice>>,  // Latest quote per market source
+    database: Option<DatabaseManager>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -469,7 +773,7 @@ impl MultiCurrencyOracleNetwork {
             config: config.clone(),
             price_history: Vec::new(),
             exchange_rates: ExchangeRates::new(),
-            circuit_breaker: CircuitBreakerConfig {
+            circuit_breakers: CircuitBreakerSystem::new(CircuitBreakerConfig {
                 tier1_threshold: 0.10,        // 10% requires 15/20+ oracles
                 tier2_threshold: 0.20,        // 20% requires 18/20+ oracles  
                 tier3_threshold: 0.30,        // 30% emergency governance override
@@ -477,13 +781,92 @@ impl MultiCurrencyOracleNetwork {
                 min_oracles_tier2: 18,        // Increased from 7 to 18
                 emergency_override: false,
                 cooldown_minutes: 15,
-            },
-            last_price_update: HashMap::new(),
+            }),
+            consensus_twap: HashMap::new(),
+            block_height: None,
+            peg_prices: HashMap::new(),
+            database: None,
         })
     }
 
+    /// Open WebSocket tickers for endpoints that configure a `stream_url`, one per
+    /// currency the oracle has a price feed for
+    pub fn start_streams(&mut self) {
+        for endpoint in &self.config.oracle_endpoints {
+            let Some(stream_url) = &endpoint.stream_url else { continue };
+            if let Some(oracle) = self.oracles.iter_mut().find(|o| o.name == endpoint.name) {
+                let currencies: Vec<Currency> = oracle.urls.keys().cloned().collect();
+                for currency in currencies {
+                    let url = price_stream::default_stream_url(&endpoint.name, stream_url, &currency);
+                    let subscription = price_stream::default_subscription(&endpoint.name, &currency);
+                    oracle.start_stream(StreamConfig::new(url.clone(), currency.clone()).with_subscription(subscription));
+                    log::info!("Streaming {} BTC/{} prices from {}", endpoint.name, currency.to_string(), url);
+                }
+            }
+        }
+    }
+
+    /// Persist breakers, oracle state and consensus rounds, restoring them from a previous run
+    pub fn with_database(mut self, database: DatabaseManager) -> Result<Self> {
+        self.circuit_breakers = self.circuit_breakers.with_database(database.clone())?;
+
+        for state in database.load_oracle_states()? {
+            if let Some(oracle) = self.oracles.iter_mut().find(|o| o.name == state.name) {
+                oracle.restore_state(state);
+            }
+        }
+
+        self.price_history = database.get_recent_consensus_rounds(1000)?;
+        if !self.price_history.is_empty() {
+            log::info!(
+                "Restored {} consensus rounds and {} bonded oracles",
+                self.price_history.len(),
+                self.oracles.iter().filter(|o| o.is_bonded).count()
+            );
+        }
+
+        self.database = Some(database);
+        Ok(self)
+    }
+
+    pub fn get_oracle(&self, name: &str) -> Option<&Oracle> {
+        self.oracles.iter().find(|o| o.name == name)
+    }
+
+    /// Bond an oracle and persist its new state
+    pub fn bond_oracle(&mut self, name: &str, bond_amount: Amount, btc_price: f64, max_daily_volume: f64) -> Result<()> {
+        let oracle = self.oracles.iter_mut().find(|o| o.name == name)
+            .ok_or_else(|| BitStableError::InvalidConfig(format!("Unknown oracle: {}", name)))?;
+
+        oracle.submit_bond(bond_amount, btc_price, max_daily_volume)?;
+
+        if let Some(database) = &self.database {
+            database.save_oracle_state(&oracle.state())?;
+        }
+        Ok(())
+    }
+
+    /// Store a finished round with its submissions and the oracles' updated state
+    fn persist_round(&self, consensus: &ConsensusPrices, submissions: &[OracleSubmission]) -> Result<()> {
+        let database = match &self.database {
+            Some(database) => database,
+            None => return Ok(()),
+        };
+
+        database.save_consensus_round(consensus)?;
+        for submission in submissions {
+            database.save_oracle_submission(submission)?;
+        }
+        for oracle in &self.oracles {
+            database.save_oracle_state(&oracle.state())?;
+        }
+        Ok(())
+    }
+
     pub async fn get_consensus_prices(&mut self) -> Result<ExchangeRates> {
-        let mut all_prices: HashMap<Currency, Vec<f64>> = HashMap::new();
+        let mut all_quotes: HashMap<Currency, Vec<PriceQuote>> = HashMap::new();
+        let max_venue_weight = self.config.max_venue_weight;
+        let mut submissions = Vec::new();
         let mut successful_bonded_oracles = 0;
         let mut total_bonded_oracles = 0;
 
@@ -491,6 +874,7 @@ impl MultiCurrencyOracleNetwork {
         for oracle in &mut self.oracles {
             if oracle.is_bonded {
                 total_bonded_oracles += 1;
+                oracle.sync_streams();
                 
                 // Check price freshness and use failover if needed
                 let mut oracle_prices = HashMap::new();
@@ -499,13 +883,16 @@ impl MultiCurrencyOracleNetwork {
                 for currency in currencies {
                     if oracle.is_price_fresh(&currency) {
                         if let Some(last_price) = oracle.last_prices.get(&currency) {
-                            oracle_prices.insert(currency.clone(), last_price.prices.get(&currency).copied().unwrap_or(0.0));
+                            oracle_prices.insert(currency.clone(), PriceQuote::new(
+                                last_price.prices.get(&currency).copied().unwrap_or(0.0),
+                                last_price.volumes.get(&currency).copied(),
+                            ));
                         }
                     } else {
-                        // Price stale, fetch new with failover
-                        match oracle.fetch_price_with_failover(&currency).await {
-                            Ok(price) => {
-                                oracle_prices.insert(currency.clone(), price);
+                        // Price stale, fetch new across all of the oracle's sources
+                        match oracle.fetch_volume_weighted_quote(&currency, max_venue_weight).await {
+                            Ok(quote) => {
+                                oracle_prices.insert(currency.clone(), quote);
                             }
                             Err(e) => {
                                 log::warn!("Oracle {} failed for {}: {}", oracle.name, currency.to_string(), e);
@@ -516,8 +903,15 @@ impl MultiCurrencyOracleNetwork {
                 
                 if !oracle_prices.is_empty() {
                     successful_bonded_oracles += 1;
-                    for (currency, price) in oracle_prices {
-                        all_prices.entry(currency).or_insert_with(Vec::new).push(price);
+                    for (currency, quote) in oracle_prices {
+                        submissions.push(OracleSubmission {
+                            oracle_name: oracle.name.clone(),
+                            currency: currency.clone(),
+                            price: quote.price,
+                            volume: quote.volume,
+                            timestamp: Utc::now(),
+                        });
+                        all_quotes.entry(currency).or_default().push(quote);
                     }
                 }
             }
@@ -538,23 +932,18 @@ impl MultiCurrencyOracleNetwork {
             });
         }
 
-        // Calculate median prices for each currency
+        // Volume-weighted price for each currency, median where volume is missing
         let mut consensus_prices = HashMap::new();
-        for (currency, mut prices) in all_prices {
-            if !prices.is_empty() {
-                prices.sort_by(|a, b| a.partial_cmp(b).unwrap());
-                let median = if prices.len() % 2 == 0 {
-                    (prices[prices.len() / 2 - 1] + prices[prices.len() / 2]) / 2.0
-                } else {
-                    prices[prices.len() / 2]
-                };
-                
+        for (currency, quotes) in all_quotes {
+            if let Some(consensus_price) = volume_weighted_price(&quotes, max_venue_weight) {
+                let prices: Vec<f64> = quotes.iter().map(|q| q.price).collect();
+
                 // Apply graduated circuit breaker with bonded oracle count
-                if self.validate_price_movement(&currency, median, successful_bonded_oracles) {
-                    consensus_prices.insert(currency.clone(), median);
+                if self.validate_price_movement(&currency, consensus_price, successful_bonded_oracles) {
+                    consensus_prices.insert(currency.clone(), consensus_price);
                     
                     // Check for price deviation and slash oracles if needed
-                    self.check_price_deviations(&currency, median, &prices).await?;
+                    self.check_price_deviations(&currency, consensus_price, &prices).await?;
                 } else {
                     log::warn!("Price movement for {} rejected by circuit breaker", currency.to_string());
                 }
@@ -562,7 +951,7 @@ impl MultiCurrencyOracleNetwork {
         }
 
         // Update exchange rates
-        self.update_exchange_rates(&consensus_prices)?;
+        self.apply_btc_prices(&consensus_prices, Utc::now())?;
 
         // Record consensus
         let consensus = ConsensusPrices {
@@ -573,6 +962,7 @@ impl MultiCurrencyOracleNetwork {
             total_oracles: self.oracles.len(),
         };
 
+        self.persist_round(&consensus, &submissions)?;
         self.price_history.push(consensus);
         
         // Keep only last 1000 price points
@@ -585,6 +975,15 @@ impl MultiCurrencyOracleNetwork {
         Ok(self.exchange_rates.clone())
     }
 
+    /// Adopt agreed BTC prices, updating spot rates and the consensus TWAP
+    pub fn apply_btc_prices(&mut self, btc_prices: &HashMap<Currency, f64>, now: DateTime<Utc>) -> Result<()> {
+        self.update_exchange_rates(btc_prices)?;
+        for (currency, price) in btc_prices {
+            self.record_twap_sample(currency.clone(), *price, now);
+        }
+        Ok(())
+    }
+
     fn update_exchange_rates(&mut self, btc_prices: &HashMap<Currency, f64>) -> Result<()> {
         // Update BTC prices
         for (currency, price) in btc_prices {
@@ -606,67 +1005,157 @@ impl MultiCurrencyOracleNetwork {
         Ok(())
     }
 
-    /// Enhanced graduated circuit breaker validation
+    /// Tag subsequent TWAP samples with the current Bitcoin block height
+    pub fn set_block_height(&mut self, height: u64) {
+        self.block_height = Some(height);
+    }
+
+    /// Consensus TWAP of the BTC price in a currency over the configured window
+    pub fn get_consensus_twap(&self, currency: &Currency) -> Option<f64> {
+        self.exchange_rates.get_twap_price(currency)
+    }
+
+    fn record_twap_sample(&mut self, currency: Currency, price: f64, now: DateTime<Utc>) {
+        let window = self.config.twap_window;
+        let block_height = self.block_height;
+        let samples = self.consensus_twap.entry(currency.clone()).or_default();
+
+        samples.push_back(TwapSample { timestamp: now, block_height, price });
+
+        // Block windows fall back to wall-clock time for samples taken without a height
+        let cutoff = now - window.duration();
+        while let Some(oldest) = samples.front() {
+            let expired = match (window, oldest.block_height, block_height) {
+                (TwapWindow::Blocks(blocks), Some(height), Some(current)) => height + blocks <= current,
+                _ => oldest.timestamp < cutoff,
+            };
+            if expired && samples.len() > 1 {
+                samples.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        // Each sample is weighted by how long it stood, the newest until now
+        let mut weighted_sum = 0.0;
+        let mut total_weight = 0.0;
+        for (i, sample) in samples.iter().enumerate() {
+            let until = samples.get(i + 1).map(|next| next.timestamp).unwrap_or(now);
+            let weight = until.signed_duration_since(sample.timestamp).num_milliseconds().max(0) as f64;
+            weighted_sum += sample.price * weight;
+            total_weight += weight;
+        }
+
+        let twap = if total_weight > 0.0 {
+            weighted_sum / total_weight
+        } else {
+            samples.iter().map(|s| s.price).sum::<f64>() / samples.len() as f64
+        };
+
+        self.exchange_rates.update_twap_price(currency, twap);
+    }
+
+    /// Record a market quote for a stable unit against its fiat peg
+    pub fn submit_peg_price(&mut self, peg_price: PegPrice) -> Result<()> {
+        if !peg_price.price.is_finite() || peg_price.price <= 0.0 {
+            return Err(BitStableError::PriceFeedError(format!(
+                "Invalid peg price {} for {} from {}",
+                peg_price.price, peg_price.currency.to_string(), peg_price.source
+            )));
+        }
+
+        self.peg_prices
+            .entry(peg_price.currency.clone())
+            .or_default()
+            .insert(peg_price.source.clone(), peg_price);
+        Ok(())
+    }
+
+    /// Poll the configured peg price endpoints, recording each quote that comes back.
+    /// A failing market is logged and skipped; returns how many quotes were recorded.
+    pub async fn fetch_peg_prices(&mut self) -> usize {
+        let client = reqwest::Client::new();
+        let mut recorded = 0;
+
+        for endpoint in self.config.peg_price_endpoints.clone() {
+            let quote = async {
+                let response = client
+                    .get(&endpoint.url)
+                    .timeout(std::time::Duration::from_secs(10))
+                    .send()
+                    .await?;
+                let price = parse_peg_price(&response.text().await?)?;
+                self.submit_peg_price(PegPrice {
+                    currency: endpoint.currency.clone(),
+                    price,
+                    source: endpoint.name.clone(),
+                    timestamp: Utc::now(),
+                })
+            };
+            match quote.await {
+                Ok(()) => recorded += 1,
+                Err(e) => log::warn!("Failed to fetch {} peg price from {}: {}", endpoint.currency.to_string(), endpoint.name, e),
+            }
+        }
+
+        recorded
+    }
+
+    /// Median peg price per currency across sources that have reported recently
+    pub fn get_peg_prices(&self, now: DateTime<Utc>) -> HashMap<Currency, PegPrice> {
+        let cutoff = now - Duration::minutes(PEG_PRICE_MAX_AGE_MINUTES);
+        let mut peg_prices = HashMap::new();
+
+        for (currency, sources) in &self.peg_prices {
+            let fresh: Vec<&PegPrice> = sources.values().filter(|p| p.timestamp >= cutoff).collect();
+            let mut prices: Vec<f64> = fresh.iter().map(|p| p.price).collect();
+            let Some(price) = median_price(&mut prices) else { continue };
+
+            peg_prices.insert(currency.clone(), PegPrice {
+                currency: currency.clone(),
+                price,
+                source: format!("median of {}", fresh.len()),
+                timestamp: fresh.iter().map(|p| p.timestamp).max().unwrap_or(now),
+            });
+        }
+
+        peg_prices
+    }
+
+    /// Graduated circuit breaker validation, tracked per currency across rounds
     pub fn validate_price_movement(&mut self, currency: &Currency, new_price: f64, successful_oracles: usize) -> bool {
-        if let Some(last_consensus) = self.price_history.last() {
-            if let Some(last_price) = last_consensus.btc_prices.get(currency) {
-                let change_percent = ((new_price - last_price) / last_price).abs();
-                
-                // Check cooldown period for large moves
-                if let Some(last_update) = self.last_price_update.get(currency) {
-                    let cooldown = Duration::minutes(self.circuit_breaker.cooldown_minutes as i64);
-                    if Utc::now().signed_duration_since(*last_update) < cooldown && change_percent > 0.05 {
-                        log::warn!("Price update for {} in cooldown period", currency.to_string());
-                        return false;
-                    }
-                }
-                
-                // Graduated circuit breaker logic
-                if change_percent > self.circuit_breaker.tier3_threshold {
-                    // Emergency threshold - requires governance override
-                    if !self.circuit_breaker.emergency_override {
-                        log::error!("Price movement for {} ({:.2}%) exceeds emergency threshold, requires governance override", 
-                                  currency.to_string(), change_percent * 100.0);
-                        return false;
-                    }
-                } else if change_percent > self.circuit_breaker.tier2_threshold {
-                    // Tier 2: 20%+ requires 7/7 oracles
-                    if successful_oracles < self.circuit_breaker.min_oracles_tier2 {
-                        log::warn!("Price movement for {} ({:.2}%) requires {} oracles, only {} available", 
-                                 currency.to_string(), change_percent * 100.0, 
-                                 self.circuit_breaker.min_oracles_tier2, successful_oracles);
-                        return false;
-                    }
-                } else if change_percent > self.circuit_breaker.tier1_threshold {
-                    // Tier 1: 10%+ requires 5/7 oracles
-                    if successful_oracles < self.circuit_breaker.min_oracles_tier1 {
-                        log::warn!("Price movement for {} ({:.2}%) requires {} oracles, only {} available", 
-                                 currency.to_string(), change_percent * 100.0, 
-                                 self.circuit_breaker.min_oracles_tier1, successful_oracles);
-                        return false;
-                    }
-                }
-                
-                // Update last price update timestamp
-                self.last_price_update.insert(currency.clone(), Utc::now());
-                
-                log::info!("Price movement for {} validated: {:.2}% with {} oracles", 
-                         currency.to_string(), change_percent * 100.0, successful_oracles);
-                return true;
+        match self.circuit_breakers.observe(currency, new_price, successful_oracles, Utc::now()) {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                log::error!("Failed to record circuit breaker state for {}: {}", currency.to_string(), e);
+                false
             }
         }
-        true // First price always valid
     }
     
     /// Enable emergency override for governance
     pub fn enable_emergency_override(&mut self, enabled: bool) {
-        self.circuit_breaker.emergency_override = enabled;
+        self.circuit_breakers.set_emergency_override(enabled);
         log::info!("Emergency circuit breaker override: {}", enabled);
     }
+
+    /// Apply a passed `CircuitBreakerOverride` proposal to one or all currencies
+    pub fn apply_governance_override(&mut self, currency: Option<&Currency>, duration: Duration) -> Result<()> {
+        self.circuit_breakers.apply_governance_override(currency, duration, Utc::now())
+    }
+
+    pub fn get_circuit_breakers(&self) -> &CircuitBreakerSystem {
+        &self.circuit_breakers
+    }
+
+    /// Take circuit breaker state changes emitted since the last call
+    pub fn drain_breaker_events(&mut self) -> Vec<BreakerEvent> {
+        self.circuit_breakers.drain_events()
+    }
     
     /// Get current circuit breaker status
     pub fn get_circuit_breaker_status(&self) -> &CircuitBreakerConfig {
-        &self.circuit_breaker
+        self.circuit_breakers.config()
     }
 
     pub fn get_latest_consensus(&self) -> Option<&ConsensusPrices> {
@@ -677,6 +1166,15 @@ impl MultiCurrencyOracleNetwork {
         &self.exchange_rates
     }
 
+    /// Replace the rates outright with prices decided elsewhere; no TWAP samples are recorded
+    pub fn set_exchange_rates(&mut self, exchange_rates: ExchangeRates) {
+        self.exchange_rates = exchange_rates;
+    }
+
+    pub fn get_oracles(&self) -> &[Oracle] {
+        &self.oracles
+    }
+
     pub fn get_price_history(&self, limit: usize) -> Vec<&ConsensusPrices> {
         let start = if self.price_history.len() > limit {
             self.price_history.len() - limit
@@ -719,7 +1217,8 @@ impl MultiCurrencyOracleNetwork {
                 .copied()
                 .unwrap_or(50000.0);
             
-            oracle.slash_bond(slash_type, btc_price)?;
+            let amount = oracle.slash_bond(slash_type.clone(), btc_price)?;
+            record_slash(self.database.as_ref(), oracle, slash_type, amount)?;
         }
         
         Ok(())
@@ -730,6 +1229,7 @@ impl MultiCurrencyOracleNetwork {
         let btc_price = consensus_price; // Assuming this is BTC price
         
         // Check each oracle's price against consensus
+        let database = self.database.as_ref();
         for oracle in &mut self.oracles {
             if oracle.is_bonded {
                 if let Some(oracle_price_data) = oracle.last_prices.get(currency) {
@@ -738,7 +1238,8 @@ impl MultiCurrencyOracleNetwork {
                         
                         // Slash if deviation > 5%
                         if deviation > 0.05 {
-                            oracle.slash_bond(SlashType::PriceDeviation, btc_price)?;
+                            let amount = oracle.slash_bond(SlashType::PriceDeviation, btc_price)?;
+                            record_slash(database, oracle, SlashType::PriceDeviation, amount)?;
                             log::warn!("Oracle {} slashed for {:.2}% price deviation on {}", 
                                      oracle.name, deviation * 100.0, currency.to_string());
                         }
@@ -751,6 +1252,21 @@ impl MultiCurrencyOracleNetwork {
     }
 }
 
+/// Persist a slash and the oracle's reduced bond
+fn record_slash(database: Option<&DatabaseManager>, oracle: &Oracle, slash_type: SlashType, amount: Amount) -> Result<()> {
+    if let Some(database) = database {
+        database.save_slash_record(&SlashRecord {
+            oracle_name: oracle.name.clone(),
+            slash_type,
+            amount,
+            remaining_bond: oracle.metrics.bond.bond_amount,
+            timestamp: oracle.metrics.bond.last_slash_timestamp.unwrap_or_else(Utc::now),
+        })?;
+        database.save_oracle_state(&oracle.state())?;
+    }
+    Ok(())
+}
+
 /// Price consensus implementation (renamed from ThresholdSignature)
 pub struct PriceConsensus {
     pub aggregated_hash: String,  // XOR of price data for verification
@@ -764,6 +1280,22 @@ impl PriceConsensus {
     pub fn aggregate_prices(
         oracle_data: Vec<(String, HashMap<Currency, f64>)>, // (oracle_name, prices)
         threshold: usize,
+    ) -> Result<Self> {
+        let oracle_data = oracle_data
+            .into_iter()
+            .map(|(name, prices)| {
+                let quotes = prices.into_iter().map(|(c, p)| (c, PriceQuote::new(p, None))).collect();
+                (name, quotes)
+            })
+            .collect();
+        Self::aggregate_quotes(oracle_data, threshold, 1.0)
+    }
+
+    /// Create a volume-weighted price consensus, falling back to the median per currency
+    pub fn aggregate_quotes(
+        oracle_data: Vec<(String, HashMap<Currency, PriceQuote>)>, // (oracle_name, quotes)
+        threshold: usize,
+        max_venue_weight: f64,
     ) -> Result<Self> {
         if oracle_data.len() < threshold {
             return Err(BitStableError::InsufficientOracleConsensus {
@@ -772,24 +1304,19 @@ impl PriceConsensus {
             });
         }
 
-        // Calculate consensus prices (median per currency)
-        let mut all_prices: HashMap<Currency, Vec<f64>> = HashMap::new();
+        let mut all_quotes: HashMap<Currency, Vec<PriceQuote>> = HashMap::new();
         
-        for (_, prices) in &oracle_data {
-            for (currency, price) in prices {
-                all_prices.entry(currency.clone()).or_insert_with(Vec::new).push(*price);
+        for (_, quotes) in &oracle_data {
+            for (currency, quote) in quotes {
+                all_quotes.entry(currency.clone()).or_default().push(*quote);
             }
         }
 
         let mut consensus_prices = HashMap::new();
-        for (currency, mut prices) in all_prices {
-            prices.sort_by(|a, b| a.partial_cmp(b).unwrap());
-            let median = if prices.len() % 2 == 0 {
-                (prices[prices.len() / 2 - 1] + prices[prices.len() / 2]) / 2.0
-            } else {
-                prices[prices.len() / 2]
-            };
-            consensus_prices.insert(currency, median);
+        for (currency, quotes) in all_quotes {
+            if let Some(price) = volume_weighted_price(&quotes, max_venue_weight) {
+                consensus_prices.insert(currency, price);
+            }
         }
 
         // Create aggregated hash for verification (simplified)
@@ -864,6 +1391,184 @@ mod tests {
         assert_eq!(consensus.consensus_prices.get(&Currency::USD), Some(&100000.0));
         assert_eq!(consensus.consensus_prices.get(&Currency::EUR), Some(&95000.0));
     }
+
+    #[test]
+    fn test_volume_weighted_consensus() {
+        let quote = |price, volume| PriceQuote::new(price, Some(volume));
+
+        // Uncapped: 100k with 3x the volume of 104k
+        let quotes = [quote(100000.0, 30.0), quote(104000.0, 10.0)];
+        assert_eq!(volume_weighted_price(&quotes, 1.0), Some(101000.0));
+
+        // A dominant venue is held to 50%, its excess spread over the others by volume
+        let quotes = [quote(90000.0, 900.0), quote(100000.0, 60.0), quote(110000.0, 40.0)];
+        let price = volume_weighted_price(&quotes, 0.5).unwrap();
+        assert!((price - (0.5 * 90000.0 + 0.3 * 100000.0 + 0.2 * 110000.0)).abs() < 1e-6);
+
+        // One venue without volume drops the whole set back to the median
+        let mixed = [quote(90000.0, 900.0), PriceQuote::new(100000.0, None), quote(110000.0, 40.0)];
+        assert_eq!(volume_weighted_price(&mixed, 0.5), Some(100000.0));
+
+        // Non-finite prices are dropped rather than panicking the sort
+        let broken = [PriceQuote::new(f64::NAN, None), PriceQuote::new(100000.0, None), PriceQuote::new(f64::INFINITY, None)];
+        assert_eq!(volume_weighted_price(&broken, 0.5), Some(100000.0));
+        assert!(PriceQuote::new(f64::NAN, Some(1.0)).validate().is_err());
+        assert_eq!(median_price(&mut [3.0, 1.0, 2.0, 4.0]), Some(2.5));
+        assert_eq!(median_price(&mut []), None);
+
+        let mut oracle_data = Vec::new();
+        for (name, price, volume) in [("A", 100000.0, 10.0), ("B", 101000.0, 10.0), ("C", 150000.0, 1000.0)] {
+            let mut quotes = HashMap::new();
+            quotes.insert(Currency::USD, quote(price, volume));
+            oracle_data.push((name.to_string(), quotes));
+        }
+        let consensus = PriceConsensus::aggregate_quotes(oracle_data, 3, 0.4).unwrap();
+        let usd = consensus.consensus_prices[&Currency::USD];
+        assert!((usd - (0.3 * 100000.0 + 0.3 * 101000.0 + 0.4 * 150000.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_parse_volume_from_sources() {
+        let pubkey = PublicKey::from_slice(&[2; 33]).unwrap();
+
+        let kraken = Oracle::new("Kraken".to_string(), pubkey);
+        let text = r#"{"error":[],"result":{"XXBTZUSD":{"c":["100000.0","0.01"],"v":["120.5","2400.25"]}}}"#;
+        let parsed = kraken.parse_price_response(text, &Currency::USD).unwrap();
+        assert_eq!(parsed, PriceQuote::new(100000.0, Some(2400.25)));
+
+        let coingecko = Oracle::new("CoinGecko".to_string(), pubkey);
+        let text = r#"{"bitcoin":{"eur":95000.0,"eur_24h_vol":190000000.0}}"#;
+        let parsed = coingecko.parse_price_response(text, &Currency::EUR).unwrap();
+        assert_eq!(parsed, PriceQuote::new(95000.0, Some(2000.0)));
+
+        let binance = Oracle::new("Binance".to_string(), pubkey);
+        let parsed = binance.parse_price_response(r#"{"symbol":"BTCUSDT","price":"100000.00"}"#, &Currency::USD).unwrap();
+        assert_eq!(parsed.volume, None);
+    }
+
+    #[test]
+    fn test_parse_peg_price() {
+        assert_eq!(parse_peg_price(r#"{"price":0.998}"#).unwrap(), 0.998);
+        assert_eq!(parse_peg_price(r#"{"pair":"USDBS/USD","price":"1.0025"}"#).unwrap(), 1.0025);
+        assert!(parse_peg_price(r#"{"price":"par"}"#).is_err());
+        assert!(parse_peg_price(r#"{"last":1.0}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_oracle_state_survives_restart() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = ProtocolConfig::testnet();
+        let start = Utc::now();
+
+        {
+            let database = DatabaseManager::new(temp_dir.path()).unwrap();
+            let mut network = MultiCurrencyOracleNetwork::new(&config).unwrap()
+                .with_database(database).unwrap();
+            network.bond_oracle("Coinbase", Amount::from_btc(1.0).unwrap(), 1.0, 36.5).unwrap();
+
+            // Coinbase reports 20% away from consensus and is slashed
+            let mut prices = HashMap::new();
+            prices.insert(Currency::USD, 120000.0);
+            network.oracles[0].last_prices.insert(Currency::USD, PriceData {
+                prices,
+                timestamp: start,
+                source: "Coinbase".to_string(),
+                signature: None,
+                volumes: HashMap::new(),
+            });
+            network.check_price_deviations(&Currency::USD, 100000.0, &[]).await.unwrap();
+
+            let mut btc_prices = HashMap::new();
+            btc_prices.insert(Currency::USD, 100000.0);
+            btc_prices.insert(Currency::EUR, 92000.0);
+            let round = ConsensusPrices {
+                btc_prices,
+                exchange_rates: HashMap::new(),
+                timestamp: start,
+                participating_oracles: 3,
+                total_oracles: 5,
+            };
+            let submission = OracleSubmission {
+                oracle_name: "Kraken".to_string(),
+                currency: Currency::EUR,
+                price: 92100.0,
+                volume: Some(12.0),
+                timestamp: start,
+            };
+            network.persist_round(&round, &[submission]).unwrap();
+        }
+
+        // The network and its database handle were dropped above, releasing sled's lock
+        let database = DatabaseManager::new(temp_dir.path()).unwrap();
+        let network = MultiCurrencyOracleNetwork::new(&config).unwrap()
+            .with_database(database.clone()).unwrap();
+
+        let coinbase = network.get_oracle("Coinbase").unwrap();
+        assert!(coinbase.is_bonded);
+        assert_eq!(coinbase.metrics.bond.bond_amount, Amount::from_btc(0.9).unwrap());
+        assert_eq!(network.get_latest_consensus().unwrap().btc_prices[&Currency::EUR], 92000.0);
+
+        let window = (start - Duration::minutes(1), start + Duration::minutes(1));
+        let slashes = database.get_slash_history(Some("Coinbase"), window.0, window.1).unwrap();
+        assert_eq!(slashes.len(), 1);
+        assert_eq!(slashes[0].amount, Amount::from_btc(0.1).unwrap());
+
+        let eur = database.get_consensus_price_range(&Currency::EUR, window.0, window.1).unwrap();
+        assert_eq!(eur.len(), 1);
+        assert_eq!(database.get_oracle_submissions(&Currency::EUR, window.0, window.1).unwrap().len(), 1);
+        assert!(database.get_oracle_submissions(&Currency::USD, window.0, window.1).unwrap().is_empty());
+        assert!(database.get_consensus_rounds(start + Duration::minutes(1), start + Duration::minutes(2)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_consensus_twap_block_window() {
+        let mut config = ProtocolConfig::testnet();
+        config.twap_window = TwapWindow::Blocks(2);
+        let mut network = MultiCurrencyOracleNetwork::new(&config).unwrap();
+        let start = Utc::now();
+
+        network.set_block_height(100);
+        network.record_twap_sample(Currency::USD, 100000.0, start);
+        network.set_block_height(101);
+        network.record_twap_sample(Currency::USD, 110000.0, start + Duration::minutes(10));
+
+        // Two equal intervals: 100k for 10 minutes, 110k for 0
+        assert_eq!(network.get_consensus_twap(&Currency::USD), Some(100000.0));
+
+        // Block 100 falls out of a 2 block window at height 102
+        network.set_block_height(102);
+        network.record_twap_sample(Currency::USD, 120000.0, start + Duration::minutes(20));
+        assert_eq!(network.get_consensus_twap(&Currency::USD), Some(110000.0));
+    }
+
+    #[tokio::test]
+    async fn test_streams_cover_every_feed_currency() {
+        let mut config = ProtocolConfig::testnet();
+        for endpoint in &mut config.oracle_endpoints {
+            if endpoint.stream_url.is_some() {
+                endpoint.stream_url = Some(format!("ws://127.0.0.1:9/ws/btcusdt@ticker?venue={}", endpoint.name));
+            }
+        }
+        let mut network = MultiCurrencyOracleNetwork::new(&config).unwrap();
+        for oracle in &mut network.oracles {
+            oracle.add_price_feed(Currency::EUR, oracle.urls[&Currency::USD].clone());
+        }
+        network.start_streams();
+
+        for oracle in &network.oracles {
+            let mut streamed: Vec<String> = oracle.streams.keys().map(|c| c.to_string()).collect();
+            streamed.sort();
+            let streaming = config.oracle_endpoints.iter().any(|e| e.name == oracle.name && e.stream_url.is_some());
+            let expected: Vec<String> = if streaming { vec!["EUR".into(), "USD".into()] } else { Vec::new() };
+            assert_eq!(streamed, expected, "{}", oracle.name);
+        }
+
+        // Binance carries the pair in the URL
+        let url = "wss://stream.binance.com:9443/ws/btcusdt@ticker";
+        assert_eq!(price_stream::default_stream_url("Binance", url, &Currency::EUR), "wss://stream.binance.com:9443/ws/btceur@ticker");
+        assert_eq!(price_stream::default_stream_url("Binance", url, &Currency::USD), url);
+        assert_eq!(price_stream::default_stream_url("Kraken", "wss://ws.kraken.com/v2", &Currency::EUR), "wss://ws.kraken.com/v2");
+    }
 }
 
 
@@ -0,0 +1,395 @@
+//! WebSocket ticker subscriptions for oracle price sources
+//! Keeps a live feed per currency with reconnect, backoff and gap detection
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Utc};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+use crate::{BitStableError, Result};
+use crate::multi_currency::Currency;
+use crate::oracle::PriceQuote;
+
+/// Ticks held for the oracle between syncs; older ones are dropped first
+const MAX_BUFFERED_TICKS: usize = 1024;
+
+/// Connection and timing settings for one ticker subscription
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamConfig {
+    pub url: String,
+    pub currency: Currency,
+    pub subscribe_message: Option<String>,  // Sent once after every (re)connect
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub gap_timeout_ms: u64,                // Silence longer than this is a gap and forces a reconnect
+}
+
+impl StreamConfig {
+    pub fn new(url: String, currency: Currency) -> Self {
+        Self {
+            url,
+            currency,
+            subscribe_message: None,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 30_000,
+            gap_timeout_ms: 15_000,
+        }
+    }
+
+    pub fn with_subscription(mut self, message: Option<String>) -> Self {
+        self.subscribe_message = message;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StreamStatus {
+    Connecting,
+    Live,
+    Reconnecting,
+}
+
+/// A price pushed by the venue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamTick {
+    pub currency: Currency,
+    pub quote: PriceQuote,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Connection health counters for a stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamStats {
+    pub status: StreamStatus,
+    pub connects: u64,
+    pub reconnects: u64,
+    pub gaps: u64,
+    pub ticks: u64,
+    pub last_message: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug)]
+struct SharedState {
+    stats: StreamStats,
+    ticks: VecDeque<StreamTick>,
+}
+
+/// Aborts the background connection once the last handle is dropped
+#[derive(Debug)]
+struct StreamTask(JoinHandle<()>);
+
+impl Drop for StreamTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Handle to a background WebSocket ticker subscription
+#[derive(Debug, Clone)]
+pub struct PriceStream {
+    config: StreamConfig,
+    state: Arc<Mutex<SharedState>>,
+    _task: Arc<StreamTask>,
+}
+
+impl PriceStream {
+    /// Start streaming; must be called from within a tokio runtime
+    pub fn spawn(venue: &str, config: StreamConfig) -> Self {
+        let state = Arc::new(Mutex::new(SharedState {
+            stats: StreamStats {
+                status: StreamStatus::Connecting,
+                connects: 0,
+                reconnects: 0,
+                gaps: 0,
+                ticks: 0,
+                last_message: None,
+                last_error: None,
+            },
+            ticks: VecDeque::new(),
+        }));
+
+        let task = tokio::spawn(run_stream(venue.to_string(), config.clone(), state.clone()));
+
+        Self {
+            config,
+            state,
+            _task: Arc::new(StreamTask(task)),
+        }
+    }
+
+    /// Take the ticks received since the last call, oldest first
+    pub fn drain_ticks(&self) -> Vec<StreamTick> {
+        let mut state = self.state.lock().unwrap();
+        state.ticks.drain(..).collect()
+    }
+
+    pub fn stats(&self) -> StreamStats {
+        self.state.lock().unwrap().stats.clone()
+    }
+
+    pub fn config(&self) -> &StreamConfig {
+        &self.config
+    }
+}
+
+async fn run_stream(venue: String, config: StreamConfig, state: Arc<Mutex<SharedState>>) {
+    let gap_timeout = std::time::Duration::from_millis(config.gap_timeout_ms);
+    let mut backoff = config.initial_backoff_ms;
+
+    loop {
+        match tokio_tungstenite::connect_async(config.url.as_str()).await {
+            Ok((mut ws, _)) => {
+                {
+                    let mut state = state.lock().unwrap();
+                    if state.stats.connects > 0 {
+                        state.stats.reconnects += 1;
+                    }
+                    state.stats.connects += 1;
+                    state.stats.status = StreamStatus::Live;
+                }
+                backoff = config.initial_backoff_ms;
+                log::info!("{} stream connected for {}", venue, config.currency.to_string());
+
+                if let Some(message) = &config.subscribe_message {
+                    if let Err(e) = ws.send(Message::Text(message.clone())).await {
+                        record_error(&state, format!("Subscribe failed: {}", e));
+                    }
+                }
+
+                loop {
+                    let message = match tokio::time::timeout(gap_timeout, ws.next()).await {
+                        Ok(Some(Ok(message))) => message,
+                        Ok(Some(Err(e))) => {
+                            record_error(&state, e.to_string());
+                            break;
+                        }
+                        Ok(None) => {
+                            record_error(&state, "Connection closed".to_string());
+                            break;
+                        }
+                        Err(_) => {
+                            state.lock().unwrap().stats.gaps += 1;
+                            record_error(&state, format!("No message for {}ms", config.gap_timeout_ms));
+                            log::warn!("{} stream gap on {}, reconnecting", venue, config.currency.to_string());
+                            break;
+                        }
+                    };
+
+                    let now = Utc::now();
+                    state.lock().unwrap().stats.last_message = Some(now);
+
+                    match message {
+                        Message::Text(text) => match parse_stream_message(&venue, &text, &config.currency) {
+                            Ok(Some(quote)) => {
+                                let mut state = state.lock().unwrap();
+                                state.stats.ticks += 1;
+                                state.ticks.push_back(StreamTick {
+                                    currency: config.currency.clone(),
+                                    quote,
+                                    timestamp: now,
+                                });
+                                if state.ticks.len() > MAX_BUFFERED_TICKS {
+                                    state.ticks.pop_front();
+                                }
+                            }
+                            Ok(None) => {}
+                            Err(e) => log::debug!("{} stream ignored message: {}", venue, e),
+                        },
+                        Message::Ping(payload) => {
+                            let _ = ws.send(Message::Pong(payload)).await;
+                        }
+                        Message::Close(_) => {
+                            record_error(&state, "Closed by server".to_string());
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Err(e) => record_error(&state, format!("Connect failed: {}", e)),
+        }
+
+        state.lock().unwrap().stats.status = StreamStatus::Reconnecting;
+        tokio::time::sleep(std::time::Duration::from_millis(backoff)).await;
+        backoff = (backoff * 2).min(config.max_backoff_ms);
+    }
+}
+
+fn record_error(state: &Arc<Mutex<SharedState>>, error: String) {
+    state.lock().unwrap().stats.last_error = Some(error);
+}
+
+/// Ticker URL for one currency; Binance names the pair in the URL, so its configured
+/// `btcusdt` stream is rewritten to the currency's pair
+pub fn default_stream_url(venue: &str, stream_url: &str, currency: &Currency) -> String {
+    match venue {
+        "Binance" => {
+            let quote = match currency {
+                Currency::USD => "usdt".to_string(),
+                other => other.to_string().to_lowercase(),
+            };
+            stream_url.replace("btcusdt@", &format!("btc{}@", quote))
+        }
+        _ => stream_url.to_string(),
+    }
+}
+
+/// Subscription request a venue expects after connecting, if any
+pub fn default_subscription(venue: &str, currency: &Currency) -> Option<String> {
+    let currency = currency.to_string();
+    match venue {
+        "Coinbase" => Some(serde_json::json!({
+            "type": "subscribe",
+            "product_ids": [format!("BTC-{}", currency)],
+            "channels": ["ticker"],
+        }).to_string()),
+        "Kraken" => Some(serde_json::json!({
+            "method": "subscribe",
+            "params": { "channel": "ticker", "symbol": [format!("BTC/{}", currency)] },
+        }).to_string()),
+        // Binance encodes the subscription in the stream URL
+        _ => None,
+    }
+}
+
+/// Parse a venue ticker frame; heartbeats and acks yield `Ok(None)`
+pub fn parse_stream_message(venue: &str, text: &str, _currency: &Currency) -> Result<Option<PriceQuote>> {
+    let value: Value = serde_json::from_str(text)
+        .map_err(|e| BitStableError::PriceFeedError(format!("{} stream parse error: {}", venue, e)))?;
+
+    let number = |v: Option<&Value>| -> Option<f64> {
+        match v? {
+            Value::String(s) => s.parse().ok(),
+            v => v.as_f64(),
+        }
+    };
+
+    let (price, volume) = match venue {
+        "Binance" => {
+            if value["e"] != "24hrTicker" {
+                return Ok(None);
+            }
+            (number(value.get("c")), number(value.get("v")))
+        }
+        "Coinbase" => {
+            if value["type"] != "ticker" {
+                return Ok(None);
+            }
+            (number(value.get("price")), number(value.get("volume_24h")))
+        }
+        "Kraken" => {
+            if value["channel"] != "ticker" {
+                return Ok(None);
+            }
+            let data = &value["data"][0];
+            (number(data.get("last")), number(data.get("volume")))
+        }
+        _ => return Err(BitStableError::PriceFeedError(format!("No stream format for {}", venue))),
+    };
+
+    let price = price.ok_or_else(|| BitStableError::PriceFeedError(format!("{} ticker without price", venue)))?;
+    PriceQuote::new(price, volume).validate().map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oracle::Oracle;
+    use tokio::net::TcpListener;
+
+    fn binance_tick(price: f64) -> String {
+        serde_json::json!({ "e": "24hrTicker", "s": "BTCUSDT", "c": price.to_string(), "v": "1500.5" }).to_string()
+    }
+
+    /// Serves one session per accepted connection: send the frames, then close or go silent
+    async fn ws_server(sessions: Vec<(Vec<String>, bool)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            for (frames, hold_open) in sessions {
+                let (tcp, _) = listener.accept().await.unwrap();
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                for frame in frames {
+                    ws.send(Message::Text(frame)).await.unwrap();
+                }
+                if hold_open {
+                    held.push(ws);
+                } else {
+                    ws.close(None).await.unwrap();
+                }
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+            drop(held);
+        });
+
+        url
+    }
+
+    async fn wait_for(mut done: impl FnMut() -> bool) {
+        for _ in 0..250 {
+            if done() {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("condition not reached");
+    }
+
+    #[tokio::test]
+    async fn test_stream_reconnects_and_updates_oracle() {
+        let url = ws_server(vec![
+            (vec![r#"{"result":null,"id":1}"#.to_string(), binance_tick(100000.0)], false),
+            (vec![binance_tick(101000.0)], true),
+        ]).await;
+
+        let pubkey = bitcoin::secp256k1::PublicKey::from_slice(&[2; 33]).unwrap();
+        let mut oracle = Oracle::new("Binance".to_string(), pubkey);
+        let mut config = StreamConfig::new(url, Currency::USD);
+        config.initial_backoff_ms = 10;
+        oracle.start_stream(config);
+
+        // The subscription ack is skipped; the second tick arrives after a reconnect
+        wait_for(|| {
+            oracle.sync_streams();
+            oracle.last_prices.get(&Currency::USD).map(|data| data.prices[&Currency::USD]) == Some(101000.0)
+        }).await;
+
+        let data = &oracle.last_prices[&Currency::USD];
+        assert_eq!(data.volumes.get(&Currency::USD), Some(&1500.5));
+        assert!(oracle.is_price_fresh(&Currency::USD));
+        assert!(oracle.get_twap(&Currency::USD).is_some());
+
+        let stats = oracle.stream_stats(&Currency::USD).unwrap();
+        assert_eq!(stats.reconnects, 1);
+        assert_eq!(stats.ticks, 2);
+        assert_eq!(stats.status, StreamStatus::Live);
+    }
+
+    #[tokio::test]
+    async fn test_silent_stream_is_detected_as_gap() {
+        let url = ws_server(vec![
+            (vec![binance_tick(100000.0)], true),
+            (vec![binance_tick(100500.0)], true),
+        ]).await;
+
+        let mut config = StreamConfig::new(url, Currency::USD);
+        config.initial_backoff_ms = 10;
+        config.gap_timeout_ms = 100;
+        let stream = PriceStream::spawn("Binance", config);
+
+        wait_for(|| stream.stats().ticks == 2).await;
+        let stats = stream.stats();
+        assert!(stats.gaps >= 1);
+        assert_eq!(stats.reconnects, 1);
+
+        let prices: Vec<f64> = stream.drain_ticks().iter().map(|t| t.quote.price).collect();
+        assert_eq!(prices, vec![100000.0, 100500.0]);
+        assert!(stream.drain_ticks().is_empty());
+    }
+}
@@ -4,6 +4,7 @@ use std::collections::HashMap;
 use chrono::{DateTime, Utc};
 use sha2::{Sha256, Digest};
 use crate::{BitStableError, Result, Vault, Currency};
+use crate::audit::AuditHead;
 
 /// Proof-of-reserves system for real-time transparency
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,6 +27,25 @@ pub struct ReservesCommitment {
     pub total_vaults: usize,
     pub total_collateral_btc: Amount,
     pub total_debt_usd: f64,
+    #[serde(default)]
+    pub audit_head: Option<AuditHead>,  // Audit log head anchored alongside the reserves
+}
+
+impl ReservesCommitment {
+    /// Hash published in the commitment's OP_RETURN output; the full data is too
+    /// long for one output
+    pub fn op_return_digest(&self) -> [u8; 32] {
+        let mut commitment_data = format!(
+            "{}:{}:{}",
+            self.merkle_root,
+            self.block_height,
+            self.system_state.system_collateral_ratio
+        );
+        if let Some(head) = &self.audit_head {
+            commitment_data.push_str(&format!(":{}:{}", head.sequence, head.hash));
+        }
+        Sha256::digest(commitment_data.as_bytes()).into()
+    }
 }
 
 /// Individual vault state for Merkle tree
@@ -143,6 +163,7 @@ impl ProofOfReservesSystem {
             total_vaults: vaults.len(),
             total_collateral_btc,
             total_debt_usd,
+            audit_head: None,
         };
 
         // Store current commitment
@@ -312,6 +333,20 @@ impl ProofOfReservesSystem {
         current_hash == proof.merkle_root
     }
 
+    /// Commit to the audit log's head in the current commitment, before it is submitted
+    pub fn attach_audit_head(&mut self, head: AuditHead) -> Result<ReservesCommitment> {
+        let current = self.current_commitment.as_mut()
+            .ok_or_else(|| BitStableError::InvalidConfig("No proof-of-reserves commitment to anchor in".to_string()))?;
+        current.audit_head = Some(head);
+
+        if let Some(latest) = self.commitment_history.last_mut() {
+            if latest.merkle_root == current.merkle_root && latest.commitment_timestamp == current.commitment_timestamp {
+                latest.audit_head = current.audit_head.clone();
+            }
+        }
+        Ok(current.clone())
+    }
+
     /// Submit commitment to Bitcoin blockchain via OP_RETURN
     pub fn submit_to_bitcoin(
         &mut self,
@@ -319,15 +354,7 @@ impl ProofOfReservesSystem {
         bitcoin_client: &crate::bitcoin_client::BitcoinClient,
     ) -> Result<Txid> {
         // Create OP_RETURN script with commitment data
-        let _commitment_data = format!(
-            "{}:{}:{}",
-            commitment.merkle_root,
-            commitment.block_height,
-            commitment.system_state.system_collateral_ratio
-        );
-
-        // Simplified OP_RETURN for now - in production would use proper script building
-        let op_return_script = ScriptBuf::new();
+        let op_return_script = ScriptBuf::new_op_return(commitment.op_return_digest());
         
         // Create and broadcast Bitcoin transaction
         let txid = bitcoin_client.create_op_return_transaction(op_return_script)?;
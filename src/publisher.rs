@@ -0,0 +1,307 @@
+//! Oracle price publication with deviation and heartbeat triggers
+//! Decides when a node pushes a new signed consensus update to the network
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use crate::{BitStableError, Result};
+use crate::crypto::{OracleKeyManager, OracleSignature};
+use crate::database::DatabaseManager;
+use crate::multi_currency::Currency;
+use crate::oracle::ConsensusPrices;
+
+/// When an oracle node should publish a fresh update
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicationPolicy {
+    pub deviation_threshold: f64,     // Relative move that forces a publish (0.005 = 0.5%)
+    pub heartbeat_seconds: u64,       // Maximum time between publishes
+}
+
+impl Default for PublicationPolicy {
+    fn default() -> Self {
+        Self {
+            deviation_threshold: 0.005,
+            heartbeat_seconds: 3600,
+        }
+    }
+}
+
+/// Why an update was published
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PublicationReason {
+    Initial,
+    Deviation { currency: Currency, change: f64 },
+    NewCurrency(Currency),
+    Heartbeat,
+}
+
+/// A signed consensus update pushed by an oracle node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublishedUpdate {
+    pub sequence: u64,
+    pub oracle_name: String,
+    pub prices: HashMap<Currency, f64>,
+    pub participating_oracles: usize,
+    pub total_oracles: usize,
+    pub reason: PublicationReason,
+    pub timestamp: DateTime<Utc>,
+    pub signature: String,
+    pub public_key: String,
+}
+
+impl PublishedUpdate {
+    /// Canonical message covered by the signature
+    pub fn signing_message(&self) -> String {
+        let mut prices: Vec<(String, f64)> = self.prices
+            .iter()
+            .map(|(currency, price)| (currency.to_string(), *price))
+            .collect();
+        prices.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let prices = prices
+            .iter()
+            .map(|(currency, price)| format!("{}={}", currency, price))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        format!("{}:{}:{}:{}", self.oracle_name, self.sequence, self.timestamp.timestamp(), prices)
+    }
+}
+
+/// Applies a `PublicationPolicy` to consensus rounds and signs what it publishes
+#[derive(Debug)]
+pub struct OraclePublisher {
+    oracle_name: String,
+    policy: PublicationPolicy,
+    key_manager: OracleKeyManager,
+    database: Option<DatabaseManager>,
+    last_published: Option<PublishedUpdate>,
+    history: Vec<PublishedUpdate>,
+}
+
+impl OraclePublisher {
+    pub fn new(oracle_name: &str, policy: PublicationPolicy, key_manager: OracleKeyManager) -> Result<Self> {
+        if key_manager.get_oracle_public_key(oracle_name).is_none() {
+            return Err(BitStableError::InvalidConfig(format!("Oracle key not found: {}", oracle_name)));
+        }
+        if policy.deviation_threshold <= 0.0 || policy.heartbeat_seconds == 0 {
+            return Err(BitStableError::InvalidConfig(
+                "Publication deviation threshold and heartbeat must be positive".to_string()
+            ));
+        }
+
+        Ok(Self {
+            oracle_name: oracle_name.to_string(),
+            policy,
+            key_manager,
+            database: None,
+            last_published: None,
+            history: Vec::new(),
+        })
+    }
+
+    /// Persist publications and resume from the last one stored in the database
+    pub fn with_database(mut self, database: DatabaseManager) -> Result<Self> {
+        self.last_published = database.get_latest_publication()?;
+        if let Some(last) = &self.last_published {
+            log::info!(
+                "Resuming publications after update #{} from {}",
+                last.sequence,
+                last.timestamp.format("%Y-%m-%d %H:%M:%S")
+            );
+        }
+        self.database = Some(database);
+        Ok(self)
+    }
+
+    /// Decide whether the given prices warrant a new publication
+    pub fn evaluate(&self, prices: &HashMap<Currency, f64>, now: DateTime<Utc>) -> Option<PublicationReason> {
+        let last = match &self.last_published {
+            Some(last) => last,
+            None => return Some(PublicationReason::Initial),
+        };
+
+        let mut largest: Option<(Currency, f64)> = None;
+        for (currency, price) in prices {
+            let previous = match last.prices.get(currency) {
+                Some(previous) if *previous > 0.0 => *previous,
+                _ => return Some(PublicationReason::NewCurrency(currency.clone())),
+            };
+
+            let change = (price - previous).abs() / previous;
+            if change > self.policy.deviation_threshold
+                && largest.as_ref().is_none_or(|(_, biggest)| change > *biggest)
+            {
+                largest = Some((currency.clone(), change));
+            }
+        }
+
+        if let Some((currency, change)) = largest {
+            return Some(PublicationReason::Deviation { currency, change });
+        }
+
+        let elapsed = now.signed_duration_since(last.timestamp).num_seconds();
+        if elapsed >= self.policy.heartbeat_seconds as i64 {
+            return Some(PublicationReason::Heartbeat);
+        }
+
+        None
+    }
+
+    /// Sign and record a new update if the policy calls for one
+    pub fn maybe_publish(&mut self, consensus: &ConsensusPrices) -> Result<Option<PublishedUpdate>> {
+        if consensus.btc_prices.is_empty() {
+            return Ok(None);
+        }
+
+        let reason = match self.evaluate(&consensus.btc_prices, consensus.timestamp) {
+            Some(reason) => reason,
+            None => return Ok(None),
+        };
+
+        let public_key = self.key_manager.get_oracle_public_key(&self.oracle_name)
+            .ok_or_else(|| BitStableError::InvalidConfig(format!("Oracle key not found: {}", self.oracle_name)))?;
+
+        let mut update = PublishedUpdate {
+            sequence: self.last_published.as_ref().map(|u| u.sequence + 1).unwrap_or(1),
+            oracle_name: self.oracle_name.clone(),
+            prices: consensus.btc_prices.clone(),
+            participating_oracles: consensus.participating_oracles,
+            total_oracles: consensus.total_oracles,
+            reason,
+            timestamp: consensus.timestamp,
+            signature: String::new(),
+            public_key: hex::encode(public_key.serialize()),
+        };
+        update.signature = self.key_manager.sign_message(&self.oracle_name, &update.signing_message())?;
+
+        if let Some(database) = &self.database {
+            database.save_publication(&update)?;
+        }
+
+        self.history.push(update.clone());
+        if self.history.len() > 1000 {
+            self.history.remove(0);
+        }
+        self.last_published = Some(update.clone());
+
+        log::info!("Published oracle update #{} ({:?})", update.sequence, update.reason);
+        Ok(Some(update))
+    }
+
+    /// Per-currency attestations of an update's prices, for `BitStableNetwork::send_price_attestations`
+    pub fn attestations(&self, update: &PublishedUpdate) -> Result<Vec<OracleSignature>> {
+        update.prices.iter()
+            .map(|(currency, price)| {
+                self.key_manager.sign_currency_price(&self.oracle_name, currency.clone(), *price, update.timestamp.timestamp())
+            })
+            .collect()
+    }
+
+    /// Check the signature on a published update
+    pub fn verify(&self, update: &PublishedUpdate) -> Result<bool> {
+        self.key_manager.verify_message(&update.public_key, &update.signing_message(), &update.signature)
+    }
+
+    pub fn get_last_published(&self) -> Option<&PublishedUpdate> {
+        self.last_published.as_ref()
+    }
+
+    pub fn get_policy(&self) -> &PublicationPolicy {
+        &self.policy
+    }
+
+    /// Recent publications, oldest first; read from the database when one is attached
+    pub fn get_publication_history(&self, limit: usize) -> Result<Vec<PublishedUpdate>> {
+        if let Some(database) = &self.database {
+            return database.get_publication_history(limit);
+        }
+
+        let start = self.history.len().saturating_sub(limit);
+        Ok(self.history[start..].to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn consensus(usd: f64, eur: f64, timestamp: DateTime<Utc>) -> ConsensusPrices {
+        let mut btc_prices = HashMap::new();
+        btc_prices.insert(Currency::USD, usd);
+        btc_prices.insert(Currency::EUR, eur);
+        ConsensusPrices {
+            btc_prices,
+            exchange_rates: HashMap::new(),
+            timestamp,
+            participating_oracles: 3,
+            total_oracles: 5,
+        }
+    }
+
+    fn publisher() -> OraclePublisher {
+        let mut keys = OracleKeyManager::new();
+        keys.generate_oracle_key("local_oracle").unwrap();
+        let policy = PublicationPolicy { deviation_threshold: 0.01, heartbeat_seconds: 600 };
+        OraclePublisher::new("local_oracle", policy, keys).unwrap()
+    }
+
+    #[test]
+    fn test_deviation_and_heartbeat_triggers() {
+        let mut publisher = publisher();
+        let start = Utc::now();
+
+        let first = publisher.maybe_publish(&consensus(50000.0, 46000.0, start)).unwrap().unwrap();
+        assert_eq!(first.reason, PublicationReason::Initial);
+        assert!(publisher.verify(&first).unwrap());
+        let attestations = publisher.attestations(&first).unwrap();
+        assert_eq!(attestations.len(), 2);
+        assert!(attestations.iter().all(|attestation| publisher.key_manager.verify_oracle_signature(attestation).unwrap()));
+
+        // 0.2% move inside the heartbeat window is suppressed
+        let quiet = consensus(50100.0, 46000.0, start + chrono::Duration::seconds(30));
+        assert!(publisher.maybe_publish(&quiet).unwrap().is_none());
+
+        // 2% EUR move publishes immediately
+        let moved = consensus(50100.0, 46920.0, start + chrono::Duration::seconds(60));
+        let update = publisher.maybe_publish(&moved).unwrap().unwrap();
+        assert!(matches!(update.reason, PublicationReason::Deviation { currency: Currency::EUR, .. }));
+        assert_eq!(update.sequence, 2);
+
+        // Unchanged prices still publish once the heartbeat elapses
+        let stale = consensus(50100.0, 46920.0, start + chrono::Duration::seconds(700));
+        let heartbeat = publisher.maybe_publish(&stale).unwrap().unwrap();
+        assert_eq!(heartbeat.reason, PublicationReason::Heartbeat);
+
+        let mut tampered = heartbeat.clone();
+        tampered.prices.insert(Currency::USD, 1.0);
+        assert!(!publisher.verify(&tampered).unwrap());
+    }
+
+    #[test]
+    fn test_publications_survive_restart() {
+        let temp_dir = TempDir::new().unwrap();
+        let start = Utc::now();
+
+        {
+            let database = DatabaseManager::new(temp_dir.path()).unwrap();
+            let mut publisher = publisher().with_database(database).unwrap();
+            publisher.maybe_publish(&consensus(50000.0, 46000.0, start)).unwrap();
+        }
+
+        // The publisher and its database handle were dropped above, releasing sled's lock
+        let database = DatabaseManager::new(temp_dir.path()).unwrap();
+        let mut publisher = publisher().with_database(database).unwrap();
+        assert_eq!(publisher.get_last_published().unwrap().sequence, 1);
+
+        // Restarted node does not republish unchanged prices
+        let same = consensus(50000.0, 46000.0, start + chrono::Duration::seconds(10));
+        assert!(publisher.maybe_publish(&same).unwrap().is_none());
+
+        let moved = consensus(51000.0, 46000.0, start + chrono::Duration::seconds(20));
+        publisher.maybe_publish(&moved).unwrap().unwrap();
+        let history = publisher.get_publication_history(10).unwrap();
+        assert_eq!(history.iter().map(|u| u.sequence).collect::<Vec<_>>(), vec![1, 2]);
+    }
+}
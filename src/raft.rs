@@ -0,0 +1,712 @@
+//! Embedded Raft consensus
+//! Leader election, log replication and snapshot install, driven by ticks and messages
+
+use std::collections::{HashMap, HashSet};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use crate::{BitStableError, Result};
+
+pub type NodeId = u64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RaftConfig {
+    pub election_ticks: u32,        // Followers time out after between this and twice this many ticks
+    pub heartbeat_ticks: u32,
+    pub max_append_entries: usize,  // Entries per AppendEntries message
+    pub snapshot_threshold: u64,    // Applied entries kept in the log before compacting
+}
+
+impl Default for RaftConfig {
+    fn default() -> Self {
+        Self {
+            election_ticks: 10,
+            heartbeat_ticks: 3,
+            max_append_entries: 64,
+            snapshot_threshold: 1000,
+        }
+    }
+}
+
+impl RaftConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.heartbeat_ticks == 0 || self.heartbeat_ticks >= self.election_ticks {
+            return Err(BitStableError::InvalidConfig("Raft heartbeats must be more frequent than elections".to_string()));
+        }
+        if self.max_append_entries == 0 || self.snapshot_threshold == 0 {
+            return Err(BitStableError::InvalidConfig("Raft batch size and snapshot threshold must be positive".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// `command` is None for the no-op a new leader appends to commit earlier terms
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry<C> {
+    pub term: u64,
+    pub index: u64,
+    pub command: Option<C>,
+}
+
+/// Application state as of `last_index`, replacing every entry up to it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub last_index: u64,
+    pub last_term: u64,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RaftMessage<C> {
+    RequestVote {
+        term: u64,
+        last_log_index: u64,
+        last_log_term: u64,
+    },
+    VoteResponse {
+        term: u64,
+        granted: bool,
+    },
+    AppendEntries {
+        term: u64,
+        prev_log_index: u64,
+        prev_log_term: u64,
+        entries: Vec<LogEntry<C>>,
+        leader_commit: u64,
+    },
+    AppendResponse {
+        term: u64,
+        success: bool,
+        match_index: u64,   // Last replicated index on success, a retry hint otherwise
+    },
+    InstallSnapshot {
+        term: u64,
+        snapshot: Snapshot,
+    },
+    SnapshotResponse {
+        term: u64,
+        last_index: u64,
+    },
+}
+
+impl<C> RaftMessage<C> {
+    pub fn term(&self) -> u64 {
+        match self {
+            RaftMessage::RequestVote { term, .. }
+            | RaftMessage::VoteResponse { term, .. }
+            | RaftMessage::AppendEntries { term, .. }
+            | RaftMessage::AppendResponse { term, .. }
+            | RaftMessage::InstallSnapshot { term, .. }
+            | RaftMessage::SnapshotResponse { term, .. } => *term,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope<C> {
+    pub from: NodeId,
+    pub to: NodeId,
+    pub message: RaftMessage<C>,
+}
+
+/// Output for the application, in log order
+#[derive(Debug, Clone, PartialEq)]
+pub enum Committed<C> {
+    Entry(LogEntry<C>),
+    Snapshot(Snapshot),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RaftRole {
+    Follower,
+    Candidate,
+    Leader,
+}
+
+/// State that must be on disk before any message reflecting it is sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistentState<C> {
+    pub current_term: u64,
+    pub voted_for: Option<NodeId>,
+    pub log: Vec<LogEntry<C>>,        // Entries after the snapshot
+    pub snapshot: Option<Snapshot>,
+}
+
+impl<C> Default for PersistentState<C> {
+    fn default() -> Self {
+        Self {
+            current_term: 0,
+            voted_for: None,
+            log: Vec::new(),
+            snapshot: None,
+        }
+    }
+}
+
+pub struct RaftNode<C> {
+    id: NodeId,
+    peers: Vec<NodeId>,
+    config: RaftConfig,
+    role: RaftRole,
+    state: PersistentState<C>,
+    dirty: bool,
+    commit_index: u64,
+    last_applied: u64,
+    leader: Option<NodeId>,
+    election_elapsed: u32,
+    election_timeout: u32,
+    heartbeat_elapsed: u32,
+    votes: HashSet<NodeId>,
+    next_index: HashMap<NodeId, u64>,
+    match_index: HashMap<NodeId, u64>,
+    pending_snapshot: Option<Snapshot>,   // Installed but not yet handed to the application
+    outbox: Vec<Envelope<C>>,
+    rng: StdRng,
+}
+
+impl<C: Clone> RaftNode<C> {
+    /// `peers` are the other members; `restored` is state persisted by a previous run
+    pub fn new(id: NodeId, peers: Vec<NodeId>, config: RaftConfig, restored: Option<PersistentState<C>>) -> Result<Self> {
+        config.validate()?;
+        if peers.contains(&id) {
+            return Err(BitStableError::InvalidConfig(format!("Raft node {} listed as its own peer", id)));
+        }
+
+        let state = restored.unwrap_or_default();
+        // A restored snapshot is handed back to the application, which rebuilds from it
+        let snapshot_index = state.snapshot.as_ref().map_or(0, |snapshot| snapshot.last_index);
+        let mut node = Self {
+            id,
+            peers,
+            config,
+            role: RaftRole::Follower,
+            pending_snapshot: state.snapshot.clone(),
+            state,
+            dirty: false,
+            commit_index: snapshot_index,
+            last_applied: snapshot_index,
+            leader: None,
+            election_elapsed: 0,
+            election_timeout: 0,
+            heartbeat_elapsed: 0,
+            votes: HashSet::new(),
+            next_index: HashMap::new(),
+            match_index: HashMap::new(),
+            outbox: Vec::new(),
+            rng: StdRng::from_entropy(),
+        };
+        node.reset_election_timer();
+        Ok(node)
+    }
+
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    pub fn role(&self) -> RaftRole {
+        self.role
+    }
+
+    pub fn leader(&self) -> Option<NodeId> {
+        self.leader
+    }
+
+    pub fn term(&self) -> u64 {
+        self.state.current_term
+    }
+
+    pub fn commit_index(&self) -> u64 {
+        self.commit_index
+    }
+
+    pub fn last_applied(&self) -> u64 {
+        self.last_applied
+    }
+
+    pub fn last_index(&self) -> u64 {
+        self.state.log.last().map_or(self.snapshot_index(), |entry| entry.index)
+    }
+
+    pub fn snapshot_index(&self) -> u64 {
+        self.state.snapshot.as_ref().map_or(0, |snapshot| snapshot.last_index)
+    }
+
+    /// The persistent state if it changed since the last call
+    pub fn take_unpersisted(&mut self) -> Option<&PersistentState<C>> {
+        if std::mem::take(&mut self.dirty) {
+            Some(&self.state)
+        } else {
+            None
+        }
+    }
+
+    pub fn take_messages(&mut self) -> Vec<Envelope<C>> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    /// Resume after a restart from application state that already reflects the log up
+    /// to `index`, so entries and a snapshot it has seen aren't handed back. State
+    /// older than the restored snapshot is left to be rebuilt from it.
+    pub fn resume_applied(&mut self, index: u64) -> Result<()> {
+        if index > self.last_index() {
+            return Err(BitStableError::InvalidConfig(format!(
+                "Applied index {} is past the end of the restored log ({})", index, self.last_index()
+            )));
+        }
+        if index >= self.last_applied {
+            self.pending_snapshot = None;
+            self.last_applied = index;
+            // Only committed entries are ever applied
+            self.commit_index = self.commit_index.max(index);
+        }
+        Ok(())
+    }
+
+    /// Newly committed entries and installed snapshots, marking them applied
+    pub fn take_committed(&mut self) -> Vec<Committed<C>> {
+        let mut committed = Vec::new();
+        if let Some(snapshot) = self.pending_snapshot.take() {
+            committed.push(Committed::Snapshot(snapshot));
+        }
+        while self.last_applied < self.commit_index {
+            self.last_applied += 1;
+            if let Some(entry) = self.entry(self.last_applied) {
+                committed.push(Committed::Entry(entry.clone()));
+            }
+        }
+        committed
+    }
+
+    /// Enough entries applied since the last snapshot that the log should be compacted
+    pub fn should_compact(&self) -> bool {
+        self.last_applied - self.snapshot_index() >= self.config.snapshot_threshold
+    }
+
+    /// Replace the log up to `last_applied` with the application's state at that point
+    pub fn compact(&mut self, data: Vec<u8>) -> Result<()> {
+        let last_index = self.last_applied;
+        let last_term = self.term_at(last_index)
+            .ok_or_else(|| BitStableError::InvalidConfig(format!("Raft entry {} already compacted", last_index)))?;
+        self.state.log.retain(|entry| entry.index > last_index);
+        self.state.snapshot = Some(Snapshot { last_index, last_term, data });
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Append a command; only the leader accepts proposals. Returns its log index.
+    pub fn propose(&mut self, command: C) -> Result<u64> {
+        if self.role != RaftRole::Leader {
+            return Err(BitStableError::NotLeader(self.leader));
+        }
+        let index = self.append(Some(command));
+        self.broadcast_append();
+        self.maybe_commit();
+        Ok(index)
+    }
+
+    pub fn tick(&mut self) {
+        if self.role == RaftRole::Leader {
+            self.heartbeat_elapsed += 1;
+            if self.heartbeat_elapsed >= self.config.heartbeat_ticks {
+                self.heartbeat_elapsed = 0;
+                self.broadcast_append();
+            }
+        } else {
+            self.election_elapsed += 1;
+            if self.election_elapsed >= self.election_timeout {
+                self.start_election();
+            }
+        }
+    }
+
+    pub fn step(&mut self, envelope: Envelope<C>) {
+        if envelope.to != self.id || !self.peers.contains(&envelope.from) {
+            return;
+        }
+        let from = envelope.from;
+        let term = envelope.message.term();
+        if term > self.state.current_term {
+            self.become_follower(term, None);
+        }
+
+        match envelope.message {
+            RaftMessage::RequestVote { term, last_log_index, last_log_term } => {
+                let log_ok = (last_log_term, last_log_index) >= (self.last_term(), self.last_index());
+                let granted = term == self.state.current_term
+                    && self.state.voted_for.is_none_or(|voted| voted == from)
+                    && log_ok;
+                if granted {
+                    self.state.voted_for = Some(from);
+                    self.dirty = true;
+                    self.reset_election_timer();
+                }
+                self.send(from, RaftMessage::VoteResponse { term: self.state.current_term, granted });
+            }
+            RaftMessage::VoteResponse { term, granted } => {
+                if self.role == RaftRole::Candidate && term == self.state.current_term && granted {
+                    self.votes.insert(from);
+                    if self.votes.len() >= self.quorum() {
+                        self.become_leader();
+                    }
+                }
+            }
+            RaftMessage::AppendEntries { term, prev_log_index, prev_log_term, entries, leader_commit } => {
+                if term < self.state.current_term {
+                    self.send(from, RaftMessage::AppendResponse { term: self.state.current_term, success: false, match_index: 0 });
+                    return;
+                }
+                self.become_follower(term, Some(from));
+                let response = self.append_entries(prev_log_index, prev_log_term, entries, leader_commit);
+                self.send(from, response);
+            }
+            RaftMessage::AppendResponse { term, success, match_index } => {
+                if self.role != RaftRole::Leader || term != self.state.current_term {
+                    return;
+                }
+                if success {
+                    let matched = self.match_index.entry(from).or_default();
+                    *matched = (*matched).max(match_index);
+                    self.next_index.insert(from, match_index + 1);
+                    self.maybe_commit();
+                    if match_index < self.last_index() {
+                        self.send_append(from);
+                    }
+                } else {
+                    // Back up to the follower's hint and retry
+                    let next = self.next_index.get(&from).copied().unwrap_or(1);
+                    self.next_index.insert(from, (match_index + 1).min(next.saturating_sub(1)).max(1));
+                    self.send_append(from);
+                }
+            }
+            RaftMessage::InstallSnapshot { term, snapshot } => {
+                if term < self.state.current_term {
+                    self.send(from, RaftMessage::SnapshotResponse { term: self.state.current_term, last_index: 0 });
+                    return;
+                }
+                self.become_follower(term, Some(from));
+                let last_index = snapshot.last_index;
+                self.install_snapshot(snapshot);
+                self.send(from, RaftMessage::SnapshotResponse { term: self.state.current_term, last_index });
+            }
+            RaftMessage::SnapshotResponse { term, last_index } => {
+                if self.role != RaftRole::Leader || term != self.state.current_term {
+                    return;
+                }
+                let matched = self.match_index.entry(from).or_default();
+                *matched = (*matched).max(last_index);
+                self.next_index.insert(from, last_index + 1);
+                if last_index < self.last_index() {
+                    self.send_append(from);
+                }
+            }
+        }
+    }
+
+    fn append_entries(
+        &mut self,
+        prev_log_index: u64,
+        prev_log_term: u64,
+        entries: Vec<LogEntry<C>>,
+        leader_commit: u64,
+    ) -> RaftMessage<C> {
+        let term = self.state.current_term;
+        // Entries covered by our snapshot are already committed, so only check what follows it
+        let (prev_log_index, entries) = if prev_log_index < self.snapshot_index() {
+            let snapshot_index = self.snapshot_index();
+            (snapshot_index, entries.into_iter().filter(|entry| entry.index > snapshot_index).collect())
+        } else {
+            if self.term_at(prev_log_index) != Some(prev_log_term) {
+                let hint = self.last_index().min(prev_log_index.saturating_sub(1));
+                return RaftMessage::AppendResponse { term, success: false, match_index: hint };
+            }
+            (prev_log_index, entries)
+        };
+
+        let last_new = prev_log_index + entries.len() as u64;
+        for entry in entries {
+            match self.term_at(entry.index) {
+                Some(existing) if existing == entry.term => continue,
+                Some(_) => {
+                    // A conflicting suffix was never committed; drop it
+                    self.state.log.retain(|kept| kept.index < entry.index);
+                }
+                None => {}
+            }
+            self.state.log.push(entry);
+            self.dirty = true;
+        }
+
+        if leader_commit > self.commit_index {
+            self.commit_index = leader_commit.min(last_new);
+        }
+        RaftMessage::AppendResponse { term, success: true, match_index: last_new }
+    }
+
+    fn install_snapshot(&mut self, snapshot: Snapshot) {
+        if snapshot.last_index <= self.commit_index {
+            return;
+        }
+        // Keep entries after the snapshot only if our log agrees with it
+        if self.term_at(snapshot.last_index) == Some(snapshot.last_term) {
+            self.state.log.retain(|entry| entry.index > snapshot.last_index);
+        } else {
+            self.state.log.clear();
+        }
+        self.commit_index = snapshot.last_index;
+        self.last_applied = snapshot.last_index;
+        self.pending_snapshot = Some(snapshot.clone());
+        self.state.snapshot = Some(snapshot);
+        self.dirty = true;
+    }
+
+    fn quorum(&self) -> usize {
+        let members = self.peers.len() + 1;
+        members / 2 + 1
+    }
+
+    fn last_term(&self) -> u64 {
+        self.state.log.last().map_or_else(
+            || self.state.snapshot.as_ref().map_or(0, |snapshot| snapshot.last_term),
+            |entry| entry.term,
+        )
+    }
+
+    /// None if `index` is past the log or already compacted into the snapshot
+    fn term_at(&self, index: u64) -> Option<u64> {
+        let snapshot_index = self.snapshot_index();
+        if index == snapshot_index {
+            return Some(self.state.snapshot.as_ref().map_or(0, |snapshot| snapshot.last_term));
+        }
+        self.entry(index).map(|entry| entry.term)
+    }
+
+    fn entry(&self, index: u64) -> Option<&LogEntry<C>> {
+        let offset = index.checked_sub(self.snapshot_index() + 1)?;
+        self.state.log.get(offset as usize)
+    }
+
+    fn append(&mut self, command: Option<C>) -> u64 {
+        let index = self.last_index() + 1;
+        self.state.log.push(LogEntry { term: self.state.current_term, index, command });
+        self.dirty = true;
+        index
+    }
+
+    fn reset_election_timer(&mut self) {
+        self.election_elapsed = 0;
+        self.election_timeout = self.rng.gen_range(self.config.election_ticks..self.config.election_ticks * 2);
+    }
+
+    fn become_follower(&mut self, term: u64, leader: Option<NodeId>) {
+        if term > self.state.current_term {
+            self.state.current_term = term;
+            self.state.voted_for = None;
+            self.dirty = true;
+        }
+        self.role = RaftRole::Follower;
+        if leader.is_some() {
+            self.leader = leader;
+            self.reset_election_timer();
+        }
+    }
+
+    fn start_election(&mut self) {
+        self.role = RaftRole::Candidate;
+        self.state.current_term += 1;
+        self.state.voted_for = Some(self.id);
+        self.dirty = true;
+        self.leader = None;
+        self.votes = HashSet::from([self.id]);
+        self.reset_election_timer();
+        log::debug!("Raft node {} starting election for term {}", self.id, self.state.current_term);
+
+        if self.votes.len() >= self.quorum() {
+            self.become_leader();
+            return;
+        }
+        let message = RaftMessage::RequestVote {
+            term: self.state.current_term,
+            last_log_index: self.last_index(),
+            last_log_term: self.last_term(),
+        };
+        for peer in self.peers.clone() {
+            self.send(peer, message.clone());
+        }
+    }
+
+    fn become_leader(&mut self) {
+        log::info!("Raft node {} is leader for term {}", self.id, self.state.current_term);
+        self.role = RaftRole::Leader;
+        self.leader = Some(self.id);
+        self.heartbeat_elapsed = 0;
+        let next = self.last_index() + 1;
+        self.next_index = self.peers.iter().map(|peer| (*peer, next)).collect();
+        self.match_index = self.peers.iter().map(|peer| (*peer, 0)).collect();
+
+        // Entries from earlier terms only commit behind one from this term
+        self.append(None);
+        self.broadcast_append();
+        self.maybe_commit();
+    }
+
+    fn maybe_commit(&mut self) {
+        for index in (self.commit_index + 1..=self.last_index()).rev() {
+            if self.term_at(index) != Some(self.state.current_term) {
+                break;
+            }
+            let replicated = 1 + self.match_index.values().filter(|matched| **matched >= index).count();
+            if replicated >= self.quorum() {
+                self.commit_index = index;
+                break;
+            }
+        }
+    }
+
+    fn broadcast_append(&mut self) {
+        for peer in self.peers.clone() {
+            self.send_append(peer);
+        }
+    }
+
+    fn send_append(&mut self, peer: NodeId) {
+        let next = self.next_index.get(&peer).copied().unwrap_or(1);
+        if next <= self.snapshot_index() {
+            if let Some(snapshot) = self.state.snapshot.clone() {
+                self.send(peer, RaftMessage::InstallSnapshot { term: self.state.current_term, snapshot });
+            }
+            return;
+        }
+
+        let prev_log_index = next - 1;
+        let prev_log_term = self.term_at(prev_log_index).unwrap_or(0);
+        let entries = (next..=self.last_index())
+            .take(self.config.max_append_entries)
+            .filter_map(|index| self.entry(index).cloned())
+            .collect();
+        self.send(peer, RaftMessage::AppendEntries {
+            term: self.state.current_term,
+            prev_log_index,
+            prev_log_term,
+            entries,
+            leader_commit: self.commit_index,
+        });
+    }
+
+    fn send(&mut self, to: NodeId, message: RaftMessage<C>) {
+        self.outbox.push(Envelope { from: self.id, to, message });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Cluster {
+        nodes: Vec<RaftNode<u32>>,
+        applied: Vec<Vec<u32>>,
+        isolated: Option<NodeId>,
+    }
+
+    impl Cluster {
+        fn new(size: u64, config: RaftConfig) -> Self {
+            let ids: Vec<NodeId> = (1..=size).collect();
+            let nodes = ids.iter()
+                .map(|id| {
+                    let peers = ids.iter().copied().filter(|peer| peer != id).collect();
+                    RaftNode::new(*id, peers, config.clone(), None).unwrap()
+                })
+                .collect();
+            Self { nodes, applied: vec![Vec::new(); size as usize], isolated: None }
+        }
+
+        fn node(&mut self, id: NodeId) -> &mut RaftNode<u32> {
+            &mut self.nodes[id as usize - 1]
+        }
+
+        /// Tick every node once and deliver messages until the network is quiet
+        fn round(&mut self) {
+            for node in &mut self.nodes {
+                node.tick();
+            }
+            loop {
+                let envelopes: Vec<Envelope<u32>> = self.nodes.iter_mut().flat_map(|node| node.take_messages()).collect();
+                if envelopes.is_empty() {
+                    break;
+                }
+                for envelope in envelopes {
+                    if self.isolated.is_some_and(|isolated| isolated == envelope.from || isolated == envelope.to) {
+                        continue;
+                    }
+                    let to = envelope.to;
+                    self.node(to).step(envelope);
+                }
+            }
+            for (node, applied) in self.nodes.iter_mut().zip(&mut self.applied) {
+                for committed in node.take_committed() {
+                    match committed {
+                        Committed::Entry(LogEntry { command: Some(command), .. }) => applied.push(command),
+                        Committed::Entry(_) => {}
+                        Committed::Snapshot(snapshot) => *applied = serde_json::from_slice(&snapshot.data).unwrap(),
+                    }
+                }
+                if node.should_compact() {
+                    node.compact(serde_json::to_vec(applied).unwrap()).unwrap();
+                }
+            }
+        }
+
+        fn rounds(&mut self, count: usize) {
+            for _ in 0..count {
+                self.round();
+            }
+        }
+
+        fn leader(&mut self, rounds: usize) -> NodeId {
+            for _ in 0..rounds {
+                self.round();
+                let leaders: Vec<NodeId> = self.nodes.iter()
+                    .filter(|node| node.role() == RaftRole::Leader && Some(node.id()) != self.isolated)
+                    .map(|node| node.id())
+                    .collect();
+                if let [leader] = leaders[..] {
+                    return leader;
+                }
+            }
+            panic!("no leader elected");
+        }
+    }
+
+    #[test]
+    fn test_election_replication_and_failover() {
+        let config = RaftConfig { snapshot_threshold: 4, ..RaftConfig::default() };
+        let mut cluster = Cluster::new(3, config);
+
+        let leader = cluster.leader(100);
+        for command in 1..=3 {
+            cluster.node(leader).propose(command).unwrap();
+        }
+        let follower = leader % 3 + 1;
+        assert!(matches!(cluster.node(follower).propose(9), Err(BitStableError::NotLeader(Some(id))) if id == leader));
+        // Followers learn the commit index from the next heartbeat
+        cluster.rounds(5);
+        assert!(cluster.applied.iter().all(|applied| applied == &[1, 2, 3]));
+
+        // Cut the leader off; its new entry can never commit
+        cluster.isolated = Some(leader);
+        cluster.node(leader).propose(99).unwrap();
+        let successor = cluster.leader(100);
+        assert_ne!(successor, leader);
+        for command in 4..=8 {
+            cluster.node(successor).propose(command).unwrap();
+        }
+        cluster.rounds(5);
+
+        // Rejoining, the old leader drops its uncommitted entry and catches up from a snapshot
+        cluster.isolated = None;
+        cluster.rounds(10);
+        let expected: Vec<u32> = (1..=8).collect();
+        assert!(cluster.applied.iter().all(|applied| applied == &expected));
+        assert!(cluster.node(leader).snapshot_index() > 0);
+        assert_eq!(cluster.node(leader).role(), RaftRole::Follower);
+    }
+}
@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use chrono::{DateTime, Utc};
 use crate::{BitStableError, Result, ProtocolConfig, VaultManager};
+use crate::events::{EventBus, EventEmitter, ProtocolEvent};
 use crate::multi_currency::{Currency, ExchangeRates};
 
 /// Direct redemption engine for maintaining stablecoin peg
@@ -17,6 +18,7 @@ pub struct RedemptionEngine {
     dynamic_fee_multiplier: f64,       // Multiplier based on demand
     #[allow(dead_code)]
     redemption_pool: HashMap<Currency, f64>, // Available for immediate redemption
+    events: EventEmitter,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +33,16 @@ pub struct RedemptionRecord {
     pub timestamp: DateTime<Utc>,
 }
 
+/// The engine's mutable state, persisted across restarts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedemptionState {
+    pub daily_redemption_limits: HashMap<Currency, f64>,
+    pub daily_redemption_used: HashMap<Currency, f64>,
+    pub last_reset: DateTime<Utc>,
+    pub redemption_history: Vec<RedemptionRecord>,
+    pub dynamic_fee_multiplier: f64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RedemptionOpportunity {
     pub vault_id: Txid,
@@ -57,9 +69,32 @@ impl RedemptionEngine {
             base_redemption_fee: 0.005,    // 0.5%
             dynamic_fee_multiplier: 1.0,
             redemption_pool: HashMap::new(),
+            events: EventEmitter::default(),
+        }
+    }
+
+    pub fn set_event_bus(&mut self, bus: EventBus) {
+        self.events.attach(bus);
+    }
+
+    pub fn state(&self) -> RedemptionState {
+        RedemptionState {
+            daily_redemption_limits: self.daily_redemption_limits.clone(),
+            daily_redemption_used: self.daily_redemption_used.clone(),
+            last_reset: self.last_reset,
+            redemption_history: self.redemption_history.clone(),
+            dynamic_fee_multiplier: self.dynamic_fee_multiplier,
         }
     }
 
+    pub fn restore_state(&mut self, state: RedemptionState) {
+        self.daily_redemption_limits = state.daily_redemption_limits;
+        self.daily_redemption_used = state.daily_redemption_used;
+        self.last_reset = state.last_reset;
+        self.redemption_history = state.redemption_history;
+        self.dynamic_fee_multiplier = state.dynamic_fee_multiplier;
+    }
+
     /// Redeem stablecoins for BTC collateral from least collateralized vaults
     pub async fn redeem_stablecoins(
         &mut self,
@@ -120,6 +155,7 @@ impl RedemptionEngine {
             btc_amount.to_btc(),
             redemption_fee * 100.0
         );
+        self.events.emit(ProtocolEvent::RedemptionExecuted { record: redemption_record.clone() });
         
         Ok(redemption_record)
     }
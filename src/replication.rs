@@ -0,0 +1,882 @@
+//! Replicated protocol state
+//! Vault operations, prices and governance results ordered by Raft and applied identically on every replica
+//!
+//! Envelopes travel between replicas as direct `MessageData::Raft` messages on the
+//! BitStable network, signed by each replica's node key; `run` drives a replica over it.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use bitcoin::secp256k1::SecretKey;
+use bitcoin::{Amount, PublicKey, Txid};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use crate::{BitStableError, BitStableProtocol, Result};
+use crate::custody::{EscrowContract, LiquidationSettlement};
+use crate::database::{DatabaseManager, LiquidationRecord, UnitOfWork};
+use crate::governance::ExecutionResult;
+use crate::insurance::InsuranceFund;
+use crate::multi_currency::{Currency, ExchangeRates};
+use crate::network::{self, BitStableNetwork, MessageData, MessageType, NetworkMessage};
+use crate::raft::{Committed, Envelope, LogEntry, NodeId, RaftConfig, RaftNode, PersistentState, Snapshot};
+use crate::stable::MultiCurrencyStableManager;
+use crate::vault::{Vault, VaultState};
+
+const RAFT_STATE_KEY: &str = "raft_state";   // Term, vote and snapshot; entries are stored one per key
+const APPLIED_STATE_KEY: &str = "raft_applied";   // Written with every commit of applied state
+
+/// How far the log is applied and the replicated state kept outside the protocol's
+/// own stores, kept under `APPLIED_STATE_KEY` in the same commits as that state
+#[derive(Debug, Serialize, Deserialize)]
+struct AppliedState {
+    last_applied: u64,
+    exchange_rates: ExchangeRates,
+    tripped_currencies: Vec<Currency>,
+    governance_log: Vec<ExecutedProposal>,
+}
+
+/// Raft state kept under `RAFT_STATE_KEY`
+#[derive(Debug, Serialize, Deserialize)]
+struct RaftMeta {
+    current_term: u64,
+    voted_for: Option<NodeId>,
+    snapshot: Option<Snapshot>,
+}
+
+/// A protocol mutation, applied through the same `BitStableProtocol` methods a single
+/// node uses. Everything nondeterministic (ids, creation and liquidation times,
+/// governance outcomes) is decided by the proposer so every replica applies the same
+/// change; only bookkeeping stamps such as `debts.last_updated` record local apply time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ProtocolOp {
+    SetBtcPrice {
+        currency: Currency,
+        price: f64,
+    },
+    CreateVault {
+        vault_id: Txid,
+        owner: PublicKey,
+        collateral: Amount,
+        currency: Currency,
+        amount: f64,
+        created_at: DateTime<Utc>,
+    },
+    MintAdditional {
+        vault_id: Txid,
+        currency: Currency,
+        amount: f64,
+    },
+    BurnStable {
+        vault_id: Txid,
+        currency: Currency,
+        amount: f64,
+    },
+    CloseVault {
+        vault_id: Txid,
+        owner: PublicKey,
+    },
+    LiquidateVault {
+        vault_id: Txid,
+        liquidator: PublicKey,
+        liquidated_at: DateTime<Utc>,
+    },
+    SetCircuitBreakers {
+        tripped: Vec<Currency>,
+    },
+    ExecuteGovernance {
+        proposal_id: u64,
+        result: ExecutionResult,
+        executed_at: DateTime<Utc>,
+    },
+}
+
+/// A governance result carried out on every replica
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutedProposal {
+    pub proposal_id: u64,
+    pub result: ExecutionResult,
+    pub executed_at: DateTime<Utc>,
+}
+
+/// Another replica's node key and the address to dial it at
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplicaPeer {
+    pub pubkey: PublicKey,
+    pub address: String,
+}
+
+/// Result of applying one committed operation. Rejected operations are still
+/// committed; every replica rejects them the same way.
+#[derive(Debug, Clone)]
+pub struct ApplyOutcome {
+    pub index: u64,
+    pub result: std::result::Result<(), String>,
+}
+
+/// Replicated state captured when compacting the log
+#[derive(Debug, Serialize, Deserialize)]
+struct StateSnapshot {
+    vaults: Vec<Vault>,
+    exchange_rates: ExchangeRates,
+    #[serde(default)]
+    tripped_currencies: Vec<Currency>,
+    governance_log: Vec<ExecutedProposal>,
+    stable_balances: MultiCurrencyStableManager,
+    escrow_contracts: Vec<EscrowContract>,
+    settlements: Vec<LiquidationSettlement>,
+    liquidations: Vec<LiquidationRecord>,
+    insurance_fund: InsuranceFund,
+}
+
+pub struct ReplicatedProtocol {
+    raft: RaftNode<ProtocolOp>,
+    protocol: BitStableProtocol,
+    database: DatabaseManager,
+    node_key: SecretKey,                           // Signs the audit entries for privileged ops this replica applies
+    exchange_rates: ExchangeRates,                 // Prices agreed through the log
+    tripped_currencies: HashSet<Currency>,         // Circuit breakers agreed through the log
+    governance_log: Vec<ExecutedProposal>,         // Executed proposals, in log order
+    applied_index: u64,                            // Last index recorded as applied in a commit
+    persisted_meta: (u64, Option<NodeId>, u64),    // Term, vote and snapshot index on disk
+    persisted_terms: BTreeMap<u64, u64>,           // Term of each log entry on disk, by index
+}
+
+impl ReplicatedProtocol {
+    /// A restarting replica resumes from the state it committed, which records how far
+    /// its log was applied, and only applies entries past that
+    pub fn new(id: NodeId, peers: Vec<NodeId>, mut protocol: BitStableProtocol, config: RaftConfig) -> Result<Self> {
+        let database = protocol.vault_manager.database()?;
+        let node_key = network::load_or_create_node_key(&database)?;
+        let restored = match database.load_config::<RaftMeta>(RAFT_STATE_KEY)? {
+            Some(meta) => {
+                let snapshot_index = meta.snapshot.as_ref().map_or(0, |snapshot| snapshot.last_index);
+                let log: Vec<LogEntry<ProtocolOp>> = database.load_raft_log()?;
+                Some(PersistentState {
+                    current_term: meta.current_term,
+                    voted_for: meta.voted_for,
+                    log: log.into_iter().filter(|entry| entry.index > snapshot_index).collect(),
+                    snapshot: meta.snapshot,
+                })
+            }
+            None => None,
+        };
+        let persisted_meta = restored.as_ref().map_or((0, None, 0), |state| {
+            (state.current_term, state.voted_for, state.snapshot.as_ref().map_or(0, |snapshot| snapshot.last_index))
+        });
+        let persisted_terms = restored.iter()
+            .flat_map(|state| state.log.iter().map(|entry| (entry.index, entry.term)))
+            .collect();
+
+        if restored.is_none() && !protocol.vault_manager.list_vaults().is_empty() {
+            return Err(BitStableError::InvalidConfig(
+                "A new replica must start from an empty vault database".to_string()
+            ));
+        }
+
+        let mut raft = RaftNode::new(id, peers, config, restored)?;
+        let applied = database.load_config::<AppliedState>(APPLIED_STATE_KEY)?;
+        let (applied_index, exchange_rates, tripped_currencies, governance_log) = match applied {
+            Some(applied) => {
+                raft.resume_applied(applied.last_applied)?;
+                (applied.last_applied, applied.exchange_rates, applied.tripped_currencies.into_iter().collect(), applied.governance_log)
+            }
+            None => (0, ExchangeRates::new(), HashSet::new(), Vec::new()),
+        };
+        protocol.set_exchange_rates(exchange_rates.clone());
+        // Breakers tripped by this machine's own oracles must not gate replicated ops
+        protocol.pin_circuit_breakers(tripped_currencies.clone());
+
+        Ok(Self {
+            raft,
+            protocol,
+            database,
+            node_key,
+            exchange_rates,
+            tripped_currencies,
+            governance_log,
+            applied_index,
+            persisted_meta,
+            persisted_terms,
+        })
+    }
+
+    /// Read-only view; all mutations go through `propose`
+    pub fn protocol(&self) -> &BitStableProtocol {
+        &self.protocol
+    }
+
+    pub fn raft(&self) -> &RaftNode<ProtocolOp> {
+        &self.raft
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.raft.leader() == Some(self.raft.id())
+    }
+
+    pub fn governance_log(&self) -> &[ExecutedProposal] {
+        &self.governance_log
+    }
+
+    pub fn tick(&mut self) -> Result<()> {
+        self.raft.tick();
+        self.persist()
+    }
+
+    pub fn step(&mut self, envelope: Envelope<ProtocolOp>) -> Result<()> {
+        self.raft.step(envelope);
+        self.persist()
+    }
+
+    /// Messages for other replicas. Call after `tick`, `step` or `propose`,
+    /// which persist any state the messages depend on.
+    pub fn take_messages(&mut self) -> Vec<Envelope<ProtocolOp>> {
+        self.raft.take_messages()
+    }
+
+    /// Append an operation to the log; fails with `NotLeader` on followers
+    pub fn propose(&mut self, op: ProtocolOp) -> Result<u64> {
+        let index = self.raft.propose(op)?;
+        self.persist()?;
+        Ok(index)
+    }
+
+    /// Propose a new vault, choosing its id and creation time here
+    pub fn open_vault(&mut self, owner: PublicKey, collateral: Amount, currency: Currency, amount: f64) -> Result<(Txid, u64)> {
+        let vault_id = self.protocol.vault_manager.generate_vault_id();
+        let index = self.propose(ProtocolOp::CreateVault {
+            vault_id,
+            owner,
+            collateral,
+            currency,
+            amount,
+            created_at: Utc::now(),
+        })?;
+        Ok((vault_id, index))
+    }
+
+    /// Propose liquidating a vault, timing it here
+    pub fn liquidate_vault(&mut self, vault_id: Txid, liquidator: PublicKey) -> Result<u64> {
+        self.propose(ProtocolOp::LiquidateVault { vault_id, liquidator, liquidated_at: Utc::now() })
+    }
+
+    /// Replicate the leader's oracle circuit breakers if they differ from the agreed ones
+    pub fn sync_circuit_breakers(&mut self) -> Result<Option<u64>> {
+        let tripped = self.protocol.oracle_network.get_circuit_breakers().tripped_currencies();
+        if tripped == self.tripped_currencies {
+            return Ok(None);
+        }
+        self.propose(ProtocolOp::SetCircuitBreakers { tripped: tripped.into_iter().collect() }).map(Some)
+    }
+
+    /// Propose executing a passed proposal. The leader decides its result, which every
+    /// replica carries out when applying it, audited under its own node key.
+    pub fn execute_proposal(&mut self, proposal_id: u64) -> Result<u64> {
+        if !self.is_leader() {
+            return Err(BitStableError::NotLeader(self.raft.leader()));
+        }
+        let executed_at = Utc::now();
+        let result = self.protocol.custody_manager.governance_system().proposal_outcome(proposal_id, executed_at)?;
+        self.propose(ProtocolOp::ExecuteGovernance { proposal_id, result, executed_at })
+    }
+
+    /// Apply everything committed since the last call, compacting the log when it grows
+    pub async fn apply_committed(&mut self) -> Result<Vec<ApplyOutcome>> {
+        let committed = self.raft.take_committed();
+        let applied_up_to = committed.last().map(|committed| match committed {
+            Committed::Snapshot(snapshot) => snapshot.last_index,
+            Committed::Entry(entry) => entry.index,
+        });
+        let mut outcomes = Vec::new();
+        for committed in committed {
+            match committed {
+                Committed::Snapshot(snapshot) => self.install_snapshot(&snapshot)?,
+                Committed::Entry(entry) => {
+                    let Some(op) = entry.command else { continue };
+                    // The operation's own commit records it as applied
+                    self.mark_applied(entry.index)?;
+                    self.protocol.events.begin();
+                    let result = self.apply(entry.index, op).await;
+                    if let Err(e) = &result {
+                        // A rejected operation changed nothing, so its events are dropped
+                        self.protocol.events.abandon();
+                        log::warn!("Replicated operation {} rejected: {}", entry.index, e);
+                    }
+                    outcomes.push(ApplyOutcome {
+                        index: entry.index,
+                        result: result.map_err(|e| e.to_string()),
+                    });
+                }
+            }
+        }
+        // Commit the events the applied operations emitted, with the subsystems they
+        // changed and the prices and breakers they agreed
+        if let Some(index) = applied_up_to {
+            self.mark_applied(index)?;
+            self.protocol.flush()?;
+        }
+
+        if self.raft.should_compact() {
+            let snapshot = StateSnapshot {
+                vaults: self.protocol.vault_manager.list_vaults().into_iter().cloned().collect(),
+                exchange_rates: self.exchange_rates.clone(),
+                tripped_currencies: self.tripped_currencies.iter().cloned().collect(),
+                governance_log: self.governance_log.clone(),
+                stable_balances: self.protocol.stable_manager.clone(),
+                escrow_contracts: self.protocol.custody_manager.escrow_contracts().cloned().collect(),
+                settlements: self.protocol.custody_manager.settlements().cloned().collect(),
+                liquidations: self.database.get_liquidation_history(None)?,
+                insurance_fund: self.protocol.insurance_fund.clone(),
+            };
+            self.raft.compact(serde_json::to_vec(&snapshot)?)?;
+            self.persist()?;
+        }
+        Ok(outcomes)
+    }
+
+    /// Route Raft envelopes arriving from the network into a channel for `receive`.
+    /// Replaces any handler already registered for them.
+    pub fn subscribe(network: &mut BitStableNetwork) -> mpsc::UnboundedReceiver<NetworkMessage> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        network.register_message_handler(MessageType::Replication, move |message| {
+            sender.send(message.clone())
+                .map_err(|_| BitStableError::PeerProtocolError("Replication stopped".to_string()))
+        });
+        receiver
+    }
+
+    /// Send queued envelopes straight to the replicas they address; returns how many
+    /// went out. Envelopes for unreachable replicas are dropped and Raft resends.
+    pub fn send_messages(&mut self, network: &BitStableNetwork, peers: &HashMap<NodeId, ReplicaPeer>) -> usize {
+        let mut sent = 0;
+        for envelope in self.take_messages() {
+            let Some(peer) = peers.get(&envelope.to) else {
+                log::warn!("No network address for replica {}", envelope.to);
+                continue;
+            };
+            let data = MessageData::Raft { envelope: Box::new(envelope) };
+            match network.send_direct(&peer.pubkey, MessageType::Replication, data) {
+                Ok(()) => sent += 1,
+                Err(e) => log::debug!("Couldn't reach replica {}: {}", peer.pubkey, e),
+            }
+        }
+        sent
+    }
+
+    /// Step an envelope received from the network. Its signer must be the node key of
+    /// the replica it claims to come from, and it must be addressed to this one.
+    pub fn receive(&mut self, message: &NetworkMessage, peers: &HashMap<NodeId, ReplicaPeer>) -> Result<()> {
+        let MessageData::Raft { envelope } = &message.data else {
+            return Ok(());
+        };
+        if envelope.to != self.raft.id() {
+            return Err(BitStableError::PeerProtocolError(format!(
+                "Envelope for replica {} sent to replica {}", envelope.to, self.raft.id()
+            )));
+        }
+        if peers.get(&envelope.from).map(|peer| peer.pubkey) != Some(message.sender) {
+            return Err(BitStableError::PeerProtocolError(format!(
+                "{} is not replica {}", message.sender, envelope.from
+            )));
+        }
+        self.step(envelope.as_ref().clone())
+    }
+
+    /// Run this replica over `network`: keep connections to the other replicas open,
+    /// tick Raft every `tick`, exchange envelopes and apply what commits
+    pub async fn run(
+        &mut self,
+        network: &mut BitStableNetwork,
+        peers: &HashMap<NodeId, ReplicaPeer>,
+        bind_address: &str,
+        tick: std::time::Duration,
+    ) -> Result<()> {
+        let mut inbox = Self::subscribe(network);
+        let local_address = network.listen(bind_address).await?;
+        log::info!("Replica {} started on {}", self.raft.id(), local_address);
+        Self::connect_peers(network, peers).await;
+
+        let mut ticks = tokio::time::interval(tick);
+        let mut maintenance = tokio::time::interval(std::time::Duration::from_secs(30));
+        loop {
+            tokio::select! {
+                result = network.process_next_event() => result?,
+                Some(message) = inbox.recv() => {
+                    if let Err(e) = self.receive(&message, peers) {
+                        log::warn!("Dropped replication message from {}: {}", message.sender, e);
+                    }
+                }
+                _ = ticks.tick() => self.tick()?,
+                _ = maintenance.tick() => {
+                    network.maintenance_cycle().await?;
+                    Self::connect_peers(network, peers).await;
+                }
+            }
+            self.send_messages(network, peers);
+            for outcome in self.apply_committed().await? {
+                log::debug!("Applied replicated operation {}", outcome.index);
+            }
+        }
+    }
+
+    /// Dial every replica not already connected
+    async fn connect_peers(network: &mut BitStableNetwork, peers: &HashMap<NodeId, ReplicaPeer>) {
+        let connected = network.connected_peers();
+        for (id, peer) in peers {
+            if connected.contains(&peer.pubkey) {
+                continue;
+            }
+            if let Err(e) = network.connect_to_peer(&peer.address, peer.pubkey).await {
+                log::warn!("Failed to connect to replica {} at {}: {}", id, peer.address, e);
+            }
+        }
+    }
+
+    /// Apply one operation through the protocol, which commits it with its balances,
+    /// escrow, settlement and insurance records. Transactions are built but left for
+    /// the embedding application to broadcast.
+    async fn apply(&mut self, index: u64, op: ProtocolOp) -> Result<()> {
+        let protocol = &mut self.protocol;
+        match op {
+            ProtocolOp::SetBtcPrice { currency, price } => {
+                if !price.is_finite() || price <= 0.0 {
+                    return Err(BitStableError::PriceFeedError(format!("Invalid BTC price {}", price)));
+                }
+                self.exchange_rates.update_btc_price(currency, price);
+                protocol.set_exchange_rates(self.exchange_rates.clone());
+                Ok(())
+            }
+            ProtocolOp::CreateVault { vault_id, owner, collateral, currency, amount, created_at } => {
+                protocol.open_vault_at(vault_id, owner, collateral, currency, amount, created_at).await.map(|_| ())
+            }
+            ProtocolOp::MintAdditional { vault_id, currency, amount } => {
+                protocol.mint_stable(vault_id, currency, amount).await
+            }
+            ProtocolOp::BurnStable { vault_id, currency, amount } => {
+                protocol.burn_stable(vault_id, currency, amount).await
+            }
+            ProtocolOp::CloseVault { vault_id, owner } => {
+                protocol.prepare_vault_closure(vault_id, owner).await.map(|_| ())
+            }
+            ProtocolOp::LiquidateVault { vault_id, liquidator, liquidated_at } => {
+                // Queue and size the liquidation from the replicated vaults and prices alone
+                let vaults: Vec<Vault> = protocol.vault_manager.list_vaults()
+                    .into_iter()
+                    .filter(|vault| vault.state == VaultState::Active)
+                    .cloned()
+                    .collect();
+                let btc_price = self.exchange_rates.get_btc_price(&Currency::USD).unwrap_or(0.0);
+                let collateral_usd: f64 = vaults.iter().map(|vault| vault.collateral_btc.to_btc() * btc_price).sum();
+                protocol.liquidation_engine.update_system_collateral(collateral_usd);
+                protocol.liquidation_engine.scan_for_liquidations(&vaults.iter().collect::<Vec<_>>(), &self.exchange_rates);
+                protocol.prepare_liquidation(vault_id, liquidator, liquidated_at).await.map(|_| ())
+            }
+            ProtocolOp::SetCircuitBreakers { tripped } => {
+                self.tripped_currencies = tripped.into_iter().collect();
+                protocol.pin_circuit_breakers(self.tripped_currencies.clone());
+                Ok(())
+            }
+            ProtocolOp::ExecuteGovernance { proposal_id, result, executed_at } => {
+                // The leader may have proposed it twice before the first applied
+                if self.governance_log.iter().any(|executed| executed.proposal_id == proposal_id) {
+                    return Err(BitStableError::InvalidConfig(format!("Proposal {} already executed", proposal_id)));
+                }
+                // Logged before it commits, so the commit records it
+                self.governance_log.push(ExecutedProposal { proposal_id, result: result.clone(), executed_at });
+                self.mark_applied(index)?;
+                let applied = self.protocol.apply_proposal_result(&self.node_key, proposal_id, result, executed_at);
+                if applied.is_err() {
+                    self.governance_log.pop();
+                }
+                applied
+            }
+        }
+    }
+
+    /// Record `index` as applied in every commit from now on, along with the prices,
+    /// breakers and governance results agreed so far
+    fn mark_applied(&mut self, index: u64) -> Result<()> {
+        self.applied_index = index;
+        self.protocol.mark_commits(APPLIED_STATE_KEY, &AppliedState {
+            last_applied: index,
+            exchange_rates: self.exchange_rates.clone(),
+            tripped_currencies: self.tripped_currencies.iter().cloned().collect(),
+            governance_log: self.governance_log.clone(),
+        })
+    }
+
+    fn install_snapshot(&mut self, installed: &Snapshot) -> Result<()> {
+        let snapshot: StateSnapshot = serde_json::from_slice(&installed.data)?;
+
+        // Proposals executed before the snapshot that this replica missed still apply
+        // here. Each commits before the snapshot is recorded as applied, so a restart
+        // in between installs the snapshot again but skips the proposals.
+        for executed in &snapshot.governance_log {
+            if self.governance_log.iter().any(|applied| applied.proposal_id == executed.proposal_id) {
+                continue;
+            }
+            self.governance_log.push(executed.clone());
+            self.mark_applied(self.applied_index)?;
+            if let Err(e) = self.protocol.apply_proposal_result(&self.node_key, executed.proposal_id, executed.result.clone(), executed.executed_at) {
+                self.governance_log.pop();
+                return Err(e);
+            }
+        }
+
+        // Store the records this replica missed along with the state replaced below
+        let mut work = UnitOfWork::new();
+        for liquidation in &snapshot.liquidations {
+            // Keyed by vault and time, so rewriting one already stored is harmless
+            work.put_liquidation(liquidation)?;
+        }
+        for settlement in &snapshot.settlements {
+            let stored = self.protocol.custody_manager.get_settlement(settlement.vault_id)
+                .is_some_and(|stored| stored.settled_at == settlement.settled_at);
+            if !stored {
+                work.put_settlement(settlement)?;
+            }
+        }
+        let contributions = &self.protocol.insurance_fund.contribution_history;
+        for contribution in &snapshot.insurance_fund.contribution_history {
+            let stored = contributions.iter()
+                .any(|stored| stored.timestamp == contribution.timestamp && stored.amount == contribution.amount);
+            if !stored {
+                work.put_insurance_contribution(contribution)?;
+            }
+        }
+
+        self.protocol.vault_manager.replace_all_vaults(snapshot.vaults)?;
+        self.protocol.stable_manager = snapshot.stable_balances;
+        self.protocol.custody_manager.replace_contracts(snapshot.escrow_contracts, snapshot.settlements);
+        self.protocol.insurance_fund = snapshot.insurance_fund;
+        self.exchange_rates = snapshot.exchange_rates;
+        self.protocol.set_exchange_rates(self.exchange_rates.clone());
+        self.tripped_currencies = snapshot.tripped_currencies.into_iter().collect();
+        self.protocol.pin_circuit_breakers(self.tripped_currencies.clone());
+        self.governance_log = snapshot.governance_log;
+        self.mark_applied(installed.last_index)?;
+        self.protocol.flush_with(work)?;
+        log::info!("Installed replicated snapshot at index {}", installed.last_index);
+        Ok(())
+    }
+
+    /// Write what changed since the last call: new or replaced entries, entries
+    /// truncated or compacted away, and the term, vote and snapshot when they move
+    fn persist(&mut self) -> Result<()> {
+        let Some(state) = self.raft.take_unpersisted() else {
+            return Ok(());
+        };
+        let meta = (state.current_term, state.voted_for, state.snapshot.as_ref().map_or(0, |snapshot| snapshot.last_index));
+        let changed_meta = (meta != self.persisted_meta).then(|| RaftMeta {
+            current_term: state.current_term,
+            voted_for: state.voted_for,
+            snapshot: state.snapshot.clone(),
+        });
+        // An index only ever holds a different entry under a different term
+        let written: Vec<(u64, &LogEntry<ProtocolOp>)> = state.log.iter()
+            .filter(|entry| self.persisted_terms.get(&entry.index) != Some(&entry.term))
+            .map(|entry| (entry.index, entry))
+            .collect();
+        let kept: HashSet<u64> = state.log.iter().map(|entry| entry.index).collect();
+        let removed: Vec<u64> = self.persisted_terms.keys()
+            .filter(|index| !kept.contains(index))
+            .copied()
+            .collect();
+
+        self.database.save_raft_log(RAFT_STATE_KEY, changed_meta.as_ref(), &written, &removed)?;
+        self.persisted_meta = meta;
+        self.persisted_terms = state.log.iter().map(|entry| (entry.index, entry.term)).collect();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ProtocolConfig, ProtocolEvent};
+    use crate::raft::RaftMessage;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+    fn replica(id: NodeId, dir: &tempfile::TempDir, config: RaftConfig) -> ReplicatedProtocol {
+        let mut protocol_config = ProtocolConfig::testnet();
+        protocol_config.database_path = dir.path().to_string_lossy().to_string();
+        let protocol = BitStableProtocol::new(protocol_config).unwrap();
+        let peers = (1..=3).filter(|peer| *peer != id).collect();
+        ReplicatedProtocol::new(id, peers, protocol, config).unwrap()
+    }
+
+    /// One tick on every replica, delivering messages except to or from `isolated`
+    async fn round(replicas: &mut [ReplicatedProtocol], isolated: Option<NodeId>) {
+        for replica in replicas.iter_mut() {
+            replica.tick().unwrap();
+        }
+        loop {
+            let envelopes: Vec<_> = replicas.iter_mut().flat_map(|replica| replica.take_messages()).collect();
+            if envelopes.is_empty() {
+                break;
+            }
+            for envelope in envelopes {
+                if isolated.is_some_and(|isolated| isolated == envelope.from || isolated == envelope.to) {
+                    continue;
+                }
+                replicas[envelope.to as usize - 1].step(envelope).unwrap();
+            }
+        }
+        for replica in replicas.iter_mut() {
+            replica.apply_committed().await.unwrap();
+        }
+    }
+
+    async fn elect_leader(replicas: &mut [ReplicatedProtocol]) -> usize {
+        for _ in 0..100 {
+            round(replicas, None).await;
+            if let Some(leader) = replicas.iter().position(|replica| replica.is_leader()) {
+                return leader;
+            }
+        }
+        panic!("no leader elected");
+    }
+
+    /// The vault as replicated, without the local apply-time stamp
+    fn vault_json(replica: &ReplicatedProtocol, vault_id: Txid) -> serde_json::Value {
+        let mut vault = serde_json::to_value(replica.protocol().vault_manager.get_vault(vault_id).unwrap()).unwrap();
+        vault["debts"].as_object_mut().unwrap().remove("last_updated");
+        vault
+    }
+
+    #[tokio::test]
+    async fn test_replicas_apply_the_same_log_and_catch_up_from_snapshots() {
+        let dirs: Vec<tempfile::TempDir> = (0..3).map(|_| tempfile::TempDir::new().unwrap()).collect();
+        let config = RaftConfig { snapshot_threshold: 5, ..RaftConfig::default() };
+        let mut replicas: Vec<ReplicatedProtocol> = (1..=3).map(|id| replica(id, &dirs[id as usize - 1], config.clone())).collect();
+        let leader = elect_leader(&mut replicas).await;
+        let lagging = (leader + 1) % 3;
+
+        let secp = Secp256k1::new();
+        let owner = PublicKey::new(SecretKey::from_slice(&[7; 32]).unwrap().public_key(&secp));
+        let liquidator = PublicKey::new(SecretKey::from_slice(&[8; 32]).unwrap().public_key(&secp));
+        replicas[leader].propose(ProtocolOp::SetBtcPrice { currency: Currency::USD, price: 100000.0 }).unwrap();
+        let (vault_id, _) = replicas[leader].open_vault(owner, Amount::from_btc(1.0).unwrap(), Currency::USD, 1000.0).unwrap();
+        // The liquidator's own vault funds the stable value they repay with
+        replicas[leader].open_vault(liquidator, Amount::from_btc(100.0).unwrap(), Currency::USD, 2000.0).unwrap();
+        assert!(matches!(
+            replicas[lagging].propose(ProtocolOp::CloseVault { vault_id, owner }),
+            Err(BitStableError::NotLeader(_))
+        ));
+        for _ in 0..5 {
+            round(&mut replicas, None).await;
+        }
+        let expected = vault_json(&replicas[leader], vault_id);
+        assert!(replicas.iter().all(|replica| vault_json(replica, vault_id) == expected));
+
+        // While one replica is cut off the log grows past the snapshot threshold
+        let lagging_id = lagging as NodeId + 1;
+        for _ in 0..6 {
+            replicas[leader].propose(ProtocolOp::MintAdditional { vault_id, currency: Currency::USD, amount: 100.0 }).unwrap();
+        }
+        // Rejected on every replica alike: the owner still has debt
+        replicas[leader].propose(ProtocolOp::CloseVault { vault_id, owner }).unwrap();
+        for _ in 0..5 {
+            round(&mut replicas, Some(lagging_id)).await;
+        }
+        assert!(replicas[leader].raft().snapshot_index() > replicas[lagging].raft().last_index());
+
+        for _ in 0..10 {
+            round(&mut replicas, None).await;
+        }
+        let expected = vault_json(&replicas[leader], vault_id);
+        assert_eq!(expected["debts"]["debts"]["USD"], 1600.0);
+        assert_eq!(vault_json(&replicas[lagging], vault_id), expected);
+        assert_eq!(replicas[lagging].raft().last_applied(), replicas[leader].raft().last_applied());
+
+        // Breakers gate minting on every replica once agreed through the log
+        replicas[leader].propose(ProtocolOp::SetCircuitBreakers { tripped: vec![Currency::USD] }).unwrap();
+        replicas[leader].propose(ProtocolOp::MintAdditional { vault_id, currency: Currency::USD, amount: 100.0 }).unwrap();
+        for _ in 0..5 {
+            round(&mut replicas, None).await;
+        }
+        assert!(replicas.iter().all(|replica| vault_json(replica, vault_id)["debts"]["debts"]["USD"] == 1600.0));
+
+        // Applied operations leave their events in the store; rejected ones leave none
+        let events: Vec<ProtocolEvent> = replicas[leader].protocol().events.replay(0).unwrap()
+            .into_iter()
+            .map(|record| record.event)
+            .collect();
+        assert!(matches!(events[0], ProtocolEvent::VaultCreated { vault_id: id, .. } if id == vault_id));
+        assert_eq!(events.iter().filter(|event| matches!(event, ProtocolEvent::StableMinted { .. })).count(), 6);
+        assert!(!events.iter().any(|event| matches!(event, ProtocolEvent::VaultClosed { .. })));
+
+        // Repaying burns the owner's balance; at 128% a quarter of the rest is liquidated
+        replicas[leader].propose(ProtocolOp::SetCircuitBreakers { tripped: Vec::new() }).unwrap();
+        replicas[leader].propose(ProtocolOp::BurnStable { vault_id, currency: Currency::USD, amount: 600.0 }).unwrap();
+        replicas[leader].propose(ProtocolOp::SetBtcPrice { currency: Currency::USD, price: 1280.0 }).unwrap();
+        replicas[leader].liquidate_vault(vault_id, liquidator).unwrap();
+        for _ in 0..5 {
+            round(&mut replicas, None).await;
+        }
+        let last = replicas[leader].protocol().events.replay(0).unwrap().pop().unwrap().event;
+        assert!(matches!(
+            last,
+            ProtocolEvent::VaultLiquidated { collateral_seized, debt_covered_usd, .. }
+                if collateral_seized > Amount::ZERO && debt_covered_usd == 250.0
+        ));
+
+        // Every replica, the one that caught up from a snapshot included, holds the same
+        // balances, liquidation records and insurance fund
+        let stored = |replica: &ReplicatedProtocol| {
+            let protocol = replica.protocol();
+            let settlement = protocol.custody_manager.get_settlement(vault_id).unwrap();
+            serde_json::json!({
+                "balances": [
+                    protocol.stable_manager.get_balance(owner, &Currency::USD),
+                    protocol.stable_manager.get_balance(liquidator, &Currency::USD),
+                ],
+                "liquidations": replica.database.get_liquidation_history(None).unwrap(),
+                "settled_at": settlement.settled_at,
+                "insurance_contributions": replica.database.get_insurance_contributions(usize::MAX).unwrap(),
+                "insurance_balance": protocol.insurance_fund.balance_btc,
+            })
+        };
+        let expected = stored(&replicas[leader]);
+        assert_eq!(expected["balances"], serde_json::json!([1000.0, 1750.0]));
+        assert_eq!(expected["liquidations"].as_array().unwrap().len(), 1);
+        assert_eq!(expected["insurance_contributions"].as_array().unwrap().len(), 1);
+        assert!(replicas.iter().all(|replica| stored(replica) == expected));
+        assert!(replicas.iter().all(|replica| vault_json(replica, vault_id)["debts"]["debts"]["USD"] == 750.0));
+
+        // Only entries past the snapshot stay on disk, and a restarted replica reloads them
+        let on_disk: Vec<LogEntry<ProtocolOp>> = replicas[lagging].database.load_raft_log().unwrap();
+        let last_index = replicas[lagging].raft().last_index();
+        let last_applied = replicas[lagging].raft().last_applied();
+        let events = replicas[lagging].protocol().events.replay(0).unwrap().len();
+        assert_eq!(on_disk.len() as u64, last_index - replicas[lagging].raft().snapshot_index());
+        drop(replicas.remove(lagging));
+        let restarted = replica(lagging_id, &dirs[lagging], config);
+        assert_eq!(restarted.raft().last_index(), last_index);
+
+        // It resumes from the state it committed instead of applying the log again
+        assert_eq!(restarted.raft().last_applied(), last_applied);
+        replicas.insert(lagging, restarted);
+        for _ in 0..5 {
+            round(&mut replicas, None).await;
+        }
+        assert_eq!(replicas[lagging].protocol().events.replay(0).unwrap().len(), events);
+        assert!(replicas.iter().all(|replica| stored(replica) == expected));
+        assert_eq!(vault_json(&replicas[lagging], vault_id), vault_json(&replicas[leader], vault_id));
+    }
+
+    #[tokio::test]
+    async fn test_governance_results_apply_on_every_replica() {
+        let dirs: Vec<tempfile::TempDir> = (0..3).map(|_| tempfile::TempDir::new().unwrap()).collect();
+        let config = RaftConfig { snapshot_threshold: 2, ..RaftConfig::default() };
+        let mut replicas: Vec<ReplicatedProtocol> = (1..=3).map(|id| replica(id, &dirs[id as usize - 1], config.clone())).collect();
+        let leader = elect_leader(&mut replicas).await;
+
+        // Followers never saw the proposal; the leader's result is enough
+        let op = ProtocolOp::ExecuteGovernance {
+            proposal_id: 7,
+            result: ExecutionResult::EmergencyShutdown { reason: "oracle compromise".to_string() },
+            executed_at: Utc::now(),
+        };
+        replicas[leader].propose(op.clone()).unwrap();
+        replicas[leader].propose(op).unwrap();
+        for _ in 0..5 {
+            round(&mut replicas, None).await;
+        }
+
+        for replica in &replicas {
+            let protocol = replica.protocol();
+            assert_ne!(protocol.emergency_system.shutdown_state, crate::ShutdownState::Normal);
+            let triggered = protocol.emergency_system.shutdown_history.iter()
+                .filter(|event| matches!(event.event_type, crate::emergency::ShutdownEventType::EmergencyShutdownTriggered))
+                .count();
+            assert_eq!(triggered, 1);
+            assert_eq!(replica.governance_log().len(), 1);
+            // One audit entry and one event per replica, the repeated proposal rejected
+            let entries = protocol.audit_log.entries().unwrap();
+            assert_eq!(entries.len(), 1);
+            assert!(matches!(entries[0].action, crate::audit::AuditAction::GovernanceExecuted { proposal_id: 7, .. }));
+            let executed = protocol.events.replay(0).unwrap().into_iter()
+                .filter(|record| matches!(record.event, ProtocolEvent::ProposalExecuted { proposal_id: 7, .. }))
+                .count();
+            assert_eq!(executed, 1);
+        }
+
+        // A restarted replica neither executes the proposal again nor re-emits its events
+        let counts = |replica: &ReplicatedProtocol| {
+            let protocol = replica.protocol();
+            (
+                protocol.events.replay(0).unwrap().len(),
+                protocol.audit_log.entries().unwrap().len(),
+                protocol.emergency_system.shutdown_history.len(),
+            )
+        };
+        let follower = (leader + 1) % 3;
+        let before = counts(&replicas[follower]);
+        drop(replicas.remove(follower));
+        replicas.insert(follower, replica(follower as NodeId + 1, &dirs[follower], config));
+        assert_eq!(replicas[follower].governance_log().len(), 1);
+        for _ in 0..5 {
+            round(&mut replicas, None).await;
+        }
+        assert_eq!(counts(&replicas[follower]), before);
+        assert!(replicas[follower].protocol().audit_log.verify().unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_replicas_exchange_envelopes_over_the_network() {
+        let dirs: Vec<_> = (0..3).map(|_| tempfile::tempdir().unwrap()).collect();
+        let mut replicas: Vec<ReplicatedProtocol> = (1..=3).map(|id| replica(id, &dirs[id as usize - 1], RaftConfig::default())).collect();
+        let mut networks = Vec::new();
+        let mut inboxes = Vec::new();
+        let mut peers = HashMap::new();
+        for (i, replica) in replicas.iter().enumerate() {
+            let mut network = BitStableNetwork::new(replica.node_key, 8, []);
+            inboxes.push(ReplicatedProtocol::subscribe(&mut network));
+            let address = network.listen("127.0.0.1:0").await.unwrap();
+            peers.insert(i as NodeId + 1, ReplicaPeer { pubkey: network.local_pubkey(), address: address.to_string() });
+            networks.push(network);
+        }
+        // Each replica dials the ones before it, so every pair shares one connection
+        for (i, network) in networks.iter_mut().enumerate() {
+            let earlier = peers.iter()
+                .filter(|(id, _)| **id <= i as NodeId)
+                .map(|(id, peer)| (*id, peer.clone()))
+                .collect();
+            ReplicatedProtocol::connect_peers(network, &earlier).await;
+        }
+
+        let mut proposed = false;
+        for _ in 0..500 {
+            for i in 0..3 {
+                replicas[i].tick().unwrap();
+                networks[i].process_pending_events().unwrap();
+                while let Ok(message) = inboxes[i].try_recv() {
+                    replicas[i].receive(&message, &peers).unwrap();
+                }
+                replicas[i].send_messages(&networks[i], &peers);
+                replicas[i].apply_committed().await.unwrap();
+            }
+            if !proposed {
+                if let Some(leader) = replicas.iter().position(|replica| replica.is_leader()) {
+                    replicas[leader].propose(ProtocolOp::SetBtcPrice { currency: Currency::USD, price: 100000.0 }).unwrap();
+                    proposed = true;
+                }
+            }
+            if replicas.iter().all(|replica| replica.exchange_rates.get_btc_price(&Currency::USD) == Some(100000.0)) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        assert!(replicas.iter().all(|replica| replica.exchange_rates.get_btc_price(&Currency::USD) == Some(100000.0)));
+
+        // An envelope signed by anyone but the replica it names is refused
+        let forged = NetworkMessage::new(
+            MessageType::Replication,
+            peers[&3].pubkey,
+            MessageData::Raft {
+                envelope: Box::new(Envelope { from: 2, to: 1, message: RaftMessage::VoteResponse { term: 0, granted: true } }),
+            },
+            1,
+        );
+        assert!(replicas[0].receive(&forged, &peers).is_err());
+    }
+}
@@ -5,6 +5,12 @@ use chrono::{DateTime, Utc, Duration};
 use crate::{Result, ProtocolConfig};
 use crate::multi_currency::{Currency, ExchangeRates};
 use crate::{Vault, VaultManager, Oracle};
+use crate::oracle::PegPrice;
+use crate::candles::{self, CandleInterval};
+use crate::database::DatabaseManager;
+
+/// Most raised alerts kept in `risk_alerts`
+const MAX_STORED_ALERTS: usize = 1000;
 
 /// Advanced risk metrics and monitoring system
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +23,7 @@ pub struct RiskMetricsSystem {
     pub correlation_matrices: HashMap<String, CorrelationMatrix>,
     pub value_at_risk: ValueAtRiskMetrics,
     pub last_update: DateTime<Utc>,
+    pub peg_status: HashMap<Currency, PegStatus>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -105,6 +112,20 @@ pub struct AlertThresholds {
     pub concentration_warning: f64,      // 0.3 (30% in single vault)
     pub volatility_warning: f64,        // 80% annualized
     pub var_breach_threshold: f64,      // VaR breach significance
+    pub depeg_warning_band: f64,        // 0.01 (stable unit 1% off peg)
+    pub depeg_critical_band: f64,       // 0.03
+    pub depeg_sustained_minutes: i64,   // How long outside the band before alerting
+}
+
+/// Tracks how long a stable unit has traded outside its peg band
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PegStatus {
+    pub price: f64,
+    pub deviation: f64,
+    pub outside_band_since: Option<DateTime<Utc>>,
+    pub last_update: DateTime<Utc>,
+    #[serde(default)]
+    pub alerted: Option<AlertSeverity>,  // Severity last alerted for this depeg, if any
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -154,9 +175,10 @@ pub enum RiskAlertType {
     VaRBreach,
     TailRisk,
     CorrelationBreakdown,
+    StablecoinDepeg,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AlertSeverity {
     Info,
     Warning,
@@ -209,6 +231,9 @@ impl RiskMetricsSystem {
                 concentration_warning: 0.3,
                 volatility_warning: 0.8,
                 var_breach_threshold: 0.05,
+                depeg_warning_band: 0.01,
+                depeg_critical_band: 0.03,
+                depeg_sustained_minutes: 15,
             },
             monitoring_frequency_minutes: 5,
         };
@@ -222,7 +247,88 @@ impl RiskMetricsSystem {
             correlation_matrices: HashMap::new(),
             value_at_risk: ValueAtRiskMetrics::default(),
             last_update: Utc::now(),
+            peg_status: HashMap::new(),
+        }
+    }
+
+    /// Update peg tracking and alert on stable units that stay outside the band
+    pub fn check_peg_deviation(
+        &mut self,
+        peg_prices: &HashMap<Currency, PegPrice>,
+        now: DateTime<Utc>,
+    ) -> Vec<RiskAlert> {
+        let thresholds = self.risk_config.alert_thresholds.clone();
+        let mut alerts = Vec::new();
+
+        for (currency, peg_price) in peg_prices {
+            let deviation = peg_price.deviation();
+            let status = self.peg_status.entry(currency.clone()).or_insert(PegStatus {
+                price: peg_price.price,
+                deviation,
+                outside_band_since: None,
+                last_update: now,
+                alerted: None,
+            });
+
+            status.price = peg_price.price;
+            status.deviation = deviation;
+            status.last_update = now;
+
+            if deviation <= thresholds.depeg_warning_band {
+                status.outside_band_since = None;
+                status.alerted = None;
+                continue;
+            }
+
+            let since = *status.outside_band_since.get_or_insert(now);
+            if now.signed_duration_since(since) < Duration::minutes(thresholds.depeg_sustained_minutes) {
+                continue;
+            }
+
+            let (severity, threshold) = if deviation > thresholds.depeg_critical_band {
+                (AlertSeverity::Critical, thresholds.depeg_critical_band)
+            } else {
+                (AlertSeverity::Warning, thresholds.depeg_warning_band)
+            };
+            // Only a new depeg or a change in severity is worth another alert
+            if status.alerted.as_ref() == Some(&severity) {
+                continue;
+            }
+            status.alerted = Some(severity.clone());
+
+            alerts.push(RiskAlert {
+                alert_type: RiskAlertType::StablecoinDepeg,
+                severity,
+                message: format!("{} stable unit at {:.4}, {:.2}% off peg since {}",
+                               currency.to_string(), peg_price.price, deviation * 100.0,
+                               since.format("%H:%M:%S")),
+                metric_value: deviation,
+                threshold,
+                timestamp: now,
+                acknowledged: false,
+                auto_resolved: false,
+            });
         }
+
+        self.record_alerts(&alerts);
+        alerts
+    }
+
+    /// Store raised alerts, dropping the oldest past `MAX_STORED_ALERTS`
+    fn record_alerts(&mut self, alerts: &[RiskAlert]) {
+        self.risk_alerts.extend(alerts.iter().cloned());
+        let excess = self.risk_alerts.len().saturating_sub(MAX_STORED_ALERTS);
+        self.risk_alerts.drain(..excess);
+    }
+
+    /// Largest peg deviation that has lasted past the sustained window, for shutdown triggers
+    pub fn sustained_peg_deviation(&self, now: DateTime<Utc>) -> f64 {
+        let window = Duration::minutes(self.risk_config.alert_thresholds.depeg_sustained_minutes);
+        self.peg_status.values()
+            .filter(|status| status.outside_band_since
+                .is_some_and(|since| now.signed_duration_since(since) >= window))
+            .map(|status| status.deviation)
+            .fold(0.0, f64::max)
     }
 
     /// Update all risk metrics
@@ -264,6 +370,21 @@ impl RiskMetricsSystem {
         Ok(new_alerts)
     }
 
+    /// Update risk metrics from stored daily BTC/USD candles rather than a caller-held history
+    pub fn update_risk_metrics_from_candles(
+        &mut self,
+        vault_manager: &VaultManager,
+        exchange_rates: &ExchangeRates,
+        oracle_network: &[Oracle],
+        database: &DatabaseManager,
+    ) -> Result<Vec<RiskAlert>> {
+        let now = Utc::now();
+        let from = now - Duration::days(365);
+        let price_history = candles::close_series(database, &Currency::USD, CandleInterval::Day, from, now)?;
+
+        self.update_risk_metrics(vault_manager, exchange_rates, oracle_network, &price_history)
+    }
+
     /// Calculate comprehensive system risk metrics
     fn calculate_system_metrics(
         &self,
@@ -726,15 +847,7 @@ impl RiskMetricsSystem {
             });
         }
 
-        // Store alerts
-        for alert in &alerts {
-            self.risk_alerts.push(alert.clone());
-        }
-
-        // Keep only recent alerts (last 1000)
-        if self.risk_alerts.len() > 1000 {
-            self.risk_alerts.truncate(1000);
-        }
+        self.record_alerts(&alerts);
 
         Ok(alerts)
     }
@@ -1038,6 +1151,47 @@ mod tests {
         assert!(volatility < 10.0); // Reasonable range
     }
 
+    #[test]
+    fn test_sustained_depeg_alert() {
+        let config = ProtocolConfig::testnet();
+        let mut risk_system = RiskMetricsSystem::new(&config);
+        let start = Utc::now();
+
+        let peg = |price: f64, at: DateTime<Utc>| {
+            let mut prices = HashMap::new();
+            prices.insert(Currency::EUR, PegPrice {
+                currency: Currency::EUR,
+                price,
+                source: "dex".to_string(),
+                timestamp: at,
+            });
+            prices
+        };
+
+        // A brief wobble outside the band is not yet an alert
+        assert!(risk_system.check_peg_deviation(&peg(0.96, start), start).is_empty());
+        let later = start + Duration::minutes(5);
+        assert!(risk_system.check_peg_deviation(&peg(0.995, later), later).is_empty());
+        assert!(risk_system.peg_status[&Currency::EUR].outside_band_since.is_none());
+
+        // Staying 4% below peg for the full window raises a critical alert
+        let later = start + Duration::minutes(10);
+        risk_system.check_peg_deviation(&peg(0.96, later), later);
+        let later = start + Duration::minutes(26);
+        let alerts = risk_system.check_peg_deviation(&peg(0.96, later), later);
+        assert_eq!(alerts.len(), 1);
+        assert!(matches!(alerts[0].severity, AlertSeverity::Critical));
+        assert!((risk_system.sustained_peg_deviation(later) - 0.04).abs() < 1e-9);
+
+        // Holding the same depeg does not repeat the alert, a change in severity does
+        let later = start + Duration::minutes(27);
+        assert!(risk_system.check_peg_deviation(&peg(0.96, later), later).is_empty());
+        let later = start + Duration::minutes(28);
+        let alerts = risk_system.check_peg_deviation(&peg(0.985, later), later);
+        assert!(matches!(alerts[0].severity, AlertSeverity::Warning));
+        assert_eq!(risk_system.risk_alerts.len(), 2);
+    }
+
     #[test]
     fn test_overall_risk_score() {
         let config = ProtocolConfig::testnet();
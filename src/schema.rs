@@ -0,0 +1,508 @@
+//! Database schema versioning
+//! Versioned record envelopes, per-record upgrades and the migrations run when a database is opened
+
+use std::collections::BTreeSet;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use crate::{BitStableError, Result, Vault};
+use crate::audit::{AuditAnchor, AuditEntry};
+use crate::custody::{EscrowContract, LiquidationSettlement};
+use crate::database::{self, LiquidationRecord, OraclePriceRecord, Subsystem};
+use crate::emergency::EmergencyShutdownSystem;
+use crate::events::EventRecord;
+use crate::governance::GovernanceSystem;
+use crate::insurance::{InsuranceContribution, InsuranceFund};
+use crate::redemption::{RedemptionRecord, RedemptionState};
+use crate::stability_pool::StabilityPool;
+use crate::stable::MultiCurrencyStableManager;
+use crate::storage::{KeyRange, Storage, Write, DEFAULT_TREE};
+
+/// Version of the database layout as a whole, stored in the config tree
+pub const SCHEMA_VERSION: u32 = 3;
+pub const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Version 1 databases stored bare JSON records with no version tag
+const LEGACY_SCHEMA_VERSION: u32 = 1;
+
+/// Trees holding versioned records; the default tree is VaultManager's live vault store
+const VAULT_TREES: [&str; 2] = [DEFAULT_TREE, "vaults"];
+const LIQUIDATIONS_TREE: &str = "liquidations";
+const ORACLE_PRICES_TREE: &str = "oracle_prices";
+const SETTLEMENTS_TREE: &str = "settlements";
+const REDEMPTIONS_TREE: &str = "redemptions";
+const INSURANCE_CONTRIBUTIONS_TREE: &str = "insurance_contributions";
+const ESCROW_CONTRACTS_TREE: &str = "escrow_contracts";
+const CONFIG_TREE: &str = "config";
+const AUDIT_LOG_TREE: &str = "audit_log";
+const AUDIT_ANCHORS_TREE: &str = "audit_anchors";
+const EVENTS_TREE: &str = "protocol_events";
+
+/// A record type stored inside a versioned envelope
+pub trait Versioned: Serialize + DeserializeOwned {
+    const KIND: &'static str;
+    const VERSION: u32;
+
+    /// Rewrite a record stored at `version` into the shape of `version + 1`
+    fn upgrade(version: u32, _record: Value) -> Result<Value> {
+        Err(BitStableError::InvalidConfig(format!("No upgrade for {} record version {}", Self::KIND, version)))
+    }
+}
+
+impl Versioned for Vault {
+    const KIND: &'static str = "vault";
+    const VERSION: u32 = 2;
+
+    /// Version 1 vaults carried a single `stable_debt_usd` instead of per-currency `debts`
+    fn upgrade(version: u32, mut record: Value) -> Result<Value> {
+        if version != 1 {
+            return Err(BitStableError::InvalidConfig(format!("No upgrade for vault record version {}", version)));
+        }
+        let fields = record.as_object_mut()
+            .ok_or_else(|| BitStableError::InvalidConfig("Vault record is not an object".to_string()))?;
+        if let Some(debt) = fields.remove("stable_debt_usd") {
+            let debt = debt.as_f64()
+                .ok_or_else(|| BitStableError::InvalidConfig("Vault stable_debt_usd is not a number".to_string()))?;
+            let mut debts = serde_json::Map::new();
+            if debt > 0.0 {
+                debts.insert("USD".to_string(), debt.into());
+            }
+            let last_updated = fields.get("last_fee_update").cloned().unwrap_or(Value::Null);
+            fields.insert("debts".to_string(), serde_json::json!({ "debts": debts, "last_updated": last_updated }));
+        }
+        Ok(record)
+    }
+}
+
+impl Versioned for LiquidationRecord {
+    const KIND: &'static str = "liquidation";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for OraclePriceRecord {
+    const KIND: &'static str = "oracle price";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for LiquidationSettlement {
+    const KIND: &'static str = "settlement";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for RedemptionRecord {
+    const KIND: &'static str = "redemption";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for InsuranceContribution {
+    const KIND: &'static str = "insurance contribution";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for EscrowContract {
+    const KIND: &'static str = "escrow contract";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for GovernanceSystem {
+    const KIND: &'static str = "governance state";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for InsuranceFund {
+    const KIND: &'static str = "insurance fund state";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for StabilityPool {
+    const KIND: &'static str = "stability pool state";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for EmergencyShutdownSystem {
+    const KIND: &'static str = "emergency shutdown state";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for RedemptionState {
+    const KIND: &'static str = "redemption engine state";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for MultiCurrencyStableManager {
+    const KIND: &'static str = "stable balances";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for AuditEntry {
+    const KIND: &'static str = "audit entry";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for AuditAnchor {
+    const KIND: &'static str = "audit anchor";
+    const VERSION: u32 = 1;
+}
+
+impl Versioned for EventRecord {
+    const KIND: &'static str = "protocol event";
+    const VERSION: u32 = 1;
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordEnvelope<T> {
+    schema: u32,
+    record: T,
+}
+
+pub fn encode_record<T: Versioned>(record: &T) -> Result<Vec<u8>> {
+    serde_json::to_vec(&RecordEnvelope { schema: T::VERSION, record })
+        .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize {}: {}", T::KIND, e)))
+}
+
+/// Decode an enveloped or legacy bare record, upgrading it to the current version
+pub fn decode_record<T: Versioned>(bytes: &[u8]) -> Result<T> {
+    let value: Value = serde_json::from_slice(bytes)
+        .map_err(|e| BitStableError::InvalidConfig(format!("Failed to deserialize {}: {}", T::KIND, e)))?;
+    let (mut version, mut record) = split_envelope(value);
+
+    if version > T::VERSION {
+        return Err(BitStableError::InvalidConfig(format!(
+            "{} record version {} is newer than supported version {}", T::KIND, version, T::VERSION
+        )));
+    }
+    while version < T::VERSION {
+        record = T::upgrade(version, record)?;
+        version += 1;
+    }
+
+    serde_json::from_value(record)
+        .map_err(|e| BitStableError::InvalidConfig(format!("Failed to deserialize {}: {}", T::KIND, e)))
+}
+
+/// Legacy records have no envelope and count as version 1
+fn split_envelope(value: Value) -> (u32, Value) {
+    if let Value::Object(mut fields) = value {
+        let version = fields.get("schema").and_then(Value::as_u64);
+        if let (2, Some(version)) = (fields.len(), version) {
+            if let Some(record) = fields.remove("record") {
+                return (version as u32, record);
+            }
+        }
+        return (1, Value::Object(fields));
+    }
+    (1, value)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationMode {
+    Apply,
+    DryRun,   // Check every record would migrate, writing nothing
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStep {
+    pub version: u32,
+    pub description: String,
+    pub records: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub steps: Vec<MigrationStep>,
+    pub applied: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyReport {
+    pub schema_version: u32,
+    pub records_checked: usize,
+    pub errors: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.schema_version == SCHEMA_VERSION && self.errors.is_empty()
+    }
+}
+
+struct Migration {
+    version: u32,   // Schema version after this migration
+    description: &'static str,
+    run: fn(&dyn Storage, MigrationMode) -> Result<usize>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 2,
+        description: "Wrap vault, liquidation and oracle price records in versioned envelopes",
+        run: envelope_records,
+    },
+    Migration {
+        version: 3,
+        description: "Fold the legacy vaults tree into the live store and index vaults by owner, state and liquidation price",
+        run: index_vaults,
+    },
+];
+
+/// Bring the database up to `SCHEMA_VERSION`, or report what that would do
+pub fn migrate(db: &dyn Storage, mode: MigrationMode) -> Result<MigrationReport> {
+    let (from_version, stored) = stored_version(db)?;
+    if from_version > SCHEMA_VERSION {
+        return Err(BitStableError::InvalidConfig(format!(
+            "Database schema version {} is newer than this build supports ({})", from_version, SCHEMA_VERSION
+        )));
+    }
+
+    let mut report = MigrationReport {
+        from_version,
+        to_version: SCHEMA_VERSION,
+        steps: Vec::new(),
+        applied: mode == MigrationMode::Apply,
+    };
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > from_version) {
+        let records = (migration.run)(db, mode)?;
+        if mode == MigrationMode::Apply {
+            set_version(db, migration.version)?;
+            log::info!("Migrated database to schema {} ({} records): {}", migration.version, records, migration.description);
+        }
+        report.steps.push(MigrationStep {
+            version: migration.version,
+            description: migration.description.to_string(),
+            records,
+        });
+    }
+
+    if mode == MigrationMode::Apply && !stored {
+        set_version(db, SCHEMA_VERSION)?;
+    }
+    Ok(report)
+}
+
+/// Decode every versioned record, collecting failures instead of stopping at the first
+pub fn verify(db: &dyn Storage) -> Result<VerifyReport> {
+    let mut report = VerifyReport {
+        schema_version: stored_version(db)?.0,
+        records_checked: 0,
+        errors: Vec::new(),
+    };
+    if report.schema_version != SCHEMA_VERSION {
+        report.errors.push(format!(
+            "Schema version {} needs migrating to {}", report.schema_version, SCHEMA_VERSION
+        ));
+    }
+
+    for name in VAULT_TREES {
+        check_tree::<Vault>(db, name, &mut report)?;
+    }
+    check_tree::<LiquidationRecord>(db, LIQUIDATIONS_TREE, &mut report)?;
+    check_tree::<OraclePriceRecord>(db, ORACLE_PRICES_TREE, &mut report)?;
+    check_tree::<LiquidationSettlement>(db, SETTLEMENTS_TREE, &mut report)?;
+    check_tree::<RedemptionRecord>(db, REDEMPTIONS_TREE, &mut report)?;
+    check_tree::<InsuranceContribution>(db, INSURANCE_CONTRIBUTIONS_TREE, &mut report)?;
+    check_tree::<EscrowContract>(db, ESCROW_CONTRACTS_TREE, &mut report)?;
+    check_tree::<GovernanceSystem>(db, Subsystem::Governance.tree(), &mut report)?;
+    check_tree::<InsuranceFund>(db, Subsystem::InsuranceFund.tree(), &mut report)?;
+    check_tree::<StabilityPool>(db, Subsystem::StabilityPool.tree(), &mut report)?;
+    check_tree::<EmergencyShutdownSystem>(db, Subsystem::EmergencyShutdown.tree(), &mut report)?;
+    check_tree::<RedemptionState>(db, Subsystem::RedemptionEngine.tree(), &mut report)?;
+    check_tree::<MultiCurrencyStableManager>(db, Subsystem::StableBalances.tree(), &mut report)?;
+    check_tree::<AuditEntry>(db, AUDIT_LOG_TREE, &mut report)?;
+    check_tree::<AuditAnchor>(db, AUDIT_ANCHORS_TREE, &mut report)?;
+    check_tree::<EventRecord>(db, EVENTS_TREE, &mut report)?;
+    Ok(report)
+}
+
+fn check_tree<T: Versioned>(db: &dyn Storage, name: &str, report: &mut VerifyReport) -> Result<()> {
+    for (key, value) in records::<T>(db, name)? {
+        report.records_checked += 1;
+        if let Err(e) = decode_record::<T>(&value) {
+            report.errors.push(format!("{}/{}: {}", tree_label(name), String::from_utf8_lossy(&key), e));
+        }
+    }
+    Ok(())
+}
+
+/// The schema version the database is at
+pub fn schema_version(db: &dyn Storage) -> Result<u32> {
+    Ok(stored_version(db)?.0)
+}
+
+/// The schema version and whether it was recorded. Unversioned databases with data
+/// are version 1; empty ones start at the current version.
+fn stored_version(db: &dyn Storage) -> Result<(u32, bool)> {
+    let stored = db.get(CONFIG_TREE, SCHEMA_VERSION_KEY.as_bytes())
+        .map_err(|e| BitStableError::InvalidConfig(format!("Failed to read schema version: {}", e)))?;
+    if let Some(bytes) = stored {
+        let version = serde_json::from_slice(&bytes)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to deserialize schema version: {}", e)))?;
+        return Ok((version, true));
+    }
+
+    let mut names: Vec<&str> = VAULT_TREES.to_vec();
+    names.extend([LIQUIDATIONS_TREE, ORACLE_PRICES_TREE]);
+    for name in names {
+        if !db.is_empty(name)? {
+            return Ok((LEGACY_SCHEMA_VERSION, false));
+        }
+    }
+    Ok((SCHEMA_VERSION, false))
+}
+
+fn set_version(db: &dyn Storage, version: u32) -> Result<()> {
+    let value = serde_json::to_vec(&version)
+        .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize schema version: {}", e)))?;
+    db.insert(CONFIG_TREE, SCHEMA_VERSION_KEY.as_bytes(), &value)
+        .map_err(|e| BitStableError::InvalidConfig(format!("Failed to save schema version: {}", e)))?;
+    db.flush()
+        .map_err(|e| BitStableError::InvalidConfig(format!("Failed to flush database: {}", e)))?;
+    Ok(())
+}
+
+fn envelope_records(db: &dyn Storage, mode: MigrationMode) -> Result<usize> {
+    // Convert everything before writing anything, so a bad record leaves the database untouched
+    let mut writes = Vec::new();
+    for name in VAULT_TREES {
+        writes.extend(rewrite_records::<Vault>(db, name)?);
+    }
+    writes.extend(rewrite_records::<LiquidationRecord>(db, LIQUIDATIONS_TREE)?);
+    writes.extend(rewrite_records::<OraclePriceRecord>(db, ORACLE_PRICES_TREE)?);
+
+    if mode == MigrationMode::Apply {
+        db.apply(&writes)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to write migrated records: {}", e)))?;
+    }
+    Ok(writes.len())
+}
+
+fn index_vaults(db: &dyn Storage, mode: MigrationMode) -> Result<usize> {
+    // The live tree goes first, so its copy wins over a legacy record with the same id
+    let mut writes = Vec::new();
+    let mut indexed = BTreeSet::new();
+    for name in VAULT_TREES {
+        for (key, value) in records::<Vault>(db, name)? {
+            if name != DEFAULT_TREE {
+                writes.push(Write::remove(name, key.clone()));
+            }
+            if !indexed.insert(key.clone()) {
+                continue;
+            }
+            let vault: Vault = decode_record(&value).map_err(|e| BitStableError::InvalidConfig(format!(
+                "Cannot index vault {}/{}: {}", tree_label(name), String::from_utf8_lossy(&key), e
+            )))?;
+            if name != DEFAULT_TREE {
+                writes.push(Write::insert(DEFAULT_TREE, key, encode_record(&vault)?));
+            }
+            writes.extend(database::vault_index_writes(None, Some(&vault)));
+        }
+    }
+
+    if mode == MigrationMode::Apply {
+        db.apply(&writes)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to write vault indexes: {}", e)))?;
+    }
+    Ok(indexed.len())
+}
+
+fn rewrite_records<T: Versioned>(db: &dyn Storage, name: &str) -> Result<Vec<Write>> {
+    let mut writes = Vec::new();
+    for (key, value) in records::<T>(db, name)? {
+        let record: T = decode_record(&value).map_err(|e| BitStableError::InvalidConfig(format!(
+            "Cannot migrate {}/{}: {}", tree_label(name), String::from_utf8_lossy(&key), e
+        )))?;
+        let value = encode_record(&record)?;
+        writes.push(Write::insert(name, key, value));
+    }
+    Ok(writes)
+}
+
+fn records<T: Versioned>(db: &dyn Storage, name: &str) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    db.scan(name, &KeyRange::all(), false, None)
+        .map_err(|e| BitStableError::InvalidConfig(format!("Failed to iterate {} records: {}", T::KIND, e)))
+}
+
+/// The default tree has an empty name
+fn tree_label(name: &str) -> &str {
+    if name == DEFAULT_TREE { "default" } else { name }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DatabaseManager;
+    use crate::multi_currency::Currency;
+    use crate::storage::SledStorage;
+
+    const VAULT_ID: &str = "0000000000000000000000000000000000000000000000000000000000000001";
+    const LEGACY_ONLY_ID: &str = "0000000000000000000000000000000000000000000000000000000000000002";
+
+    fn legacy_vault() -> Value {
+        serde_json::json!({
+            "id": VAULT_ID,
+            "owner": "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            "collateral_btc": 100000000u64,
+            "stable_debt_usd": 25000.0,
+            "created_at": "2024-01-01T00:00:00Z",
+            "last_fee_update": "2024-02-01T00:00:00Z",
+            "state": "Active",
+        })
+    }
+
+    #[test]
+    fn test_legacy_database_migrates_on_open() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let legacy_bytes = serde_json::to_vec(&legacy_vault()).unwrap();
+        {
+            let db = sled::open(temp_dir.path()).unwrap();
+            db.insert(VAULT_ID, legacy_bytes.clone()).unwrap();
+            db.open_tree("vaults").unwrap().insert(VAULT_ID, legacy_bytes.clone()).unwrap();
+            let mut only_legacy = legacy_vault();
+            only_legacy["id"] = LEGACY_ONLY_ID.into();
+            db.open_tree("vaults").unwrap().insert(LEGACY_ONLY_ID, serde_json::to_vec(&only_legacy).unwrap()).unwrap();
+            db.flush().unwrap();
+        }
+
+        let db = DatabaseManager::open_unmigrated(&temp_dir.path().to_string_lossy()).unwrap();
+        let report = db.verify().unwrap();
+        assert_eq!(report.schema_version, 1);
+        assert!(!report.is_ok());
+
+        // A dry run reports the work without touching the records
+        let report = db.migrate(MigrationMode::DryRun).unwrap();
+        assert_eq!((report.from_version, report.steps.len(), report.steps[0].records), (1, 2, 3));
+        assert_eq!(report.steps[1].records, 2);
+        assert_eq!(db.verify().unwrap().schema_version, 1);
+        drop(db);
+
+        let db = DatabaseManager::new(temp_dir.path()).unwrap();
+        let vault = db.load_vault(VAULT_ID.parse().unwrap()).unwrap();
+        assert_eq!(vault.debts.get_debt(&Currency::USD), 25000.0);
+        assert_eq!(vault.debts.last_updated, vault.last_fee_update);
+
+        // The legacy-only vault now lives in the indexed store, and the legacy tree is empty
+        let owner = vault.owner;
+        let owned: Vec<String> = db.get_vaults_by_owner(&owner).unwrap().iter().map(|v| v.id.to_string()).collect();
+        assert_eq!(owned.len(), 2);
+        assert!(owned.contains(&LEGACY_ONLY_ID.to_string()));
+        assert_eq!(db.list_vaults().unwrap().len(), 2);
+
+        let report = db.verify().unwrap();
+        assert!(report.is_ok(), "{:?}", report.errors);
+        assert_eq!(report.records_checked, 2);
+        assert!(db.migrate(MigrationMode::Apply).unwrap().steps.is_empty());
+    }
+
+    #[test]
+    fn test_newer_records_and_schemas_are_refused() {
+        let mut vault = legacy_vault();
+        vault = Vault::upgrade(1, vault).unwrap();
+        let newer = serde_json::to_vec(&serde_json::json!({ "schema": 3, "record": vault })).unwrap();
+        assert!(decode_record::<Vault>(&newer).is_err());
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db = sled::open(temp_dir.path()).unwrap();
+        db.open_tree("config").unwrap().insert(SCHEMA_VERSION_KEY, serde_json::to_vec(&(SCHEMA_VERSION + 1)).unwrap()).unwrap();
+        assert!(migrate(&SledStorage::new(db), MigrationMode::Apply).is_err());
+    }
+}
@@ -0,0 +1,500 @@
+//! In-process multi-node network simulation
+//! Runs N `BitStableNetwork` nodes over in-memory links with latency, loss and partitions
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use bitcoin::{Amount, PublicKey, Txid};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use crate::{BitStableError, Currency, Result};
+use crate::crypto::OracleKeyManager;
+use crate::network::{BitStableNetwork, MessageData, MessageType, ServiceType};
+use crate::wire;
+
+const LINK_BUFFER: usize = 64 * 1024;
+
+/// Links behave like TCP: bytes are never dropped or reordered, so a lost
+/// segment shows up as a retransmission delay on everything behind it.
+#[derive(Debug, Clone)]
+pub struct LinkProfile {
+    pub latency: Duration,
+    pub jitter: Duration,              // Uniform extra delay per segment
+    pub loss_rate: f64,                // Chance each transmission of a segment is lost
+    pub retransmit_timeout: Duration,  // Added per lost transmission
+}
+
+impl Default for LinkProfile {
+    fn default() -> Self {
+        Self {
+            latency: Duration::from_millis(5),
+            jitter: Duration::ZERO,
+            loss_rate: 0.0,
+            retransmit_timeout: Duration::from_millis(40),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+    pub link: LinkProfile,
+    pub seed: u64,                     // Drives topology, vault ids and link loss
+    pub liquidation_threshold: f64,    // Collateral ratio below which liquidators raise alerts
+    pub max_connections: usize,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            link: LinkProfile::default(),
+            seed: 0,
+            liquidation_threshold: 1.1,
+            max_connections: 16,
+        }
+    }
+}
+
+impl SimulationConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !(0.0..1.0).contains(&self.link.loss_rate) {
+            return Err(BitStableError::InvalidConfig("Link loss rate must be in [0, 1)".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Scripted behaviour a simulated node plays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimRole {
+    Oracle,      // Publishes prices
+    Liquidator,  // Alerts on vaults below the liquidation threshold
+    VaultOwner,  // Opens vaults
+    Observer,
+}
+
+impl SimRole {
+    fn services(&self) -> Vec<ServiceType> {
+        match self {
+            SimRole::Oracle => vec![ServiceType::Oracle],
+            SimRole::Liquidator => vec![ServiceType::Liquidator],
+            SimRole::VaultOwner => vec![ServiceType::VaultProvider],
+            SimRole::Observer => vec![ServiceType::StableHolder],
+        }
+    }
+}
+
+/// What a node has learned from the network
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeView {
+    pub btc_price: Option<f64>,
+    pub price_updates: usize,
+    pub vaults: HashMap<Txid, (Amount, f64)>,   // Collateral and USD debt
+    pub liquidation_alerts: HashSet<Txid>,
+}
+
+pub struct SimNode {
+    pub role: SimRole,
+    pub network: BitStableNetwork,
+    view: Arc<Mutex<NodeView>>,
+    alerted: HashSet<Txid>,
+}
+
+impl SimNode {
+    pub fn view(&self) -> NodeView {
+        self.view.lock().unwrap().clone()
+    }
+
+    fn register_handlers(&mut self) {
+        let view = self.view.clone();
+        self.network.register_message_handler(MessageType::PriceUpdate, move |message| {
+            if let MessageData::PriceAttestations { prices, .. } = &message.data {
+                let mut view = view.lock().unwrap();
+                view.btc_price = prices.get(&Currency::USD).copied();
+                view.price_updates += 1;
+            }
+            Ok(())
+        });
+
+        let view = self.view.clone();
+        self.network.register_message_handler(MessageType::VaultCreated, move |message| {
+            if let MessageData::VaultCreated { vault_id, collateral_amount, stable_debt } = message.data {
+                view.lock().unwrap().vaults.insert(vault_id, (collateral_amount, stable_debt));
+            }
+            Ok(())
+        });
+
+        let view = self.view.clone();
+        self.network.register_message_handler(MessageType::LiquidationAlert, move |message| {
+            if let MessageData::LiquidationAlert { vault_id, .. } = message.data {
+                view.lock().unwrap().liquidation_alerts.insert(vault_id);
+            }
+            Ok(())
+        });
+    }
+
+    /// Liquidators alert once on every known vault below the threshold
+    async fn run_script(&mut self, threshold: f64) -> Result<()> {
+        if self.role != SimRole::Liquidator {
+            return Ok(());
+        }
+
+        let view = self.view();
+        let Some(price) = view.btc_price else {
+            return Ok(());
+        };
+        for (vault_id, (collateral, debt)) in view.vaults {
+            let ratio = collateral.to_btc() * price / debt;
+            if ratio < threshold && self.alerted.insert(vault_id) {
+                let bonus = Amount::from_sat(collateral.to_sat() / 20);
+                self.network.send_liquidation_alert(vault_id, ratio, bonus).await?;
+                self.view.lock().unwrap().liquidation_alerts.insert(vault_id);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Node index per partition group; empty means fully connected
+type Partition = Vec<usize>;
+
+fn partitioned(partition: &Partition, a: usize, b: usize) -> bool {
+    !partition.is_empty() && partition[a] != partition[b]
+}
+
+pub struct Simulation {
+    nodes: Vec<SimNode>,
+    oracles: OracleKeyManager,   // Attestation keys of the oracle nodes
+    config: SimulationConfig,
+    rng: StdRng,
+    partition: watch::Sender<Partition>,
+    links: Vec<JoinHandle<()>>,
+}
+
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        for link in &self.links {
+            link.abort();
+        }
+    }
+}
+
+fn oracle_name(index: usize) -> String {
+    format!("sim-oracle-{}", index)
+}
+
+impl Simulation {
+    pub fn new(roles: &[SimRole], config: SimulationConfig) -> Result<Self> {
+        config.validate()?;
+        // Every node trusts every oracle node's attestations
+        let mut oracles = OracleKeyManager::new();
+        let mut oracle_keys = Vec::new();
+        for (index, role) in roles.iter().enumerate() {
+            if *role == SimRole::Oracle {
+                oracle_keys.push(oracles.generate_oracle_key(&oracle_name(index))?);
+            }
+        }
+
+        let nodes = roles
+            .iter()
+            .enumerate()
+            .map(|(index, role)| {
+                // Node keys are derived from the index so runs are reproducible
+                let mut key = [0u8; 32];
+                key[..8].copy_from_slice(&(index as u64 + 1).to_be_bytes());
+                let secret = bitcoin::secp256k1::SecretKey::from_slice(&key)
+                    .map_err(|e| BitStableError::InvalidConfig(e.to_string()))?;
+                let mut node = SimNode {
+                    role: *role,
+                    network: BitStableNetwork::new(secret, config.max_connections, oracle_keys.clone())
+                        .with_services(role.services()),
+                    view: Arc::default(),
+                    alerted: HashSet::new(),
+                };
+                node.register_handlers();
+                Ok(node)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            nodes,
+            oracles,
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            partition: watch::channel(Vec::new()).0,
+            links: Vec::new(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, index: usize) -> &SimNode {
+        &self.nodes[index]
+    }
+
+    pub fn pubkey(&self, index: usize) -> PublicKey {
+        self.nodes[index].network.local_pubkey()
+    }
+
+    /// Open a link from `a` to `b` and complete the handshake on both ends
+    pub async fn connect(&mut self, a: usize, b: usize) -> Result<()> {
+        let (a_stream, a_end) = tokio::io::duplex(LINK_BUFFER);
+        let (b_stream, b_end) = tokio::io::duplex(LINK_BUFFER);
+        let (a_read, a_write) = tokio::io::split(a_end);
+        let (b_read, b_write) = tokio::io::split(b_end);
+        for (from, to, reader, writer) in [(a, b, a_read, b_write), (b, a, b_read, a_write)] {
+            let rng = StdRng::seed_from_u64(self.rng.gen());
+            self.links.extend(self.spawn_pipe(from, to, reader, writer, rng));
+        }
+
+        let a_key = self.pubkey(a);
+        let b_key = self.pubkey(b);
+        self.nodes[b].network.accept_stream(b_stream, format!("sim://{}", a));
+        self.nodes[a].network.connect_stream(a_stream, &format!("sim://{}", b), b_key).await?;
+
+        let responder = &mut self.nodes[b].network;
+        while !responder.connected_peers().contains(&a_key) {
+            tokio::time::timeout(wire::HANDSHAKE_TIMEOUT, responder.process_next_event())
+                .await
+                .map_err(|_| BitStableError::PeerProtocolError(format!("Node {} never attached node {}", b, a)))??;
+        }
+        Ok(())
+    }
+
+    /// Connect each node to the next, then add `extra` random links per node
+    pub async fn connect_ring(&mut self, extra: usize) -> Result<()> {
+        let count = self.nodes.len();
+        let mut linked = HashSet::new();
+        for a in 0..count {
+            let b = (a + 1) % count;
+            if a != b && linked.insert((a.min(b), a.max(b))) {
+                self.connect(a, b).await?;
+            }
+        }
+        for a in 0..count {
+            for _ in 0..extra {
+                let b = self.rng.gen_range(0..count);
+                if a != b && linked.insert((a.min(b), a.max(b))) {
+                    self.connect(a, b).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Hold traffic between groups until `heal`; nodes not listed form their own group
+    pub fn partition(&self, groups: &[&[usize]]) {
+        let mut partition = vec![groups.len(); self.nodes.len()];
+        for (group, members) in groups.iter().enumerate() {
+            for member in *members {
+                partition[*member] = group;
+            }
+        }
+        self.partition.send_replace(partition);
+    }
+
+    pub fn heal(&self) {
+        self.partition.send_replace(Vec::new());
+    }
+
+    /// Broadcast a BTC/USD price from an oracle node
+    pub async fn publish_price(&mut self, oracle: usize, price: f64) -> Result<()> {
+        let attestation = self.oracles.sign_currency_price(&oracle_name(oracle), Currency::USD, price, chrono::Utc::now().timestamp());
+        let node = self.scripted(oracle, SimRole::Oracle)?;
+        node.network.send_price_attestations(vec![attestation?]).await?;
+        let mut view = node.view.lock().unwrap();
+        view.btc_price = Some(price);
+        view.price_updates += 1;
+        Ok(())
+    }
+
+    /// Announce a new vault from a vault owner node
+    pub async fn open_vault(&mut self, owner: usize, collateral: Amount, debt_usd: f64) -> Result<Txid> {
+        use bitcoin::hashes::Hash;
+        let vault_id = Txid::from_byte_array(self.rng.gen());
+        let node = self.scripted(owner, SimRole::VaultOwner)?;
+        node.network.announce_vault_creation(vault_id, collateral, debt_usd).await?;
+        node.view.lock().unwrap().vaults.insert(vault_id, (collateral, debt_usd));
+        Ok(vault_id)
+    }
+
+    fn scripted(&mut self, index: usize, role: SimRole) -> Result<&mut SimNode> {
+        let node = self.nodes.get_mut(index)
+            .ok_or_else(|| BitStableError::InvalidConfig(format!("No node {}", index)))?;
+        if node.role != role {
+            return Err(BitStableError::InvalidConfig(format!("Node {} is a {:?}, not a {:?}", index, node.role, role)));
+        }
+        Ok(node)
+    }
+
+    /// Deliver queued events on every node, then run role scripts
+    pub async fn step(&mut self) -> Result<usize> {
+        let mut handled = 0;
+        for node in &mut self.nodes {
+            handled += node.network.process_pending_events()?;
+        }
+        let threshold = self.config.liquidation_threshold;
+        for node in &mut self.nodes {
+            node.run_script(threshold).await?;
+        }
+        Ok(handled)
+    }
+
+    /// Step until `done` holds; returns how long that took
+    pub async fn run_until(&mut self, timeout: Duration, done: impl Fn(&Simulation) -> bool) -> Result<Duration> {
+        let started = Instant::now();
+        loop {
+            self.step().await?;
+            if done(self) {
+                return Ok(started.elapsed());
+            }
+            if started.elapsed() > timeout {
+                return Err(BitStableError::PeerProtocolError(format!("Simulation did not settle within {:?}", timeout)));
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    /// Step for a fixed time, for checking that something does not happen
+    pub async fn run_for(&mut self, duration: Duration) -> Result<()> {
+        let started = Instant::now();
+        while started.elapsed() < duration {
+            self.step().await?;
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        Ok(())
+    }
+
+    /// Whether every node agrees on `view`
+    pub fn converged<T: PartialEq>(&self, view: impl Fn(&NodeView) -> T) -> bool {
+        let mut views = self.nodes.iter().map(|node| view(&node.view.lock().unwrap()));
+        match views.next() {
+            Some(first) => views.all(|other| other == first),
+            None => true,
+        }
+    }
+
+    pub fn views(&self) -> Vec<NodeView> {
+        self.nodes.iter().map(SimNode::view).collect()
+    }
+
+    pub fn total_messages_sent(&self) -> u64 {
+        self.nodes.iter().map(|node| node.network.get_network_stats().total_messages_sent).sum()
+    }
+
+    /// Carry bytes one way, delayed by the link profile and held across partitions
+    fn spawn_pipe(
+        &self,
+        from: usize,
+        to: usize,
+        mut reader: ReadHalf<DuplexStream>,
+        mut writer: WriteHalf<DuplexStream>,
+        mut rng: StdRng,
+    ) -> [JoinHandle<()>; 2] {
+        let profile = self.config.link.clone();
+        let (segments, mut queued) = mpsc::unbounded_channel::<(Instant, Vec<u8>)>();
+
+        let transmit = tokio::spawn(async move {
+            let mut buffer = vec![0u8; LINK_BUFFER];
+            let mut last_delivery = Instant::now();
+            while let Ok(read) = reader.read(&mut buffer).await {
+                if read == 0 {
+                    break;
+                }
+                let mut delay = profile.latency + profile.jitter.mul_f64(rng.gen::<f64>());
+                while rng.gen_bool(profile.loss_rate) {
+                    delay += profile.retransmit_timeout;
+                }
+                // Later segments never overtake earlier ones
+                last_delivery = last_delivery.max(Instant::now() + delay);
+                if segments.send((last_delivery, buffer[..read].to_vec())).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut partition = self.partition.subscribe();
+        let deliver = tokio::spawn(async move {
+            while let Some((deliver_at, segment)) = queued.recv().await {
+                tokio::time::sleep_until(deliver_at).await;
+                while partitioned(&partition.borrow_and_update(), from, to) {
+                    if partition.changed().await.is_err() {
+                        return;
+                    }
+                }
+                if writer.write_all(&segment).await.is_err() {
+                    break;
+                }
+            }
+            let _ = writer.shutdown().await;
+        });
+
+        [transmit, deliver]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roles() -> Vec<SimRole> {
+        let mut roles = vec![SimRole::Oracle, SimRole::Liquidator, SimRole::VaultOwner];
+        roles.extend([SimRole::Observer; 7]);
+        roles
+    }
+
+    #[tokio::test]
+    async fn test_lossy_network_converges_on_prices_vaults_and_alerts() {
+        let config = SimulationConfig {
+            link: LinkProfile { loss_rate: 0.2, jitter: Duration::from_millis(5), ..LinkProfile::default() },
+            seed: 7,
+            ..SimulationConfig::default()
+        };
+        let mut sim = Simulation::new(&roles(), config).unwrap();
+        sim.connect_ring(1).await.unwrap();
+        let settle = Duration::from_secs(10);
+
+        sim.publish_price(0, 64_000.0).await.unwrap();
+        let vault = sim.open_vault(2, Amount::from_btc(1.0).unwrap(), 50_000.0).await.unwrap();
+        sim.run_until(settle, |sim| sim.converged(|view| (view.btc_price, view.vaults.clone()))).await.unwrap();
+        assert!(sim.views().iter().all(|view| view.liquidation_alerts.is_empty()));
+
+        // A price drop puts the vault under water; the liquidator's alert reaches everyone
+        sim.publish_price(0, 52_000.0).await.unwrap();
+        sim.run_until(settle, |sim| sim.converged(|view| view.liquidation_alerts.clone())
+            && sim.node(0).view().liquidation_alerts.contains(&vault)).await.unwrap();
+
+        // Flooding delivers each price once per node despite redundant paths
+        assert!(sim.views().iter().all(|view| view.price_updates == 2 && view.btc_price == Some(52_000.0)));
+        assert!(sim.total_messages_sent() as usize >= 3 * (sim.len() - 1));
+
+        // Scripts only run for the role they belong to
+        assert!(sim.publish_price(1, 1.0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_partition_holds_messages_until_healed() {
+        let mut sim = Simulation::new(&roles(), SimulationConfig::default()).unwrap();
+        sim.connect_ring(0).await.unwrap();
+
+        // The oracle's half hears the price, the other half doesn't until the split heals
+        sim.partition(&[&[0, 1, 2, 3, 4]]);
+        sim.publish_price(0, 64_000.0).await.unwrap();
+        sim.run_until(Duration::from_secs(5), |sim| (0..5).all(|i| sim.node(i).view().btc_price.is_some())).await.unwrap();
+        sim.run_for(Duration::from_millis(100)).await.unwrap();
+        assert!((5..sim.len()).all(|i| sim.node(i).view().btc_price.is_none()));
+
+        sim.heal();
+        sim.run_until(Duration::from_secs(5), |sim| sim.converged(|view| view.btc_price)).await.unwrap();
+        assert!(sim.views().iter().all(|view| view.price_updates == 1));
+    }
+}
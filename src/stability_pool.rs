@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use chrono::{DateTime, Utc};
 use crate::{BitStableError, Result, ProtocolConfig};
+use crate::events::{EventBus, EventEmitter, ProtocolEvent};
 use crate::multi_currency::{Currency, ExchangeRates};
 
 /// Stability pool where users pre-commit stablecoins for liquidations and earn rewards
@@ -15,6 +16,8 @@ pub struct StabilityPool {
     pub pool_config: StabilityPoolConfig,
     pub reward_snapshots: Vec<RewardSnapshot>,
     pub last_reward_distribution: DateTime<Utc>,
+    #[serde(skip)]
+    events: EventEmitter,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -83,9 +86,14 @@ impl StabilityPool {
             pool_config,
             reward_snapshots: Vec::new(),
             last_reward_distribution: Utc::now(),
+            events: EventEmitter::default(),
         }
     }
 
+    pub fn set_event_bus(&mut self, bus: EventBus) {
+        self.events.attach(bus);
+    }
+
     /// Deposit stablecoins into the stability pool
     pub fn deposit(
         &mut self,
@@ -124,6 +132,7 @@ impl StabilityPool {
             amount,
             currency.to_string()
         );
+        self.events.emit(ProtocolEvent::StabilityDeposit { depositor, currency, amount });
 
         Ok(())
     }
@@ -185,6 +194,7 @@ impl StabilityPool {
             currency.to_string(),
             penalty
         );
+        self.events.emit(ProtocolEvent::StabilityWithdrawal { withdrawal: result.clone() });
 
         Ok(result)
     }
@@ -286,6 +296,7 @@ impl StabilityPool {
             vault_id,
             liquidation.participants.len()
         );
+        self.events.emit(ProtocolEvent::StabilityLiquidation { liquidation: liquidation.clone() });
 
         Ok(liquidation)
     }
@@ -314,6 +325,7 @@ impl StabilityPool {
             depositor,
             rewards.to_btc()
         );
+        self.events.emit(ProtocolEvent::StabilityRewardsClaimed { depositor, currency, rewards });
 
         Ok(rewards)
     }
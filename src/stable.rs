@@ -15,6 +15,7 @@ pub struct StableTransfer {
     pub timestamp: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MultiCurrencyStableManager {
     positions: HashMap<PublicKey, MultiCurrencyPosition>,
     total_supply: HashMap<Currency, f64>,
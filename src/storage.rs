@@ -0,0 +1,809 @@
+//! Storage backends
+//! An ordered key-value store of named trees, with sled, SQLite and in-memory implementations
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use rusqlite::{params, Connection, OptionalExtension};
+use sled::transaction::{ConflictableTransactionError, TransactionError, Transactional};
+use serde::{Deserialize, Serialize};
+use crate::{BitStableError, Result};
+
+/// The unnamed tree VaultManager keeps live vaults in (sled's default tree)
+pub const DEFAULT_TREE: &str = "";
+
+/// Sequence-keyed record of which keys each write touched, read by incremental backups
+pub const CHANGE_LOG_TREE: &str = "change_log";
+
+const CONFIG_TREE: &str = "config";
+const ID_OFFSET_KEY: &str = "sled_id_offset";
+
+/// Keys are compared bytewise, and every backend returns them in that order
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+/// Half-open key range `start <= key < end`; a missing bound is unbounded
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyRange {
+    pub start: Option<Vec<u8>>,
+    pub end: Option<Vec<u8>>,
+}
+
+impl KeyRange {
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    pub fn between(start: impl Into<Vec<u8>>, end: impl Into<Vec<u8>>) -> Self {
+        Self { start: Some(start.into()), end: Some(end.into()) }
+    }
+
+    pub fn before(end: impl Into<Vec<u8>>) -> Self {
+        Self { start: None, end: Some(end.into()) }
+    }
+
+    pub fn starting_at(start: impl Into<Vec<u8>>) -> Self {
+        Self { start: Some(start.into()), end: None }
+    }
+
+    /// Every key starting with `prefix`
+    pub fn prefix(prefix: impl Into<Vec<u8>>) -> Self {
+        let start = prefix.into();
+        let mut end = start.clone();
+        // The first key past the prefix bumps its last byte that isn't 0xff
+        while let Some(last) = end.pop() {
+            if last < u8::MAX {
+                end.push(last + 1);
+                return Self { start: Some(start), end: Some(end) };
+            }
+        }
+        Self { start: Some(start), end: None }
+    }
+
+    fn contains(&self, key: &[u8]) -> bool {
+        self.start.as_deref().is_none_or(|start| key >= start)
+            && self.end.as_deref().is_none_or(|end| key < end)
+    }
+}
+
+/// One write in an atomic batch; a `None` value removes the key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Write {
+    pub tree: String,
+    pub key: Vec<u8>,
+    pub value: Option<Vec<u8>>,
+}
+
+impl Write {
+    pub fn insert(tree: &str, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) -> Self {
+        Self { tree: tree.to_string(), key: key.into(), value: Some(value.into()) }
+    }
+
+    pub fn remove(tree: &str, key: impl Into<Vec<u8>>) -> Self {
+        Self { tree: tree.to_string(), key: key.into(), value: None }
+    }
+}
+
+/// Persistent state is a set of named trees of ordered byte keys. `DatabaseManager`
+/// builds the typed vault, liquidation, price, config and subsystem records on top.
+pub trait Storage: Send + Sync + std::fmt::Debug {
+    /// Short backend name, e.g. for logs
+    fn backend(&self) -> &'static str;
+
+    fn get(&self, tree: &str, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    fn insert(&self, tree: &str, key: &[u8], value: &[u8]) -> Result<()>;
+
+    fn remove(&self, tree: &str, key: &[u8]) -> Result<()>;
+
+    /// Entries in `range` in key order, or newest-key first when `reverse`
+    fn scan(&self, tree: &str, range: &KeyRange, reverse: bool, limit: Option<usize>) -> Result<Vec<Entry>>;
+
+    fn len(&self, tree: &str) -> Result<usize>;
+
+    fn clear(&self, tree: &str) -> Result<()>;
+
+    /// Apply every write or none of them
+    fn apply(&self, writes: &[Write]) -> Result<()>;
+
+    /// Monotonic id, unique for the life of the store
+    fn generate_id(&self) -> Result<u64>;
+
+    /// Make every later `generate_id` return at least `floor`
+    fn advance_ids(&self, floor: u64) -> Result<()>;
+
+    fn flush(&self) -> Result<()>;
+
+    /// Names of trees that may hold data
+    fn tree_names(&self) -> Result<Vec<String>>;
+
+    fn size_on_disk(&self) -> u64 {
+        0
+    }
+
+    fn is_empty(&self, tree: &str) -> Result<bool> {
+        Ok(self.scan(tree, &KeyRange::all(), false, Some(1))?.is_empty())
+    }
+}
+
+/// Open a store from a location such as `sled://./bitstable.db`, `sqlite://./bitstable.sqlite`
+/// or `memory://`. A bare path is a sled database, as before backends were selectable.
+pub fn open_storage(location: &str) -> Result<Arc<dyn Storage>> {
+    if let Some(path) = location.strip_prefix("sqlite://") {
+        return Ok(Arc::new(ChangeLogStorage::new(Arc::new(SqliteStorage::open(path)?))));
+    }
+    if location == "memory" || location.starts_with("memory://") {
+        return Ok(Arc::new(ChangeLogStorage::new(Arc::new(MemoryStorage::new()))));
+    }
+    let path = location.strip_prefix("sled://").unwrap_or(location);
+    Ok(Arc::new(ChangeLogStorage::new(Arc::new(SledStorage::open(path)?))))
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CopyReport {
+    pub trees: usize,
+    pub records: usize,
+}
+
+/// Copy every tree from one store into an empty one, record for record
+pub fn copy_storage(from: &dyn Storage, to: &dyn Storage) -> Result<CopyReport> {
+    for tree in to.tree_names()? {
+        if !to.is_empty(&tree)? {
+            return Err(BitStableError::InvalidConfig(format!(
+                "Destination {} store is not empty (tree '{}' has records)", to.backend(), tree
+            )));
+        }
+    }
+
+    let mut report = CopyReport::default();
+    // Change log sequence numbers belong to the source, so the copy starts its own log
+    for tree in from.tree_names()?.into_iter().filter(|tree| tree != CHANGE_LOG_TREE) {
+        let entries = from.scan(&tree, &KeyRange::all(), false, None)?;
+        if entries.is_empty() {
+            continue;
+        }
+        let writes: Vec<Write> = entries.into_iter()
+            .map(|(key, value)| Write::insert(&tree, key, value))
+            .collect();
+        to.apply(&writes)?;
+        report.trees += 1;
+        report.records += writes.len();
+    }
+
+    // Some trees are keyed by bare ids, so the copy must not hand them out again
+    to.advance_ids(from.generate_id()?)?;
+    to.flush()?;
+    Ok(report)
+}
+
+/// A key written by one change, or a whole tree when `key` is `None` (cleared)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    pub tree: String,
+    pub key: Option<Vec<u8>>,
+}
+
+impl Change {
+    fn encode(&self) -> Result<Vec<u8>> {
+        serde_json::to_vec(self)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize change: {}", e)))
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        serde_json::from_slice(bytes)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to deserialize change: {}", e)))
+    }
+}
+
+/// Wraps a backend so every write also appends a `Change` to `CHANGE_LOG_TREE`,
+/// in the same atomic batch. `open_storage` returns stores wrapped this way.
+#[derive(Debug)]
+pub struct ChangeLogStorage {
+    inner: Arc<dyn Storage>,
+    sequence: Mutex<()>,   // Keeps log order the same as commit order
+}
+
+impl ChangeLogStorage {
+    pub fn new(inner: Arc<dyn Storage>) -> Self {
+        Self { inner, sequence: Mutex::new(()) }
+    }
+
+    /// Sequence number the next change will be logged at or after
+    pub fn next_sequence(storage: &dyn Storage) -> Result<u64> {
+        let last = storage.scan(CHANGE_LOG_TREE, &KeyRange::all(), true, Some(1))?;
+        Ok(last.first().map_or(0, |(key, _)| sequence_of(key) + 1))
+    }
+
+    /// Changes logged at `from` or later, oldest first, with their sequence numbers.
+    /// Fails if entries from `from` on have been pruned.
+    pub fn changes_since(storage: &dyn Storage, from: u64) -> Result<Vec<(u64, Change)>> {
+        let range = KeyRange { start: Some(from.to_be_bytes().to_vec()), end: None };
+        let changes = storage.scan(CHANGE_LOG_TREE, &range, false, None)?
+            .iter()
+            .map(|(key, value)| Ok((sequence_of(key), Change::decode(value)?)))
+            .collect::<Result<Vec<_>>>()?;
+        if let Some((sequence, _)) = changes.first().filter(|(_, change)| change.tree == CHANGE_LOG_TREE) {
+            return Err(BitStableError::InvalidConfig(format!(
+                "Change log is pruned up to {}, past the requested start {}", sequence + 1, from
+            )));
+        }
+        Ok(changes)
+    }
+
+    /// Drop log entries before `before`, once a full backup covers them. A marker
+    /// just below `before` makes `changes_since` refuse to start in the dropped range.
+    pub fn prune(storage: &dyn Storage, before: u64) -> Result<usize> {
+        let Some(marker) = before.checked_sub(1) else {
+            return Ok(0);
+        };
+        let mut writes: Vec<Write> = storage.scan(CHANGE_LOG_TREE, &KeyRange::before(before.to_be_bytes().to_vec()), false, None)?
+            .into_iter()
+            .map(|(key, _)| Write::remove(CHANGE_LOG_TREE, key))
+            .collect();
+        let pruned = writes.len();
+        let pruned_marker = Change { tree: CHANGE_LOG_TREE.to_string(), key: None };
+        writes.push(Write::insert(CHANGE_LOG_TREE, marker.to_be_bytes(), pruned_marker.encode()?));
+        storage.apply(&writes)?;
+        Ok(pruned)
+    }
+
+    fn log(&self, change: &Change) -> Result<Write> {
+        let sequence = self.inner.generate_id()?;
+        Ok(Write::insert(CHANGE_LOG_TREE, sequence.to_be_bytes(), change.encode()?))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        self.sequence.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn sequence_of(key: &[u8]) -> u64 {
+    key.try_into().map(u64::from_be_bytes).unwrap_or(0)
+}
+
+impl Storage for ChangeLogStorage {
+    fn backend(&self) -> &'static str {
+        self.inner.backend()
+    }
+
+    fn get(&self, tree: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.inner.get(tree, key)
+    }
+
+    fn insert(&self, tree: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        self.apply(&[Write::insert(tree, key, value)])
+    }
+
+    fn remove(&self, tree: &str, key: &[u8]) -> Result<()> {
+        self.apply(&[Write::remove(tree, key)])
+    }
+
+    fn scan(&self, tree: &str, range: &KeyRange, reverse: bool, limit: Option<usize>) -> Result<Vec<Entry>> {
+        self.inner.scan(tree, range, reverse, limit)
+    }
+
+    fn len(&self, tree: &str) -> Result<usize> {
+        self.inner.len(tree)
+    }
+
+    fn clear(&self, tree: &str) -> Result<()> {
+        if tree == CHANGE_LOG_TREE {
+            return self.inner.clear(tree);
+        }
+        // Logged first: an incremental backup re-copies a cleared tree whole, so a
+        // crash before the clear only costs a redundant copy
+        let _guard = self.lock();
+        self.inner.apply(&[self.log(&Change { tree: tree.to_string(), key: None })?])?;
+        self.inner.clear(tree)
+    }
+
+    fn apply(&self, writes: &[Write]) -> Result<()> {
+        let _guard = self.lock();
+        let mut logged = writes.to_vec();
+        for write in writes.iter().filter(|write| write.tree != CHANGE_LOG_TREE) {
+            logged.push(self.log(&Change { tree: write.tree.clone(), key: Some(write.key.clone()) })?);
+        }
+        self.inner.apply(&logged)
+    }
+
+    fn generate_id(&self) -> Result<u64> {
+        self.inner.generate_id()
+    }
+
+    fn advance_ids(&self, floor: u64) -> Result<()> {
+        self.inner.advance_ids(floor)
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn tree_names(&self) -> Result<Vec<String>> {
+        self.inner.tree_names()
+    }
+
+    fn size_on_disk(&self) -> u64 {
+        self.inner.size_on_disk()
+    }
+}
+
+/// Embedded sled database
+#[derive(Debug, Clone)]
+pub struct SledStorage {
+    db: sled::Db,
+}
+
+impl SledStorage {
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        let db = sled::open(path)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to open database: {}", e)))?;
+        Ok(Self { db })
+    }
+
+    pub fn new(db: sled::Db) -> Self {
+        Self { db }
+    }
+
+    /// Amount added to sled's own id counter, raised by `advance_ids`
+    fn id_offset(&self) -> Result<u64> {
+        match self.tree(CONFIG_TREE)?.get(ID_OFFSET_KEY)? {
+            Some(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| BitStableError::InvalidConfig(format!("Failed to deserialize id offset: {}", e))),
+            None => Ok(0),
+        }
+    }
+
+    fn tree(&self, name: &str) -> Result<sled::Tree> {
+        if name == DEFAULT_TREE {
+            return Ok((*self.db).clone());
+        }
+        self.db.open_tree(name)
+            .map_err(|e| BitStableError::InvalidConfig(format!("Failed to open {} tree: {}", name, e)))
+    }
+}
+
+impl Storage for SledStorage {
+    fn backend(&self) -> &'static str {
+        "sled"
+    }
+
+    fn get(&self, tree: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.tree(tree)?.get(key)?.map(|value| value.to_vec()))
+    }
+
+    fn insert(&self, tree: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        self.tree(tree)?.insert(key, value)?;
+        Ok(())
+    }
+
+    fn remove(&self, tree: &str, key: &[u8]) -> Result<()> {
+        self.tree(tree)?.remove(key)?;
+        Ok(())
+    }
+
+    fn scan(&self, tree: &str, range: &KeyRange, reverse: bool, limit: Option<usize>) -> Result<Vec<Entry>> {
+        use std::ops::Bound;
+        let tree = self.tree(tree)?;
+        let start = range.start.clone().map_or(Bound::Unbounded, Bound::Included);
+        let end = range.end.clone().map_or(Bound::Unbounded, Bound::Excluded);
+        let items = tree.range::<Vec<u8>, _>((start, end));
+        let items: Box<dyn Iterator<Item = sled::Result<(sled::IVec, sled::IVec)>>> = if reverse {
+            Box::new(items.rev())
+        } else {
+            Box::new(items)
+        };
+
+        let mut entries = Vec::new();
+        for item in items.take(limit.unwrap_or(usize::MAX)) {
+            let (key, value) = item?;
+            entries.push((key.to_vec(), value.to_vec()));
+        }
+        Ok(entries)
+    }
+
+    fn len(&self, tree: &str) -> Result<usize> {
+        Ok(self.tree(tree)?.len())
+    }
+
+    fn clear(&self, tree: &str) -> Result<()> {
+        self.tree(tree)?.clear()?;
+        Ok(())
+    }
+
+    fn apply(&self, writes: &[Write]) -> Result<()> {
+        if writes.is_empty() {
+            return Ok(());
+        }
+        let names: Vec<&str> = writes.iter()
+            .map(|write| write.tree.as_str())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let trees = names.iter().map(|name| self.tree(name)).collect::<Result<Vec<_>>>()?;
+
+        trees.as_slice()
+            .transaction(|views| {
+                for write in writes {
+                    let index = names.binary_search(&write.tree.as_str()).expect("every written tree is open");
+                    let view = &views[index];
+                    match &write.value {
+                        Some(value) => view.insert(write.key.as_slice(), value.as_slice())?,
+                        None => view.remove(write.key.as_slice())?,
+                    };
+                }
+                Ok::<(), ConflictableTransactionError<BitStableError>>(())
+            })
+            .map_err(|e: TransactionError<BitStableError>| {
+                BitStableError::InvalidConfig(format!("Failed to apply writes: {}", e))
+            })
+    }
+
+    fn generate_id(&self) -> Result<u64> {
+        Ok(self.db.generate_id()? + self.id_offset()?)
+    }
+
+    fn advance_ids(&self, floor: u64) -> Result<()> {
+        // sled can't set its id counter, so ids are shifted by a stored offset instead
+        let offset = self.id_offset()?;
+        let next = self.db.generate_id()? + offset;
+        if next < floor {
+            let value = serde_json::to_vec(&(offset + floor - next))
+                .map_err(|e| BitStableError::InvalidConfig(format!("Failed to serialize id offset: {}", e)))?;
+            self.tree(CONFIG_TREE)?.insert(ID_OFFSET_KEY, value)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn tree_names(&self) -> Result<Vec<String>> {
+        Ok(self.db.tree_names()
+            .into_iter()
+            .map(|name| String::from_utf8_lossy(&name).into_owned())
+            .map(|name| if name == "__sled__default" { DEFAULT_TREE.to_string() } else { name })
+            .collect())
+    }
+
+    fn size_on_disk(&self) -> u64 {
+        self.db.size_on_disk().unwrap_or(0)
+    }
+}
+
+/// Single SQLite file holding every tree in one table
+#[derive(Debug)]
+pub struct SqliteStorage {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        // BLOB keys compare with memcmp, matching sled's ordering
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS records (
+                tree TEXT NOT NULL,
+                key BLOB NOT NULL,
+                value BLOB NOT NULL,
+                PRIMARY KEY (tree, key)
+            ) WITHOUT ROWID;
+            CREATE TABLE IF NOT EXISTS counters (
+                name TEXT PRIMARY KEY,
+                value INTEGER NOT NULL
+            );
+            INSERT OR IGNORE INTO counters (name, value) VALUES ('next_id', 0);"
+        )?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    fn conn(&self) -> Result<MutexGuard<'_, Connection>> {
+        self.conn.lock()
+            .map_err(|_| BitStableError::InvalidConfig("SQLite connection lock poisoned".to_string()))
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn backend(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn get(&self, tree: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.conn()?
+            .query_row("SELECT value FROM records WHERE tree = ?1 AND key = ?2", params![tree, key], |row| row.get(0))
+            .optional()?)
+    }
+
+    fn insert(&self, tree: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        self.conn()?.execute(
+            "INSERT OR REPLACE INTO records (tree, key, value) VALUES (?1, ?2, ?3)",
+            params![tree, key, value],
+        )?;
+        Ok(())
+    }
+
+    fn remove(&self, tree: &str, key: &[u8]) -> Result<()> {
+        self.conn()?.execute("DELETE FROM records WHERE tree = ?1 AND key = ?2", params![tree, key])?;
+        Ok(())
+    }
+
+    fn scan(&self, tree: &str, range: &KeyRange, reverse: bool, limit: Option<usize>) -> Result<Vec<Entry>> {
+        let mut sql = "SELECT key, value FROM records WHERE tree = ?1".to_string();
+        let mut bounds: Vec<&[u8]> = Vec::new();
+        if let Some(start) = &range.start {
+            bounds.push(start);
+            sql.push_str(&format!(" AND key >= ?{}", bounds.len() + 1));
+        }
+        if let Some(end) = &range.end {
+            bounds.push(end);
+            sql.push_str(&format!(" AND key < ?{}", bounds.len() + 1));
+        }
+        sql.push_str(if reverse { " ORDER BY key DESC" } else { " ORDER BY key" });
+        if let Some(limit) = limit {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+
+        let conn = self.conn()?;
+        let mut statement = conn.prepare(&sql)?;
+        let mut values: Vec<&dyn rusqlite::ToSql> = vec![&tree];
+        values.extend(bounds.iter().map(|bound| bound as &dyn rusqlite::ToSql));
+        let rows = statement.query_map(values.as_slice(), |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<Entry>>>()?)
+    }
+
+    fn len(&self, tree: &str) -> Result<usize> {
+        let count: i64 = self.conn()?
+            .query_row("SELECT COUNT(*) FROM records WHERE tree = ?1", params![tree], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    fn clear(&self, tree: &str) -> Result<()> {
+        self.conn()?.execute("DELETE FROM records WHERE tree = ?1", params![tree])?;
+        Ok(())
+    }
+
+    fn apply(&self, writes: &[Write]) -> Result<()> {
+        let mut conn = self.conn()?;
+        let transaction = conn.transaction()?;
+        for write in writes {
+            match &write.value {
+                Some(value) => transaction.execute(
+                    "INSERT OR REPLACE INTO records (tree, key, value) VALUES (?1, ?2, ?3)",
+                    params![write.tree, write.key, value],
+                )?,
+                None => transaction.execute(
+                    "DELETE FROM records WHERE tree = ?1 AND key = ?2",
+                    params![write.tree, write.key],
+                )?,
+            };
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn generate_id(&self) -> Result<u64> {
+        let id: i64 = self.conn()?.query_row(
+            "UPDATE counters SET value = value + 1 WHERE name = 'next_id' RETURNING value - 1",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(id as u64)
+    }
+
+    fn advance_ids(&self, floor: u64) -> Result<()> {
+        self.conn()?.execute(
+            "UPDATE counters SET value = MAX(value, ?1) WHERE name = 'next_id'",
+            params![floor as i64],
+        )?;
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        // Every statement commits on its own
+        Ok(())
+    }
+
+    fn tree_names(&self) -> Result<Vec<String>> {
+        let conn = self.conn()?;
+        let mut statement = conn.prepare("SELECT DISTINCT tree FROM records ORDER BY tree")?;
+        let names = statement.query_map([], |row| row.get(0))?;
+        Ok(names.collect::<rusqlite::Result<Vec<String>>>()?)
+    }
+
+    fn size_on_disk(&self) -> u64 {
+        self.conn()
+            .and_then(|conn| Ok(conn.query_row(
+                "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+                [],
+                |row| row.get::<_, i64>(0),
+            )?))
+            .map_or(0, |size| size as u64)
+    }
+}
+
+type MemoryTrees = BTreeMap<String, BTreeMap<Vec<u8>, Vec<u8>>>;
+
+/// Volatile store for tests and simulations
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    trees: Mutex<MemoryTrees>,
+    next_id: AtomicU64,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn trees(&self) -> Result<MutexGuard<'_, MemoryTrees>> {
+        self.trees.lock()
+            .map_err(|_| BitStableError::InvalidConfig("Memory storage lock poisoned".to_string()))
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn backend(&self) -> &'static str {
+        "memory"
+    }
+
+    fn get(&self, tree: &str, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.trees()?.get(tree).and_then(|entries| entries.get(key).cloned()))
+    }
+
+    fn insert(&self, tree: &str, key: &[u8], value: &[u8]) -> Result<()> {
+        self.trees()?.entry(tree.to_string()).or_default().insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn remove(&self, tree: &str, key: &[u8]) -> Result<()> {
+        if let Some(entries) = self.trees()?.get_mut(tree) {
+            entries.remove(key);
+        }
+        Ok(())
+    }
+
+    fn scan(&self, tree: &str, range: &KeyRange, reverse: bool, limit: Option<usize>) -> Result<Vec<Entry>> {
+        let trees = self.trees()?;
+        let Some(entries) = trees.get(tree) else {
+            return Ok(Vec::new());
+        };
+        let matching = entries.iter().filter(|(key, _)| range.contains(key));
+        let matching: Box<dyn Iterator<Item = (&Vec<u8>, &Vec<u8>)>> = if reverse {
+            Box::new(matching.rev())
+        } else {
+            Box::new(matching)
+        };
+        Ok(matching
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+
+    fn len(&self, tree: &str) -> Result<usize> {
+        Ok(self.trees()?.get(tree).map_or(0, BTreeMap::len))
+    }
+
+    fn clear(&self, tree: &str) -> Result<()> {
+        self.trees()?.remove(tree);
+        Ok(())
+    }
+
+    fn apply(&self, writes: &[Write]) -> Result<()> {
+        // Holding the lock for the whole batch makes it atomic
+        let mut trees = self.trees()?;
+        for write in writes {
+            let entries = trees.entry(write.tree.clone()).or_default();
+            match &write.value {
+                Some(value) => entries.insert(write.key.clone(), value.clone()),
+                None => entries.remove(&write.key),
+            };
+        }
+        Ok(())
+    }
+
+    fn generate_id(&self) -> Result<u64> {
+        Ok(self.next_id.fetch_add(1, Ordering::SeqCst))
+    }
+
+    fn advance_ids(&self, floor: u64) -> Result<()> {
+        self.next_id.fetch_max(floor, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn tree_names(&self) -> Result<Vec<String>> {
+        Ok(self.trees()?.keys().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::DatabaseManager;
+    use crate::Vault;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{Amount, Txid};
+
+    fn backends(dir: &std::path::Path) -> Vec<Arc<dyn Storage>> {
+        vec![
+            open_storage(&format!("sled://{}", dir.join("sled").display())).unwrap(),
+            open_storage(&format!("sqlite://{}", dir.join("state.sqlite").display())).unwrap(),
+            open_storage("memory://").unwrap(),
+        ]
+    }
+
+    #[test]
+    fn test_backends_agree_on_ordering_and_atomic_batches() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        for storage in backends(temp_dir.path()) {
+            let backend = storage.backend();
+            for key in [b"a/2".as_slice(), b"a/1", b"b/1", b"a\xff"] {
+                storage.insert("items", key, key).unwrap();
+            }
+            storage.insert(DEFAULT_TREE, b"vault", b"live").unwrap();
+
+            let keys = |range: &KeyRange, reverse: bool, limit: Option<usize>| -> Vec<Vec<u8>> {
+                storage.scan("items", range, reverse, limit).unwrap().into_iter().map(|(key, _)| key).collect()
+            };
+            assert_eq!(keys(&KeyRange::prefix("a/"), false, None), vec![b"a/1".to_vec(), b"a/2".to_vec()], "{}", backend);
+            assert_eq!(keys(&KeyRange::all(), true, Some(2)), vec![b"b/1".to_vec(), b"a\xff".to_vec()], "{}", backend);
+            assert_eq!(keys(&KeyRange::before("a/2"), false, None), vec![b"a/1".to_vec()], "{}", backend);
+            assert_eq!(storage.len("items").unwrap(), 4, "{}", backend);
+            assert_eq!(storage.get(DEFAULT_TREE, b"vault").unwrap(), Some(b"live".to_vec()), "{}", backend);
+
+            storage.apply(&[
+                Write::remove("items", b"a/1".to_vec()),
+                Write::insert("other", b"k".to_vec(), b"v".to_vec()),
+            ]).unwrap();
+            assert_eq!(storage.len("items").unwrap(), 3, "{}", backend);
+            assert_eq!(storage.get("other", b"k").unwrap(), Some(b"v".to_vec()), "{}", backend);
+
+            let first = storage.generate_id().unwrap();
+            storage.advance_ids(first + 100).unwrap();
+            assert!(storage.generate_id().unwrap() >= first + 100, "{}", backend);
+
+            // A far floor, such as one copied from a long-lived store, doesn't step the counter there
+            let floor = first + (1 << 40);
+            storage.advance_ids(floor).unwrap();
+            let next = storage.generate_id().unwrap();
+            assert!(next >= floor && next < floor + 10, "{}", backend);
+        }
+
+        // The sled offset is stored with the data, so ids stay ahead after a reopen
+        let path = temp_dir.path().join("sled");
+        let next = SledStorage::open(&path).unwrap().generate_id().unwrap();
+        assert!(next > 1 << 40);
+    }
+
+    #[test]
+    fn test_copy_between_backends_preserves_records() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let [sled, sqlite, memory]: [Arc<dyn Storage>; 3] = backends(temp_dir.path()).try_into().unwrap();
+
+        let vault_id = Txid::from_raw_hash(bitcoin::hashes::sha256d::Hash::all_zeros());
+        let owner = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".parse().unwrap();
+        let source = DatabaseManager::with_storage(sled.clone()).unwrap();
+        source.save_vault(&Vault::new(vault_id, owner, Amount::from_btc(1.0).unwrap())).unwrap();
+        source.save_config("network", &"testnet").unwrap();
+
+        let report = copy_storage(sled.as_ref(), sqlite.as_ref()).unwrap();
+        assert!(report.records >= 3);
+        copy_storage(sqlite.as_ref(), memory.as_ref()).unwrap();
+        assert!(copy_storage(sled.as_ref(), memory.as_ref()).is_err());
+
+        let copied = DatabaseManager::with_storage(memory).unwrap();
+        assert_eq!(copied.load_vault(vault_id).unwrap().id, vault_id);
+        assert_eq!(copied.load_config::<String>("network").unwrap().as_deref(), Some("testnet"));
+        assert!(copied.verify().unwrap().is_ok());
+    }
+}
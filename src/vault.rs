@@ -1,9 +1,12 @@
-use bitcoin::{Amount, PublicKey, Txid};
+use bitcoin::{Amount, OutPoint, PublicKey, Txid};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use chrono::{DateTime, Utc};
 use crate::{BitStableError, Result, ProtocolConfig};
-use crate::multi_currency::{Currency, MultiCurrencyDebt, ExchangeRates, CurrencyConfig};
+use crate::database::{DatabaseManager, UnitOfWork};
+use crate::events::{EventBus, EventEmitter, ProtocolEvent};
+use crate::storage;
+use crate::multi_currency::{Currency, MultiCurrencyDebt, ExchangeRates, CurrencyConfig, PriceMode};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Vault {
@@ -14,6 +17,26 @@ pub struct Vault {
     pub created_at: DateTime<Utc>,
     pub last_fee_update: DateTime<Utc>,
     pub state: VaultState,
+    #[serde(default)]
+    pub funding: Option<EscrowFunding>,  // Set once the escrow is funded on-chain
+    #[serde(default)]
+    pub revision: u64,                   // Bumped on every change, so peers can tell which copy is newer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<VaultAttestation>,  // Signature peers check before accepting a synced copy
+}
+
+/// Signature over a vault record by its owner or a custody key; stale once the vault changes
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct VaultAttestation {
+    pub signer: PublicKey,
+    pub signature: Vec<u8>,   // Compact ECDSA signature over the record with no attestation
+}
+
+/// On-chain output holding a vault's collateral, so other nodes can check it exists
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EscrowFunding {
+    pub outpoint: OutPoint,
+    pub address: String,   // Escrow multisig address the output pays
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -39,6 +62,9 @@ impl Vault {
             created_at: now,
             last_fee_update: now,
             state: VaultState::Active,
+            funding: None,
+            revision: 0,
+            attestation: None,
         }
     }
     
@@ -51,6 +77,7 @@ impl Vault {
     ) -> Result<()> {
         // Reduce debt
         self.debts.remove_debt(currency.clone(), stable_amount)?;
+        self.revision += 1;
         
         // Reduce collateral
         if collateral_amount > self.collateral_btc {
@@ -75,12 +102,16 @@ impl Vault {
 
     /// Add debt in a specific currency
     pub fn mint_debt(&mut self, currency: Currency, amount: f64) -> Result<()> {
-        self.debts.add_debt(currency, amount)
+        self.debts.add_debt(currency, amount)?;
+        self.revision += 1;
+        Ok(())
     }
 
     /// Remove debt in a specific currency
     pub fn burn_debt(&mut self, currency: Currency, amount: f64) -> Result<()> {
-        self.debts.remove_debt(currency, amount)
+        self.debts.remove_debt(currency, amount)?;
+        self.revision += 1;
+        Ok(())
     }
 
     /// Calculate collateral ratio using total debt in USD
@@ -98,8 +129,12 @@ impl Vault {
 
     /// Calculate collateral ratio for a specific currency
     pub fn collateral_ratio_for_currency(&self, currency: &Currency, exchange_rates: &ExchangeRates) -> f64 {
-        let btc_price = exchange_rates.calculate_btc_price(currency, 
-            exchange_rates.get_btc_price(&Currency::USD).unwrap_or(0.0));
+        self.collateral_ratio_with_mode(currency, exchange_rates, PriceMode::Spot)
+    }
+
+    /// Calculate collateral ratio for a currency using spot, TWAP or a blend of both
+    pub fn collateral_ratio_with_mode(&self, currency: &Currency, exchange_rates: &ExchangeRates, mode: PriceMode) -> f64 {
+        let btc_price = exchange_rates.price_for_mode(currency, mode);
         let collateral_value = self.collateral_btc.to_btc() * btc_price;
         let debt = self.debts.get_debt(currency);
         
@@ -120,7 +155,7 @@ impl Vault {
         for (currency, _) in self.debts.debts.iter() {
             let config = currency_configs.get(currency);
             if let Some(config) = config {
-                let ratio = self.collateral_ratio_for_currency(currency, exchange_rates);
+                let ratio = self.collateral_ratio_with_mode(currency, exchange_rates, config.liquidation_price_mode);
                 if ratio < config.liquidation_threshold {
                     return true;
                 }
@@ -159,6 +194,7 @@ impl Vault {
         
         self.debts = new_debts;
         self.last_fee_update = now;
+        self.revision += 1;
         
         Ok(())
     }
@@ -190,13 +226,20 @@ pub struct VaultManager {
     _config: ProtocolConfig,
     currency_configs: HashMap<Currency, CurrencyConfig>,
     exchange_rates: ExchangeRates,
-    db: sled::Db,
+    tripped_currencies: HashSet<Currency>,  // Currencies halted by the oracle circuit breaker
+    database: DatabaseManager,
+    staged: Option<HashSet<Txid>>,          // Vaults changed since `begin_work`, written on commit
+    events: EventEmitter,
 }
 
 impl VaultManager {
     pub fn new(config: &ProtocolConfig) -> Result<Self> {
-        let db = sled::open(&config.database_path)?;
-        
+        // Opening the database manager brings the stored schema up to date
+        Self::with_database(config, DatabaseManager::with_storage(storage::open_storage(&config.database_path)?)?)
+    }
+
+    /// Manage the vaults in an already open database instead of `config.database_path`
+    pub fn with_database(config: &ProtocolConfig, database: DatabaseManager) -> Result<Self> {
         // Initialize with default currency configurations
         let mut currency_configs = HashMap::new();
         currency_configs.insert(Currency::USD, CurrencyConfig::default());
@@ -206,7 +249,10 @@ impl VaultManager {
             _config: config.clone(),
             currency_configs,
             exchange_rates: ExchangeRates::new(),
-            db,
+            tripped_currencies: HashSet::new(),
+            database,
+            staged: None,
+            events: EventEmitter::default(),
         };
         
         manager.load_vaults()?;
@@ -218,11 +264,82 @@ impl VaultManager {
         self.currency_configs.insert(currency, config);
     }
 
+    pub fn get_currency_configs(&self) -> &HashMap<Currency, CurrencyConfig> {
+        &self.currency_configs
+    }
+
     /// Update exchange rates
     pub fn update_exchange_rates(&mut self, rates: ExchangeRates) {
         self.exchange_rates = rates;
     }
 
+    /// Update which currencies are halted by a tripped circuit breaker
+    pub fn update_circuit_breakers(&mut self, tripped: HashSet<Currency>) {
+        self.tripped_currencies = tripped;
+    }
+
+    pub fn set_event_bus(&mut self, bus: EventBus) {
+        self.events.attach(bus);
+    }
+
+    /// Shared handle to the vault database for other subsystems
+    pub fn database(&self) -> Result<DatabaseManager> {
+        Ok(self.database.clone())
+    }
+
+    /// Hold vault writes back until `commit_work`, so they land together with
+    /// the records of the rest of a state change
+    pub fn begin_work(&mut self) {
+        self.staged.get_or_insert_with(HashSet::new);
+    }
+
+    /// Commit the staged vaults along with `work` in one transaction.
+    /// On failure the staged vaults revert to their stored state.
+    pub fn commit_work(&mut self, mut work: UnitOfWork) -> Result<()> {
+        let staged = self.staged.take().unwrap_or_default();
+        for vault_id in &staged {
+            if let Some(vault) = self.vaults.get(vault_id) {
+                work.put_vault(vault)?;
+            }
+        }
+        
+        if let Err(e) = self.database.commit(work) {
+            self.reload_vaults(&staged)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Drop staged changes, restoring those vaults from the database
+    pub fn rollback_work(&mut self) -> Result<()> {
+        let staged = self.staged.take().unwrap_or_default();
+        self.reload_vaults(&staged)
+    }
+
+    fn reload_vaults(&mut self, vault_ids: &HashSet<Txid>) -> Result<()> {
+        for vault_id in vault_ids {
+            match self.database.get_vault(*vault_id)? {
+                Some(vault) => {
+                    self.vaults.insert(*vault_id, vault);
+                }
+                None => {
+                    self.vaults.remove(vault_id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Collateral is valued through BTC/USD, so a tripped USD breaker halts every currency
+    fn check_circuit_breaker(&self, currency: &Currency) -> Result<()> {
+        for halted in [currency, &Currency::USD] {
+            if self.tripped_currencies.contains(halted) {
+                return Err(BitStableError::CircuitBreakerTripped(halted.to_string()));
+            }
+        }
+        Ok(())
+    }
+
     pub async fn create_vault(
         &mut self,
         owner: PublicKey,
@@ -230,6 +347,22 @@ impl VaultManager {
         currency: Currency,
         stable_amount: f64,
     ) -> Result<Txid> {
+        let vault_id = self.generate_vault_id();
+        self.create_vault_at(vault_id, owner, collateral, currency, stable_amount, Utc::now()).await?;
+        Ok(vault_id)
+    }
+
+    /// Create a vault with a caller-chosen id and creation time, so replicas applying
+    /// the same operation end up with identical vaults
+    pub async fn create_vault_at(
+        &mut self,
+        vault_id: Txid,
+        owner: PublicKey,
+        collateral: Amount,
+        currency: Currency,
+        stable_amount: f64,
+        created_at: DateTime<Utc>,
+    ) -> Result<()> {
         // Get currency configuration
         let currency_config = self.currency_configs.get(&currency)
             .ok_or_else(|| BitStableError::InvalidConfig(format!("Currency {} not supported", currency.to_string())))?;
@@ -239,6 +372,8 @@ impl VaultManager {
             return Err(BitStableError::InvalidConfig(format!("Currency {} is disabled", currency.to_string())));
         }
 
+        self.check_circuit_breaker(&currency)?;
+
         // Check minimum mint amount
         if stable_amount < currency_config.min_mint_amount {
             return Err(BitStableError::InvalidConfig(
@@ -247,8 +382,7 @@ impl VaultManager {
         }
 
         // Calculate required collateral
-        let btc_price = self.exchange_rates.calculate_btc_price(&currency, 
-            self.exchange_rates.get_btc_price(&Currency::USD).unwrap_or(0.0));
+        let btc_price = self.exchange_rates.price_for_mode(&currency, currency_config.mint_price_mode);
         
         let collateral_value = collateral.to_btc() * btc_price;
         let required_collateral = stable_amount * currency_config.min_collateral_ratio;
@@ -260,27 +394,25 @@ impl VaultManager {
             });
         }
 
-        // Generate vault ID
-        let vault_id = self.generate_vault_id();
-        
         if self.vaults.contains_key(&vault_id) {
             return Err(BitStableError::VaultAlreadyExists(vault_id));
         }
 
         // Create vault with multi-currency support
         let mut vault = Vault::new(vault_id, owner, collateral);
+        vault.created_at = created_at;
+        vault.last_fee_update = created_at;
         vault.mint_debt(currency.clone(), stable_amount)?;
+        vault.debts.last_updated = created_at;
         
-        // Store in database
-        self.store_vault(&vault)?;
-        
-        // Store in memory
         self.vaults.insert(vault_id, vault);
+        self.store_vault(vault_id)?;
         
         log::info!("Created vault {} with {} BTC collateral for {} {}", 
                   vault_id, collateral.to_btc(), stable_amount, currency.to_string());
+        self.events.emit(ProtocolEvent::VaultCreated { vault_id, owner, collateral, currency, amount: stable_amount });
         
-        Ok(vault_id)
+        Ok(())
     }
 
     /// Mint additional stable value in a specific currency
@@ -294,6 +426,7 @@ impl VaultManager {
         let currency_config = self.currency_configs.get(&currency)
             .ok_or_else(|| BitStableError::InvalidConfig(format!("Currency {} not supported", currency.to_string())))?
             .clone();
+        self.check_circuit_breaker(&currency)?;
         let exchange_rates = self.exchange_rates.clone();
         
         {
@@ -303,7 +436,7 @@ impl VaultManager {
             let mut test_vault = vault.clone();
             test_vault.mint_debt(currency.clone(), amount)?;
             
-            let new_ratio = test_vault.collateral_ratio_for_currency(&currency, &exchange_rates);
+            let new_ratio = test_vault.collateral_ratio_with_mode(&currency, &exchange_rates, currency_config.mint_price_mode);
             if new_ratio < currency_config.min_collateral_ratio {
                 return Err(BitStableError::InsufficientCollateral {
                     required: currency_config.min_collateral_ratio,
@@ -316,8 +449,8 @@ impl VaultManager {
         }
         
         // Store after releasing the mutable borrow
-        let vault = self.get_vault(vault_id)?;
-        self.store_vault(vault)?;
+        self.store_vault(vault_id)?;
+        self.events.emit(ProtocolEvent::StableMinted { vault_id, currency, amount });
         
         Ok(())
     }
@@ -331,12 +464,12 @@ impl VaultManager {
     ) -> Result<()> {
         {
             let vault = self.get_vault_mut(vault_id)?;
-            vault.burn_debt(currency, amount)?;
+            vault.burn_debt(currency.clone(), amount)?;
         }
         
         // Store after releasing the mutable borrow
-        let vault = self.get_vault(vault_id)?;
-        self.store_vault(vault)?;
+        self.store_vault(vault_id)?;
+        self.events.emit(ProtocolEvent::StableBurned { vault_id, currency, amount });
         Ok(())
     }
 
@@ -348,6 +481,28 @@ impl VaultManager {
         self.vaults.get_mut(&vault_id).ok_or(BitStableError::VaultNotFound(vault_id))
     }
 
+    /// Record the escrow output funding a vault
+    pub fn record_funding(&mut self, vault_id: Txid, funding: EscrowFunding) -> Result<()> {
+        let vault = self.get_vault_mut(vault_id)?;
+        vault.funding = Some(funding);
+        vault.revision += 1;
+        self.store_vault(vault_id)
+    }
+
+    /// Store a vault learned from a peer, replacing any older copy
+    pub fn apply_synced_vault(&mut self, vault: Vault) -> Result<()> {
+        let vault_id = vault.id;
+        self.vaults.insert(vault_id, vault);
+        self.store_vault(vault_id)
+    }
+
+    /// Replace every stored vault, e.g. when installing a replicated snapshot
+    pub fn replace_all_vaults(&mut self, vaults: Vec<Vault>) -> Result<()> {
+        self.database.replace_vaults(&vaults)?;
+        self.vaults = vaults.into_iter().map(|vault| (vault.id, vault)).collect();
+        Ok(())
+    }
+
     pub fn list_vaults(&self) -> Vec<&Vault> {
         self.vaults.values().collect()
     }
@@ -364,7 +519,12 @@ impl VaultManager {
         let exchange_rates = self.exchange_rates.clone();
         let currency_configs = self.currency_configs.clone();
         
-        {
+        let debt_currencies: Vec<Currency> = self.get_vault(vault_id)?.debts.debts.keys().cloned().collect();
+        for currency in &debt_currencies {
+            self.check_circuit_breaker(currency)?;
+        }
+        
+        let (collateral_seized, debt_covered_usd) = {
             let vault = self.get_vault_mut(vault_id)?;
             
             if !vault.is_liquidatable(&exchange_rates, &currency_configs) {
@@ -374,13 +534,20 @@ impl VaultManager {
             }
 
             vault.state = VaultState::Liquidated;
-        }
+            vault.revision += 1;
+            (vault.collateral_btc, vault.debts.total_debt_in_usd(&exchange_rates))
+        };
         
         // Store after releasing the mutable borrow
-        let vault = self.get_vault(vault_id)?;
-        self.store_vault(vault)?;
+        self.store_vault(vault_id)?;
         
         log::info!("Vault {} liquidated by {}", vault_id, liquidator);
+        self.events.emit(ProtocolEvent::VaultLiquidated {
+            vault_id,
+            collateral_seized,
+            debt_covered_usd,
+            state: VaultState::Liquidated,
+        });
         
         Ok(())
     }
@@ -404,17 +571,50 @@ impl VaultManager {
             let collateral_to_return = vault.collateral_btc;
             vault.state = VaultState::Closed;
             vault.collateral_btc = Amount::ZERO;
+            vault.revision += 1;
             
             collateral_to_return
         };
         
         // Store the updated vault after releasing the mutable borrow
-        let vault = self.get_vault(vault_id)?;
-        self.store_vault(vault)?;
+        self.store_vault(vault_id)?;
+        self.events.emit(ProtocolEvent::VaultClosed { vault_id, owner, collateral_returned: collateral_to_return });
         
         Ok(collateral_to_return)
     }
 
+    /// Apply a settled liquidation: seize collateral and cover debt pro rata,
+    /// marking the vault liquidated once no debt remains
+    pub fn apply_liquidation(
+        &mut self,
+        vault_id: Txid,
+        collateral_seized: Amount,
+        debt_covered_usd: f64,
+        exchange_rates: &ExchangeRates,
+    ) -> Result<()> {
+        let state = {
+            let vault = self.get_vault_mut(vault_id)?;
+            let total_debt_usd = vault.debts.total_debt_in_usd(exchange_rates);
+            let covered = if total_debt_usd > 0.0 { debt_covered_usd / total_debt_usd } else { 1.0 };
+            
+            if covered >= 1.0 - 1e-9 {
+                vault.debts.debts.clear();
+                vault.state = VaultState::Liquidated;
+            } else {
+                for debt in vault.debts.debts.values_mut() {
+                    *debt *= 1.0 - covered;
+                }
+            }
+            vault.collateral_btc = vault.collateral_btc.checked_sub(collateral_seized).unwrap_or(Amount::ZERO);
+            vault.revision += 1;
+            vault.state.clone()
+        };
+        
+        self.store_vault(vault_id)?;
+        self.events.emit(ProtocolEvent::VaultLiquidated { vault_id, collateral_seized, debt_covered_usd, state });
+        Ok(())
+    }
+
     pub fn update_all_stability_fees(&mut self) -> Result<()> {
         let vault_ids: Vec<Txid> = self.vaults.keys().copied().collect();
         
@@ -422,8 +622,7 @@ impl VaultManager {
             if let Some(vault) = self.vaults.get_mut(&vault_id) {
                 if vault.state == VaultState::Active {
                     vault.update_stability_fees(&self.currency_configs)?;
-                    let vault_clone = vault.clone();
-                    self.store_vault(&vault_clone)?;
+                    self.store_vault(vault_id)?;
                 }
             }
         }
@@ -446,7 +645,7 @@ impl VaultManager {
             .sum()
     }
 
-    fn generate_vault_id(&self) -> Txid {
+    pub(crate) fn generate_vault_id(&self) -> Txid {
         use rand::RngCore;
         use bitcoin::hashes::{Hash, sha256d};
         let mut rng = rand::thread_rng();
@@ -455,17 +654,22 @@ impl VaultManager {
         Txid::from_raw_hash(sha256d::Hash::from_byte_array(bytes))
     }
 
-    fn store_vault(&self, vault: &Vault) -> Result<()> {
-        let key = vault.id.to_string();
-        let value = serde_json::to_vec(vault)?;
-        self.db.insert(key.as_bytes(), value)?;
-        Ok(())
+    /// Write a vault now, or stage it if a unit of work is open
+    fn store_vault(&mut self, vault_id: Txid) -> Result<()> {
+        if let Some(staged) = &mut self.staged {
+            staged.insert(vault_id);
+            return Ok(());
+        }
+        let vault = self.get_vault(vault_id)?;
+        self.write_vault(vault)
+    }
+
+    fn write_vault(&self, vault: &Vault) -> Result<()> {
+        self.database.save_vault(vault)
     }
 
     fn load_vaults(&mut self) -> Result<()> {
-        for item in self.db.iter() {
-            let (_, value) = item?;
-            let vault: Vault = serde_json::from_slice(&value)?;
+        for vault in self.database.list_vaults()? {
             self.vaults.insert(vault.id, vault);
         }
         log::info!("Loaded {} vaults from database", self.vaults.len());
@@ -492,9 +696,12 @@ impl VaultManager {
         
         // Reduce debt by redemption amount
         vault.debts.remove_debt(currency.clone(), amount)?;
+        vault.revision += 1;
+        self.store_vault(vault_id)?;
         
         log::info!("Processed redemption: {} {} from vault {} for {}", 
                   amount, currency.to_string(), vault_id, redeemer);
+        self.events.emit(ProtocolEvent::StableBurned { vault_id, currency, amount });
         
         Ok(())
     }
@@ -543,4 +750,105 @@ mod tests {
         let liq_price = vault.calculate_liquidation_price(&Currency::USD, &exchange_rates, 1.2);
         assert_eq!(liq_price, 60000.0);
     }
+
+    #[test]
+    fn test_twap_liquidation_scan() {
+        let secp = Secp256k1::new();
+        let owner = PublicKey::new(SecretKey::from_slice(&[3; 32]).unwrap().public_key(&secp));
+        let vault_with_debt = |seed: u8, currency: Currency| {
+            let vault_id = Txid::from_raw_hash(bitcoin::hashes::sha256d::Hash::hash(&[seed]));
+            let mut vault = Vault::new(vault_id, owner, Amount::from_btc(1.0).unwrap());
+            vault.mint_debt(currency, 42000.0).unwrap();
+            vault
+        };
+        let usd_vault = vault_with_debt(1, Currency::USD);
+        let eur_vault = vault_with_debt(2, Currency::EUR);
+
+        // Spot has dipped to 119% while the TWAP still reads 143%
+        let mut exchange_rates = ExchangeRates::new();
+        exchange_rates.update_btc_price(Currency::USD, 50000.0);
+        exchange_rates.update_exchange_rate(Currency::EUR, 1.0);
+        exchange_rates.update_twap_price(Currency::USD, 60000.0);
+
+        let mut engine = crate::LiquidationEngine::new(&ProtocolConfig::testnet()).unwrap();
+        engine.set_price_mode(Currency::EUR, PriceMode::Twap);
+        let queued = |engine: &mut crate::LiquidationEngine, exchange_rates: &ExchangeRates| {
+            engine.scan_for_liquidations(&[&usd_vault, &eur_vault], exchange_rates);
+            engine.get_liquidation_opportunities().iter().map(|opp| opp.vault_id).collect::<Vec<_>>()
+        };
+
+        // Only the spot-priced USD debt is liquidatable
+        assert_eq!(queued(&mut engine, &exchange_rates), vec![usd_vault.id]);
+
+        // Once the TWAP catches down, the EUR debt is too
+        exchange_rates.update_twap_price(Currency::USD, 48000.0);
+        let mut ids = queued(&mut engine, &exchange_rates);
+        ids.sort();
+        let mut expected = vec![usd_vault.id, eur_vault.id];
+        expected.sort();
+        assert_eq!(ids, expected);
+        let eur = engine.get_liquidation_opportunities().into_iter().find(|opp| opp.vault_id == eur_vault.id).unwrap();
+        assert!((eur.collateral_ratio - 48000.0 / 42000.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_blocks_minting() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = ProtocolConfig::testnet();
+        config.database_path = temp_dir.path().to_string_lossy().to_string();
+        let mut manager = VaultManager::new(&config).unwrap();
+
+        let mut exchange_rates = ExchangeRates::new();
+        exchange_rates.update_btc_price(Currency::USD, 100000.0);
+        manager.update_exchange_rates(exchange_rates);
+
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::new(&mut rand::thread_rng());
+        let owner = PublicKey::from_private_key(&secp, &PrivateKey::new(secret_key, Network::Testnet));
+        let collateral = Amount::from_btc(1.0).unwrap();
+
+        manager.update_circuit_breakers(HashSet::from([Currency::USD]));
+        let result = manager.create_vault(owner, collateral, Currency::USD, 1000.0).await;
+        assert!(matches!(result, Err(BitStableError::CircuitBreakerTripped(_))));
+
+        manager.update_circuit_breakers(HashSet::new());
+        let vault_id = manager.create_vault(owner, collateral, Currency::USD, 1000.0).await.unwrap();
+
+        manager.update_circuit_breakers(HashSet::from([Currency::USD]));
+        let result = manager.mint_additional(vault_id, Currency::USD, 500.0).await;
+        assert!(matches!(result, Err(BitStableError::CircuitBreakerTripped(_))));
+    }
+
+    #[tokio::test]
+    async fn test_staged_work_commits_or_rolls_back() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = ProtocolConfig::testnet();
+        config.database_path = temp_dir.path().to_string_lossy().to_string();
+        let mut manager = VaultManager::new(&config).unwrap();
+
+        let mut exchange_rates = ExchangeRates::new();
+        exchange_rates.update_btc_price(Currency::USD, 100000.0);
+        manager.update_exchange_rates(exchange_rates);
+
+        let secp = Secp256k1::new();
+        let owner = PublicKey::new(SecretKey::from_slice(&[3; 32]).unwrap().public_key(&secp));
+        let stored = |manager: &VaultManager, vault_id: Txid| -> Option<Vault> {
+            manager.database.get_vault(vault_id).unwrap()
+        };
+
+        // Nothing reaches disk until commit
+        manager.begin_work();
+        let vault_id = manager.create_vault(owner, Amount::from_btc(1.0).unwrap(), Currency::USD, 1000.0).await.unwrap();
+        assert!(stored(&manager, vault_id).is_none());
+        manager.commit_work(UnitOfWork::new()).unwrap();
+        assert_eq!(stored(&manager, vault_id).unwrap().revision, 1);
+
+        // A failed flow restores the vault in memory and leaves disk untouched
+        manager.burn_stable(vault_id, Currency::USD, 1000.0).await.unwrap();
+        manager.begin_work();
+        manager.close_vault(vault_id, owner).await.unwrap();
+        manager.rollback_work().unwrap();
+        assert_eq!(manager.get_vault(vault_id).unwrap().state, VaultState::Active);
+        assert_eq!(stored(&manager, vault_id).unwrap().state, VaultState::Active);
+    }
 }
@@ -0,0 +1,731 @@
+//! Vault state synchronization between nodes
+//! Merkle summaries of the vault set, bucket range fetches and on-chain checks of synced records
+
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::{ecdsa::Signature, Message, Secp256k1, SecretKey};
+use bitcoin::{Amount, PublicKey, Txid};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+use crate::{BitStableError, Result};
+use crate::bitcoin_client::BitcoinClient;
+use crate::network::{BitStableNetwork, MessageData, MessageType, NetworkMessage};
+use crate::vault::{EscrowFunding, Vault, VaultAttestation, VaultManager, VaultState};
+use crate::wire::FEATURE_VAULT_SYNC;
+
+/// Vault ids are split into this many ranges by the high bits of their first byte
+pub const SYNC_BUCKETS: usize = 16;
+pub const MAX_VAULTS_PER_MESSAGE: usize = 500;
+pub const MIN_FUNDING_CONFIRMATIONS: u32 = 1;
+const SYNC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+const ANNOUNCE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+/// Config key holding the sequence of the first event not yet announced
+const ANNOUNCE_CURSOR_CONFIG: &str = "vault_sync_announced";
+
+pub fn bucket_of(vault_id: &Txid) -> u8 {
+    vault_id.to_byte_array()[0] >> 4
+}
+
+/// Hash of a vault record without its attestation; debts are hashed in sorted-key form
+fn vault_hash(vault: &Vault) -> Result<[u8; 32]> {
+    let mut value = serde_json::to_value(vault)?;
+    if let Some(record) = value.as_object_mut() {
+        record.remove("attestation");
+    }
+    Ok(Sha256::digest(serde_json::to_vec(&value)?).into())
+}
+
+/// Attest to `vault` as its owner or with a custody key
+pub fn sign_vault(vault: &mut Vault, key: &SecretKey) -> Result<()> {
+    let secp = Secp256k1::signing_only();
+    let signature = secp.sign_ecdsa(&Message::from_digest(vault_hash(vault)?), key);
+    vault.attestation = Some(VaultAttestation {
+        signer: PublicKey::new(key.public_key(&secp)),
+        signature: signature.serialize_compact().to_vec(),
+    });
+    Ok(())
+}
+
+fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| {
+                let mut hasher = Sha256::new();
+                hasher.update(pair[0]);
+                hasher.update(pair.get(1).unwrap_or(&pair[0]));
+                hasher.finalize().into()
+            })
+            .collect();
+    }
+    level.first().copied().unwrap_or_default()
+}
+
+/// Two-level Merkle summary: one hash per id bucket, and a root over the buckets
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VaultSetSummary {
+    pub root: [u8; 32],
+    pub buckets: Vec<[u8; 32]>,
+    pub vault_count: usize,
+}
+
+impl VaultSetSummary {
+    pub fn from_vaults<'a>(vaults: impl IntoIterator<Item = &'a Vault>) -> Result<Self> {
+        let mut leaves: Vec<Vec<(Txid, [u8; 32])>> = vec![Vec::new(); SYNC_BUCKETS];
+        let mut vault_count = 0;
+        for vault in vaults {
+            leaves[bucket_of(&vault.id) as usize].push((vault.id, vault_hash(vault)?));
+            vault_count += 1;
+        }
+
+        let buckets: Vec<[u8; 32]> = leaves
+            .into_iter()
+            .map(|mut bucket| {
+                bucket.sort_by_key(|(id, _)| *id);
+                let mut hasher = Sha256::new();
+                for (_, hash) in bucket {
+                    hasher.update(hash);
+                }
+                hasher.finalize().into()
+            })
+            .collect();
+
+        Ok(Self {
+            root: merkle_root(&buckets),
+            buckets,
+            vault_count,
+        })
+    }
+
+    /// Reject summaries whose shape or root doesn't match their buckets
+    pub fn validate(&self) -> Result<()> {
+        if self.buckets.len() != SYNC_BUCKETS || merkle_root(&self.buckets) != self.root {
+            return Err(BitStableError::PeerProtocolError("Inconsistent vault set summary".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Buckets whose contents differ between the two summaries
+    pub fn differing_buckets(&self, other: &VaultSetSummary) -> Vec<u8> {
+        if self.root == other.root {
+            return Vec::new();
+        }
+        self.buckets
+            .iter()
+            .zip(&other.buckets)
+            .enumerate()
+            .filter(|(_, (ours, theirs))| ours != theirs)
+            .map(|(bucket, _)| bucket as u8)
+            .collect()
+    }
+}
+
+/// On-chain lookups used to check a synced vault's escrow
+pub trait FundingVerifier {
+    /// Confirmed value of the escrow output, or None if it doesn't exist or pays elsewhere
+    fn funded_amount(&self, funding: &EscrowFunding) -> Result<Option<Amount>>;
+
+    fn is_spent(&self, funding: &EscrowFunding) -> Result<bool>;
+}
+
+impl FundingVerifier for BitcoinClient {
+    fn funded_amount(&self, funding: &EscrowFunding) -> Result<Option<Amount>> {
+        // An unknown transaction is an unfunded vault, not a node failure
+        let Ok(tx_info) = self.get_transaction(funding.outpoint.txid) else {
+            return Ok(None);
+        };
+        if tx_info.confirmations < MIN_FUNDING_CONFIRMATIONS {
+            return Ok(None);
+        }
+
+        Ok(tx_info.outputs
+            .get(funding.outpoint.vout as usize)
+            .filter(|output| output.address.as_ref().map(|address| address.to_string()) == Some(funding.address.clone()))
+            .map(|output| output.value))
+    }
+
+    fn is_spent(&self, funding: &EscrowFunding) -> Result<bool> {
+        self.is_output_spent(funding.outpoint)
+    }
+}
+
+/// Active < Liquidating < Liquidated/Closed; a vault never moves backwards
+fn state_rank(state: &VaultState) -> u8 {
+    match state {
+        VaultState::Active => 0,
+        VaultState::Liquidating => 1,
+        VaultState::Liquidated | VaultState::Closed => 2,
+    }
+}
+
+/// A later state always wins; within a state the signed revision must rise
+/// without fee accrual moving backwards
+fn supersedes(remote: &Vault, local: &Vault) -> bool {
+    match state_rank(&remote.state).cmp(&state_rank(&local.state)) {
+        std::cmp::Ordering::Greater => true,
+        std::cmp::Ordering::Less => false,
+        std::cmp::Ordering::Equal => remote.revision > local.revision && remote.last_fee_update >= local.last_fee_update,
+    }
+}
+
+/// Who vouched for a synced vault record
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Signer {
+    Owner,
+    Custody,
+}
+
+/// Debts must be real amounts, and an ended vault can't still owe anything
+fn check_debts(vault: &Vault) -> Result<()> {
+    if vault.debts.debts.values().any(|debt| !debt.is_finite() || *debt < 0.0) {
+        return Err(BitStableError::PeerProtocolError(format!("Vault {} carries an invalid debt", vault.id)));
+    }
+    if vault.state == VaultState::Closed && !vault.debts.is_empty() {
+        return Err(BitStableError::PeerProtocolError(format!("Closed vault {} still owes debt", vault.id)));
+    }
+    Ok(())
+}
+
+/// Owners can only mint against their vault, never write debt off or liquidate it;
+/// custody keys can only reduce debt, beyond the stability fee accrued since our copy
+fn check_debt_change(local: &Vault, remote: &Vault, signer: Signer, manager: &VaultManager) -> Result<()> {
+    const TOLERANCE: f64 = 1e-6;
+    let conflict = |what: &str| Err(BitStableError::PeerProtocolError(format!("Vault {} {}", remote.id, what)));
+
+    let currencies: std::collections::HashSet<_> = local.debts.debts.keys().chain(remote.debts.debts.keys()).collect();
+    match signer {
+        Signer::Owner => {
+            if matches!(remote.state, VaultState::Liquidating | VaultState::Liquidated) {
+                return conflict("can't be liquidated by its owner");
+            }
+            if currencies.iter().any(|currency| remote.debts.get_debt(currency) < local.debts.get_debt(currency) - TOLERANCE) {
+                return conflict("debt was reduced by its owner");
+            }
+        }
+        Signer::Custody => {
+            let years = (remote.last_fee_update - local.last_fee_update).num_seconds().max(0) as f64 / (365.25 * 24.0 * 3600.0);
+            for currency in currencies {
+                let apr = manager.get_currency_configs().get(currency).map_or(0.0, |config| config.stability_fee_apr);
+                let allowed = local.debts.get_debt(currency) * (1.0 + apr * years);
+                if remote.debts.get_debt(currency) > allowed + TOLERANCE {
+                    return conflict("debt grew beyond accrued fees");
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Drives the sync protocol for one node's `VaultManager`
+pub struct VaultSync<V: FundingVerifier> {
+    verifier: V,
+    custody_keys: Vec<PublicKey>,
+    signing_key: Option<SecretKey>,
+}
+
+impl<V: FundingVerifier> VaultSync<V> {
+    /// Records are accepted when signed by their owner or one of `custody_keys`
+    pub fn new(verifier: V, custody_keys: Vec<PublicKey>) -> Self {
+        Self { verifier, custody_keys, signing_key: None }
+    }
+
+    /// Custody key to sign our own vault changes with when serving them to peers
+    pub fn with_signing_key(mut self, key: SecretKey) -> Self {
+        self.signing_key = Some(key);
+        self
+    }
+
+    pub fn summary(&self, manager: &VaultManager) -> Result<VaultSetSummary> {
+        VaultSetSummary::from_vaults(manager.list_vaults())
+    }
+
+    /// Route sync requests and vault gossip into a channel for `process`.
+    /// Replaces any handlers already registered for these message types.
+    pub fn subscribe(network: &mut BitStableNetwork) -> mpsc::UnboundedReceiver<NetworkMessage> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        for message_type in [
+            MessageType::VaultSync,
+            MessageType::VaultCreated,
+            MessageType::VaultLiquidated,
+            MessageType::VaultClosed,
+        ] {
+            let sender = sender.clone();
+            network.register_message_handler(message_type, move |message| {
+                sender.send(message.clone())
+                    .map_err(|_| BitStableError::PeerProtocolError("Vault sync stopped".to_string()))
+            });
+        }
+        receiver
+    }
+
+    /// Ask every sync-capable peer for its summary
+    pub fn request_sync(&self, network: &BitStableNetwork) -> Result<usize> {
+        let peers = network.peers_supporting(FEATURE_VAULT_SYNC);
+        for peer in &peers {
+            network.send_direct(peer, MessageType::VaultSync, MessageData::GetVaultSummary)?;
+        }
+        Ok(peers.len())
+    }
+
+    /// Announce the vaults changed by events committed since the last call, by this or
+    /// any other process sharing the database. A fresh node starts from the current
+    /// end of the log and leaves older changes to summary sync.
+    pub async fn announce_changes(&self, network: &BitStableNetwork, manager: &mut VaultManager) -> Result<usize> {
+        let database = manager.database()?;
+        let Some(from) = database.load_config::<u64>(ANNOUNCE_CURSOR_CONFIG)? else {
+            let next = database.last_event()?.map_or(0, |record| record.sequence + 1);
+            database.save_config(ANNOUNCE_CURSOR_CONFIG, &next)?;
+            return Ok(0);
+        };
+        let records = database.get_events(from, None)?;
+        let Some(last) = records.last() else {
+            return Ok(0);
+        };
+        let next = last.sequence + 1;
+
+        let mut vault_ids = Vec::new();
+        for vault_id in records.iter().filter_map(|record| record.event.vault_id()) {
+            if !vault_ids.contains(&vault_id) {
+                vault_ids.push(vault_id);
+            }
+        }
+
+        let mut announced = 0;
+        for vault_id in vault_ids {
+            // The stored record is current even if another process made the change
+            let Some(vault) = database.get_vault(vault_id)? else {
+                continue;
+            };
+            manager.apply_synced_vault(vault.clone())?;
+            match self.attested(&vault)? {
+                Some(vault) => {
+                    network.announce_vault(&vault).await?;
+                    announced += 1;
+                }
+                None => log::debug!("Not announcing vault {}: no key to sign it with", vault_id),
+            }
+        }
+        database.save_config(ANNOUNCE_CURSOR_CONFIG, &next)?;
+        Ok(announced)
+    }
+
+    /// Handle one sync message or vault announcement, replying to its sender where needed
+    pub fn process(&self, network: &BitStableNetwork, manager: &mut VaultManager, message: &NetworkMessage) -> Result<()> {
+        let reply = match &message.data {
+            MessageData::GetVaultSummary => Some(MessageData::VaultSummary { summary: self.summary(manager)? }),
+            MessageData::VaultSummary { summary } => {
+                summary.validate()?;
+                let buckets = self.summary(manager)?.differing_buckets(summary);
+                (!buckets.is_empty()).then_some(MessageData::GetVaults { buckets, after: None })
+            }
+            MessageData::GetVaults { buckets, after } => Some(self.page(manager, buckets, *after)?),
+            MessageData::Vaults { vaults, buckets, resume_after } => {
+                if vaults.len() > MAX_VAULTS_PER_MESSAGE {
+                    return Err(BitStableError::PeerProtocolError(format!("{} vaults in one message", vaults.len())));
+                }
+                let mut rejected = 0;
+                for vault in vaults {
+                    if let Err(e) = self.merge(manager, vault.clone()) {
+                        log::warn!("Rejected vault {} from {}: {}", vault.id, message.sender, e);
+                        rejected += 1;
+                    }
+                }
+                if let Some(after) = resume_after {
+                    network.send_direct(&message.sender, MessageType::VaultSync, MessageData::GetVaults {
+                        buckets: buckets.clone(),
+                        after: Some(*after),
+                    })?;
+                }
+                if rejected > 0 {
+                    return Err(BitStableError::PeerProtocolError(format!("{} vault records failed verification", rejected)));
+                }
+                None
+            }
+            MessageData::VaultRecord { vault } => {
+                self.merge(manager, vault.as_ref().clone())?;
+                None
+            }
+            // v1 announcements carry too little to verify, so fetch the record they refer to
+            MessageData::VaultCreated { vault_id, .. } | MessageData::VaultCreatedMultiCurrency { vault_id, .. } => {
+                manager.get_vault(*vault_id).is_err()
+                    .then(|| MessageData::GetVaults { buckets: vec![bucket_of(vault_id)], after: None })
+            }
+            MessageData::VaultLiquidated { vault_id, .. } => {
+                let ended = manager.get_vault(*vault_id).is_ok_and(|vault| state_rank(&vault.state) == 2);
+                (!ended).then(|| MessageData::GetVaults { buckets: vec![bucket_of(vault_id)], after: None })
+            }
+            _ => None,
+        };
+
+        match reply {
+            Some(reply) => network.send_direct(&message.sender, MessageType::VaultSync, reply),
+            None => Ok(()),
+        }
+    }
+
+    /// Attested vaults in `buckets` after `after`, in id order. A full page says where
+    /// to resume, so buckets larger than one message still converge.
+    fn page(&self, manager: &VaultManager, buckets: &[u8], after: Option<Txid>) -> Result<MessageData> {
+        let mut matching: Vec<&Vault> = manager.list_vaults()
+            .into_iter()
+            .filter(|vault| buckets.contains(&bucket_of(&vault.id)) && after.is_none_or(|after| vault.id > after))
+            .collect();
+        matching.sort_by_key(|vault| vault.id);
+
+        let resume_after = (matching.len() > MAX_VAULTS_PER_MESSAGE).then(|| matching[MAX_VAULTS_PER_MESSAGE - 1].id);
+        let mut vaults = Vec::new();
+        for vault in matching.into_iter().take(MAX_VAULTS_PER_MESSAGE) {
+            if let Some(vault) = self.attested(vault)? {
+                vaults.push(vault);
+            }
+        }
+        Ok(MessageData::Vaults { vaults, buckets: buckets.to_vec(), resume_after })
+    }
+
+    /// Our copy of a vault as peers will accept it, or None if nobody we can vouch for signed it
+    fn attested(&self, vault: &Vault) -> Result<Option<Vault>> {
+        if self.signer(vault).is_ok() {
+            return Ok(Some(vault.clone()));
+        }
+        match &self.signing_key {
+            Some(key) => {
+                let mut vault = vault.clone();
+                sign_vault(&mut vault, key)?;
+                Ok(Some(vault))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Store `vault` if it is newer than our copy and its escrow checks out; returns whether it was applied
+    pub fn merge(&self, manager: &mut VaultManager, vault: Vault) -> Result<bool> {
+        let signer = self.signer(&vault)?;
+        check_debts(&vault)?;
+        if let Ok(local) = manager.get_vault(vault.id) {
+            if !supersedes(&vault, local) {
+                return Ok(false);
+            }
+            if vault.owner != local.owner
+                || vault.created_at != local.created_at
+                || (local.funding.is_some() && vault.funding != local.funding)
+            {
+                return Err(BitStableError::PeerProtocolError(format!("Vault {} conflicts with our record", vault.id)));
+            }
+            check_debt_change(local, &vault, signer, manager)?;
+        }
+
+        self.verify(&vault)?;
+        manager.apply_synced_vault(vault)?;
+        Ok(true)
+    }
+
+    /// The record must carry a valid signature by its owner or a custody key
+    fn signer(&self, vault: &Vault) -> Result<Signer> {
+        let attestation = vault.attestation.as_ref()
+            .ok_or_else(|| BitStableError::PeerProtocolError(format!("Vault {} is not signed", vault.id)))?;
+        let signer = if attestation.signer == vault.owner {
+            Signer::Owner
+        } else if self.custody_keys.contains(&attestation.signer) {
+            Signer::Custody
+        } else {
+            return Err(BitStableError::PeerProtocolError(format!(
+                "Vault {} signed by {}, neither its owner nor a custody key", vault.id, attestation.signer
+            )));
+        };
+
+        let signature = Signature::from_compact(&attestation.signature)
+            .map_err(|e| BitStableError::PeerProtocolError(format!("Malformed signature on vault {}: {}", vault.id, e)))?;
+        Secp256k1::verification_only()
+            .verify_ecdsa(&Message::from_digest(vault_hash(vault)?), &signature, &attestation.signer.inner)
+            .map_err(|_| BitStableError::PeerProtocolError(format!("Bad signature on vault {}", vault.id)))?;
+        Ok(signer)
+    }
+
+    /// The escrow must exist and hold the collateral; it is spent exactly when the vault has ended
+    fn verify(&self, vault: &Vault) -> Result<()> {
+        let funding = vault.funding.as_ref()
+            .ok_or_else(|| BitStableError::PeerProtocolError(format!("Vault {} has no escrow funding", vault.id)))?;
+
+        let funded = self.verifier.funded_amount(funding)?
+            .ok_or_else(|| BitStableError::PeerProtocolError(format!("Escrow {} not found on-chain", funding.outpoint)))?;
+        if funded < vault.collateral_btc {
+            return Err(BitStableError::PeerProtocolError(format!(
+                "Escrow {} holds {} but vault {} claims {}", funding.outpoint, funded, vault.id, vault.collateral_btc
+            )));
+        }
+
+        let ended = state_rank(&vault.state) == 2;
+        if self.verifier.is_spent(funding)? != ended {
+            return Err(BitStableError::PeerProtocolError(format!(
+                "Escrow {} spend status doesn't match vault state {:?}", funding.outpoint, vault.state
+            )));
+        }
+        Ok(())
+    }
+
+    /// Run a network node that keeps `manager` in sync with its peers
+    pub async fn run(&self, network: &mut BitStableNetwork, manager: &mut VaultManager, bind_address: &str) -> Result<()> {
+        let mut inbox = Self::subscribe(network);
+        let local_address = network.listen(bind_address).await?;
+        log::info!("BitStable network node started on {} with vault sync", local_address);
+        network.start_peer_discovery().await?;
+
+        let mut maintenance = tokio::time::interval(std::time::Duration::from_secs(30));
+        let mut sync = tokio::time::interval(SYNC_INTERVAL);
+        let mut announce = tokio::time::interval(ANNOUNCE_INTERVAL);
+        loop {
+            tokio::select! {
+                result = network.process_next_event() => result?,
+                Some(message) = inbox.recv() => {
+                    if let Err(e) = self.process(network, manager, &message) {
+                        log::warn!("Vault sync with {} failed: {}", message.sender, e);
+                    }
+                }
+                _ = maintenance.tick() => network.maintenance_cycle().await?,
+                _ = sync.tick() => {
+                    if let Err(e) = self.request_sync(network) {
+                        log::warn!("Vault sync request failed: {}", e);
+                    }
+                }
+                _ = announce.tick() => {
+                    if let Err(e) = self.announce_changes(network, manager).await {
+                        log::warn!("Announcing vault changes failed: {}", e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use bitcoin::OutPoint;
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use crate::ProtocolConfig;
+    use crate::database::UnitOfWork;
+    use crate::events::{EventRecord, ProtocolEvent};
+
+    /// Escrow outputs by outpoint: (value, spent)
+    type Chain = Arc<Mutex<HashMap<OutPoint, (Amount, bool)>>>;
+
+    impl FundingVerifier for Chain {
+        fn funded_amount(&self, funding: &EscrowFunding) -> Result<Option<Amount>> {
+            Ok(self.lock().unwrap().get(&funding.outpoint).map(|(value, _)| *value))
+        }
+
+        fn is_spent(&self, funding: &EscrowFunding) -> Result<bool> {
+            Ok(self.lock().unwrap().get(&funding.outpoint).is_some_and(|(_, spent)| *spent))
+        }
+    }
+
+    struct Node {
+        network: BitStableNetwork,
+        manager: VaultManager,
+        sync: VaultSync<Chain>,
+        inbox: mpsc::UnboundedReceiver<NetworkMessage>,
+        _dir: tempfile::TempDir,
+    }
+
+    impl Node {
+        fn new(key: u8, chain: &Chain) -> Self {
+            let dir = tempfile::TempDir::new().unwrap();
+            let mut config = ProtocolConfig::testnet();
+            config.database_path = dir.path().to_string_lossy().to_string();
+            let secret = bitcoin::secp256k1::SecretKey::from_slice(&[key; 32]).unwrap();
+            let mut network = BitStableNetwork::new(secret, 8, []);
+            let inbox = VaultSync::<Chain>::subscribe(&mut network);
+            Self {
+                network,
+                manager: VaultManager::new(&config).unwrap(),
+                sync: VaultSync::new(chain.clone(), vec![custody_pubkey()]),
+                inbox,
+                _dir: dir,
+            }
+        }
+
+        fn pump(&mut self) {
+            self.network.process_pending_events().unwrap();
+            while let Ok(message) = self.inbox.try_recv() {
+                if let Err(e) = self.sync.process(&self.network, &mut self.manager, &message) {
+                    log::debug!("{}", e);
+                }
+            }
+        }
+    }
+
+    fn custody_key() -> SecretKey {
+        SecretKey::from_slice(&[99; 32]).unwrap()
+    }
+
+    fn custody_pubkey() -> PublicKey {
+        PublicKey::new(custody_key().public_key(&Secp256k1::signing_only()))
+    }
+
+    fn owner_key(seed: u16) -> SecretKey {
+        let mut bytes = [1u8; 32];
+        bytes[..2].copy_from_slice(&seed.to_be_bytes());
+        SecretKey::from_slice(&bytes).unwrap()
+    }
+
+    fn vault_id(seed: u16) -> Txid {
+        Txid::from_raw_hash(bitcoin::hashes::sha256d::Hash::hash(&seed.to_be_bytes()))
+    }
+
+    /// A vault holding 30,000 USD of debt, funded on `chain` and signed by its owner
+    fn funded_vault(seed: u16, chain: &Chain) -> Vault {
+        let id = vault_id(seed);
+        let owner = PublicKey::new(owner_key(seed).public_key(&Secp256k1::signing_only()));
+        let mut vault = Vault::new(id, owner, Amount::from_btc(1.0).unwrap());
+        vault.mint_debt(crate::Currency::USD, 30_000.0).unwrap();
+        let outpoint = OutPoint::new(id, 0);
+        chain.lock().unwrap().insert(outpoint, (vault.collateral_btc, false));
+        vault.funding = Some(EscrowFunding { outpoint, address: "escrow".to_string() });
+        sign_vault(&mut vault, &owner_key(seed)).unwrap();
+        vault
+    }
+
+    async fn pump_until(alice: &mut Node, bob: &mut Node, done: impl Fn(&Node) -> bool) {
+        for _ in 0..250 {
+            alice.pump();
+            bob.pump();
+            if done(bob) {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("vault sync did not converge");
+    }
+
+    #[tokio::test]
+    async fn test_vault_sets_sync_and_follow_gossip() {
+        let chain: Chain = Arc::default();
+        let mut alice = Node::new(1, &chain);
+        let mut bob = Node::new(2, &chain);
+
+        // Alice knows two funded vaults and one whose escrow never hit the chain
+        for seed in [10, 20] {
+            alice.manager.apply_synced_vault(funded_vault(seed, &chain)).unwrap();
+        }
+        let unfunded = funded_vault(30, &chain);
+        chain.lock().unwrap().remove(&unfunded.funding.as_ref().unwrap().outpoint);
+        alice.manager.apply_synced_vault(unfunded.clone()).unwrap();
+
+        let alice_address = alice.network.listen("127.0.0.1:0").await.unwrap().to_string();
+        bob.network.connect_to_peer(&alice_address, alice.network.local_pubkey()).await.unwrap();
+        while alice.network.connected_peers().is_empty() {
+            alice.pump();
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        assert_eq!(bob.sync.request_sync(&bob.network).unwrap(), 1);
+        pump_until(&mut alice, &mut bob, |bob| bob.manager.list_vaults().len() == 2).await;
+        assert!(bob.manager.get_vault(unfunded.id).is_err());
+
+        // The funded vaults now hash the same on both sides
+        let ours = bob.sync.summary(&bob.manager).unwrap();
+        let theirs = alice.sync.summary(&alice.manager).unwrap();
+        let mut differing = ours.differing_buckets(&theirs);
+        differing.retain(|bucket| *bucket != bucket_of(&unfunded.id));
+        assert!(differing.is_empty());
+
+        // Closing spends the escrow; Alice announces the committed change and Bob's copy follows
+        assert_eq!(alice.sync.announce_changes(&alice.network, &mut alice.manager).await.unwrap(), 0);
+        let mut closed = alice.manager.get_vault(vault_id(10)).unwrap().clone();
+        closed.debts.debts.clear();
+        closed.state = VaultState::Closed;
+        closed.revision += 1;
+        sign_vault(&mut closed, &custody_key()).unwrap();
+        chain.lock().unwrap().get_mut(&closed.funding.as_ref().unwrap().outpoint).unwrap().1 = true;
+        let mut work = UnitOfWork::new();
+        work.put_vault(&closed).unwrap();
+        work.put_event(&EventRecord {
+            sequence: 0,
+            timestamp: chrono::Utc::now(),
+            event: ProtocolEvent::VaultClosed { vault_id: closed.id, owner: closed.owner, collateral_returned: closed.collateral_btc },
+        }).unwrap();
+        alice.manager.database().unwrap().commit(work).unwrap();
+        assert_eq!(alice.sync.announce_changes(&alice.network, &mut alice.manager).await.unwrap(), 1);
+        assert_eq!(alice.manager.get_vault(closed.id).unwrap().state, VaultState::Closed);
+        pump_until(&mut alice, &mut bob, |bob| bob.manager.get_vault(closed.id).unwrap().state == VaultState::Closed).await;
+
+        // Stale copies and records contradicting the chain are not applied
+        let mut stale = closed.clone();
+        stale.state = VaultState::Active;
+        sign_vault(&mut stale, &custody_key()).unwrap();
+        assert!(!bob.sync.merge(&mut bob.manager, stale).unwrap());
+        let mut reopened = bob.manager.get_vault(vault_id(20)).unwrap().clone();
+        reopened.state = VaultState::Liquidated;
+        sign_vault(&mut reopened, &custody_key()).unwrap();
+        assert!(bob.sync.merge(&mut bob.manager, reopened).is_err());
+    }
+
+    #[test]
+    fn test_merge_requires_signed_consistent_records() {
+        let chain: Chain = Arc::default();
+        let mut node = Node::new(1, &chain);
+        let vault = funded_vault(10, &chain);
+
+        // Unsigned, tampered and stranger-signed records are refused
+        let mut unsigned = vault.clone();
+        unsigned.attestation = None;
+        assert!(node.sync.merge(&mut node.manager, unsigned).is_err());
+        let mut tampered = vault.clone();
+        tampered.revision = u64::MAX;
+        assert!(node.sync.merge(&mut node.manager, tampered).is_err());
+        let mut stranger = vault.clone();
+        sign_vault(&mut stranger, &owner_key(11)).unwrap();
+        assert!(node.sync.merge(&mut node.manager, stranger).is_err());
+        assert!(node.sync.merge(&mut node.manager, vault.clone()).unwrap());
+
+        // The owner can't write off its own debt, whatever the revision says
+        let mut forgiven = vault.clone();
+        forgiven.debts.debts.clear();
+        forgiven.revision += 100;
+        sign_vault(&mut forgiven, &owner_key(10)).unwrap();
+        assert!(node.sync.merge(&mut node.manager, forgiven).is_err());
+
+        // Custody can't inflate debt beyond accrued fees, but can reduce it
+        let mut inflated = vault.clone();
+        inflated.mint_debt(crate::Currency::USD, 1_000.0).unwrap();
+        sign_vault(&mut inflated, &custody_key()).unwrap();
+        assert!(node.sync.merge(&mut node.manager, inflated).is_err());
+        let mut redeemed = vault.clone();
+        redeemed.burn_debt(crate::Currency::USD, 1_000.0).unwrap();
+        sign_vault(&mut redeemed, &custody_key()).unwrap();
+        assert!(node.sync.merge(&mut node.manager, redeemed).unwrap());
+        assert_eq!(node.manager.get_vault(vault.id).unwrap().debts.get_debt(&crate::Currency::USD), 29_000.0);
+
+        // A higher revision alone doesn't roll fee accrual back
+        let mut rewound = node.manager.get_vault(vault.id).unwrap().clone();
+        rewound.last_fee_update -= chrono::Duration::days(1);
+        rewound.revision += 1;
+        sign_vault(&mut rewound, &custody_key()).unwrap();
+        assert!(!node.sync.merge(&mut node.manager, rewound).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_large_buckets_sync_in_pages() {
+        let chain: Chain = Arc::default();
+        let mut alice = Node::new(1, &chain);
+        let mut bob = Node::new(2, &chain);
+        let total = MAX_VAULTS_PER_MESSAGE * 2 + 50;
+        for seed in 0..total as u16 {
+            alice.manager.apply_synced_vault(funded_vault(seed, &chain)).unwrap();
+        }
+
+        let alice_address = alice.network.listen("127.0.0.1:0").await.unwrap().to_string();
+        bob.network.connect_to_peer(&alice_address, alice.network.local_pubkey()).await.unwrap();
+        while alice.network.connected_peers().is_empty() {
+            alice.pump();
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        bob.sync.request_sync(&bob.network).unwrap();
+        pump_until(&mut alice, &mut bob, |bob| bob.manager.list_vaults().len() == total).await;
+        assert_eq!(bob.sync.summary(&bob.manager).unwrap(), alice.sync.summary(&alice.manager).unwrap());
+    }
+}
@@ -0,0 +1,270 @@
+//! Wire format for the BitStable peer protocol
+//! Versioned, length-prefixed frames and the hello handshake that opens every connection
+
+use bitcoin::PublicKey;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use crate::{BitStableError, Result};
+use crate::network::{NetworkMessage, ServiceType};
+
+pub const PROTOCOL_VERSION: u16 = 2;
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+pub const NETWORK_MAGIC: [u8; 4] = *b"BTSB";
+pub const MAX_FRAME_SIZE: usize = 4 * 1024 * 1024;
+pub const USER_AGENT: &str = concat!("bitstable/", env!("CARGO_PKG_VERSION"));
+
+/// Optional features, negotiated as the intersection of both hellos.
+/// Version 1 peers send no feature bits.
+pub const FEATURE_MULTI_CURRENCY: u64 = 1;   // Per-currency attested prices, multi-currency debt and transfers
+pub const FEATURE_VAULT_SYNC: u64 = 2;       // Vault set summaries, range fetches and full vault records
+pub const FEATURE_REPLICATION: u64 = 4;      // Raft envelopes between replicas of the protocol state
+pub const SUPPORTED_FEATURES: u64 = FEATURE_MULTI_CURRENCY | FEATURE_VAULT_SYNC | FEATURE_REPLICATION;
+
+/// magic (4) | version (2) | kind (1) | payload length (4), all big-endian
+const HEADER_LEN: usize = 11;
+pub(crate) const HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Hello = 0,
+    Message = 1,
+    Ping = 2,
+    Pong = 3,
+}
+
+impl TryFrom<u8> for FrameKind {
+    type Error = BitStableError;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(FrameKind::Hello),
+            1 => Ok(FrameKind::Message),
+            2 => Ok(FrameKind::Ping),
+            3 => Ok(FrameKind::Pong),
+            other => Err(BitStableError::PeerProtocolError(format!("Unknown frame kind {}", other))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub version: u16,
+    pub kind: FrameKind,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(kind: FrameKind, payload: Vec<u8>) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            kind,
+            payload,
+        }
+    }
+
+    pub fn message(message: &NetworkMessage) -> Result<Self> {
+        Ok(Self::new(FrameKind::Message, serde_json::to_vec(message)?))
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.payload.len());
+        bytes.extend_from_slice(&NETWORK_MAGIC);
+        bytes.extend_from_slice(&self.version.to_be_bytes());
+        bytes.push(self.kind as u8);
+        bytes.extend_from_slice(&(self.payload.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+}
+
+pub async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, frame: &Frame) -> Result<()> {
+    if frame.payload.len() > MAX_FRAME_SIZE {
+        return Err(BitStableError::PeerProtocolError(format!(
+            "Frame of {} bytes exceeds the {} byte limit", frame.payload.len(), MAX_FRAME_SIZE
+        )));
+    }
+
+    writer.write_all(&frame.encode()).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Frame> {
+    let mut header = [0u8; HEADER_LEN];
+    reader.read_exact(&mut header).await?;
+
+    if header[..4] != NETWORK_MAGIC {
+        return Err(BitStableError::PeerProtocolError("Bad network magic".to_string()));
+    }
+
+    let version = u16::from_be_bytes([header[4], header[5]]);
+    let kind = FrameKind::try_from(header[6])?;
+    let length = u32::from_be_bytes([header[7], header[8], header[9], header[10]]) as usize;
+    if length > MAX_FRAME_SIZE {
+        return Err(BitStableError::PeerProtocolError(format!("Frame length {} exceeds limit", length)));
+    }
+
+    let mut payload = vec![0u8; length];
+    reader.read_exact(&mut payload).await?;
+
+    Ok(Frame { version, kind, payload })
+}
+
+/// First frame each side sends after connecting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hello {
+    pub version: u16,
+    pub pubkey: PublicKey,
+    pub services: u32,                    // `ServiceType` bit flags
+    #[serde(default)]
+    pub features: u64,                    // `FEATURE_*` bits the sender understands
+    pub listen_address: Option<String>,   // Where the peer accepts connections, if anywhere
+    pub user_agent: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Hello {
+    pub fn new(pubkey: PublicKey, services: &[ServiceType], listen_address: Option<String>) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            pubkey,
+            services: ServiceType::to_flags(services),
+            features: SUPPORTED_FEATURES,
+            listen_address,
+            user_agent: USER_AGENT.to_string(),
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+/// What we learned about the remote side during the handshake
+#[derive(Debug, Clone)]
+pub struct PeerHandshake {
+    pub hello: Hello,
+    pub version: u16,   // Highest version both sides speak
+    pub features: u64,  // Features both sides advertised
+}
+
+impl PeerHandshake {
+    pub fn supports(&self, feature: u64) -> bool {
+        self.features & feature == feature
+    }
+
+    pub fn services(&self) -> Vec<ServiceType> {
+        ServiceType::from_flags(self.hello.services)
+    }
+}
+
+/// Exchange hellos; outbound connections pass the pubkey they expect to reach
+pub async fn handshake<S>(stream: &mut S, local: &Hello, expected: Option<PublicKey>) -> Result<PeerHandshake>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let exchange = async {
+        write_frame(stream, &Frame::new(FrameKind::Hello, serde_json::to_vec(local)?)).await?;
+        read_frame(stream).await
+    };
+
+    let frame = tokio::time::timeout(HANDSHAKE_TIMEOUT, exchange)
+        .await
+        .map_err(|_| BitStableError::PeerProtocolError("Handshake timed out".to_string()))??;
+
+    if frame.kind != FrameKind::Hello {
+        return Err(BitStableError::PeerProtocolError(format!("Expected hello, got {:?}", frame.kind)));
+    }
+
+    let hello: Hello = serde_json::from_slice(&frame.payload)?;
+    if hello.version < MIN_PROTOCOL_VERSION {
+        return Err(BitStableError::PeerProtocolError(format!(
+            "Peer speaks protocol {}, minimum is {}", hello.version, MIN_PROTOCOL_VERSION
+        )));
+    }
+    if hello.pubkey.inner == local.pubkey.inner {
+        return Err(BitStableError::PeerProtocolError("Connected to ourselves".to_string()));
+    }
+    if let Some(expected) = expected {
+        if hello.pubkey.inner != expected.inner {
+            return Err(BitStableError::PeerProtocolError(format!(
+                "Expected peer {}, got {}", expected, hello.pubkey
+            )));
+        }
+    }
+
+    let version = hello.version.min(PROTOCOL_VERSION);
+    let features = hello.features & local.features;
+    Ok(PeerHandshake { hello, version, features })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pubkey(byte: u8) -> PublicKey {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let secret = bitcoin::secp256k1::SecretKey::from_slice(&[byte; 32]).unwrap();
+        PublicKey::new(bitcoin::secp256k1::PublicKey::from_secret_key(&secp, &secret))
+    }
+
+    #[tokio::test]
+    async fn test_frame_roundtrip_and_limits() {
+        let (mut a, mut b) = tokio::io::duplex(1024);
+
+        let frame = Frame::new(FrameKind::Ping, vec![1, 2, 3]);
+        write_frame(&mut a, &frame).await.unwrap();
+        assert_eq!(read_frame(&mut b).await.unwrap(), frame);
+
+        // Wrong magic is rejected before the payload is read
+        let mut bytes = frame.encode();
+        bytes[0] = b'X';
+        a.write_all(&bytes).await.unwrap();
+        assert!(matches!(read_frame(&mut b).await, Err(BitStableError::PeerProtocolError(_))));
+
+        // Oversized lengths are refused rather than allocated
+        let (mut a, mut b) = tokio::io::duplex(1024);
+        let mut header = NETWORK_MAGIC.to_vec();
+        header.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+        header.push(FrameKind::Message as u8);
+        header.extend_from_slice(&(MAX_FRAME_SIZE as u32 + 1).to_be_bytes());
+        a.write_all(&header).await.unwrap();
+        assert!(read_frame(&mut b).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_checks_identity() {
+        let (mut a, mut b) = tokio::io::duplex(4096);
+        let alice = Hello::new(pubkey(1), &[ServiceType::Oracle], Some("127.0.0.1:9000".to_string()));
+        let bob = Hello::new(pubkey(2), &[ServiceType::Liquidator, ServiceType::StableHolder], None);
+
+        let (from_bob, from_alice) = tokio::join!(
+            handshake(&mut a, &alice, Some(pubkey(2))),
+            handshake(&mut b, &bob, None),
+        );
+        let from_bob = from_bob.unwrap();
+        assert_eq!(from_bob.services(), vec![ServiceType::Liquidator, ServiceType::StableHolder]);
+        assert!(from_bob.supports(FEATURE_MULTI_CURRENCY));
+        assert_eq!(from_alice.unwrap().hello.listen_address.as_deref(), Some("127.0.0.1:9000"));
+
+        // Dialing a key we didn't expect fails the handshake
+        let (mut a, mut b) = tokio::io::duplex(4096);
+        let (wrong, _) = tokio::join!(
+            handshake(&mut a, &alice, Some(pubkey(3))),
+            handshake(&mut b, &bob, None),
+        );
+        assert!(wrong.is_err());
+
+        // A version 1 peer has no feature field, so nothing optional is negotiated
+        let (mut a, mut b) = tokio::io::duplex(4096);
+        let mut legacy = serde_json::to_value(&bob).unwrap();
+        legacy["version"] = 1.into();
+        legacy.as_object_mut().unwrap().remove("features");
+        let legacy_frame = Frame { version: 1, kind: FrameKind::Hello, payload: serde_json::to_vec(&legacy).unwrap() };
+        let (from_legacy, _) = tokio::join!(
+            handshake(&mut a, &alice, None),
+            write_frame(&mut b, &legacy_frame),
+        );
+        let from_legacy = from_legacy.unwrap();
+        assert_eq!((from_legacy.version, from_legacy.features), (1, 0));
+        assert!(!from_legacy.supports(FEATURE_MULTI_CURRENCY));
+    }
+}